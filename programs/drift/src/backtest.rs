@@ -0,0 +1,720 @@
+//! Replays a stream of oracle prices and taker trades against a perp market so amm parameters
+//! (base_spread, max_spread, curve_update_intensity, amm_jit_intensity, ...) can be tuned
+//! against history. Only built for tests and with the `backtest` feature, it isn't part of the
+//! program.
+//!
+//! The market's amm is driven through the same controller functions the program uses:
+//! `_update_amm` on every oracle update and fill, `update_funding_rate` whenever funding is due,
+//! `fulfill_perp_order_with_amm` for fills and `apply_lp_rebase_to_perp_market` for lp rebases.
+//!
+//! Records are csv lines `ts,slot,kind,...` where kind is one of
+//!     oracle,<price>,<confidence>
+//!     trade,<long|short>,<base_asset_amount>[,<limit_price>]
+//!     match,<long|short>,<base_asset_amount>,<maker_price>,<maker_base_asset_amount>
+//!     lp_rebase,<expo_diff>
+//! prices are PRICE_PRECISION and base amounts BASE_PRECISION. Blank lines and lines starting
+//! with `#` are skipped.
+//!
+//! A `match` is a taker crossing a maker outside the amm. Only the amm's jit share of it is
+//! filled against the market, the rest is assumed to be filled by the maker.
+
+use solana_program::msg;
+use solana_program::pubkey::Pubkey;
+
+use crate::controller::lp::apply_lp_rebase_to_perp_market;
+use crate::controller::orders::fulfill_perp_order_with_amm;
+use crate::controller::position::PositionDirection;
+use crate::controller::repeg::{_update_amm, update_amm_and_check_validity};
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::amm;
+use crate::math::amm_jit::calculate_amm_jit_liquidity;
+use crate::math::casting::Cast;
+use crate::math::constants::{
+    AMM_RESERVE_PRECISION, DEFAULT_BASE_ASSET_AMOUNT_STEP_SIZE,
+    DEFAULT_QUOTE_ASSET_AMOUNT_TICK_SIZE, MAX_CONCENTRATION_COEFFICIENT, ONE_HOUR,
+};
+use crate::math::funding::calculate_funding_rate_long_short;
+use crate::math::oracle::DriftAction;
+use crate::math::safe_math::SafeMath;
+use crate::state::oracle::{HistoricalOracleData, OraclePriceData};
+use crate::state::oracle_map::OracleMap;
+use crate::state::perp_market::{AMMLiquiditySplit, ContractTier, MarketStatus, PerpMarket, AMM};
+use crate::state::state::State;
+use crate::state::user::{
+    MarketType, Order, OrderStatus, OrderType, PerpPosition, User, UserStats,
+};
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BacktestEvent {
+    Oracle {
+        price: i64,
+        confidence: u64,
+    },
+    Trade {
+        direction: PositionDirection,
+        base_asset_amount: u64,
+        limit_price: Option<u64>,
+    },
+    Match {
+        direction: PositionDirection,
+        base_asset_amount: u64,
+        maker_price: u64,
+        maker_base_asset_amount: u64,
+    },
+    LPRebase {
+        expo_diff: i8,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BacktestRecord {
+    pub ts: i64,
+    pub slot: u64,
+    pub event: BacktestEvent,
+}
+
+pub fn parse_backtest_records(csv: &str) -> DriftResult<Vec<BacktestRecord>> {
+    let mut records = vec![];
+    for (index, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let record = parse_backtest_record(line).map_err(|e| {
+            msg!("invalid backtest record on line {}: {}", index + 1, line);
+            e
+        })?;
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+fn parse_backtest_record(line: &str) -> DriftResult<BacktestRecord> {
+    let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+
+    if fields.len() < 3 {
+        return Err(ErrorCode::DefaultError);
+    }
+
+    let ts = parse_field::<i64>(&fields, 0)?;
+    let slot = parse_field::<u64>(&fields, 1)?;
+
+    let event = match (fields[2], fields.len()) {
+        ("oracle", 5) => BacktestEvent::Oracle {
+            price: parse_field(&fields, 3)?,
+            confidence: parse_field(&fields, 4)?,
+        },
+        ("trade", 5) | ("trade", 6) => BacktestEvent::Trade {
+            direction: parse_direction(fields[3])?,
+            base_asset_amount: parse_field(&fields, 4)?,
+            limit_price: if fields.len() == 6 {
+                Some(parse_field(&fields, 5)?)
+            } else {
+                None
+            },
+        },
+        ("match", 7) => BacktestEvent::Match {
+            direction: parse_direction(fields[3])?,
+            base_asset_amount: parse_field(&fields, 4)?,
+            maker_price: parse_field(&fields, 5)?,
+            maker_base_asset_amount: parse_field(&fields, 6)?,
+        },
+        ("lp_rebase", 4) => BacktestEvent::LPRebase {
+            expo_diff: parse_field(&fields, 3)?,
+        },
+        _ => return Err(ErrorCode::DefaultError),
+    };
+
+    Ok(BacktestRecord { ts, slot, event })
+}
+
+fn parse_field<T: std::str::FromStr>(fields: &[&str], index: usize) -> DriftResult<T> {
+    fields
+        .get(index)
+        .ok_or(ErrorCode::DefaultError)?
+        .parse::<T>()
+        .map_err(|_| ErrorCode::DefaultError)
+}
+
+fn parse_direction(field: &str) -> DriftResult<PositionDirection> {
+    match field {
+        "long" => Ok(PositionDirection::Long),
+        "short" => Ok(PositionDirection::Short),
+        _ => Err(ErrorCode::DefaultError),
+    }
+}
+
+/// The parameters to build a fresh market from. To backtest an existing market, load its
+/// account and pass it to `Backtest::new` instead
+#[derive(Clone, Copy, Debug)]
+pub struct BacktestConfig {
+    /// precision: PRICE_PRECISION
+    pub oracle_price: i64,
+    /// the base and quote reserves the market starts balanced at, also its sqrt_k
+    /// precision: AMM_RESERVE_PRECISION
+    pub amm_reserve: u128,
+    /// the part of sqrt_k owned by lps
+    /// precision: AMM_RESERVE_PRECISION
+    pub user_lp_shares: u128,
+    pub funding_period: i64,
+    pub base_spread: u32,
+    pub max_spread: u32,
+    pub curve_update_intensity: u8,
+    pub amm_jit_intensity: u8,
+    pub margin_ratio_initial: u32,
+    pub margin_ratio_maintenance: u32,
+    pub order_step_size: u64,
+    pub order_tick_size: u64,
+    pub min_order_size: u64,
+    pub start_ts: i64,
+    pub start_slot: u64,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        BacktestConfig {
+            oracle_price: 0,
+            amm_reserve: 1_000_000 * AMM_RESERVE_PRECISION,
+            user_lp_shares: 0,
+            funding_period: ONE_HOUR,
+            base_spread: 1000,
+            max_spread: 47500,
+            curve_update_intensity: 100,
+            amm_jit_intensity: 0,
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            order_step_size: DEFAULT_BASE_ASSET_AMOUNT_STEP_SIZE,
+            order_tick_size: DEFAULT_QUOTE_ASSET_AMOUNT_TICK_SIZE,
+            min_order_size: DEFAULT_BASE_ASSET_AMOUNT_STEP_SIZE,
+            start_ts: 0,
+            start_slot: 0,
+        }
+    }
+}
+
+impl BacktestConfig {
+    /// mirrors the market `initialize_perp_market` creates, pegged to the oracle price
+    pub fn perp_market(&self) -> DriftResult<PerpMarket> {
+        let peg_multiplier = self.oracle_price.cast::<u128>()?;
+        let (min_base_asset_reserve, max_base_asset_reserve) =
+            amm::calculate_bid_ask_bounds(MAX_CONCENTRATION_COEFFICIENT, self.amm_reserve)?;
+
+        Ok(PerpMarket {
+            status: MarketStatus::Active,
+            contract_tier: ContractTier::Speculative,
+            margin_ratio_initial: self.margin_ratio_initial,
+            margin_ratio_maintenance: self.margin_ratio_maintenance,
+            next_fill_record_id: 1,
+            next_funding_rate_record_id: 1,
+            next_curve_record_id: 1,
+            amm: AMM {
+                base_asset_reserve: self.amm_reserve,
+                quote_asset_reserve: self.amm_reserve,
+                terminal_quote_asset_reserve: self.amm_reserve,
+                ask_base_asset_reserve: self.amm_reserve,
+                ask_quote_asset_reserve: self.amm_reserve,
+                bid_base_asset_reserve: self.amm_reserve,
+                bid_quote_asset_reserve: self.amm_reserve,
+                sqrt_k: self.amm_reserve,
+                user_lp_shares: self.user_lp_shares,
+                concentration_coef: MAX_CONCENTRATION_COEFFICIENT,
+                min_base_asset_reserve,
+                max_base_asset_reserve,
+                peg_multiplier,
+                funding_period: self.funding_period,
+                last_funding_rate_ts: self.start_ts,
+                last_mark_price_twap: peg_multiplier.cast()?,
+                last_mark_price_twap_5min: peg_multiplier.cast()?,
+                last_mark_price_twap_ts: self.start_ts,
+                last_bid_price_twap: peg_multiplier.cast()?,
+                last_ask_price_twap: peg_multiplier.cast()?,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price: self.oracle_price,
+                    last_oracle_price_twap: self.oracle_price,
+                    last_oracle_price_twap_5min: self.oracle_price,
+                    last_oracle_price_twap_ts: self.start_ts,
+                    ..HistoricalOracleData::default()
+                },
+                last_oracle_normalised_price: self.oracle_price,
+                order_step_size: self.order_step_size,
+                order_tick_size: self.order_tick_size,
+                min_order_size: self.min_order_size,
+                max_slippage_ratio: 50,
+                max_fill_reserve_fraction: 100,
+                base_spread: self.base_spread,
+                long_spread: self.base_spread / 2,
+                short_spread: self.base_spread / 2,
+                max_spread: self.max_spread,
+                curve_update_intensity: self.curve_update_intensity,
+                amm_jit_intensity: self.amm_jit_intensity,
+                last_trade_ts: self.start_ts,
+                last_update_slot: self.start_slot,
+                ..AMM::default()
+            },
+            ..PerpMarket::default()
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BacktestSnapshot {
+    pub ts: i64,
+    pub oracle_price: i64,
+    pub reserve_price: u64,
+    pub bid_price: u64,
+    pub ask_price: u64,
+    pub long_spread: u32,
+    pub short_spread: u32,
+    pub base_asset_amount_with_amm: i128,
+    /// the amm's pnl against users, marked to the oracle price
+    /// precision: QUOTE_PRECISION
+    pub amm_pnl: i128,
+    /// precision: QUOTE_PRECISION
+    pub total_fee_minus_distributions: i128,
+    /// running sum of funding paid to (positive) or by the amm
+    /// precision: QUOTE_PRECISION
+    pub funding_pnl: i128,
+    /// running sum of the fees the k updates at each funding spent (positive) or recovered
+    /// precision: QUOTE_PRECISION
+    pub curve_update_cost: i128,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BacktestReport {
+    /// taken after every oracle update
+    pub snapshots: Vec<BacktestSnapshot>,
+    pub trades_filled: u64,
+    /// trades the amm refused or couldn't fill, e.g. from an invalid oracle or a limit price it
+    /// doesn't cross
+    pub trades_rejected: u64,
+    /// precision: BASE_PRECISION
+    pub base_asset_amount_filled_by_amm: u64,
+    /// precision: BASE_PRECISION
+    pub base_asset_amount_filled_by_jit: u64,
+    /// precision: QUOTE_PRECISION
+    pub quote_asset_amount_filled_by_amm: u64,
+    pub funding_updates: u64,
+    /// precision: QUOTE_PRECISION
+    pub funding_pnl: i128,
+    /// precision: QUOTE_PRECISION
+    pub curve_update_cost: i128,
+    /// precision: QUOTE_PRECISION
+    pub total_fee: i128,
+    /// precision: QUOTE_PRECISION
+    pub total_fee_minus_distributions: i128,
+    /// precision: QUOTE_PRECISION
+    pub amm_pnl: i128,
+    /// long_spread + short_spread across snapshots
+    /// precision: BID_ASK_SPREAD_PRECISION
+    pub min_spread: u64,
+    pub max_spread: u64,
+    pub avg_spread: u64,
+}
+
+pub struct Backtest {
+    pub market: PerpMarket,
+    pub state: State,
+    pub oracle_price_data: OraclePriceData,
+    taker: User,
+    taker_stats: UserStats,
+    report: BacktestReport,
+}
+
+impl Backtest {
+    /// The market is read from a price fed by the records rather than its oracle account
+    pub fn new(mut market: PerpMarket, state: State) -> Backtest {
+        // the default key makes the oracle map return the price we hand it
+        market.amm.oracle = Pubkey::default();
+
+        let oracle_price_data = OraclePriceData {
+            price: market.amm.historical_oracle_data.last_oracle_price,
+            confidence: 0,
+            delay: 0,
+            has_sufficient_number_of_data_points: true,
+        };
+
+        let mut taker = User::default();
+        taker.perp_positions[0] = PerpPosition {
+            market_index: market.market_index,
+            ..PerpPosition::default()
+        };
+
+        Backtest {
+            market,
+            state,
+            oracle_price_data,
+            taker,
+            taker_stats: UserStats::default(),
+            report: BacktestReport::default(),
+        }
+    }
+
+    pub fn run(&mut self, records: &[BacktestRecord]) -> DriftResult {
+        for record in records.iter() {
+            self.process(record)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn process(&mut self, record: &BacktestRecord) -> DriftResult {
+        let BacktestRecord { ts, slot, event } = *record;
+
+        match event {
+            BacktestEvent::Oracle { price, confidence } => {
+                self.update_oracle(ts, slot, price, confidence)
+            }
+            BacktestEvent::Trade {
+                direction,
+                base_asset_amount,
+                limit_price,
+            } => {
+                self.fill(ts, slot, direction, base_asset_amount, limit_price, None);
+                Ok(())
+            }
+            BacktestEvent::Match {
+                direction,
+                base_asset_amount,
+                maker_price,
+                maker_base_asset_amount,
+            } => {
+                self.fill(
+                    ts,
+                    slot,
+                    direction,
+                    base_asset_amount,
+                    Some(maker_price),
+                    Some(maker_base_asset_amount),
+                );
+                Ok(())
+            }
+            BacktestEvent::LPRebase { expo_diff } => {
+                apply_lp_rebase_to_perp_market(&mut self.market, expo_diff)
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> DriftResult<BacktestReport> {
+        let oracle_price = self.oracle_price_data.price;
+        let report = &mut self.report;
+
+        report.total_fee = self.market.amm.total_fee;
+        report.total_fee_minus_distributions = self.market.amm.total_fee_minus_distributions;
+        report.amm_pnl = -amm::calculate_net_user_pnl(&self.market.amm, oracle_price)?;
+
+        if !report.snapshots.is_empty() {
+            let spreads = report
+                .snapshots
+                .iter()
+                .map(|snapshot| {
+                    snapshot
+                        .long_spread
+                        .cast::<u64>()?
+                        .safe_add(snapshot.short_spread.cast()?)
+                })
+                .collect::<DriftResult<Vec<u64>>>()?;
+
+            report.min_spread = spreads.iter().copied().min().unwrap_or(0);
+            report.max_spread = spreads.iter().copied().max().unwrap_or(0);
+            report.avg_spread = spreads
+                .iter()
+                .try_fold(0_u64, |sum, spread| sum.safe_add(*spread))?
+                .safe_div(spreads.len().cast()?)?;
+        }
+
+        Ok(self.report)
+    }
+
+    fn get_oracle_map(&self, slot: u64) -> OracleMap<'static> {
+        let mut oracle_map = OracleMap::empty();
+        oracle_map.slot = slot;
+        oracle_map.oracle_guard_rails = self.state.oracle_guard_rails;
+        oracle_map.quote_asset_price_data = self.oracle_price_data;
+        oracle_map
+    }
+
+    fn update_oracle(&mut self, now: i64, slot: u64, price: i64, confidence: u64) -> DriftResult {
+        self.oracle_price_data = OraclePriceData {
+            price,
+            confidence,
+            delay: 0,
+            has_sufficient_number_of_data_points: true,
+        };
+
+        _update_amm(
+            &mut self.market,
+            &self.oracle_price_data,
+            &self.state,
+            now,
+            slot,
+        )?;
+
+        let market_before = self.market;
+        let mut oracle_map = self.get_oracle_map(slot);
+        let funding_updated = crate::controller::funding::update_funding_rate(
+            self.market.market_index,
+            &mut self.market,
+            &mut oracle_map,
+            now,
+            slot,
+            &self.state.oracle_guard_rails,
+            self.state.funding_paused()?,
+            None,
+        )?;
+
+        if funding_updated {
+            self.report.funding_updates = self.report.funding_updates.safe_add(1)?;
+
+            // replay the rate on the market funding saw to get the (capped) funding the amm booked
+            let mut funding_market = market_before;
+            let (_, _, funding_pnl) = calculate_funding_rate_long_short(
+                &mut funding_market,
+                self.market.amm.last_funding_rate.cast()?,
+            )?;
+
+            // whatever else left the fee pool paid for the k update
+            let curve_update_cost = market_before
+                .amm
+                .total_fee_minus_distributions
+                .safe_add(funding_pnl)?
+                .safe_sub(self.market.amm.total_fee_minus_distributions)?;
+
+            self.report.funding_pnl = self.report.funding_pnl.safe_add(funding_pnl)?;
+            self.report.curve_update_cost =
+                self.report.curve_update_cost.safe_add(curve_update_cost)?;
+        }
+
+        self.take_snapshot(now)
+    }
+
+    fn take_snapshot(&mut self, now: i64) -> DriftResult {
+        let reserve_price = self.market.amm.reserve_price()?;
+        let (bid_price, ask_price) = self.market.amm.bid_ask_price(reserve_price)?;
+
+        self.report.snapshots.push(BacktestSnapshot {
+            ts: now,
+            oracle_price: self.oracle_price_data.price,
+            reserve_price,
+            bid_price,
+            ask_price,
+            long_spread: self.market.amm.long_spread,
+            short_spread: self.market.amm.short_spread,
+            base_asset_amount_with_amm: self.market.amm.base_asset_amount_with_amm,
+            amm_pnl: -amm::calculate_net_user_pnl(&self.market.amm, self.oracle_price_data.price)?,
+            total_fee_minus_distributions: self.market.amm.total_fee_minus_distributions,
+            funding_pnl: self.report.funding_pnl,
+            curve_update_cost: self.report.curve_update_cost,
+        });
+
+        Ok(())
+    }
+
+    fn fill(
+        &mut self,
+        now: i64,
+        slot: u64,
+        direction: PositionDirection,
+        base_asset_amount: u64,
+        limit_price: Option<u64>,
+        maker_base_asset_amount: Option<u64>,
+    ) {
+        // a refused fill shouldn't leave the market half updated
+        let market_before = self.market;
+        let taker_before = self.taker;
+        let taker_stats_before = self.taker_stats;
+
+        match self.try_fill(
+            now,
+            slot,
+            direction,
+            base_asset_amount,
+            limit_price,
+            maker_base_asset_amount,
+        ) {
+            Ok(true) => {
+                self.report.trades_filled = self.report.trades_filled.saturating_add(1);
+            }
+            Ok(false) => {
+                self.report.trades_rejected = self.report.trades_rejected.saturating_add(1);
+            }
+            Err(_) => {
+                self.market = market_before;
+                self.taker = taker_before;
+                self.taker_stats = taker_stats_before;
+                self.report.trades_rejected = self.report.trades_rejected.saturating_add(1);
+            }
+        }
+
+        self.reset_taker_order();
+    }
+
+    fn try_fill(
+        &mut self,
+        now: i64,
+        slot: u64,
+        direction: PositionDirection,
+        base_asset_amount: u64,
+        limit_price: Option<u64>,
+        maker_base_asset_amount: Option<u64>,
+    ) -> DriftResult<bool> {
+        update_amm_and_check_validity(
+            &mut self.market,
+            &self.oracle_price_data,
+            &self.state,
+            now,
+            slot,
+            Some(DriftAction::FillOrderAmm),
+        )?;
+
+        let reserve_price_before = self.market.amm.reserve_price()?;
+
+        self.taker.orders[0] = Order {
+            status: OrderStatus::Open,
+            order_type: if limit_price.is_some() {
+                OrderType::Limit
+            } else {
+                OrderType::Market
+            },
+            market_type: MarketType::Perp,
+            market_index: self.market.market_index,
+            direction,
+            base_asset_amount,
+            price: limit_price.unwrap_or(0),
+            slot,
+            ..Order::default()
+        };
+        self.taker.increment_open_orders(false);
+
+        let position = &mut self.taker.perp_positions[0];
+        position.open_orders = 1;
+        match direction {
+            PositionDirection::Long => position.open_bids = base_asset_amount.cast()?,
+            PositionDirection::Short => position.open_asks = -base_asset_amount.cast::<i64>()?,
+        }
+
+        let (base_asset_amount_filled, quote_asset_amount_filled, is_jit) =
+            match maker_base_asset_amount {
+                Some(maker_base_asset_amount) => {
+                    let maker_price = limit_price.ok_or(ErrorCode::DefaultError)?;
+                    let (jit_base_asset_amount, liquidity_split) = calculate_amm_jit_liquidity(
+                        &mut self.market,
+                        direction,
+                        maker_price,
+                        Some(self.oracle_price_data.price),
+                        base_asset_amount.min(maker_base_asset_amount),
+                        base_asset_amount,
+                        maker_base_asset_amount,
+                        true,
+                    )?;
+
+                    // the maker takes all of it
+                    if jit_base_asset_amount == 0 {
+                        return Ok(true);
+                    }
+
+                    let (base_asset_amount_filled, quote_asset_amount_filled) = self
+                        .fulfill_with_amm(
+                            now,
+                            slot,
+                            reserve_price_before,
+                            limit_price,
+                            Some(jit_base_asset_amount),
+                            Some(maker_price),
+                            liquidity_split,
+                        )?;
+
+                    (base_asset_amount_filled, quote_asset_amount_filled, true)
+                }
+                None => {
+                    let (base_asset_amount_filled, quote_asset_amount_filled) = self
+                        .fulfill_with_amm(
+                            now,
+                            slot,
+                            reserve_price_before,
+                            limit_price,
+                            None,
+                            None,
+                            AMMLiquiditySplit::Shared,
+                        )?;
+
+                    if base_asset_amount_filled == 0 {
+                        return Ok(false);
+                    }
+
+                    (base_asset_amount_filled, quote_asset_amount_filled, false)
+                }
+            };
+
+        self.report.base_asset_amount_filled_by_amm = self
+            .report
+            .base_asset_amount_filled_by_amm
+            .safe_add(base_asset_amount_filled)?;
+
+        if is_jit {
+            self.report.base_asset_amount_filled_by_jit = self
+                .report
+                .base_asset_amount_filled_by_jit
+                .safe_add(base_asset_amount_filled)?;
+        }
+
+        self.report.quote_asset_amount_filled_by_amm = self
+            .report
+            .quote_asset_amount_filled_by_amm
+            .safe_add(quote_asset_amount_filled)?;
+
+        Ok(true)
+    }
+
+    fn fulfill_with_amm(
+        &mut self,
+        now: i64,
+        slot: u64,
+        reserve_price_before: u64,
+        limit_price: Option<u64>,
+        override_base_asset_amount: Option<u64>,
+        override_fill_price: Option<u64>,
+        liquidity_split: AMMLiquiditySplit,
+    ) -> DriftResult<(u64, u64)> {
+        let mut oracle_map = self.get_oracle_map(slot);
+        let taker_key = Pubkey::default();
+
+        fulfill_perp_order_with_amm(
+            &mut self.taker,
+            &mut self.taker_stats,
+            0,
+            &mut self.market,
+            &mut oracle_map,
+            reserve_price_before,
+            now,
+            slot,
+            &taker_key,
+            &taker_key,
+            &mut None,
+            &mut None,
+            &mut None,
+            &mut None,
+            &self.state.perp_fee_structure,
+            limit_price,
+            override_base_asset_amount,
+            override_fill_price,
+            liquidity_split,
+        )
+    }
+
+    /// the taker only ever has the one order being replayed
+    fn reset_taker_order(&mut self) {
+        self.taker.orders[0] = Order::default();
+        self.taker.open_orders = 0;
+        self.taker.has_open_order = false;
+
+        let position = &mut self.taker.perp_positions[0];
+        position.open_orders = 0;
+        position.open_bids = 0;
+        position.open_asks = 0;
+    }
+}
@@ -0,0 +1,153 @@
+use crate::backtest::*;
+use crate::math::constants::{
+    AMM_RESERVE_PRECISION, BASE_PRECISION_U64, PRICE_PRECISION_I64, PRICE_PRECISION_U64,
+};
+use crate::math::funding::calculate_funding_payment_in_quote_precision;
+
+#[test]
+fn parse_records() {
+    let csv = "
+# ts,slot,kind,...
+0,0,oracle,100000000,10000
+1,2,trade,long,1000000000
+2,4,trade,short,1000000000,99000000
+
+3,6,match,long,2000000000,100100000,1000000000
+4,8,lp_rebase,-1
+";
+
+    let records = parse_backtest_records(csv).unwrap();
+
+    assert_eq!(
+        records,
+        vec![
+            BacktestRecord {
+                ts: 0,
+                slot: 0,
+                event: BacktestEvent::Oracle {
+                    price: 100 * PRICE_PRECISION_I64,
+                    confidence: 10000,
+                },
+            },
+            BacktestRecord {
+                ts: 1,
+                slot: 2,
+                event: BacktestEvent::Trade {
+                    direction: PositionDirection::Long,
+                    base_asset_amount: BASE_PRECISION_U64,
+                    limit_price: None,
+                },
+            },
+            BacktestRecord {
+                ts: 2,
+                slot: 4,
+                event: BacktestEvent::Trade {
+                    direction: PositionDirection::Short,
+                    base_asset_amount: BASE_PRECISION_U64,
+                    limit_price: Some(99 * PRICE_PRECISION_U64),
+                },
+            },
+            BacktestRecord {
+                ts: 3,
+                slot: 6,
+                event: BacktestEvent::Match {
+                    direction: PositionDirection::Long,
+                    base_asset_amount: 2 * BASE_PRECISION_U64,
+                    maker_price: 100_100_000,
+                    maker_base_asset_amount: BASE_PRECISION_U64,
+                },
+            },
+            BacktestRecord {
+                ts: 4,
+                slot: 8,
+                event: BacktestEvent::LPRebase { expo_diff: -1 },
+            },
+        ]
+    );
+
+    assert!(parse_backtest_records("0,0,oracle,100000000").is_err());
+    assert!(parse_backtest_records("0,0,trade,up,1000000000").is_err());
+    assert!(parse_backtest_records("0,0,swap,long,1000000000").is_err());
+    assert!(parse_backtest_records("a,0,oracle,100000000,10000").is_err());
+}
+
+#[test]
+fn replay_oracle_and_trades() {
+    let config = BacktestConfig {
+        oracle_price: 100 * PRICE_PRECISION_I64,
+        amm_reserve: 100_000 * AMM_RESERVE_PRECISION,
+        ..BacktestConfig::default()
+    };
+    let market = config.perp_market().unwrap();
+    assert_eq!(
+        market.amm.reserve_price().unwrap(),
+        100 * PRICE_PRECISION_U64
+    );
+
+    let mut backtest = Backtest::new(market, State::default());
+
+    let records = parse_backtest_records(
+        "
+0,0,oracle,100000000,10000
+10,20,trade,long,10000000000
+20,40,oracle,100100000,10000
+30,60,trade,short,5000000000
+# a long limit well under the ask can't fill
+40,80,trade,long,1000000000,90000000
+3600,7200,oracle,100000000,10000
+",
+    )
+    .unwrap();
+
+    backtest.run(&records).unwrap();
+
+    assert_eq!(
+        backtest.market.amm.base_asset_amount_with_amm,
+        5_000_000_000
+    );
+    let funding_rate = backtest.market.amm.last_funding_rate as i128;
+
+    let report = backtest.finish().unwrap();
+
+    assert_eq!(report.snapshots.len(), 3);
+    assert_eq!(report.trades_filled, 2);
+    assert_eq!(report.trades_rejected, 1);
+    assert_eq!(
+        report.base_asset_amount_filled_by_amm,
+        15 * BASE_PRECISION_U64
+    );
+    assert_eq!(report.base_asset_amount_filled_by_jit, 0);
+    assert!(report.quote_asset_amount_filled_by_amm > 0);
+    assert_eq!(report.funding_updates, 1);
+
+    // fees accrue to the amm on every fill
+    assert!(report.total_fee > 0);
+    assert_eq!(
+        report.snapshots[2].total_fee_minus_distributions,
+        report.total_fee_minus_distributions
+    );
+    assert_eq!(report.snapshots[2].funding_pnl, report.funding_pnl);
+    assert_eq!(
+        report.snapshots[2].curve_update_cost,
+        report.curve_update_cost
+    );
+
+    // funding is the rate on the users' 5 long, only capped when the amm pays it
+    let uncapped_funding_pnl =
+        -calculate_funding_payment_in_quote_precision(funding_rate, 5_000_000_000).unwrap();
+    if uncapped_funding_pnl >= 0 {
+        assert_eq!(report.funding_pnl, uncapped_funding_pnl);
+    } else {
+        assert!(report.funding_pnl >= uncapped_funding_pnl);
+    }
+
+    assert_eq!(report.snapshots[0].base_asset_amount_with_amm, 0);
+    assert_eq!(
+        report.snapshots[1].base_asset_amount_with_amm,
+        10_000_000_000
+    );
+
+    assert!(report.min_spread > 0);
+    assert!(report.min_spread <= report.avg_spread);
+    assert!(report.avg_spread <= report.max_spread);
+}
@@ -0,0 +1,145 @@
+//! Replays a csv of oracle prices and trades against a fresh perp market and prints the
+//! snapshots and summary. See `drift::backtest` for the record format.
+//!
+//! cargo run --bin amm_backtest --features backtest -- <records.csv> [--base_spread=1000 ...]
+
+use std::process::exit;
+
+use drift::backtest::{parse_backtest_records, Backtest, BacktestConfig, BacktestEvent};
+use drift::state::state::State;
+use solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+
+/// the controller functions log like they would on chain, which drowns out the report
+struct QuietSyscallStubs;
+
+impl SyscallStubs for QuietSyscallStubs {
+    fn sol_log(&self, _message: &str) {}
+
+    fn sol_log_data(&self, _fields: &[&[u8]]) {}
+}
+
+const USAGE: &str = "usage: amm_backtest <records.csv> [--verbose] [--amm_reserve=] \
+[--user_lp_shares=] [--funding_period=] [--base_spread=] [--max_spread=] \
+[--curve_update_intensity=] [--amm_jit_intensity=]";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    exit(1)
+}
+
+fn parse_arg<T: std::str::FromStr>(key: &str, value: &str) -> T {
+    value
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("invalid value for {}: {}", key, value)))
+}
+
+fn main() {
+    let mut path = None;
+    let mut verbose = false;
+    let mut config = BacktestConfig::default();
+
+    for arg in std::env::args().skip(1) {
+        if arg == "--verbose" {
+            verbose = true;
+            continue;
+        }
+
+        if let Some(arg) = arg.strip_prefix("--") {
+            let (key, value) = arg.split_once('=').unwrap_or_else(|| fail(USAGE));
+            match key {
+                "amm_reserve" => config.amm_reserve = parse_arg(key, value),
+                "user_lp_shares" => config.user_lp_shares = parse_arg(key, value),
+                "funding_period" => config.funding_period = parse_arg(key, value),
+                "base_spread" => config.base_spread = parse_arg(key, value),
+                "max_spread" => config.max_spread = parse_arg(key, value),
+                "curve_update_intensity" => config.curve_update_intensity = parse_arg(key, value),
+                "amm_jit_intensity" => config.amm_jit_intensity = parse_arg(key, value),
+                _ => fail(USAGE),
+            }
+            continue;
+        }
+
+        if path.replace(arg).is_some() {
+            fail(USAGE);
+        }
+    }
+
+    let path = path.unwrap_or_else(|| fail(USAGE));
+
+    // parse before quieting the logs so the bad line gets printed
+    let csv = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| fail(&format!("failed to read {}: {}", path, e)));
+    let records = parse_backtest_records(&csv)
+        .unwrap_or_else(|e| fail(&format!("failed to parse {}: {:?}", path, e)));
+
+    if !verbose {
+        set_syscall_stubs(Box::new(QuietSyscallStubs));
+    }
+
+    // the market starts pegged to the first oracle price
+    let first_oracle = records.iter().find_map(|record| match record.event {
+        BacktestEvent::Oracle { price, .. } => Some((record.ts, record.slot, price)),
+        _ => None,
+    });
+    let (start_ts, start_slot, oracle_price) =
+        first_oracle.unwrap_or_else(|| fail("records need at least one oracle price"));
+    config.start_ts = start_ts;
+    config.start_slot = start_slot;
+    config.oracle_price = oracle_price;
+
+    let market = config
+        .perp_market()
+        .unwrap_or_else(|e| fail(&format!("invalid config: {:?}", e)));
+
+    let mut backtest = Backtest::new(market, State::default());
+    if let Err(e) = backtest.run(&records) {
+        fail(&format!("backtest failed: {:?}", e));
+    }
+
+    let report = backtest
+        .finish()
+        .unwrap_or_else(|e| fail(&format!("backtest failed: {:?}", e)));
+
+    println!("ts,oracle_price,reserve_price,bid_price,ask_price,long_spread,short_spread,base_asset_amount_with_amm,amm_pnl,total_fee_minus_distributions,funding_pnl,curve_update_cost");
+    for snapshot in report.snapshots.iter() {
+        println!(
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            snapshot.ts,
+            snapshot.oracle_price,
+            snapshot.reserve_price,
+            snapshot.bid_price,
+            snapshot.ask_price,
+            snapshot.long_spread,
+            snapshot.short_spread,
+            snapshot.base_asset_amount_with_amm,
+            snapshot.amm_pnl,
+            snapshot.total_fee_minus_distributions,
+            snapshot.funding_pnl,
+            snapshot.curve_update_cost,
+        );
+    }
+
+    eprintln!("trades filled: {}", report.trades_filled);
+    eprintln!("trades rejected: {}", report.trades_rejected);
+    eprintln!(
+        "base filled by amm: {} (jit {})",
+        report.base_asset_amount_filled_by_amm, report.base_asset_amount_filled_by_jit
+    );
+    eprintln!(
+        "quote filled by amm: {}",
+        report.quote_asset_amount_filled_by_amm
+    );
+    eprintln!("funding updates: {}", report.funding_updates);
+    eprintln!("funding pnl: {}", report.funding_pnl);
+    eprintln!("curve update cost: {}", report.curve_update_cost);
+    eprintln!("total fee: {}", report.total_fee);
+    eprintln!(
+        "total fee minus distributions: {}",
+        report.total_fee_minus_distributions
+    );
+    eprintln!("amm pnl: {}", report.amm_pnl);
+    eprintln!(
+        "spread min/avg/max: {}/{}/{}",
+        report.min_spread, report.avg_spread, report.max_spread
+    );
+}
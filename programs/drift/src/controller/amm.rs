@@ -15,10 +15,11 @@ use crate::math::amm::calculate_quote_asset_amount_swapped;
 use crate::math::amm_spread::{calculate_spread_reserves, get_spread_reserves};
 use crate::math::casting::Cast;
 use crate::math::constants::{
-    CONCENTRATION_PRECISION, FEE_POOL_TO_REVENUE_POOL_THRESHOLD, K_BPS_UPDATE_SCALE,
-    MAX_CONCENTRATION_COEFFICIENT, MAX_K_BPS_INCREASE, MAX_SQRT_K,
+    BASE_PRECISION_U64, CONCENTRATION_PRECISION, FEE_POOL_TO_REVENUE_POOL_THRESHOLD,
+    K_BPS_UPDATE_SCALE, MAX_CONCENTRATION_COEFFICIENT, MAX_K_BPS_INCREASE, MAX_SQRT_K,
 };
 use crate::math::cp_curve::get_update_k_result;
+use crate::math::orders::{calculate_fill_price, standardize_base_asset_amount};
 use crate::math::repeg::get_total_fee_lower_bound;
 use crate::math::safe_math::SafeMath;
 use crate::math::spot_balance::get_token_amount;
@@ -166,6 +167,90 @@ pub fn update_spread_reserves(amm: &mut AMM) -> DriftResult {
     Ok(())
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AMML2Level {
+    /// The average price a fill of the level's size gets
+    /// precision: PRICE_PRECISION
+    pub price: u64,
+    /// precision: BASE_PRECISION
+    pub base_asset_amount: u64,
+}
+
+/// The most levels a side view_amm_l2 can return. Return data is capped at MAX_RETURN_DATA (1024)
+/// bytes and each side takes a 4 byte length plus 16 bytes a level
+pub const MAX_AMM_L2_LEVELS: u8 = 31;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AMML2 {
+    pub bids: Vec<AMML2Level>,
+    pub asks: Vec<AMML2Level>,
+}
+
+pub fn calculate_amm_l2(amm: &AMM, num_levels: u8) -> DriftResult<AMML2> {
+    Ok(AMML2 {
+        bids: calculate_amm_l2_levels(amm, PositionDirection::Long, num_levels)?,
+        asks: calculate_amm_l2_levels(amm, PositionDirection::Short, num_levels)?,
+    })
+}
+
+/// Splits the most the amm fills for one order into levels and prices each by swapping against a
+/// copy of the amm, the way consecutive amm fills would. Level sizes are capped by the amm's
+/// available liquidity, which accounts for max_fill_reserve_fraction and the concentration bounds,
+/// and standardized to the step size. The ladder stops once less than a step is left
+pub fn calculate_amm_l2_levels(
+    amm: &AMM,
+    side: PositionDirection,
+    num_levels: u8,
+) -> DriftResult<Vec<AMML2Level>> {
+    let mut levels = Vec::with_capacity(num_levels as usize);
+    if num_levels == 0 {
+        return Ok(levels);
+    }
+
+    // bids are filled by shorts, asks by longs
+    let taker_direction = side.opposite();
+    let swap_direction = match taker_direction {
+        PositionDirection::Long => SwapDirection::Remove,
+        PositionDirection::Short => SwapDirection::Add,
+    };
+
+    let mut amm = *amm;
+    let level_base_asset_amount = standardize_base_asset_amount(
+        amm::calculate_amm_available_liquidity(&amm, &taker_direction)?
+            .safe_div(num_levels.cast()?)?,
+        amm.order_step_size,
+    )?
+    .max(amm.order_step_size);
+
+    for _ in 0..num_levels {
+        let base_asset_amount = standardize_base_asset_amount(
+            level_base_asset_amount.min(amm::calculate_amm_available_liquidity(
+                &amm,
+                &taker_direction,
+            )?),
+            amm.order_step_size,
+        )?;
+
+        if base_asset_amount < amm.order_step_size {
+            break;
+        }
+
+        let (new_base_asset_reserve, new_quote_asset_reserve, quote_asset_amount, _) =
+            calculate_base_swap_output_with_spread(&amm, base_asset_amount, swap_direction)?;
+
+        amm.base_asset_reserve = new_base_asset_reserve;
+        amm.quote_asset_reserve = new_quote_asset_reserve;
+        update_spread_reserves(&mut amm)?;
+
+        levels.push(AMML2Level {
+            price: calculate_fill_price(quote_asset_amount, base_asset_amount, BASE_PRECISION_U64)?,
+            base_asset_amount,
+        });
+    }
+
+    Ok(levels)
+}
+
 pub fn update_spreads(amm: &mut AMM, reserve_price: u64) -> DriftResult<(u32, u32)> {
     let max_ref_offset = amm.get_max_reference_price_offset()?;
 
@@ -216,6 +301,8 @@ pub fn update_spreads(amm: &mut AMM, reserve_price: u64) -> DriftResult<(u32, u3
             amm.long_intensity_volume,
             amm.short_intensity_volume,
             amm.volume_24h,
+            amm.realized_volatility,
+            amm.realized_volatility_spread_multiplier,
         )?
     } else {
         let half_base_spread = amm.base_spread.safe_div(2)?;
@@ -311,6 +398,43 @@ pub fn formulaic_update_k(
         0
     };
 
+    if let Some(adjustment_cost) = update_k_with_budget(market, budget)? {
+        let peg_multiplier_after = market.amm.peg_multiplier;
+        let base_asset_reserve_after = market.amm.base_asset_reserve;
+        let quote_asset_reserve_after = market.amm.quote_asset_reserve;
+        let sqrt_k_after = market.amm.sqrt_k;
+
+        emit!(CurveRecord {
+            ts: now,
+            record_id: get_then_update_id!(market, next_curve_record_id),
+            market_index: market.market_index,
+            peg_multiplier_before,
+            base_asset_reserve_before,
+            quote_asset_reserve_before,
+            sqrt_k_before,
+            peg_multiplier_after,
+            base_asset_reserve_after,
+            quote_asset_reserve_after,
+            sqrt_k_after,
+            base_asset_amount_long: market.amm.base_asset_amount_long.unsigned_abs(),
+            base_asset_amount_short: market.amm.base_asset_amount_short.unsigned_abs(),
+            base_asset_amount_with_amm: market.amm.base_asset_amount_with_amm,
+            number_of_users: market.number_of_users,
+            adjustment_cost,
+            total_fee: market.amm.total_fee,
+            total_fee_minus_distributions: market.amm.total_fee_minus_distributions,
+            oracle_price: market.amm.historical_oracle_data.last_oracle_price,
+            fill_record: market.next_fill_record_id as u128,
+            curve_update_budget_spent: 0,
+            curve_update_budget_remaining: 0,
+        });
+    }
+    Ok(())
+}
+
+/// scales k by up to the budget (negative budget lowers k), bounded by the curve update intensity
+/// returns the adjustment cost if it was applied to the market
+pub fn update_k_with_budget(market: &mut PerpMarket, budget: i64) -> DriftResult<Option<i128>> {
     if (budget > 0 && market.amm.sqrt_k < MAX_SQRT_K) || (budget < 0 && market.amm.can_lower_k()?) {
         // single k scale is capped by .1% increase and .1% decrease (regardless of budget)
         let k_pct_upper_bound = K_BPS_UPDATE_SCALE
@@ -338,37 +462,11 @@ pub fn formulaic_update_k(
 
         if cost_applied {
             cp_curve::update_k(market, &update_k_result)?;
-
-            let peg_multiplier_after = market.amm.peg_multiplier;
-            let base_asset_reserve_after = market.amm.base_asset_reserve;
-            let quote_asset_reserve_after = market.amm.quote_asset_reserve;
-            let sqrt_k_after = market.amm.sqrt_k;
-
-            emit!(CurveRecord {
-                ts: now,
-                record_id: get_then_update_id!(market, next_curve_record_id),
-                market_index: market.market_index,
-                peg_multiplier_before,
-                base_asset_reserve_before,
-                quote_asset_reserve_before,
-                sqrt_k_before,
-                peg_multiplier_after,
-                base_asset_reserve_after,
-                quote_asset_reserve_after,
-                sqrt_k_after,
-                base_asset_amount_long: market.amm.base_asset_amount_long.unsigned_abs(),
-                base_asset_amount_short: market.amm.base_asset_amount_short.unsigned_abs(),
-                base_asset_amount_with_amm: market.amm.base_asset_amount_with_amm,
-                number_of_users: market.number_of_users,
-                adjustment_cost,
-                total_fee: market.amm.total_fee,
-                total_fee_minus_distributions: market.amm.total_fee_minus_distributions,
-                oracle_price: market.amm.historical_oracle_data.last_oracle_price,
-                fill_record: market.next_fill_record_id as u128,
-            });
+            return Ok(Some(adjustment_cost));
         }
     }
-    Ok(())
+
+    Ok(None)
 }
 
 pub fn get_fee_pool_tokens(
@@ -1,10 +1,13 @@
 use crate::controller::amm::*;
 use crate::controller::insurance::settle_revenue_to_insurance_fund;
 use crate::math::constants::{
-    AMM_RESERVE_PRECISION, MAX_CONCENTRATION_COEFFICIENT, PRICE_PRECISION_I64, QUOTE_PRECISION,
-    QUOTE_SPOT_MARKET_INDEX, SPOT_BALANCE_PRECISION, SPOT_CUMULATIVE_INTEREST_PRECISION,
+    AMM_RESERVE_PRECISION, BASE_PRECISION_U64, MAX_CONCENTRATION_COEFFICIENT, PEG_PRECISION,
+    PRICE_PRECISION_I64, QUOTE_PRECISION, QUOTE_SPOT_MARKET_INDEX, SPOT_BALANCE_PRECISION,
+    SPOT_CUMULATIVE_INTEREST_PRECISION,
 };
 use crate::state::perp_market::{InsuranceClaim, PoolBalance};
+use anchor_lang::AnchorSerialize;
+use solana_program::program::MAX_RETURN_DATA;
 
 #[test]
 fn concentration_coef_tests() {
@@ -1711,3 +1714,103 @@ mod revenue_pool_transfer_tests {
         assert_eq!(spot_market.revenue_pool.scaled_balance, 9870000000000);
     }
 }
+
+#[test]
+fn amm_l2_levels() {
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            sqrt_k: 100 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 100 * PEG_PRECISION,
+            max_fill_reserve_fraction: 10,
+            order_step_size: BASE_PRECISION_U64 / 1000,
+            order_tick_size: 1,
+            base_spread: 1000,
+            long_spread: 1000,
+            short_spread: 2000,
+            max_spread: 10000,
+            concentration_coef: MAX_CONCENTRATION_COEFFICIENT,
+            ..AMM::default()
+        },
+        ..PerpMarket::default()
+    };
+    let (min_base_asset_reserve, max_base_asset_reserve) =
+        crate::math::amm::calculate_bid_ask_bounds(
+            market.amm.concentration_coef,
+            market.amm.sqrt_k,
+        )
+        .unwrap();
+    market.amm.min_base_asset_reserve = min_base_asset_reserve;
+    market.amm.max_base_asset_reserve = max_base_asset_reserve;
+    update_spread_reserves(&mut market.amm).unwrap();
+
+    let l2 = calculate_amm_l2(&market.amm, 4).unwrap();
+    assert_eq!(l2.bids.len(), 4);
+    assert_eq!(l2.asks.len(), 4);
+
+    let reserve_price = market.amm.reserve_price().unwrap();
+    assert!(l2.bids[0].price <= market.amm.bid_price(reserve_price).unwrap());
+    assert!(l2.asks[0].price >= market.amm.ask_price(reserve_price).unwrap());
+
+    for i in 1..4 {
+        assert!(l2.bids[i].price < l2.bids[i - 1].price);
+        assert!(l2.asks[i].price > l2.asks[i - 1].price);
+    }
+
+    // the top ask is what a long filling its size against the amm pays
+    let (_, _, quote_asset_amount, _) = calculate_base_swap_output_with_spread(
+        &market.amm,
+        l2.asks[0].base_asset_amount,
+        SwapDirection::Remove,
+    )
+    .unwrap();
+    assert_eq!(
+        l2.asks[0].price,
+        quote_asset_amount * BASE_PRECISION_U64 / l2.asks[0].base_asset_amount
+    );
+
+    // one fill can take at most a tenth of the reserves
+    let max_fill = (market.amm.base_asset_reserve / 10) as u64;
+    assert_eq!(l2.asks[0].base_asset_amount, max_fill / 4);
+    assert!(
+        l2.asks
+            .iter()
+            .map(|level| level.base_asset_amount)
+            .sum::<u64>()
+            <= max_fill
+    );
+
+    assert!(calculate_amm_l2(&market.amm, 0).unwrap().bids.is_empty());
+
+    // the available liquidity shrinks as the ladder walks the curve, levels stay whole steps
+    let l2 = calculate_amm_l2(&market.amm, MAX_AMM_L2_LEVELS).unwrap();
+    for level in l2.bids.iter().chain(l2.asks.iter()) {
+        assert!(level.base_asset_amount >= market.amm.order_step_size);
+        assert_eq!(level.base_asset_amount % market.amm.order_step_size, 0);
+    }
+
+    // a thin amm stops the ladder rather than returning sub step levels
+    let mut thin_market = market;
+    thin_market.amm.order_step_size = market.amm.base_asset_reserve as u64 / 10 / 3;
+    let l2 = calculate_amm_l2(&thin_market.amm, MAX_AMM_L2_LEVELS).unwrap();
+    assert!(l2.asks.len() < MAX_AMM_L2_LEVELS as usize);
+    for level in l2.asks.iter() {
+        assert_eq!(level.base_asset_amount, thin_market.amm.order_step_size);
+    }
+}
+
+#[test]
+fn amm_l2_fits_in_return_data() {
+    let level = AMML2Level {
+        price: u64::MAX,
+        base_asset_amount: u64::MAX,
+    };
+    let l2 = |num_levels: u8| AMML2 {
+        bids: vec![level; num_levels as usize],
+        asks: vec![level; num_levels as usize],
+    };
+
+    assert!(l2(MAX_AMM_L2_LEVELS).try_to_vec().unwrap().len() <= MAX_RETURN_DATA);
+    assert!(l2(MAX_AMM_L2_LEVELS + 1).try_to_vec().unwrap().len() > MAX_RETURN_DATA);
+}
@@ -0,0 +1,696 @@
+use crate::controller::orders::{
+    get_auction_params, init_max_slippage_order, update_market_and_position_for_amm_fill,
+    update_order_after_fill, AmmFill,
+};
+use crate::controller::position::{
+    update_position_and_market, update_quote_asset_and_break_even_amount, PositionDirection,
+};
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::amm::update_mark_twap_from_estimates;
+use crate::math::amm_jit::calculate_amm_jit_liquidity;
+use crate::math::auction::is_batch_auction_order;
+use crate::math::casting::Cast;
+use crate::math::constants::{BASE_PRECISION_U64, PERP_DECIMALS};
+use crate::math::fees::{calculate_fee_for_fulfillment_with_match, FillFees};
+use crate::math::fulfillment::{
+    calculate_taker_price_for_match, determine_perp_fulfillment_methods, get_pro_rata_allocations,
+    get_pro_rata_fulfillment_methods, update_amm_fee_pool_for_match_fill, validate_fill_or_kill,
+    validate_min_fill,
+};
+use crate::math::matching::{
+    calculate_fill_for_matched_orders, do_orders_cross, is_maker_for_taker,
+};
+use crate::math::orders::{
+    calculate_fill_price, get_position_delta_for_fill, get_price_for_perp_order,
+    limit_price_breaches_maker_oracle_price_bands, standardize_base_asset_amount,
+    standardize_price, validate_fill_price,
+};
+use crate::math::safe_math::SafeMath;
+use crate::state::fill_mode::FillMode;
+use crate::state::fulfillment::PerpFulfillmentMethod;
+use crate::state::oracle::OraclePriceData;
+use crate::state::order_params::{OrderParams, PostOnlyParam};
+use crate::state::paused_operations::PerpOperation;
+use crate::state::perp_market::{AMMLiquiditySplit, MatchingMode, PerpMarket, AMM};
+use crate::state::state::State;
+use crate::state::user::{
+    MarketType, Order, OrderBitFlag, OrderStatus, OrderType, PerpPosition, UserStats,
+};
+use crate::validate;
+use solana_program::pubkey::Pubkey;
+use std::collections::BTreeMap;
+
+#[cfg(test)]
+mod tests;
+
+/// A resting maker order the simulated taker order can fill against
+#[derive(Clone, Copy, Debug)]
+pub struct SimulatedMakerOrder {
+    pub maker: Pubkey,
+    pub order: Order,
+    pub maker_stats: UserStats,
+    /// The maker's position in the market
+    pub position: PerpPosition,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimulatedFillSource {
+    AMM,
+    /// Index into the maker orders passed to the simulation
+    Maker(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SimulatedFill {
+    pub source: SimulatedFillSource,
+    /// precision: BASE_PRECISION
+    pub base_asset_amount: u64,
+    /// precision: QUOTE_PRECISION
+    pub quote_asset_amount: u64,
+    /// precision: PRICE_PRECISION
+    pub fill_price: u64,
+    /// precision: QUOTE_PRECISION
+    pub taker_fee: u64,
+    /// precision: QUOTE_PRECISION
+    pub maker_rebate: u64,
+    /// precision: QUOTE_PRECISION
+    pub fee_to_market: i64,
+    /// Only set for amm fills
+    /// precision: QUOTE_PRECISION
+    pub quote_asset_amount_surplus: i64,
+}
+
+#[derive(Clone, Debug)]
+pub struct PerpFillSimulation {
+    pub fills: Vec<SimulatedFill>,
+    /// precision: BASE_PRECISION
+    pub base_asset_amount_filled: u64,
+    /// precision: QUOTE_PRECISION
+    pub quote_asset_amount_filled: u64,
+    /// The amm after the fills, with the updated reserves, mark twap, position and lp bookkeeping
+    /// and fee pool
+    pub amm: AMM,
+    /// The taker's position in the market after the fills
+    pub taker_position: PerpPosition,
+}
+
+/// Simulates placing a taker order and filling it right away against the amm and maker orders,
+/// the way place_and_take_perp_order would. The market is copied, so it should already have its
+/// amm updated for the slot (see controller::repeg::_update_amm) and the oracle price is taken
+/// as valid. Like place_and_take, fill or kill and min fill orders error if they don't fill
+/// enough. Amm fills go through the same update_market_and_position_for_amm_fill as real fills.
+/// Fillers, referrers and self trade prevention are left out
+pub fn simulate_perp_fill(
+    state: &State,
+    market: &PerpMarket,
+    oracle_price: i64,
+    params: &OrderParams,
+    taker_stats: &UserStats,
+    taker_position: &PerpPosition,
+    maker_orders: &[SimulatedMakerOrder],
+    now: i64,
+    slot: u64,
+) -> DriftResult<PerpFillSimulation> {
+    let mut market = *market;
+    let mut maker_orders = maker_orders.to_vec();
+    let mut taker_position = *taker_position;
+    let mut taker_order = get_simulated_taker_order(
+        params,
+        &market,
+        oracle_price,
+        taker_position.base_asset_amount,
+        state.min_perp_auction_duration,
+        now,
+        slot,
+    )?;
+    let taker_order_before_fill = taker_order;
+
+    let mut simulation = PerpFillSimulation {
+        fills: vec![],
+        base_asset_amount_filled: 0,
+        quote_asset_amount_filled: 0,
+        amm: market.amm,
+        taker_position,
+    };
+
+    // makers only match against a batch auction once it's over
+    if is_batch_auction_order(&taker_order, &market) {
+        validate_min_fill(0, params.min_fill_base_asset_amount)?;
+        return Ok(simulation);
+    }
+
+    let tick_size = market.amm.order_tick_size;
+    let reserve_price_before = market.amm.reserve_price()?;
+    let amm_is_available =
+        !state.amm_paused()? && !market.is_operation_paused(PerpOperation::AmmFill);
+
+    let maker_orders_info =
+        get_simulated_maker_orders_info(&market, &maker_orders, &taker_order, oracle_price, slot)?;
+
+    let limit_price = FillMode::PlaceAndTake.get_limit_price(
+        &taker_order,
+        Some(oracle_price),
+        slot,
+        tick_size,
+    )?;
+
+    let fulfillment_methods = determine_perp_fulfillment_methods(
+        &taker_order,
+        &maker_orders_info,
+        &market.amm,
+        reserve_price_before,
+        Some(oracle_price),
+        limit_price,
+        amm_is_available,
+        slot,
+        state.min_perp_auction_duration,
+    )?;
+
+    let fulfillment_methods = if market.matching_mode == MatchingMode::ProRata {
+        get_pro_rata_fulfillment_methods(fulfillment_methods, &maker_orders_info)?
+    } else {
+        fulfillment_methods
+    };
+
+    let mut pro_rata_allocations: BTreeMap<usize, u64> = BTreeMap::new();
+    for (fulfillment_method_index, fulfillment_method) in fulfillment_methods.iter().enumerate() {
+        if taker_order.status != OrderStatus::Open {
+            break;
+        }
+
+        let fills = match fulfillment_method {
+            PerpFulfillmentMethod::AMM(maker_price) => simulate_amm_fill(
+                &mut market,
+                &mut taker_order,
+                &mut taker_position,
+                taker_stats,
+                state,
+                reserve_price_before,
+                limit_price,
+                None,
+                *maker_price,
+                AMMLiquiditySplit::Shared,
+                now,
+                slot,
+            )?
+            .into_iter()
+            .collect::<Vec<SimulatedFill>>(),
+            PerpFulfillmentMethod::Match(_, maker_index)
+            | PerpFulfillmentMethod::ProRataMatch(_, maker_index, _) => {
+                let maker_index = *maker_index as usize;
+
+                if let PerpFulfillmentMethod::ProRataMatch(..) = fulfillment_method {
+                    if !pro_rata_allocations.contains_key(&maker_index) {
+                        let allocations = get_pro_rata_allocations(
+                            &taker_order,
+                            taker_position.base_asset_amount,
+                            &fulfillment_methods[fulfillment_method_index..],
+                            market.amm.order_step_size,
+                            |_, maker_index| {
+                                let maker_order = &maker_orders[maker_index as usize];
+                                Ok((maker_order.order, maker_order.position.base_asset_amount))
+                            },
+                        )?;
+
+                        pro_rata_allocations.extend(allocations.into_iter().map(
+                            |((_, maker_index), allocation)| (maker_index as usize, allocation),
+                        ));
+                    }
+                }
+
+                simulate_match_fill(
+                    &mut market,
+                    &mut taker_order,
+                    &mut taker_position,
+                    taker_stats,
+                    &mut maker_orders,
+                    maker_index,
+                    state,
+                    reserve_price_before,
+                    oracle_price,
+                    limit_price,
+                    pro_rata_allocations.get(&maker_index).copied(),
+                    now,
+                    slot,
+                )?
+            }
+        };
+
+        for fill in fills {
+            simulation.base_asset_amount_filled = simulation
+                .base_asset_amount_filled
+                .safe_add(fill.base_asset_amount)?;
+            simulation.quote_asset_amount_filled = simulation
+                .quote_asset_amount_filled
+                .safe_add(fill.quote_asset_amount)?;
+            simulation.fills.push(fill);
+        }
+    }
+
+    validate_fill_or_kill(
+        &taker_order_before_fill,
+        simulation.base_asset_amount_filled,
+    )?;
+    validate_min_fill(
+        simulation.base_asset_amount_filled,
+        params.min_fill_base_asset_amount,
+    )?;
+
+    simulation.amm = market.amm;
+    simulation.taker_position = taker_position;
+
+    Ok(simulation)
+}
+
+/// Builds the order place_perp_order would create from the params
+fn get_simulated_taker_order(
+    params: &OrderParams,
+    market: &PerpMarket,
+    oracle_price: i64,
+    taker_position_base_asset_amount: i64,
+    min_auction_duration: u8,
+    now: i64,
+    slot: u64,
+) -> DriftResult<Order> {
+    validate!(
+        params.market_type == MarketType::Perp,
+        ErrorCode::InvalidOrderMarketType,
+        "must be perp order"
+    )?;
+
+    validate!(
+        params.post_only == PostOnlyParam::None,
+        ErrorCode::InvalidOrderPostOnly,
+        "post only orders dont take liquidity"
+    )?;
+
+    validate!(
+        matches!(
+            params.order_type,
+            OrderType::Market | OrderType::Limit | OrderType::Oracle
+        ),
+        ErrorCode::InvalidOrder,
+        "only market, limit and oracle orders can be simulated"
+    )?;
+
+    validate!(
+        params.base_asset_amount >= market.amm.order_step_size,
+        ErrorCode::OrderAmountTooSmall,
+        "params.base_asset_amount={} cannot be below market.amm.order_step_size={}",
+        params.base_asset_amount,
+        market.amm.order_step_size
+    )?;
+
+    let mut params = *params;
+    params.update_perp_auction_params(market, oracle_price)?;
+
+    let oracle_price_data = OraclePriceData {
+        price: oracle_price,
+        confidence: 0,
+        delay: 0,
+        has_sufficient_number_of_data_points: true,
+    };
+
+    let (auction_start_price, auction_end_price, auction_duration) = get_auction_params(
+        &params,
+        &oracle_price_data,
+        market.amm.order_tick_size,
+        min_auction_duration,
+    )?;
+
+    let max_ts = match params.max_ts {
+        Some(max_ts) => max_ts,
+        None => match params.order_type {
+            OrderType::Market | OrderType::Oracle => {
+                now.safe_add(30_i64.max((auction_duration / 2) as i64))?
+            }
+            _ => 0_i64,
+        },
+    };
+
+    let mut order = Order {
+        status: OrderStatus::Open,
+        order_type: params.order_type,
+        market_type: params.market_type,
+        slot,
+        market_index: params.market_index,
+        price: get_price_for_perp_order(
+            params.price,
+            params.direction,
+            params.post_only,
+            params.max_slide_ticks,
+            &market.amm,
+        )?,
+        existing_position_direction: if taker_position_base_asset_amount >= 0 {
+            PositionDirection::Long
+        } else {
+            PositionDirection::Short
+        },
+        base_asset_amount: standardize_base_asset_amount(
+            params.base_asset_amount,
+            market.amm.order_step_size,
+        )?,
+        direction: params.direction,
+        reduce_only: params.reduce_only || market.is_reduce_only()?,
+        trigger_price: standardize_price(
+            params.trigger_price.unwrap_or(0),
+            market.amm.order_tick_size,
+            params.direction,
+        )?,
+        trigger_condition: params.trigger_condition,
+        oracle_price_offset: params.oracle_price_offset.unwrap_or(0),
+        immediate_or_cancel: params.immediate_or_cancel,
+        auction_start_price,
+        auction_end_price,
+        auction_duration,
+        max_ts,
+        bit_flags: params.bit_flags,
+        ..Order::default()
+    };
+
+    if params.max_slippage_bps > 0 || order.is_bit_flag_set(OrderBitFlag::MaxSlippage) {
+        init_max_slippage_order(&mut order, params.max_slippage_bps)?;
+    }
+
+    Ok(order)
+}
+
+/// The maker orders fill_perp_order would consider, best price first. Makers resting at the
+/// same price keep the order they were passed in
+fn get_simulated_maker_orders_info(
+    market: &PerpMarket,
+    maker_orders: &[SimulatedMakerOrder],
+    taker_order: &Order,
+    oracle_price: i64,
+    slot: u64,
+) -> DriftResult<Vec<(Pubkey, usize, u64)>> {
+    let maker_direction = taker_order.direction.opposite();
+
+    let mut maker_orders_info = Vec::with_capacity(maker_orders.len());
+    for (maker_index, maker_order) in maker_orders.iter().enumerate() {
+        let order = &maker_order.order;
+        if order.status != OrderStatus::Open
+            || order.direction != maker_direction
+            || order.market_type != MarketType::Perp
+            || order.market_index != taker_order.market_index
+        {
+            continue;
+        }
+
+        if !order.is_limit_order() || (order.must_be_triggered() && !order.triggered()) {
+            continue;
+        }
+
+        if !is_maker_for_taker(order, taker_order, slot)? {
+            continue;
+        }
+
+        let maker_price = order.force_get_limit_price(
+            Some(oracle_price),
+            None,
+            slot,
+            market.amm.order_tick_size,
+        )?;
+
+        if limit_price_breaches_maker_oracle_price_bands(
+            maker_price,
+            order.direction,
+            oracle_price,
+            market.margin_ratio_initial,
+        )? {
+            continue;
+        }
+
+        maker_orders_info.push((maker_order.maker, maker_index, maker_price));
+    }
+
+    maker_orders_info.sort_by(|a, b| match maker_direction {
+        PositionDirection::Long => b.2.cmp(&a.2),
+        PositionDirection::Short => a.2.cmp(&b.2),
+    });
+
+    Ok(maker_orders_info)
+}
+
+/// Fills against the amm on the copied market and taker position, the way
+/// fulfill_perp_order_with_amm does
+fn simulate_amm_fill(
+    market: &mut PerpMarket,
+    taker_order: &mut Order,
+    taker_position: &mut PerpPosition,
+    taker_stats: &UserStats,
+    state: &State,
+    reserve_price_before: u64,
+    limit_price: Option<u64>,
+    override_base_asset_amount: Option<u64>,
+    override_fill_price: Option<u64>,
+    liquidity_split: AMMLiquiditySplit,
+    now: i64,
+    slot: u64,
+) -> DriftResult<Option<SimulatedFill>> {
+    let amm_fill = update_market_and_position_for_amm_fill(
+        market,
+        taker_order,
+        taker_position,
+        taker_stats,
+        &state.perp_fee_structure,
+        reserve_price_before,
+        limit_price,
+        override_base_asset_amount,
+        override_fill_price,
+        liquidity_split,
+        false,
+        false,
+        &None,
+        now,
+        slot,
+    )?;
+
+    let AmmFill {
+        base_asset_amount,
+        quote_asset_amount,
+        quote_asset_amount_surplus,
+        fill_fees:
+            FillFees {
+                user_fee,
+                maker_rebate,
+                fee_to_market,
+                ..
+            },
+    } = match amm_fill {
+        Some(amm_fill) => amm_fill,
+        None => return Ok(None),
+    };
+
+    Ok(Some(SimulatedFill {
+        source: SimulatedFillSource::AMM,
+        base_asset_amount,
+        quote_asset_amount,
+        fill_price: calculate_fill_price(
+            quote_asset_amount,
+            base_asset_amount,
+            BASE_PRECISION_U64,
+        )?,
+        taker_fee: user_fee,
+        maker_rebate,
+        fee_to_market,
+        quote_asset_amount_surplus,
+    }))
+}
+
+/// Mirrors fulfill_perp_order_with_match, including the amm's jit fill, on the copied market
+/// and positions
+fn simulate_match_fill(
+    market: &mut PerpMarket,
+    taker_order: &mut Order,
+    taker_position: &mut PerpPosition,
+    taker_stats: &UserStats,
+    maker_orders: &mut [SimulatedMakerOrder],
+    maker_index: usize,
+    state: &State,
+    reserve_price_before: u64,
+    oracle_price: i64,
+    taker_limit_price: Option<u64>,
+    maker_max_base_asset_amount: Option<u64>,
+    now: i64,
+    slot: u64,
+) -> DriftResult<Vec<SimulatedFill>> {
+    let mut fills = vec![];
+
+    let maker_order = &mut maker_orders[maker_index];
+    if maker_order.order.status != OrderStatus::Open {
+        return Ok(fills);
+    }
+
+    let taker_direction = taker_order.direction;
+    let taker_price = calculate_taker_price_for_match(
+        &market.amm,
+        taker_order,
+        taker_limit_price,
+        oracle_price,
+        now,
+    )?;
+
+    let taker_base_asset_amount =
+        taker_order.get_base_asset_amount_unfilled(Some(taker_position.base_asset_amount))?;
+
+    let maker_price = maker_order.order.force_get_limit_price(
+        Some(oracle_price),
+        None,
+        slot,
+        market.amm.order_tick_size,
+    )?;
+    let maker_direction = maker_order.order.direction;
+    let mut maker_base_asset_amount = maker_order
+        .order
+        .get_visible_base_asset_amount_unfilled(Some(maker_order.position.base_asset_amount))?;
+
+    if let Some(maker_max_base_asset_amount) = maker_max_base_asset_amount {
+        maker_base_asset_amount = maker_base_asset_amount.min(maker_max_base_asset_amount);
+    }
+
+    if !do_orders_cross(maker_direction, maker_price, taker_price) {
+        return Ok(fills);
+    }
+
+    let (base_asset_amount, _) = calculate_fill_for_matched_orders(
+        maker_base_asset_amount,
+        maker_price,
+        taker_base_asset_amount,
+        PERP_DECIMALS,
+        maker_direction,
+    )?;
+
+    if base_asset_amount == 0 {
+        return Ok(fills);
+    }
+
+    let sanitize_clamp_denominator = market.get_sanitize_clamp_denominator()?;
+    update_mark_twap_from_estimates(
+        &mut market.amm,
+        now,
+        Some(maker_price),
+        Some(taker_direction),
+        sanitize_clamp_denominator,
+    )?;
+
+    let (jit_base_asset_amount, amm_liquidity_split) = calculate_amm_jit_liquidity(
+        market,
+        taker_direction,
+        maker_price,
+        Some(oracle_price),
+        base_asset_amount,
+        taker_base_asset_amount,
+        maker_base_asset_amount,
+        taker_order.has_limit_price(slot)?,
+    )?;
+
+    if jit_base_asset_amount > 0 && maker_max_base_asset_amount.is_none() {
+        if let Some(fill) = simulate_amm_fill(
+            market,
+            taker_order,
+            taker_position,
+            taker_stats,
+            state,
+            reserve_price_before,
+            taker_limit_price,
+            Some(jit_base_asset_amount),
+            Some(maker_price),
+            amm_liquidity_split,
+            now,
+            slot,
+        )? {
+            fills.push(fill);
+        }
+    }
+
+    let taker_base_asset_amount =
+        taker_order.get_base_asset_amount_unfilled(Some(taker_position.base_asset_amount))?;
+
+    let (base_asset_amount, quote_asset_amount) = calculate_fill_for_matched_orders(
+        maker_base_asset_amount,
+        maker_price,
+        taker_base_asset_amount,
+        PERP_DECIMALS,
+        maker_direction,
+    )?;
+
+    if base_asset_amount == 0 {
+        return Ok(fills);
+    }
+
+    validate_fill_price(
+        quote_asset_amount,
+        base_asset_amount,
+        BASE_PRECISION_U64,
+        taker_direction,
+        taker_price,
+        true,
+    )?;
+
+    validate_fill_price(
+        quote_asset_amount,
+        base_asset_amount,
+        BASE_PRECISION_U64,
+        maker_direction,
+        maker_price,
+        false,
+    )?;
+
+    let mut maker_stats = maker_order.maker_stats;
+    let FillFees {
+        user_fee: taker_fee,
+        maker_rebate,
+        fee_to_market,
+        ..
+    } = calculate_fee_for_fulfillment_with_match(
+        taker_stats,
+        &Some(&mut maker_stats),
+        quote_asset_amount,
+        &state.perp_fee_structure,
+        taker_order.slot,
+        slot,
+        0,
+        false,
+        &None,
+        &MarketType::Perp,
+        market.fee_adjustment,
+    )?;
+
+    let maker_position_delta =
+        get_position_delta_for_fill(base_asset_amount, quote_asset_amount, maker_direction)?;
+    update_position_and_market(&mut maker_order.position, market, &maker_position_delta)?;
+
+    let taker_position_delta =
+        get_position_delta_for_fill(base_asset_amount, quote_asset_amount, taker_direction)?;
+    update_position_and_market(taker_position, market, &taker_position_delta)?;
+
+    update_amm_fee_pool_for_match_fill(&mut market.amm, fee_to_market)?;
+
+    update_quote_asset_and_break_even_amount(taker_position, market, -taker_fee.cast()?)?;
+    update_quote_asset_and_break_even_amount(
+        &mut maker_order.position,
+        market,
+        maker_rebate.cast()?,
+    )?;
+
+    update_order_after_fill(taker_order, base_asset_amount, quote_asset_amount)?;
+    update_order_after_fill(
+        &mut maker_order.order,
+        base_asset_amount,
+        quote_asset_amount,
+    )?;
+
+    fills.push(SimulatedFill {
+        source: SimulatedFillSource::Maker(maker_index),
+        base_asset_amount,
+        quote_asset_amount,
+        fill_price: maker_price,
+        taker_fee,
+        maker_rebate,
+        fee_to_market,
+        quote_asset_amount_surplus: 0,
+    });
+
+    Ok(fills)
+}
@@ -0,0 +1,521 @@
+mod simulate_perp_fill {
+    use std::str::FromStr;
+
+    use anchor_lang::prelude::{AccountLoader, Clock};
+    use anchor_lang::Owner;
+
+    use crate::controller::amm::{calculate_base_swap_output_with_spread, SwapDirection};
+    use crate::controller::fill_simulation::{
+        get_simulated_taker_order, simulate_perp_fill, SimulatedFillSource, SimulatedMakerOrder,
+    };
+    use crate::controller::orders::fill_perp_order_and_get_fill_amounts;
+    use crate::controller::position::PositionDirection;
+    use crate::error::ErrorCode;
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64, PEG_PRECISION,
+        PRICE_PRECISION, PRICE_PRECISION_I64, PRICE_PRECISION_U64, QUOTE_PRECISION_I64,
+        SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+    };
+    use crate::math::fees::calculate_fee_for_fulfillment_with_match;
+    use crate::state::fill_mode::FillMode;
+    use crate::state::oracle::{HistoricalOracleData, OracleSource};
+    use crate::state::oracle_map::OracleMap;
+    use crate::state::order_params::OrderParams;
+    use crate::state::perp_market::{AuctionClearingMode, MarketStatus, PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::state::{FeeStructure, State};
+    use crate::state::user::{
+        MarketType, Order, OrderBitFlag, OrderStatus, OrderType, PerpPosition, SpotPosition, User,
+        UserStats,
+    };
+    use crate::state::user_map::{UserMap, UserStatsMap};
+    use crate::test_utils::*;
+    use crate::{create_account_info, create_anchor_account_info};
+    use solana_program::pubkey::Pubkey;
+
+    fn get_market() -> PerpMarket {
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                ask_base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                ask_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100_050 * PEG_PRECISION / 1000,
+                max_slippage_ratio: 50,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                base_spread: 100,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price: (100 * PRICE_PRECISION) as i64,
+                    last_oracle_price_twap: (100 * PRICE_PRECISION) as i64,
+                    last_oracle_price_twap_5min: (100 * PRICE_PRECISION) as i64,
+
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            ..PerpMarket::default_test()
+        };
+        market.amm.max_base_asset_reserve = u128::MAX;
+        market.amm.min_base_asset_reserve = 0;
+        market
+    }
+
+    fn get_state() -> State {
+        State {
+            perp_fee_structure: FeeStructure::test_default(),
+            ..State::default()
+        }
+    }
+
+    fn get_maker_order(price: u64, base_asset_amount: u64) -> SimulatedMakerOrder {
+        SimulatedMakerOrder {
+            maker: Pubkey::new_unique(),
+            order: Order {
+                status: OrderStatus::Open,
+                market_index: 0,
+                post_only: true,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount,
+                price,
+                ..Order::default()
+            },
+            maker_stats: UserStats::default(),
+            position: PerpPosition::default(),
+        }
+    }
+
+    fn get_market_order_params(auction_duration: u8) -> OrderParams {
+        OrderParams {
+            order_type: OrderType::Market,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: 150 * PRICE_PRECISION_U64,
+            auction_duration: Some(auction_duration),
+            auction_start_price: Some(100 * PRICE_PRECISION_I64),
+            auction_end_price: Some(150 * PRICE_PRECISION_I64),
+            ..OrderParams::default()
+        }
+    }
+
+    #[test]
+    fn fill_with_maker_then_amm() {
+        let market = get_market();
+        let state = get_state();
+        let maker_price = 100_010_000 * PRICE_PRECISION_U64 / 1_000_000;
+        let maker_orders = [get_maker_order(maker_price, BASE_PRECISION_U64 / 2)];
+
+        let simulation = simulate_perp_fill(
+            &state,
+            &market,
+            100 * PRICE_PRECISION_I64,
+            &get_market_order_params(0),
+            &UserStats::default(),
+            &PerpPosition::default(),
+            &maker_orders,
+            0,
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(simulation.fills.len(), 2);
+        assert_eq!(simulation.base_asset_amount_filled, BASE_PRECISION_U64);
+
+        let maker_fill = simulation.fills[0];
+        assert_eq!(maker_fill.source, SimulatedFillSource::Maker(0));
+        assert_eq!(maker_fill.base_asset_amount, BASE_PRECISION_U64 / 2);
+        assert_eq!(maker_fill.fill_price, maker_price);
+
+        let expected_fees = calculate_fee_for_fulfillment_with_match(
+            &UserStats::default(),
+            &Some(&mut UserStats::default()),
+            maker_fill.quote_asset_amount,
+            &state.perp_fee_structure,
+            0,
+            0,
+            0,
+            false,
+            &None,
+            &MarketType::Perp,
+            0,
+        )
+        .unwrap();
+        assert_eq!(maker_fill.taker_fee, expected_fees.user_fee);
+        assert_eq!(maker_fill.maker_rebate, expected_fees.maker_rebate);
+
+        // the amm fills the rest off the same reserves fill_perp_order swaps against
+        let (expected_base_asset_reserve, expected_quote_asset_reserve, expected_quote, _) =
+            calculate_base_swap_output_with_spread(
+                &market.amm,
+                BASE_PRECISION_U64 / 2,
+                SwapDirection::Remove,
+            )
+            .unwrap();
+
+        let amm_fill = simulation.fills[1];
+        assert_eq!(amm_fill.source, SimulatedFillSource::AMM);
+        assert_eq!(amm_fill.base_asset_amount, BASE_PRECISION_U64 / 2);
+        assert_eq!(amm_fill.quote_asset_amount, expected_quote);
+        assert!(amm_fill.fill_price > maker_price);
+        assert_eq!(
+            simulation.amm.base_asset_reserve,
+            expected_base_asset_reserve
+        );
+        assert_eq!(
+            simulation.amm.quote_asset_reserve,
+            expected_quote_asset_reserve
+        );
+        assert_eq!(
+            simulation.quote_asset_amount_filled,
+            maker_fill.quote_asset_amount + amm_fill.quote_asset_amount
+        );
+
+        // both fills' fees go to the amm's fee pool
+        assert_eq!(
+            simulation.amm.total_fee_minus_distributions,
+            (maker_fill.fee_to_market + amm_fill.fee_to_market) as i128
+        );
+    }
+
+    #[test]
+    fn fill_or_kill_and_min_fill() {
+        let market = get_market();
+        let maker_price = 100_010_000 * PRICE_PRECISION_U64 / 1_000_000;
+        let maker_orders = [get_maker_order(maker_price, BASE_PRECISION_U64 / 2)];
+
+        // the maker only covers half and the amm's ask is above the limit
+        let params = OrderParams {
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: maker_price,
+            immediate_or_cancel: true,
+            ..OrderParams::default()
+        };
+
+        let simulate = |params: &OrderParams| {
+            simulate_perp_fill(
+                &get_state(),
+                &market,
+                100 * PRICE_PRECISION_I64,
+                params,
+                &UserStats::default(),
+                &PerpPosition::default(),
+                &maker_orders,
+                0,
+                0,
+            )
+        };
+
+        let simulation = simulate(&params).unwrap();
+        assert_eq!(simulation.base_asset_amount_filled, BASE_PRECISION_U64 / 2);
+
+        let fill_or_kill_params = OrderParams {
+            bit_flags: OrderBitFlag::FillOrKill as u8,
+            ..params
+        };
+        assert_eq!(
+            simulate(&fill_or_kill_params).map(|simulation| simulation.fills),
+            Err(ErrorCode::FillOrKillOrderNotFilled)
+        );
+
+        let min_fill_params = OrderParams {
+            min_fill_base_asset_amount: BASE_PRECISION_U64,
+            ..params
+        };
+        assert_eq!(
+            simulate(&min_fill_params).map(|simulation| simulation.fills),
+            Err(ErrorCode::MinFillNotMet)
+        );
+    }
+
+    #[test]
+    fn matches_fill_perp_order() {
+        let clock = Clock {
+            slot: 0,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+        let state = get_state();
+        let params = get_market_order_params(0);
+        let maker_price = 100_010_000 * PRICE_PRECISION_U64 / 1_000_000;
+        let maker_order = get_maker_order(maker_price, BASE_PRECISION_U64 / 2);
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut market = get_market();
+        market.amm.oracle = oracle_price_key;
+
+        let simulation = simulate_perp_fill(
+            &state,
+            &market,
+            100 * PRICE_PRECISION_I64,
+            &params,
+            &UserStats::default(),
+            &PerpPosition::default(),
+            &[maker_order],
+            clock.unix_timestamp,
+            clock.slot,
+        )
+        .unwrap();
+        assert_eq!(simulation.fills.len(), 2);
+
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let collateral = get_spot_positions(SpotPosition {
+            market_index: 0,
+            balance_type: SpotBalanceType::Deposit,
+            scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+            ..SpotPosition::default()
+        });
+
+        // the taker order as place_perp_order would create it
+        let mut taker_order = get_simulated_taker_order(
+            &params,
+            &market,
+            100 * PRICE_PRECISION_I64,
+            0,
+            state.min_perp_auction_duration,
+            clock.unix_timestamp,
+            clock.slot,
+        )
+        .unwrap();
+        taker_order.order_id = 1;
+
+        let taker_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        let mut taker = User {
+            authority: taker_key,
+            orders: get_orders(taker_order),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_bids: BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: collateral,
+            ..User::default()
+        };
+        create_anchor_account_info!(taker, &taker_key, User, taker_account_info);
+        let taker_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&taker_account_info).unwrap();
+
+        create_anchor_account_info!(UserStats::default(), UserStats, taker_stats_account_info);
+        let taker_stats_account_loader: AccountLoader<UserStats> =
+            AccountLoader::try_from(&taker_stats_account_info).unwrap();
+
+        let mut maker = User {
+            authority: maker_order.maker,
+            orders: get_orders(Order {
+                order_id: 1,
+                ..maker_order.order
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_asks: -((BASE_PRECISION_U64 / 2) as i64),
+                ..PerpPosition::default()
+            }),
+            spot_positions: collateral,
+            ..User::default()
+        };
+        create_anchor_account_info!(maker, &maker_order.maker, User, maker_account_info);
+        let makers_and_referrer = UserMap::load_one(&maker_account_info).unwrap();
+
+        let mut maker_stats = UserStats {
+            authority: maker_order.maker,
+            ..UserStats::default()
+        };
+        create_anchor_account_info!(maker_stats, UserStats, maker_stats_account_info);
+        let makers_and_referrer_stats = UserStatsMap::load_one(&maker_stats_account_info).unwrap();
+
+        // the taker fills its own order like in place_and_take
+        let (base_asset_amount, quote_asset_amount) = fill_perp_order_and_get_fill_amounts(
+            1,
+            &state,
+            &taker_account_loader,
+            &taker_stats_account_loader,
+            &spot_market_map,
+            &market_map,
+            &mut oracle_map,
+            &taker_account_loader,
+            &taker_stats_account_loader,
+            &makers_and_referrer,
+            &makers_and_referrer_stats,
+            None,
+            &clock,
+            FillMode::PlaceAndTake,
+        )
+        .unwrap();
+
+        assert_eq!(base_asset_amount, simulation.base_asset_amount_filled);
+        assert_eq!(quote_asset_amount, simulation.quote_asset_amount_filled);
+
+        let market = market_map.get_ref(&0).unwrap();
+        assert_eq!(
+            market.amm.base_asset_reserve,
+            simulation.amm.base_asset_reserve
+        );
+        assert_eq!(
+            market.amm.quote_asset_reserve,
+            simulation.amm.quote_asset_reserve
+        );
+        assert_eq!(
+            market.amm.ask_base_asset_reserve,
+            simulation.amm.ask_base_asset_reserve
+        );
+        assert_eq!(
+            market.amm.ask_quote_asset_reserve,
+            simulation.amm.ask_quote_asset_reserve
+        );
+        assert_eq!(
+            market.amm.base_asset_amount_with_amm,
+            simulation.amm.base_asset_amount_with_amm
+        );
+        assert_eq!(market.amm.total_fee, simulation.amm.total_fee);
+        assert_eq!(
+            market.amm.total_exchange_fee,
+            simulation.amm.total_exchange_fee
+        );
+        assert_eq!(market.amm.total_mm_fee, simulation.amm.total_mm_fee);
+        assert_eq!(
+            market.amm.total_fee_minus_distributions,
+            simulation.amm.total_fee_minus_distributions
+        );
+        assert_eq!(
+            market.amm.net_revenue_since_last_funding,
+            simulation.amm.net_revenue_since_last_funding
+        );
+        assert_eq!(
+            market.amm.last_mark_price_twap,
+            simulation.amm.last_mark_price_twap
+        );
+        assert_eq!(
+            market.amm.last_ask_price_twap,
+            simulation.amm.last_ask_price_twap
+        );
+        assert_eq!(
+            market.amm.base_asset_amount_long,
+            simulation.amm.base_asset_amount_long
+        );
+        assert_eq!(
+            market.amm.quote_entry_amount_long,
+            simulation.amm.quote_entry_amount_long
+        );
+        assert_eq!(
+            market.amm.quote_asset_amount,
+            simulation.amm.quote_asset_amount
+        );
+
+        let taker = taker_account_loader.load().unwrap();
+        assert_eq!(
+            taker.perp_positions[0].base_asset_amount,
+            simulation.taker_position.base_asset_amount
+        );
+        assert_eq!(
+            taker.perp_positions[0].quote_asset_amount,
+            simulation.taker_position.quote_asset_amount
+        );
+        assert_eq!(
+            taker.perp_positions[0].quote_break_even_amount,
+            simulation.taker_position.quote_break_even_amount
+        );
+    }
+
+    #[test]
+    fn limit_order_that_doesnt_cross() {
+        let market = get_market();
+        let maker_orders = [get_maker_order(
+            101 * PRICE_PRECISION_U64,
+            BASE_PRECISION_U64,
+        )];
+
+        let params = OrderParams {
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: 99 * PRICE_PRECISION_U64,
+            ..OrderParams::default()
+        };
+
+        let simulation = simulate_perp_fill(
+            &get_state(),
+            &market,
+            100 * PRICE_PRECISION_I64,
+            &params,
+            &UserStats::default(),
+            &PerpPosition::default(),
+            &maker_orders,
+            0,
+            0,
+        )
+        .unwrap();
+
+        assert!(simulation.fills.is_empty());
+        assert_eq!(simulation.base_asset_amount_filled, 0);
+        assert_eq!(simulation.amm, market.amm);
+    }
+
+    #[test]
+    fn batch_auction_market_order_waits_for_auction() {
+        let mut market = get_market();
+        market.auction_clearing_mode = AuctionClearingMode::Batch;
+        let maker_orders = [get_maker_order(
+            100 * PRICE_PRECISION_U64,
+            BASE_PRECISION_U64,
+        )];
+
+        let simulation = simulate_perp_fill(
+            &get_state(),
+            &market,
+            100 * PRICE_PRECISION_I64,
+            &get_market_order_params(10),
+            &UserStats::default(),
+            &PerpPosition::default(),
+            &maker_orders,
+            0,
+            0,
+        )
+        .unwrap();
+
+        assert!(simulation.fills.is_empty());
+    }
+}
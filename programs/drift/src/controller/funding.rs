@@ -14,7 +14,10 @@ use crate::math::casting::Cast;
 use crate::math::constants::{
     FUNDING_RATE_BUFFER, FUNDING_RATE_OFFSET_DENOMINATOR, ONE_HOUR_I128, TWENTY_FOUR_HOUR,
 };
-use crate::math::funding::{calculate_funding_payment, calculate_funding_rate_long_short};
+use crate::math::funding::{
+    calculate_funding_payment, calculate_funding_rate_long_short,
+    calculate_premium_index_price_spread, update_premium_index_twap,
+};
 use crate::math::helpers::on_the_hour_update;
 use crate::math::safe_math::SafeMath;
 use crate::math::stats::calculate_new_twap;
@@ -23,7 +26,7 @@ use crate::math::oracle;
 
 use crate::state::events::{FundingPaymentRecord, FundingRateRecord};
 use crate::state::oracle_map::OracleMap;
-use crate::state::perp_market::{PerpMarket, AMM};
+use crate::state::perp_market::{FundingFormula, PerpMarket, AMM};
 use crate::state::perp_market_map::PerpMarketMap;
 use crate::state::state::OracleGuardRails;
 use crate::state::user::User;
@@ -179,6 +182,11 @@ pub fn update_funding_rate(
 
     if valid_funding_update {
         let oracle_price_data = oracle_map.get_price_data(&market.amm.oracle)?;
+
+        if market.amm.funding_formula == FundingFormula::PremiumIndex {
+            update_premium_index_twap(market, now)?;
+        }
+
         let sanitize_clamp_denominator = market.get_sanitize_clamp_denominator()?;
 
         let oracle_price_twap = amm::update_oracle_price_twap(
@@ -219,14 +227,22 @@ pub fn update_funding_rate(
             .safe_div(max(ONE_HOUR_I128, market.amm.funding_period as i128))?;
         // funding period = 1 hour, window = 1 day
         // low periodicity => quickly updating/settled funding rates => lower funding rate payment per interval
-        let price_spread = mid_price_twap.cast::<i64>()?.safe_sub(oracle_price_twap)?;
-
-        // add offset 1/FUNDING_RATE_OFFSET_DENOMINATOR*365. if FUNDING_RATE_OFFSET_DENOMINATOR = 5000 => 7.3% annualized rate
-        let price_spread_with_offset = price_spread.safe_add(
-            oracle_price_twap
-                .abs()
-                .safe_div(FUNDING_RATE_OFFSET_DENOMINATOR)?,
-        )?;
+        let price_spread_with_offset = match market.amm.funding_formula {
+            FundingFormula::MarkOracleTwap => {
+                let price_spread = mid_price_twap.cast::<i64>()?.safe_sub(oracle_price_twap)?;
+
+                // add offset 1/FUNDING_RATE_OFFSET_DENOMINATOR*365. if FUNDING_RATE_OFFSET_DENOMINATOR = 5000 => 7.3% annualized rate
+                price_spread.safe_add(
+                    oracle_price_twap
+                        .abs()
+                        .safe_div(FUNDING_RATE_OFFSET_DENOMINATOR)?,
+                )?
+            }
+            // the interest rate takes the place of the fixed offset
+            FundingFormula::PremiumIndex => {
+                calculate_premium_index_price_spread(market, oracle_price_twap)?
+            }
+        };
 
         // clamp price divergence based on contract tier for funding rate calculation
         let max_price_spread =
@@ -200,12 +200,14 @@ pub fn liquidate_perp(
     // burning lp shares = removing open bids/asks
     let lp_shares = user.perp_positions[position_index].lp_shares;
     if lp_shares > 0 {
+        let base_asset_amount_before = user.perp_positions[position_index].base_asset_amount;
         let (position_delta, pnl) = burn_lp_shares(
             &mut user.perp_positions[position_index],
             perp_market_map.get_ref_mut(&market_index)?.deref_mut(),
             lp_shares,
             oracle_price,
         )?;
+        user.update_perp_position_open_count(position_index, base_asset_amount_before);
 
         // emit LP record for shares removed
         emit_stack::<_, { LPRecord::SIZE }>(LPRecord {
@@ -460,6 +462,7 @@ pub fn liquidate_perp(
 
         let liquidator_position = liquidator.force_get_perp_position_mut(market_index)?;
         let liquidator_existing_position_direction = liquidator_position.get_direction();
+        let liquidator_base_asset_amount_before = liquidator_position.base_asset_amount;
         update_position_and_market(liquidator_position, &mut market, &liquidator_position_delta)?;
         update_quote_asset_and_break_even_amount(
             liquidator_position,
@@ -478,6 +481,13 @@ pub fn liquidate_perp(
             market.amm.order_step_size
         )?;
 
+        let liquidator_position_index =
+            get_position_index(&liquidator.perp_positions, market_index)?;
+        liquidator.update_perp_position_open_count(
+            liquidator_position_index,
+            liquidator_base_asset_amount_before,
+        );
+
         market.amm.total_liquidation_fee = market
             .amm
             .total_liquidation_fee
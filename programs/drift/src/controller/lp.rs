@@ -236,9 +236,12 @@ pub fn settle_lp(
     market: &mut PerpMarket,
     now: i64,
 ) -> DriftResult {
-    if let Ok(position) = user.get_perp_position_mut(market.market_index) {
+    if let Ok(position_index) = get_position_index(&user.perp_positions, market.market_index) {
+        let position = &mut user.perp_positions[position_index];
         if position.lp_shares > 0 {
+            let base_asset_amount_before = position.base_asset_amount;
             let (position_delta, pnl) = settle_lp_position(position, market)?;
+            user.update_perp_position_open_count(position_index, base_asset_amount_before);
 
             if position_delta.base_asset_amount != 0 || position_delta.quote_asset_amount != 0 {
                 crate::emit!(LPRecord {
@@ -394,9 +397,11 @@ pub fn remove_perp_lp_shares(
         ErrorCode::InsufficientLPTokens
     )?;
 
+    let base_asset_amount_before = position.base_asset_amount;
     let oracle_price = oracle_map.get_price_data(&market.amm.oracle)?.price;
     let (position_delta, pnl) =
         burn_lp_shares(position, &mut market, shares_to_burn, oracle_price)?;
+    user.update_perp_position_open_count(position_index, base_asset_amount_before);
 
     emit!(LPRecord {
         ts: now,
@@ -1,4 +1,5 @@
 pub mod amm;
+pub mod fill_simulation;
 pub mod funding;
 pub mod insurance;
 pub mod liquidation;
@@ -12,7 +12,7 @@ use crate::controller::lp::burn_lp_shares;
 use crate::controller::position;
 use crate::controller::position::{
     add_new_position, decrease_open_bids_and_asks, get_position_index, increase_open_bids_and_asks,
-    update_lp_market_position, update_position_and_market, update_quote_asset_amount,
+    update_lp_market_position, update_quote_asset_amount, update_user_position_and_market,
     PositionDirection,
 };
 use crate::controller::spot_balance::{
@@ -26,21 +26,29 @@ use crate::error::DriftResult;
 use crate::error::ErrorCode;
 use crate::get_struct_values;
 use crate::get_then_update_id;
+use crate::load;
 use crate::load_mut;
 use crate::math::amm_jit::calculate_amm_jit_liquidity;
-use crate::math::auction::{calculate_auction_params_for_trigger_order, calculate_auction_prices};
+use crate::math::auction::{
+    calculate_auction_params_for_trigger_order, calculate_auction_prices,
+    calculate_batch_auction_clearing_price, is_auction_complete, is_batch_auction_order,
+};
 use crate::math::casting::Cast;
 use crate::math::constants::{
-    BASE_PRECISION_U64, FIVE_MINUTE, ONE_HOUR, PERP_DECIMALS, QUOTE_SPOT_MARKET_INDEX,
+    BASE_PRECISION_U64, FIVE_MINUTE, MAX_OPEN_ORDERS, ONE_HOUR, PERP_DECIMALS,
+    QUOTE_SPOT_MARKET_INDEX,
 };
 use crate::math::fees::{determine_user_fee_tier, ExternalFillFees, FillFees};
 use crate::math::fulfillment::{
-    determine_perp_fulfillment_methods, determine_spot_fulfillment_methods,
+    calculate_amm_fill, calculate_taker_price_for_match, determine_perp_fulfillment_methods,
+    determine_spot_fulfillment_methods, get_pro_rata_allocations, get_pro_rata_fulfillment_methods,
+    update_amm_fee_pool_for_amm_fill, update_amm_fee_pool_for_match_fill, validate_fill_or_kill,
 };
 use crate::math::liquidation::validate_user_not_being_liquidated;
 use crate::math::matching::{
     are_orders_same_market_but_different_sides, calculate_fill_for_matched_orders,
-    calculate_filler_multiplier_for_matched_orders, do_orders_cross, is_maker_for_taker,
+    calculate_filler_multiplier_for_matched_orders, calculate_self_trade_prevention_outcome,
+    do_orders_cross, is_maker_for_taker,
 };
 use crate::math::oracle;
 use crate::math::oracle::{is_oracle_valid_for_action, DriftAction, OracleValidity};
@@ -49,10 +57,10 @@ use crate::math::spot_balance::{get_signed_token_amount, get_token_amount};
 use crate::math::stats::calculate_new_twap;
 use crate::math::{amm, fees, margin::*, orders::*};
 use crate::state::order_params::{
-    ModifyOrderParams, ModifyOrderPolicy, OrderParams, PlaceOrderOptions, PostOnlyParam,
+    ComboOrderParams, ModifyOrderParams, ModifyOrderPolicy, OrderParams, PlaceOrderOptions,
+    PostOnlyParam,
 };
 
-use crate::math::amm::calculate_amm_available_liquidity;
 use crate::math::lp::calculate_lp_shares_to_burn_for_risk_reduction;
 use crate::math::safe_unwrap::SafeUnwrap;
 use crate::math::spot_swap::select_margin_type_for_swap;
@@ -67,7 +75,7 @@ use crate::state::margin_calculation::{MarginCalculation, MarginContext};
 use crate::state::oracle::{OraclePriceData, StrictOraclePrice};
 use crate::state::oracle_map::OracleMap;
 use crate::state::paused_operations::PerpOperation;
-use crate::state::perp_market::{AMMLiquiditySplit, MarketStatus, PerpMarket};
+use crate::state::perp_market::{AMMLiquiditySplit, MarketStatus, MatchingMode, PerpMarket};
 use crate::state::perp_market_map::PerpMarketMap;
 use crate::state::spot_fulfillment_params::{ExternalSpotFill, SpotFulfillmentParams};
 use crate::state::spot_market::{SpotBalanceType, SpotMarket};
@@ -76,14 +84,15 @@ use crate::state::state::FeeStructure;
 use crate::state::state::*;
 use crate::state::traits::Size;
 use crate::state::user::{
-    AssetType, Order, OrderStatus, OrderTriggerCondition, OrderType, UserStats,
+    AssetType, Order, OrderBitFlag, OrderStatus, OrderTriggerCondition, OrderType,
+    PerpPositionTakeProfitStopLoss, SelfTradePreventionMode, TriggerSource, UserStats,
 };
-use crate::state::user::{MarketType, User};
+use crate::state::user::{MarketType, PerpPosition, User};
 use crate::state::user_map::{UserMap, UserStatsMap};
 use crate::validate;
 use crate::validation;
 use crate::validation::order::{
-    validate_order, validate_order_for_force_reduce_only, validate_spot_order,
+    validate_order, validate_order_for_force_reduce_only, validate_order_group, validate_spot_order,
 };
 
 #[cfg(test)]
@@ -132,7 +141,7 @@ pub fn place_perp_order(
 
     if user.is_reduce_only() {
         validate!(
-            params.reduce_only,
+            params.is_reduce_only(),
             ErrorCode::UserReduceOnly,
             "order must be reduce only"
         )?;
@@ -241,7 +250,7 @@ pub fn place_perp_order(
         "must be perp order"
     )?;
 
-    let new_order = Order {
+    let mut new_order = Order {
         status: OrderStatus::Open,
         order_type: params.order_type,
         market_type: params.market_type,
@@ -253,6 +262,7 @@ pub fn place_perp_order(
             params.price,
             params.direction,
             params.post_only,
+            params.max_slide_ticks,
             &market.amm,
         )?,
         existing_position_direction,
@@ -260,7 +270,7 @@ pub fn place_perp_order(
         base_asset_amount_filled: 0,
         quote_asset_amount_filled: 0,
         direction: params.direction,
-        reduce_only: params.reduce_only || force_reduce_only,
+        reduce_only: params.is_reduce_only() || force_reduce_only,
         trigger_price: standardize_price(
             params.trigger_price.unwrap_or(0),
             market.amm.order_tick_size,
@@ -274,9 +284,41 @@ pub fn place_perp_order(
         auction_end_price,
         auction_duration,
         max_ts,
-        padding: [0; 3],
+        bit_flags: params.bit_flags,
+        group_id: params.group_id,
+        slices_remaining: 0,
     };
 
+    // trailing stop without a trigger price starts trailing from the current oracle price
+    if new_order.order_type == OrderType::TrailingStop && new_order.trigger_price == 0 {
+        new_order.trigger_price = calculate_trailing_trigger_price(
+            &new_order,
+            oracle_price_data.price.unsigned_abs(),
+            market.amm.order_tick_size,
+        )?;
+    }
+
+    if new_order.order_type == OrderType::Twap {
+        init_twap_order(&mut new_order, user, params.twap_slices, now)?;
+    }
+
+    if params.display_base_asset_amount > 0 || new_order.is_bit_flag_set(OrderBitFlag::Iceberg) {
+        init_iceberg_order(&mut new_order, params.display_base_asset_amount);
+    }
+
+    if params.max_slippage_bps > 0 || new_order.is_bit_flag_set(OrderBitFlag::MaxSlippage) {
+        init_max_slippage_order(&mut new_order, params.max_slippage_bps)?;
+    }
+
+    if params.trigger_source != TriggerSource::Oracle {
+        init_trigger_source(
+            &mut new_order,
+            params.trigger_source,
+            params.trigger_market_index,
+            params.trigger_price.unwrap_or(0),
+        )?;
+    }
+
     let valid_oracle_price = Some(oracle_map.get_price_data(&market.amm.oracle)?.price);
     match validate_order(&new_order, market, valid_oracle_price, slot) {
         Ok(()) => {}
@@ -289,6 +331,15 @@ pub fn place_perp_order(
         Err(err) => return Err(err),
     };
 
+    // slices are only placed by release_twap_order_slice, which sizes them from the twap order
+    validate!(
+        !new_order.is_bit_flag_set(OrderBitFlag::TwapSlice) || options.is_twap_slice_release(),
+        ErrorCode::InvalidOrderGroup,
+        "twap slice can only be released by its twap order"
+    )?;
+
+    validate_order_group(&new_order, &user.orders)?;
+
     let risk_increasing = is_new_order_risk_increasing(
         &new_order,
         user.perp_positions[position_index].base_asset_amount,
@@ -389,7 +440,126 @@ pub fn place_perp_order(
     Ok(())
 }
 
-fn get_auction_params(
+pub fn place_orders(
+    state: &State,
+    user: &mut User,
+    user_key: Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+    params: &[OrderParams],
+) -> DriftResult {
+    validate!(
+        params.len() <= MAX_OPEN_ORDERS as usize,
+        ErrorCode::DefaultError,
+        "max {} order params",
+        MAX_OPEN_ORDERS
+    )?;
+
+    let num_orders = params.len();
+    for (i, params) in params.iter().enumerate() {
+        validate!(
+            !params.immediate_or_cancel,
+            ErrorCode::InvalidOrderIOC,
+            "immediate_or_cancel order must be in place_and_make or place_and_take"
+        )?;
+
+        validate!(
+            !params.is_place_and_take_only(),
+            ErrorCode::InvalidOrder,
+            "fill or kill and min fill orders must be in place_and_take"
+        )?;
+
+        // only enforce margin on last order and only try to expire on first order
+        let options = PlaceOrderOptions {
+            enforce_margin_check: i == num_orders - 1,
+            try_expire_orders: i == 0,
+            risk_increasing: false,
+            explanation: OrderActionExplanation::None,
+        };
+
+        if params.market_type == MarketType::Perp {
+            place_perp_order(
+                state,
+                user,
+                user_key,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                clock,
+                *params,
+                options,
+            )?;
+        } else {
+            place_spot_order(
+                state,
+                user,
+                user_key,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                clock,
+                *params,
+                options,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Cancels all of the user's open orders in the market and places the new orders in their place
+pub fn replace_orders(
+    state: &State,
+    user: &mut User,
+    user_key: Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+    market_type: MarketType,
+    market_index: u16,
+    params: &[OrderParams],
+) -> DriftResult {
+    validate!(
+        params
+            .iter()
+            .all(|params| params.market_type == market_type && params.market_index == market_index),
+        ErrorCode::DefaultError,
+        "order params must be for {:?} market {}",
+        market_type,
+        market_index
+    )?;
+
+    cancel_orders(
+        user,
+        &user_key,
+        None,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        clock.unix_timestamp,
+        clock.slot,
+        OrderActionExplanation::None,
+        Some(market_type),
+        Some(market_index),
+        None,
+    )?;
+
+    place_orders(
+        state,
+        user,
+        user_key,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        clock,
+        params,
+    )
+}
+
+pub(crate) fn get_auction_params(
     params: &OrderParams,
     oracle_price_data: &OraclePriceData,
     tick_size: u64,
@@ -593,6 +763,97 @@ pub fn cancel_order_by_user_order_id(
 }
 
 pub fn cancel_order(
+    order_index: usize,
+    user: &mut User,
+    user_key: &Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+    explanation: OrderActionExplanation,
+    filler_key: Option<&Pubkey>,
+    filler_reward: u64,
+    skip_log: bool,
+) -> DriftResult {
+    let order = user.orders[order_index];
+
+    cancel_single_order(
+        order_index,
+        user,
+        user_key,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        now,
+        slot,
+        explanation,
+        filler_key,
+        filler_reward,
+        skip_log,
+    )?;
+
+    // exits for an entry that never filled have no position to close
+    if order.is_bit_flag_set(OrderBitFlag::BracketEntry) && order.base_asset_amount_filled == 0 {
+        for exit_index in 0..user.orders.len() {
+            let exit = &user.orders[exit_index];
+            if exit.status != OrderStatus::Open
+                || exit.group_id != order.group_id
+                || !exit.is_bit_flag_set(OrderBitFlag::BracketExit)
+            {
+                continue;
+            }
+
+            cancel_single_order(
+                exit_index,
+                user,
+                user_key,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                now,
+                slot,
+                OrderActionExplanation::BracketEntryCanceled,
+                filler_key,
+                0,
+                skip_log,
+            )?;
+        }
+    }
+
+    // slices released by a twap order stop with it
+    if order.order_type == OrderType::Twap {
+        for slice_index in 0..user.orders.len() {
+            let slice = &user.orders[slice_index];
+            if slice.status != OrderStatus::Open
+                || slice.group_id != order.group_id
+                || !slice.is_bit_flag_set(OrderBitFlag::TwapSlice)
+            {
+                continue;
+            }
+
+            cancel_single_order(
+                slice_index,
+                user,
+                user_key,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                now,
+                slot,
+                OrderActionExplanation::TwapCanceled,
+                filler_key,
+                0,
+                skip_log,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Cancels the order without applying its order group semantics
+fn cancel_single_order(
     order_index: usize,
     user: &mut User,
     user_key: &Pubkey,
@@ -690,6 +951,99 @@ pub fn cancel_order(
     Ok(())
 }
 
+/// Cancels the open orders in the group that are canceled by the order filling or triggering
+pub fn cancel_order_group_siblings(
+    order: &Order,
+    user: &mut User,
+    user_key: &Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+    filler_key: Option<&Pubkey>,
+) -> DriftResult {
+    if !order.is_in_order_group() {
+        return Ok(());
+    }
+
+    for order_index in 0..user.orders.len() {
+        if user.orders[order_index].status != OrderStatus::Open
+            || !order.cancels_group_sibling(&user.orders[order_index])
+        {
+            continue;
+        }
+
+        cancel_order(
+            order_index,
+            user,
+            user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            OrderActionExplanation::OneCancelsOther,
+            filler_key,
+            0,
+            false,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Adds a twap slice's fill to its twap order. The twap order is removed once fully filled
+fn update_twap_order_for_slice_fill(
+    slice: &Order,
+    user: &mut User,
+    user_key: &Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+    base_asset_amount: u64,
+    quote_asset_amount: u64,
+) -> DriftResult {
+    if !slice.is_bit_flag_set(OrderBitFlag::TwapSlice) {
+        return Ok(());
+    }
+
+    // twap order could have been canceled or expired while the slice was open
+    let twap_order_index = match user.get_twap_order_index(slice.group_id) {
+        Some(twap_order_index) => twap_order_index,
+        None => return Ok(()),
+    };
+
+    let twap_order = &mut user.orders[twap_order_index];
+    twap_order.base_asset_amount_filled = twap_order
+        .base_asset_amount_filled
+        .safe_add(base_asset_amount)?;
+    twap_order.quote_asset_amount_filled = twap_order
+        .quote_asset_amount_filled
+        .safe_add(quote_asset_amount)?;
+
+    if twap_order.get_base_asset_amount_unfilled(None)? == 0 {
+        cancel_single_order(
+            twap_order_index,
+            user,
+            user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            OrderActionExplanation::TwapCompleted,
+            None,
+            0,
+            false,
+        )?;
+    }
+
+    Ok(())
+}
+
 pub enum ModifyOrderId {
     UserOrderId(u8),
     OrderId(u32),
@@ -735,51 +1089,145 @@ pub fn modify_order(
         },
     };
 
-    let existing_order = user.orders[order_index];
-
-    cancel_order(
+    modify_order_at_index(
         order_index,
+        &modify_order_params,
         &mut user,
-        &user_key,
+        user_key,
+        state,
         perp_market_map,
         spot_market_map,
         oracle_map,
-        clock.unix_timestamp,
-        clock.slot,
-        OrderActionExplanation::None,
-        None,
-        0,
-        false,
+        clock,
+        PlaceOrderOptions::default(),
+    )
+}
+
+/// Modifies multiple orders, only checking margin after the last modification
+pub fn modify_orders(
+    orders: &[(u32, ModifyOrderParams)],
+    user_loader: &AccountLoader<User>,
+    state: &State,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+) -> DriftResult {
+    validate!(
+        orders.len() <= MAX_OPEN_ORDERS as usize,
+        ErrorCode::DefaultError,
+        "max {} modify order params",
+        MAX_OPEN_ORDERS
     )?;
 
-    user.update_last_active_slot(clock.slot);
+    let user_key = user_loader.key();
+    let mut user = load_mut!(user_loader)?;
 
-    let order_params =
-        merge_modify_order_params_with_existing_order(&existing_order, &modify_order_params)?;
+    // find every order before modifying, the replacement orders get new order ids
+    let mut orders_to_modify: Vec<(usize, &ModifyOrderParams)> = Vec::with_capacity(orders.len());
+    for (order_id, modify_order_params) in orders.iter() {
+        validate!(
+            orders.iter().filter(|(id, _)| id == order_id).count() == 1,
+            ErrorCode::DefaultError,
+            "order id {} modified more than once",
+            order_id
+        )?;
+
+        match user.get_order_index(*order_id) {
+            Ok(order_index) => orders_to_modify.push((order_index, modify_order_params)),
+            Err(e) => {
+                msg!("Order id {} not found", order_id);
+                if modify_order_params.policy == Some(ModifyOrderPolicy::MustModify) {
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    let num_orders = orders_to_modify.len();
+    for (i, (order_index, modify_order_params)) in orders_to_modify.into_iter().enumerate() {
+        // only enforce margin and try to expire orders on the last modification
+        let options = PlaceOrderOptions {
+            enforce_margin_check: i == num_orders - 1,
+            try_expire_orders: i == num_orders - 1,
+            ..PlaceOrderOptions::default()
+        };
+
+        modify_order_at_index(
+            order_index,
+            modify_order_params,
+            &mut user,
+            user_key,
+            state,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            clock,
+            options,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn modify_order_at_index(
+    order_index: usize,
+    modify_order_params: &ModifyOrderParams,
+    user: &mut User,
+    user_key: Pubkey,
+    state: &State,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+    options: PlaceOrderOptions,
+) -> DriftResult {
+    let existing_order = user.orders[order_index];
+
+    // the replacement order keeps the group, so a bracket entry's exits stay open
+    cancel_single_order(
+        order_index,
+        user,
+        &user_key,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        clock.unix_timestamp,
+        clock.slot,
+        OrderActionExplanation::None,
+        None,
+        0,
+        false,
+    )?;
+
+    user.update_last_active_slot(clock.slot);
+
+    let order_params =
+        merge_modify_order_params_with_existing_order(&existing_order, modify_order_params)?;
 
     if order_params.market_type == MarketType::Perp {
         place_perp_order(
             state,
-            &mut user,
+            user,
             user_key,
             perp_market_map,
             spot_market_map,
             oracle_map,
             clock,
             order_params,
-            PlaceOrderOptions::default(),
+            options,
         )?;
     } else {
         place_spot_order(
             state,
-            &mut user,
+            user,
             user_key,
             perp_market_map,
             spot_market_map,
             oracle_map,
             clock,
             order_params,
-            PlaceOrderOptions::default(),
+            options,
         )?;
     }
 
@@ -830,6 +1278,12 @@ fn merge_modify_order_params_with_existing_order(
     let oracle_price_offset = modify_order_params
         .oracle_price_offset
         .or(Some(existing_order.oracle_price_offset));
+    let trigger_source = existing_order.get_trigger_source()?;
+    let trigger_market_index = if trigger_source == TriggerSource::MarketOracle {
+        existing_order.get_trigger_market_index()?
+    } else {
+        0
+    };
     let (auction_duration, auction_start_price, auction_end_price) =
         if modify_order_params.auction_duration.is_some()
             && modify_order_params.auction_start_price.is_some()
@@ -862,6 +1316,20 @@ fn merge_modify_order_params_with_existing_order(
         auction_duration,
         auction_start_price,
         auction_end_price,
+        bit_flags: existing_order.bit_flags,
+        group_id: existing_order.group_id,
+        twap_slices: existing_order.slices_remaining,
+        // min fill only applies to place_and_take, modified orders are resting
+        min_fill_base_asset_amount: 0,
+        display_base_asset_amount: existing_order
+            .get_iceberg_display_base_asset_amount()
+            .unwrap_or(0),
+        max_slippage_bps: existing_order
+            .get_max_slippage_bps()
+            .map_or(Ok(0), |bps| bps.cast())?,
+        max_slide_ticks: modify_order_params.max_slide_ticks.unwrap_or(0),
+        trigger_source,
+        trigger_market_index,
     })
 }
 
@@ -881,6 +1349,43 @@ pub fn fill_perp_order(
     clock: &Clock,
     fill_mode: FillMode,
 ) -> DriftResult<u64> {
+    let (base_asset_amount, _) = fill_perp_order_and_get_fill_amounts(
+        order_id,
+        state,
+        user,
+        user_stats,
+        spot_market_map,
+        perp_market_map,
+        oracle_map,
+        filler,
+        filler_stats,
+        makers_and_referrer,
+        makers_and_referrer_stats,
+        jit_maker_order_id,
+        clock,
+        fill_mode,
+    )?;
+
+    Ok(base_asset_amount)
+}
+
+/// Fills the order like fill_perp_order, returning both the base and quote asset amount filled
+pub fn fill_perp_order_and_get_fill_amounts(
+    order_id: u32,
+    state: &State,
+    user: &AccountLoader<User>,
+    user_stats: &AccountLoader<UserStats>,
+    spot_market_map: &SpotMarketMap,
+    perp_market_map: &PerpMarketMap,
+    oracle_map: &mut OracleMap,
+    filler: &AccountLoader<User>,
+    filler_stats: &AccountLoader<UserStats>,
+    makers_and_referrer: &UserMap,
+    makers_and_referrer_stats: &UserStatsMap,
+    jit_maker_order_id: Option<u32>,
+    clock: &Clock,
+    fill_mode: FillMode,
+) -> DriftResult<(u64, u64)> {
     let now = clock.unix_timestamp;
     let slot = clock.slot;
 
@@ -944,7 +1449,7 @@ pub fn fill_perp_order(
 
     if user.is_bankrupt() {
         msg!("user is bankrupt");
-        return Ok(0);
+        return Ok((0, 0));
     }
 
     match validate_user_not_being_liquidated(
@@ -957,7 +1462,7 @@ pub fn fill_perp_order(
         Ok(_) => {}
         Err(_) => {
             msg!("user is being liquidated");
-            return Ok(0);
+            return Ok((0, 0));
         }
     }
 
@@ -1051,7 +1556,7 @@ pub fn fill_perp_order(
         if let Some(filler) = filler.as_deref_mut() {
             filler.update_last_active_slot(slot);
         }
-        return Ok(0);
+        return Ok((0, 0));
     }
 
     validate_perp_fill_possible(state, user, order_index, slot, makers_and_referrer.0.len())?;
@@ -1103,9 +1608,35 @@ pub fn fill_perp_order(
             false,
         )?;
 
-        return Ok(0);
+        return Ok((0, 0));
     }
 
+    // in batch auction markets, makers are only matched against an auction once it's over
+    let fill_mode = if matches!(
+        fill_mode,
+        FillMode::Fill | FillMode::PlaceAndMake | FillMode::PlaceAndTake
+    ) && is_batch_auction_order(
+        &user.orders[order_index],
+        &perp_market_map.get_ref(&market_index)?,
+    ) {
+        if !is_auction_complete(
+            user.orders[order_index].slot,
+            user.orders[order_index].auction_duration,
+            slot,
+        )? {
+            msg!("Batch auction for order {} still in progress", order_id);
+            // update filler last active so tx doesn't revert
+            if let Some(filler) = filler.as_deref_mut() {
+                filler.update_last_active_slot(slot);
+            }
+            return Ok((0, 0));
+        }
+
+        FillMode::BatchAuction
+    } else {
+        fill_mode
+    };
+
     let (base_asset_amount, quote_asset_amount) = fulfill_perp_order(
         user,
         order_index,
@@ -1131,6 +1662,32 @@ pub fn fill_perp_order(
         fill_mode,
     )?;
 
+    // maker orders registered for the batch by place_and_make are ioc, what didn't clear is canceled
+    if fill_mode.is_batch_auction() {
+        for (maker_key, maker_order_index, _) in maker_orders_info.iter() {
+            let mut maker = makers_and_referrer.get_ref_mut(maker_key)?;
+            let maker_order = &maker.orders[*maker_order_index];
+            if maker_order.status != OrderStatus::Open || !maker_order.is_jit_maker() {
+                continue;
+            }
+
+            cancel_order(
+                *maker_order_index,
+                &mut maker,
+                maker_key,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                now,
+                slot,
+                OrderActionExplanation::None,
+                Some(&filler_key),
+                0,
+                false,
+            )?;
+        }
+    }
+
     if base_asset_amount != 0 {
         let fill_price =
             calculate_fill_price(quote_asset_amount, base_asset_amount, BASE_PRECISION_U64)?;
@@ -1187,8 +1744,36 @@ pub fn fill_perp_order(
         )?
     }
 
+    if should_cancel_max_slippage_order(&user.orders[order_index], slot)? {
+        let filler_reward = {
+            let mut market = perp_market_map.get_ref_mut(&market_index)?;
+            pay_keeper_flat_reward_for_perps(
+                user,
+                filler.as_deref_mut(),
+                market.deref_mut(),
+                state.perp_fee_structure.flat_filler_fee,
+                slot,
+            )?
+        };
+
+        cancel_order(
+            order_index,
+            user,
+            &user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            OrderActionExplanation::MaxSlippageExceeded,
+            Some(&filler_key),
+            filler_reward,
+            false,
+        )?
+    }
+
     if base_asset_amount == 0 {
-        return Ok(0);
+        return Ok((0, 0));
     }
 
     {
@@ -1226,7 +1811,178 @@ pub fn fill_perp_order(
 
     user.update_last_active_slot(slot);
 
-    Ok(base_asset_amount)
+    Ok((base_asset_amount, quote_asset_amount))
+}
+
+/// Places both legs of a combo order and fills them in the same instruction. Reverts unless both
+/// legs fully fill within the net price limit, checking the taker margin once after both fills.
+/// Spot legs need the fulfillment params of the external market they fill against, and their
+/// size is rounded down to the spot market's step size
+pub fn place_and_take_combo_order(
+    state: &State,
+    user: &AccountLoader<User>,
+    user_stats: &AccountLoader<UserStats>,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    makers_and_referrer: &UserMap,
+    makers_and_referrer_stats: &UserStatsMap,
+    clock: &Clock,
+    params: ComboOrderParams,
+    mut spot_fulfillment_params: Option<&mut dyn SpotFulfillmentParams>,
+) -> DriftResult {
+    let mut legs = params.get_order_params()?;
+    let user_key = user.key();
+
+    if params.second_market_type == MarketType::Spot {
+        let spot_market = spot_market_map.get_ref(&params.second_market_index)?;
+        let spot_leg = &mut legs[1];
+
+        spot_leg.base_asset_amount = params.get_spot_leg_base_asset_amount(&spot_market)?;
+
+        // spot fills price the order at the current slot, so start the auction at its end price
+        // like FillMode::ComboLeg does for perp legs
+        let oracle_price_data = oracle_map.get_price_data(&spot_market.oracle)?;
+        let (_, auction_end_price) =
+            calculate_auction_prices(oracle_price_data, spot_leg.direction, 0)?;
+        spot_leg.auction_start_price = Some(auction_end_price);
+        spot_leg.auction_end_price = Some(auction_end_price);
+    }
+
+    let mut order_ids = [0_u32; 2];
+    let mut position_decreasing = true;
+    {
+        let user = &mut load_mut!(user)?;
+        for (i, leg) in legs.iter().enumerate() {
+            let options = PlaceOrderOptions {
+                enforce_margin_check: false,
+                ..PlaceOrderOptions::default()
+            };
+
+            match leg.market_type {
+                MarketType::Perp => place_perp_order(
+                    state,
+                    user,
+                    user_key,
+                    perp_market_map,
+                    spot_market_map,
+                    oracle_map,
+                    clock,
+                    *leg,
+                    options,
+                )?,
+                MarketType::Spot => place_spot_order(
+                    state,
+                    user,
+                    user_key,
+                    perp_market_map,
+                    spot_market_map,
+                    oracle_map,
+                    clock,
+                    *leg,
+                    options,
+                )?,
+            }
+
+            order_ids[i] = user.get_last_order_id();
+            let order_index = user.get_order_index(order_ids[i])?;
+            // spot legs always use the fill margin requirement
+            position_decreasing &= leg.market_type == MarketType::Perp
+                && determine_if_user_order_is_position_decreasing(
+                    user,
+                    leg.market_index,
+                    order_index,
+                )?;
+        }
+    }
+
+    let mut fill_prices = [0_u64; 2];
+    for (i, order_id) in order_ids.iter().enumerate() {
+        let (base_asset_amount, quote_asset_amount, base_precision) = match legs[i].market_type {
+            MarketType::Perp => {
+                let (base_asset_amount, quote_asset_amount) = fill_perp_order_and_get_fill_amounts(
+                    *order_id,
+                    state,
+                    user,
+                    user_stats,
+                    spot_market_map,
+                    perp_market_map,
+                    oracle_map,
+                    user,
+                    user_stats,
+                    makers_and_referrer,
+                    makers_and_referrer_stats,
+                    None,
+                    clock,
+                    FillMode::ComboLeg,
+                )?;
+
+                (base_asset_amount, quote_asset_amount, BASE_PRECISION_U64)
+            }
+            MarketType::Spot => {
+                let (base_asset_amount, quote_asset_amount) = fill_spot_order_and_get_fill_amounts(
+                    *order_id,
+                    state,
+                    user,
+                    user_stats,
+                    spot_market_map,
+                    perp_market_map,
+                    oracle_map,
+                    user,
+                    user_stats,
+                    None,
+                    None,
+                    None,
+                    clock,
+                    spot_fulfillment_params.as_deref_mut().safe_unwrap()?,
+                    FillMode::ComboLeg,
+                )?;
+
+                let base_precision = spot_market_map
+                    .get_ref(&legs[i].market_index)?
+                    .get_precision();
+
+                (base_asset_amount, quote_asset_amount, base_precision)
+            }
+        };
+
+        // legs are fill or kill, so any fill is a full fill
+        validate!(
+            base_asset_amount > 0,
+            ErrorCode::ComboOrderNotFilled,
+            "combo leg in market {} not filled",
+            legs[i].market_index
+        )?;
+
+        fill_prices[i] =
+            calculate_fill_price(quote_asset_amount, base_asset_amount, base_precision)?;
+    }
+
+    params.validate_net_price(fill_prices[0], fill_prices[1])?;
+
+    let user = load!(user)?;
+    let margin_calculation = calculate_margin_requirement_and_total_collateral_and_liability_info(
+        &user,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        MarginContext::standard(if position_decreasing {
+            MarginRequirementType::Maintenance
+        } else {
+            MarginRequirementType::Fill
+        }),
+    )?;
+
+    if !margin_calculation.meets_margin_requirement() {
+        msg!(
+            "taker breached fill requirements for combo (margin requirement {}) (total_collateral {})",
+            margin_calculation.margin_requirement,
+            margin_calculation.total_collateral
+        );
+        return Err(ErrorCode::InsufficientCollateral);
+    }
+
+    Ok(())
 }
 
 pub fn validate_market_within_price_band(
@@ -1536,7 +2292,7 @@ fn fulfill_perp_order(
         let market = perp_market_map.get_ref(&market_index)?;
         let oracle_price = oracle_map.get_price_data(&market.amm.oracle)?.price;
 
-        determine_perp_fulfillment_methods(
+        let fulfillment_methods = determine_perp_fulfillment_methods(
             &user.orders[user_order_index],
             maker_orders_info,
             &market.amm,
@@ -1546,34 +2302,134 @@ fn fulfill_perp_order(
             amm_is_available,
             slot,
             min_auction_duration,
-        )?
+        )?;
+
+        if market.matching_mode == MatchingMode::ProRata {
+            get_pro_rata_fulfillment_methods(fulfillment_methods, maker_orders_info)?
+        } else {
+            fulfillment_methods
+        }
     };
 
     if fulfillment_methods.is_empty() {
         return Ok((0, 0));
     }
 
+    let (fulfillment_methods, batch_auction_clearing_price) = if fill_mode.is_batch_auction() {
+        get_batch_auction_fulfillment_methods(
+            user,
+            user_order_index,
+            user_stats,
+            fulfillment_methods,
+            maker_orders_info,
+            makers_and_referrer,
+            &perp_market_map.get_ref(&market_index)?,
+            fee_structure,
+            limit_price,
+        )?
+    } else {
+        (fulfillment_methods, None)
+    };
+
     let mut base_asset_amount = 0_u64;
     let mut quote_asset_amount = 0_u64;
     let mut maker_fills: BTreeMap<Pubkey, i64> = BTreeMap::new();
     let maker_direction = user.orders[user_order_index].direction.opposite();
-    for fulfillment_method in fulfillment_methods.iter() {
+    // order can be reset once fully filled, so keep a copy to apply its order group after
+    let user_order_before_fill = user.orders[user_order_index];
+    let mut filled_maker_orders_in_group: Vec<(Pubkey, Order)> = vec![];
+    let mut pro_rata_allocations: BTreeMap<(Pubkey, u16), u64> = BTreeMap::new();
+    for (fulfillment_method_index, fulfillment_method) in fulfillment_methods.iter().enumerate() {
         if user.orders[user_order_index].status != OrderStatus::Open {
             break;
         }
-        let mut market = perp_market_map.get_ref_mut(&market_index)?;
-        let user_order_direction = user.orders[user_order_index].direction;
 
-        let (fill_base_asset_amount, fill_quote_asset_amount) = match fulfillment_method {
-            PerpFulfillmentMethod::AMM(maker_price) => {
-                let (mut referrer, mut referrer_stats) = get_referrer(
-                    &referrer_info,
-                    makers_and_referrer,
-                    makers_and_referrer_stats,
-                    None,
-                )?;
+        let maker_base_asset_amount_allocated = match fulfillment_method {
+            PerpFulfillmentMethod::ProRataMatch(maker_key, maker_order_index, _) => {
+                if !pro_rata_allocations.contains_key(&(*maker_key, *maker_order_index)) {
+                    pro_rata_allocations.extend(get_pro_rata_allocations(
+                        &user.orders[user_order_index],
+                        user.get_perp_position(market_index)?.base_asset_amount,
+                        &fulfillment_methods[fulfillment_method_index..],
+                        perp_market_map.get_ref(&market_index)?.amm.order_step_size,
+                        |maker_key, maker_order_index| {
+                            let maker = makers_and_referrer.get_ref(maker_key)?;
+                            Ok((
+                                maker.orders[maker_order_index as usize],
+                                maker.get_perp_position(market_index)?.base_asset_amount,
+                            ))
+                        },
+                    )?);
+                }
 
-                let (fill_base_asset_amount, fill_quote_asset_amount) =
+                pro_rata_allocations
+                    .get(&(*maker_key, *maker_order_index))
+                    .copied()
+            }
+            _ => None,
+        };
+
+        if let PerpFulfillmentMethod::Match(maker_key, maker_order_index)
+        | PerpFulfillmentMethod::ProRataMatch(maker_key, maker_order_index, _) =
+            fulfillment_method
+        {
+            let mut maker = makers_and_referrer.get_ref_mut(maker_key)?;
+            if apply_self_trade_prevention(
+                user_stats.self_trade_prevention_mode,
+                user,
+                user_order_index,
+                user_key,
+                &mut maker,
+                *maker_order_index as usize,
+                maker_key,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                now,
+                slot,
+                filler_key,
+            )? {
+                continue;
+            }
+        }
+
+        let mut market = perp_market_map.get_ref_mut(&market_index)?;
+        let user_order_direction = user.orders[user_order_index].direction;
+
+        let (fill_base_asset_amount, fill_quote_asset_amount) = match fulfillment_method {
+            PerpFulfillmentMethod::AMM(maker_price) => {
+                let (mut referrer, mut referrer_stats) = get_referrer(
+                    &referrer_info,
+                    makers_and_referrer,
+                    makers_and_referrer_stats,
+                    None,
+                )?;
+
+                // in a batch auction the amm fills up to the clearing price, at the clearing price
+                let (override_base_asset_amount, override_fill_price) =
+                    match batch_auction_clearing_price {
+                        Some(clearing_price) => {
+                            let fee_tier = determine_user_fee_tier(
+                                user_stats,
+                                fee_structure,
+                                &MarketType::Perp,
+                            )?;
+                            let (amm_base_asset_amount, _) =
+                                calculate_base_asset_amount_for_amm_to_fulfill(
+                                    &user.orders[user_order_index],
+                                    &market,
+                                    Some(clearing_price),
+                                    None,
+                                    user.get_perp_position(market_index)?.base_asset_amount,
+                                    fee_tier,
+                                )?;
+
+                            (Some(amm_base_asset_amount), Some(clearing_price))
+                        }
+                        None => (None, *maker_price),
+                    };
+
+                let (fill_base_asset_amount, fill_quote_asset_amount) =
                     fulfill_perp_order_with_amm(
                         user,
                         user_stats,
@@ -1591,14 +2447,15 @@ fn fulfill_perp_order(
                         &mut referrer_stats.as_deref_mut(),
                         fee_structure,
                         limit_price,
-                        None,
-                        *maker_price,
+                        override_base_asset_amount,
+                        override_fill_price,
                         AMMLiquiditySplit::Shared,
                     )?;
 
                 (fill_base_asset_amount, fill_quote_asset_amount)
             }
-            PerpFulfillmentMethod::Match(maker_key, maker_order_index) => {
+            PerpFulfillmentMethod::Match(maker_key, maker_order_index)
+            | PerpFulfillmentMethod::ProRataMatch(maker_key, maker_order_index, _) => {
                 let mut maker = makers_and_referrer.get_ref_mut(maker_key)?;
                 let mut maker_stats = if maker.authority == user.authority {
                     None
@@ -1613,6 +2470,8 @@ fn fulfill_perp_order(
                     Some(&maker),
                 )?;
 
+                let maker_order_before_fill = maker.orders[*maker_order_index as usize];
+
                 let (fill_base_asset_amount, fill_quote_asset_amount, maker_fill_base_asset_amount) =
                     fulfill_perp_order_with_match(
                         market.deref_mut(),
@@ -1636,6 +2495,8 @@ fn fulfill_perp_order(
                         slot,
                         fee_structure,
                         oracle_map,
+                        maker_base_asset_amount_allocated,
+                        batch_auction_clearing_price,
                     )?;
 
                 if maker_fill_base_asset_amount != 0 {
@@ -1645,6 +2506,10 @@ fn fulfill_perp_order(
                         maker_direction,
                         maker_fill_base_asset_amount,
                     )?;
+
+                    if maker_order_before_fill.is_in_order_group() {
+                        filled_maker_orders_in_group.push((*maker_key, maker_order_before_fill));
+                    }
                 }
 
                 (fill_base_asset_amount, fill_quote_asset_amount)
@@ -1666,6 +2531,8 @@ fn fulfill_perp_order(
         quote_asset_amount
     )?;
 
+    validate_fill_or_kill(&user_order_before_fill, base_asset_amount)?;
+
     let total_maker_fill = maker_fills.values().sum::<i64>();
 
     validate!(
@@ -1676,26 +2543,74 @@ fn fulfill_perp_order(
         base_asset_amount
     )?;
 
-    let taker_margin_calculation =
-        calculate_margin_requirement_and_total_collateral_and_liability_info(
+    if base_asset_amount != 0 {
+        cancel_order_group_siblings(
+            &user_order_before_fill,
             user,
+            user_key,
             perp_market_map,
             spot_market_map,
             oracle_map,
-            MarginContext::standard(if user_order_position_decreasing {
-                MarginRequirementType::Maintenance
-            } else {
-                MarginRequirementType::Fill
-            }),
+            now,
+            slot,
+            Some(filler_key),
         )?;
 
-    if !taker_margin_calculation.meets_margin_requirement() {
-        msg!(
-            "taker breached fill requirements (margin requirement {}) (total_collateral {})",
-            taker_margin_calculation.margin_requirement,
-            taker_margin_calculation.total_collateral
-        );
-        return Err(ErrorCode::InsufficientCollateral);
+        user.update_bracket_exits_for_entry_fill(&user_order_before_fill);
+
+        update_twap_order_for_slice_fill(
+            &user_order_before_fill,
+            user,
+            user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            base_asset_amount,
+            quote_asset_amount,
+        )?;
+    }
+
+    for (maker_key, maker_order) in filled_maker_orders_in_group.iter() {
+        let mut maker = makers_and_referrer.get_ref_mut(maker_key)?;
+        cancel_order_group_siblings(
+            maker_order,
+            &mut maker,
+            maker_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            Some(filler_key),
+        )?;
+
+        maker.update_bracket_exits_for_entry_fill(maker_order);
+    }
+
+    if !fill_mode.is_combo_leg() {
+        let taker_margin_calculation =
+            calculate_margin_requirement_and_total_collateral_and_liability_info(
+                user,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                MarginContext::standard(if user_order_position_decreasing {
+                    MarginRequirementType::Maintenance
+                } else {
+                    MarginRequirementType::Fill
+                }),
+            )?;
+
+        if !taker_margin_calculation.meets_margin_requirement() {
+            msg!(
+                "taker breached fill requirements (margin requirement {}) (total_collateral {})",
+                taker_margin_calculation.margin_requirement,
+                taker_margin_calculation.total_collateral
+            );
+            return Err(ErrorCode::InsufficientCollateral);
+        }
     }
 
     for (maker_key, maker_base_asset_amount_filled) in maker_fills {
@@ -1730,6 +2645,126 @@ fn fulfill_perp_order(
     Ok((base_asset_amount, quote_asset_amount))
 }
 
+/// Clears the makers resting against a finished batch auction at one uniform price. Makers
+/// priced worse than the clearing price are dropped and the amm, if available, fills last
+#[allow(clippy::too_many_arguments)]
+fn get_batch_auction_fulfillment_methods(
+    taker: &User,
+    taker_order_index: usize,
+    taker_stats: &UserStats,
+    fulfillment_methods: Vec<PerpFulfillmentMethod>,
+    maker_orders_info: &[(Pubkey, usize, u64)],
+    makers_and_referrer: &UserMap,
+    market: &PerpMarket,
+    fee_structure: &FeeStructure,
+    limit_price: Option<u64>,
+) -> DriftResult<(Vec<PerpFulfillmentMethod>, Option<u64>)> {
+    let amm_is_available = fulfillment_methods
+        .iter()
+        .any(|fulfillment_method| matches!(fulfillment_method, PerpFulfillmentMethod::AMM(_)));
+
+    let mut maker_fulfillment_methods = vec![];
+    let mut maker_levels = vec![];
+    for fulfillment_method in fulfillment_methods.iter() {
+        let (maker_key, maker_order_index) = match fulfillment_method {
+            PerpFulfillmentMethod::Match(maker_key, maker_order_index)
+            | PerpFulfillmentMethod::ProRataMatch(maker_key, maker_order_index, _) => {
+                (*maker_key, *maker_order_index)
+            }
+            PerpFulfillmentMethod::AMM(_) => continue,
+        };
+
+        let maker_price = maker_orders_info
+            .iter()
+            .find(|(key, order_index, _)| {
+                *key == maker_key && *order_index == maker_order_index as usize
+            })
+            .map(|(_, _, price)| *price)
+            .safe_unwrap()?;
+
+        let maker = makers_and_referrer.get_ref(&maker_key)?;
+        let maker_base_asset_amount = maker.orders[maker_order_index as usize]
+            .get_visible_base_asset_amount_unfilled(Some(
+                maker
+                    .get_perp_position(market.market_index)?
+                    .base_asset_amount,
+            ))?;
+
+        maker_fulfillment_methods.push((maker_price, *fulfillment_method));
+        maker_levels.push((maker_price, maker_base_asset_amount));
+    }
+
+    // without makers there is nothing to batch, the amm fills as usual
+    if maker_levels.is_empty() {
+        return Ok((fulfillment_methods, None));
+    }
+
+    let taker_order = &taker.orders[taker_order_index];
+    let taker_existing_position = taker
+        .get_perp_position(market.market_index)?
+        .base_asset_amount;
+    let taker_base_asset_amount =
+        taker_order.get_base_asset_amount_unfilled(Some(taker_existing_position))?;
+    let fee_tier = determine_user_fee_tier(taker_stats, fee_structure, &MarketType::Perp)?;
+
+    let amm_price_range = match (amm_is_available, limit_price) {
+        (true, Some(limit_price)) => {
+            let (amm_bid_price, amm_ask_price) =
+                market.amm.bid_ask_price(market.amm.reserve_price()?)?;
+            let amm_best_price = match taker_order.direction {
+                PositionDirection::Long => amm_ask_price,
+                PositionDirection::Short => amm_bid_price,
+            };
+            Some((amm_best_price, limit_price))
+        }
+        _ => None,
+    };
+
+    let clearing_price = calculate_batch_auction_clearing_price(
+        taker_order.direction,
+        taker_base_asset_amount,
+        &maker_levels,
+        amm_price_range,
+        market.amm.order_tick_size,
+        |price| {
+            calculate_base_asset_amount_for_amm_to_fulfill(
+                taker_order,
+                market,
+                Some(price),
+                None,
+                taker_existing_position,
+                fee_tier,
+            )
+            .map(|(base_asset_amount, _)| base_asset_amount)
+        },
+    )?;
+
+    let clearing_price = match clearing_price {
+        Some(clearing_price) => clearing_price,
+        None => return Ok((fulfillment_methods, None)),
+    };
+
+    msg!("Batch auction clearing price {}", clearing_price);
+
+    let mut batch_fulfillment_methods: Vec<PerpFulfillmentMethod> = maker_fulfillment_methods
+        .into_iter()
+        .filter(|(maker_price, _)| {
+            do_orders_cross(
+                taker_order.direction.opposite(),
+                *maker_price,
+                clearing_price,
+            )
+        })
+        .map(|(_, fulfillment_method)| fulfillment_method)
+        .collect();
+
+    if amm_is_available {
+        batch_fulfillment_methods.push(PerpFulfillmentMethod::AMM(None));
+    }
+
+    Ok((batch_fulfillment_methods, Some(clearing_price)))
+}
+
 #[allow(clippy::type_complexity)]
 fn get_referrer<'a>(
     referrer_info: &'a Option<(Pubkey, Pubkey)>,
@@ -1791,79 +2826,56 @@ fn determine_if_user_order_is_position_decreasing(
     )
 }
 
-pub fn fulfill_perp_order_with_amm(
-    user: &mut User,
-    user_stats: &mut UserStats,
-    order_index: usize,
+pub struct AmmFill {
+    /// precision: BASE_PRECISION
+    pub base_asset_amount: u64,
+    /// precision: QUOTE_PRECISION
+    pub quote_asset_amount: u64,
+    /// precision: QUOTE_PRECISION
+    pub quote_asset_amount_surplus: i64,
+    pub fill_fees: FillFees,
+}
+
+/// Fills the order against the amm, updating only the market, the order and the position it
+/// fills into: the swap, the mark twap, the amm's position and lp bookkeeping, the fee pool and
+/// the user's fee. fulfill_perp_order_with_amm and the fill simulation both go through this;
+/// filler and referrer rewards, stats and records are left to the caller.
+/// Returns None if the amm can't fill any of the order
+pub fn update_market_and_position_for_amm_fill(
     market: &mut PerpMarket,
-    oracle_map: &mut OracleMap,
-    reserve_price_before: u64,
-    now: i64,
-    slot: u64,
-    user_key: &Pubkey,
-    filler_key: &Pubkey,
-    filler: &mut Option<&mut User>,
-    filler_stats: &mut Option<&mut UserStats>,
-    referrer: &mut Option<&mut User>,
-    referrer_stats: &mut Option<&mut UserStats>,
+    order: &mut Order,
+    position: &mut PerpPosition,
+    user_stats: &UserStats,
     fee_structure: &FeeStructure,
+    reserve_price_before: u64,
     limit_price: Option<u64>,
     override_base_asset_amount: Option<u64>,
     override_fill_price: Option<u64>,
     liquidity_split: AMMLiquiditySplit,
-) -> DriftResult<(u64, u64)> {
-    let position_index = get_position_index(&user.perp_positions, market.market_index)?;
-    let existing_base_asset_amount = user.perp_positions[position_index].base_asset_amount;
-
+    reward_filler: bool,
+    reward_referrer: bool,
+    referrer_stats: &Option<&mut UserStats>,
+    now: i64,
+    slot: u64,
+) -> DriftResult<Option<AmmFill>> {
     // Determine the base asset amount the market can fill
-    let (base_asset_amount, limit_price, fill_price) = match override_base_asset_amount {
-        Some(override_base_asset_amount) => {
-            (override_base_asset_amount, limit_price, override_fill_price)
-        }
-        None => {
-            let fee_tier = determine_user_fee_tier(user_stats, fee_structure, &MarketType::Perp)?;
-            let (base_asset_amount, limit_price) = calculate_base_asset_amount_for_amm_to_fulfill(
-                &user.orders[order_index],
-                market,
-                limit_price,
-                override_fill_price,
-                existing_base_asset_amount,
-                fee_tier,
-            )?;
-
-            let fill_price = if user.orders[order_index].post_only {
-                limit_price
-            } else {
-                None
-            };
-
-            (base_asset_amount, limit_price, fill_price)
-        }
-    };
-
-    // if user position is less than min order size, step size is the threshold
-    let amm_size_threshold =
-        if existing_base_asset_amount.unsigned_abs() > market.amm.min_order_size {
-            market.amm.min_order_size
-        } else {
-            market.amm.order_step_size
-        };
+    let (base_asset_amount, limit_price, fill_price) = calculate_amm_fill(
+        order,
+        market,
+        position.base_asset_amount,
+        user_stats,
+        fee_structure,
+        limit_price,
+        override_base_asset_amount,
+        override_fill_price,
+    )?;
 
-    if base_asset_amount < amm_size_threshold {
-        // if is an actual swap (and not amm jit order) then msg!
-        if override_base_asset_amount.is_none() {
-            msg!(
-                "Amm cant fulfill order. market index {} base asset amount {} market.amm.min_order_size {}",
-                market.market_index,
-                base_asset_amount,
-                market.amm.min_order_size
-            );
-        }
-        return Ok((0, 0));
+    if base_asset_amount == 0 {
+        return Ok(None);
     }
 
     let (order_post_only, order_slot, order_direction) =
-        get_struct_values!(user.orders[order_index], post_only, slot, direction);
+        get_struct_values!(order, post_only, slot, direction);
 
     validation::perp_market::validate_amm_account_for_fill(&market.amm, order_direction)?;
 
@@ -1886,8 +2898,7 @@ pub fn fulfill_perp_order_with_amm(
             base_asset_amount,
             order_direction,
             market,
-            user,
-            position_index,
+            position,
             fill_price,
         )?;
 
@@ -1902,18 +2913,7 @@ pub fn fulfill_perp_order_with_amm(
         )?;
     }
 
-    let reward_referrer = can_reward_user_with_perp_pnl(referrer, market.market_index);
-    let reward_filler = can_reward_user_with_perp_pnl(filler, market.market_index);
-
-    let FillFees {
-        user_fee,
-        fee_to_market,
-        filler_reward,
-        referee_discount,
-        referrer_reward,
-        fee_to_market_for_lp,
-        maker_rebate,
-    } = fees::calculate_fee_for_fulfillment_with_amm(
+    let fill_fees = fees::calculate_fee_for_fulfillment_with_amm(
         user_stats,
         quote_asset_amount,
         fee_structure,
@@ -1934,7 +2934,7 @@ pub fn fulfill_perp_order_with_amm(
         update_lp_market_position(
             market,
             &user_position_delta,
-            fee_to_market_for_lp.cast()?,
+            fill_fees.fee_to_market_for_lp.cast()?,
             liquidity_split,
         )?;
     }
@@ -1954,102 +2954,174 @@ pub fn fulfill_perp_order_with_amm(
     }
 
     // Increment the protocol's total fee variables
-    market.amm.total_fee = market.amm.total_fee.safe_add(fee_to_market.cast()?)?;
-    market.amm.total_exchange_fee = market.amm.total_exchange_fee.safe_add(user_fee.cast()?)?;
-    market.amm.total_mm_fee = market
-        .amm
-        .total_mm_fee
-        .safe_add(quote_asset_amount_surplus.cast()?)?;
-    market.amm.total_fee_minus_distributions = market
-        .amm
-        .total_fee_minus_distributions
-        .safe_add(fee_to_market.cast()?)?;
-    market.amm.net_revenue_since_last_funding = market
-        .amm
-        .net_revenue_since_last_funding
-        .safe_add(fee_to_market)?;
-
-    // Increment the user's total fee variables
-    user_stats.increment_total_fees(user_fee)?;
-    user_stats.increment_total_rebate(maker_rebate)?;
-    user_stats.increment_total_referee_discount(referee_discount)?;
-
-    if let (Some(referrer), Some(referrer_stats)) = (referrer.as_mut(), referrer_stats.as_mut()) {
-        if let Ok(referrer_position) = referrer.force_get_perp_position_mut(market.market_index) {
-            if referrer_reward > 0 {
-                update_quote_asset_amount(referrer_position, market, referrer_reward.cast()?)?;
-                referrer_stats.increment_total_referrer_reward(referrer_reward, now)?;
-            }
-        }
-    }
-
-    let position_index = get_position_index(&user.perp_positions, market.market_index)?;
+    update_amm_fee_pool_for_amm_fill(
+        &mut market.amm,
+        fill_fees.user_fee,
+        fill_fees.fee_to_market,
+        quote_asset_amount_surplus,
+    )?;
 
-    if user_fee != 0 {
+    if fill_fees.user_fee != 0 {
         controller::position::update_quote_asset_and_break_even_amount(
-            &mut user.perp_positions[position_index],
+            position,
             market,
-            -user_fee.cast()?,
+            -fill_fees.user_fee.cast()?,
         )?;
     }
 
-    if maker_rebate != 0 {
+    if fill_fees.maker_rebate != 0 {
         controller::position::update_quote_asset_and_break_even_amount(
-            &mut user.perp_positions[position_index],
+            position,
             market,
-            maker_rebate.cast()?,
+            fill_fees.maker_rebate.cast()?,
         )?;
     }
 
-    if order_post_only {
-        user_stats.update_maker_volume_30d(quote_asset_amount, now)?;
-    } else {
-        user_stats.update_taker_volume_30d(quote_asset_amount, now)?;
-    }
-
-    if let Some(filler) = filler.as_mut() {
-        if filler_reward > 0 {
-            let position_index = get_position_index(&filler.perp_positions, market.market_index)
-                .or_else(|_| add_new_position(&mut filler.perp_positions, market.market_index))?;
-
-            controller::position::update_quote_asset_amount(
-                &mut filler.perp_positions[position_index],
-                market,
-                filler_reward.cast()?,
-            )?;
-
-            filler_stats
-                .as_mut()
-                .safe_unwrap()?
-                .update_filler_volume(quote_asset_amount, now)?;
-        }
-        filler.update_last_active_slot(slot);
-    }
+    update_order_after_fill(order, base_asset_amount, quote_asset_amount)?;
 
-    update_order_after_fill(
-        &mut user.orders[order_index],
+    Ok(Some(AmmFill {
         base_asset_amount,
         quote_asset_amount,
-    )?;
-
-    decrease_open_bids_and_asks(
-        &mut user.perp_positions[position_index],
-        &order_direction,
-        base_asset_amount,
-    )?;
-
-    let (taker, taker_order, maker, maker_order) =
-        get_taker_and_maker_for_order_record(user_key, &user.orders[order_index]);
+        quote_asset_amount_surplus,
+        fill_fees,
+    }))
+}
 
-    let fill_record_id = get_then_update_id!(market, next_fill_record_id);
-    let order_action_explanation = match (override_base_asset_amount, override_fill_price) {
-        (Some(_), Some(_)) => liquidity_split.get_order_action_explanation(),
-        _ => OrderActionExplanation::OrderFilledWithAMM,
-    };
-    let order_action_record = get_order_action_record(
-        now,
-        OrderAction::Fill,
-        order_action_explanation,
+pub fn fulfill_perp_order_with_amm(
+    user: &mut User,
+    user_stats: &mut UserStats,
+    order_index: usize,
+    market: &mut PerpMarket,
+    oracle_map: &mut OracleMap,
+    reserve_price_before: u64,
+    now: i64,
+    slot: u64,
+    user_key: &Pubkey,
+    filler_key: &Pubkey,
+    filler: &mut Option<&mut User>,
+    filler_stats: &mut Option<&mut UserStats>,
+    referrer: &mut Option<&mut User>,
+    referrer_stats: &mut Option<&mut UserStats>,
+    fee_structure: &FeeStructure,
+    limit_price: Option<u64>,
+    override_base_asset_amount: Option<u64>,
+    override_fill_price: Option<u64>,
+    liquidity_split: AMMLiquiditySplit,
+) -> DriftResult<(u64, u64)> {
+    let position_index = get_position_index(&user.perp_positions, market.market_index)?;
+    let existing_base_asset_amount = user.perp_positions[position_index].base_asset_amount;
+
+    let reward_referrer = can_reward_user_with_perp_pnl(referrer, market.market_index);
+    let reward_filler = can_reward_user_with_perp_pnl(filler, market.market_index);
+
+    let amm_fill = update_market_and_position_for_amm_fill(
+        market,
+        &mut user.orders[order_index],
+        &mut user.perp_positions[position_index],
+        user_stats,
+        fee_structure,
+        reserve_price_before,
+        limit_price,
+        override_base_asset_amount,
+        override_fill_price,
+        liquidity_split,
+        reward_filler,
+        reward_referrer,
+        referrer_stats,
+        now,
+        slot,
+    )?;
+
+    let AmmFill {
+        base_asset_amount,
+        quote_asset_amount,
+        quote_asset_amount_surplus,
+        fill_fees:
+            FillFees {
+                user_fee,
+                filler_reward,
+                referee_discount,
+                referrer_reward,
+                maker_rebate,
+                ..
+            },
+    } = match amm_fill {
+        Some(amm_fill) => amm_fill,
+        None => {
+            // if is an actual swap (and not amm jit order) then msg!
+            if override_base_asset_amount.is_none() {
+                msg!(
+                    "Amm cant fulfill order. market index {} market.amm.min_order_size {}",
+                    market.market_index,
+                    market.amm.min_order_size
+                );
+            }
+            return Ok((0, 0));
+        }
+    };
+
+    user.update_perp_position_open_count(position_index, existing_base_asset_amount);
+
+    let (order_post_only, order_direction) =
+        get_struct_values!(user.orders[order_index], post_only, direction);
+
+    // Increment the user's total fee variables
+    user_stats.increment_total_fees(user_fee)?;
+    user_stats.increment_total_rebate(maker_rebate)?;
+    user_stats.increment_total_referee_discount(referee_discount)?;
+
+    if let (Some(referrer), Some(referrer_stats)) = (referrer.as_mut(), referrer_stats.as_mut()) {
+        if let Ok(referrer_position) = referrer.force_get_perp_position_mut(market.market_index) {
+            if referrer_reward > 0 {
+                update_quote_asset_amount(referrer_position, market, referrer_reward.cast()?)?;
+                referrer_stats.increment_total_referrer_reward(referrer_reward, now)?;
+            }
+        }
+    }
+
+    if order_post_only {
+        user_stats.update_maker_volume_30d(quote_asset_amount, now)?;
+    } else {
+        user_stats.update_taker_volume_30d(quote_asset_amount, now)?;
+    }
+
+    if let Some(filler) = filler.as_mut() {
+        if filler_reward > 0 {
+            let position_index = get_position_index(&filler.perp_positions, market.market_index)
+                .or_else(|_| add_new_position(&mut filler.perp_positions, market.market_index))?;
+
+            controller::position::update_quote_asset_amount(
+                &mut filler.perp_positions[position_index],
+                market,
+                filler_reward.cast()?,
+            )?;
+
+            filler_stats
+                .as_mut()
+                .safe_unwrap()?
+                .update_filler_volume(quote_asset_amount, now)?;
+        }
+        filler.update_last_active_slot(slot);
+    }
+
+    decrease_open_bids_and_asks(
+        &mut user.perp_positions[position_index],
+        &order_direction,
+        base_asset_amount,
+    )?;
+
+    let (taker, taker_order, maker, maker_order) =
+        get_taker_and_maker_for_order_record(user_key, &user.orders[order_index]);
+
+    let fill_record_id = get_then_update_id!(market, next_fill_record_id);
+    let order_action_explanation = match (override_base_asset_amount, override_fill_price) {
+        (Some(_), Some(_)) => liquidity_split.get_order_action_explanation(),
+        _ => OrderActionExplanation::OrderFilledWithAMM,
+    };
+    let order_action_record = get_order_action_record(
+        now,
+        OrderAction::Fill,
+        order_action_explanation,
         market.market_index,
         Some(*filler_key),
         Some(fill_record_id),
@@ -2084,6 +3156,137 @@ pub fn fulfill_perp_order_with_amm(
     Ok((base_asset_amount, quote_asset_amount))
 }
 
+/// Applies the self trade prevention mode if the maker and taker orders belong to the same authority.
+/// Returns true if the orders were kept from matching
+fn apply_self_trade_prevention(
+    self_trade_prevention_mode: SelfTradePreventionMode,
+    taker: &mut User,
+    taker_order_index: usize,
+    taker_key: &Pubkey,
+    maker: &mut User,
+    maker_order_index: usize,
+    maker_key: &Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+    filler_key: &Pubkey,
+) -> DriftResult<bool> {
+    if self_trade_prevention_mode == SelfTradePreventionMode::None
+        || maker.authority != taker.authority
+        || maker.orders[maker_order_index].status != OrderStatus::Open
+    {
+        return Ok(false);
+    }
+
+    let outcome = calculate_self_trade_prevention_outcome(
+        self_trade_prevention_mode,
+        taker.orders[taker_order_index].get_base_asset_amount_unfilled(None)?,
+        maker.orders[maker_order_index].get_base_asset_amount_unfilled(None)?,
+    );
+
+    if outcome.expire_maker {
+        cancel_order(
+            maker_order_index,
+            maker,
+            maker_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            OrderActionExplanation::SelfTradePrevented,
+            Some(filler_key),
+            0,
+            false,
+        )?;
+    } else if outcome.base_asset_amount_decrement > 0 {
+        decrement_order_base_asset_amount(
+            maker_order_index,
+            maker,
+            outcome.base_asset_amount_decrement,
+        )?;
+    }
+
+    if outcome.expire_taker {
+        cancel_order(
+            taker_order_index,
+            taker,
+            taker_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            OrderActionExplanation::SelfTradePrevented,
+            Some(filler_key),
+            0,
+            false,
+        )?;
+    } else if outcome.base_asset_amount_decrement > 0 {
+        decrement_order_base_asset_amount(
+            taker_order_index,
+            taker,
+            outcome.base_asset_amount_decrement,
+        )?;
+    }
+
+    Ok(true)
+}
+
+/// Removes base asset amount that hasn't been filled from an open order
+fn decrement_order_base_asset_amount(
+    order_index: usize,
+    user: &mut User,
+    base_asset_amount: u64,
+) -> DriftResult {
+    let order = user.orders[order_index];
+
+    validate!(
+        base_asset_amount < order.get_base_asset_amount_unfilled(None)?,
+        ErrorCode::DefaultError,
+        "cant decrement order {} by {}",
+        order.order_id,
+        base_asset_amount
+    )?;
+
+    user.orders[order_index].base_asset_amount =
+        order.base_asset_amount.safe_sub(base_asset_amount)?;
+
+    msg!(
+        "order {} decremented by {}",
+        order.order_id,
+        base_asset_amount
+    );
+
+    // only decrease open/bids ask if it's not a trigger order or if it's been triggered
+    if order.must_be_triggered() && !order.triggered() {
+        return Ok(());
+    }
+
+    match order.market_type {
+        MarketType::Perp => {
+            let position_index = get_position_index(&user.perp_positions, order.market_index)?;
+            position::decrease_open_bids_and_asks(
+                &mut user.perp_positions[position_index],
+                &order.direction,
+                base_asset_amount,
+            )?;
+        }
+        MarketType::Spot => {
+            let spot_position_index = user.get_spot_position_index(order.market_index)?;
+            decrease_spot_open_bids_and_asks(
+                &mut user.spot_positions[spot_position_index],
+                &order.direction,
+                base_asset_amount,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
 pub fn fulfill_perp_order_with_match(
     market: &mut PerpMarket,
     taker: &mut User,
@@ -2106,6 +3309,8 @@ pub fn fulfill_perp_order_with_match(
     slot: u64,
     fee_structure: &FeeStructure,
     oracle_map: &mut OracleMap,
+    maker_max_base_asset_amount: Option<u64>,
+    override_fill_price: Option<u64>,
 ) -> DriftResult<(u64, u64, u64)> {
     if !are_orders_same_market_but_different_sides(
         &maker.orders[maker_order_index],
@@ -2117,18 +3322,13 @@ pub fn fulfill_perp_order_with_match(
     let oracle_price = oracle_map.get_price_data(&market.amm.oracle)?.price;
     let taker_direction: PositionDirection = taker.orders[taker_order_index].direction;
 
-    let taker_price = if let Some(taker_limit_price) = taker_limit_price {
-        taker_limit_price
-    } else {
-        let amm_available_liquidity =
-            calculate_amm_available_liquidity(&market.amm, &taker_direction)?;
-        market.amm.get_fallback_price(
-            &taker_direction,
-            amm_available_liquidity,
-            oracle_price,
-            taker.orders[taker_order_index].seconds_til_expiry(now),
-        )?
-    };
+    let taker_price = calculate_taker_price_for_match(
+        &market.amm,
+        &taker.orders[taker_order_index],
+        taker_limit_price,
+        oracle_price,
+        now,
+    )?;
 
     let taker_existing_position = taker
         .get_perp_position(market.market_index)?
@@ -2146,8 +3346,13 @@ pub fn fulfill_perp_order_with_match(
     let maker_existing_position = maker
         .get_perp_position(market.market_index)?
         .base_asset_amount;
-    let maker_base_asset_amount = maker.orders[maker_order_index]
-        .get_base_asset_amount_unfilled(Some(maker_existing_position))?;
+    let mut maker_base_asset_amount = maker.orders[maker_order_index]
+        .get_visible_base_asset_amount_unfilled(Some(maker_existing_position))?;
+
+    // pro-rata matching caps the maker at its share of the price level
+    if let Some(maker_max_base_asset_amount) = maker_max_base_asset_amount {
+        maker_base_asset_amount = maker_base_asset_amount.min(maker_max_base_asset_amount);
+    }
 
     let orders_cross = do_orders_cross(maker_direction, maker_price, taker_price);
 
@@ -2160,9 +3365,12 @@ pub fn fulfill_perp_order_with_match(
         return Ok((0_u64, 0_u64, 0_u64));
     }
 
+    // batch auctions fill every maker at the clearing price rather than its own
+    let fill_price = override_fill_price.unwrap_or(maker_price);
+
     let (base_asset_amount, _) = calculate_fill_for_matched_orders(
         maker_base_asset_amount,
-        maker_price,
+        fill_price,
         taker_base_asset_amount,
         PERP_DECIMALS,
         maker_direction,
@@ -2176,7 +3384,7 @@ pub fn fulfill_perp_order_with_match(
     amm::update_mark_twap_from_estimates(
         &mut market.amm,
         now,
-        Some(maker_price),
+        Some(fill_price),
         Some(taker_direction),
         sanitize_clamp_denominator,
     )?;
@@ -2187,7 +3395,7 @@ pub fn fulfill_perp_order_with_match(
     let (jit_base_asset_amount, amm_liquidity_split) = calculate_amm_jit_liquidity(
         market,
         taker_direction,
-        maker_price,
+        fill_price,
         valid_oracle_price,
         base_asset_amount,
         taker_base_asset_amount,
@@ -2195,7 +3403,12 @@ pub fn fulfill_perp_order_with_match(
         taker.orders[taker_order_index].has_limit_price(slot)?,
     )?;
 
-    if jit_base_asset_amount > 0 {
+    // the amm already took part in the batch auction clearing, and inside a pro-rata price level
+    // it would take taker size the makers' shares were computed against
+    if jit_base_asset_amount > 0
+        && override_fill_price.is_none()
+        && maker_max_base_asset_amount.is_none()
+    {
         let (base_asset_amount_filled_by_amm, quote_asset_amount_filled_by_amm) =
             fulfill_perp_order_with_amm(
                 taker,
@@ -2215,7 +3428,7 @@ pub fn fulfill_perp_order_with_match(
                 fee_structure,
                 taker_limit_price,
                 Some(jit_base_asset_amount),
-                Some(maker_price), // match the makers price
+                Some(fill_price), // match the makers price
                 amm_liquidity_split,
             )?;
 
@@ -2233,7 +3446,7 @@ pub fn fulfill_perp_order_with_match(
     let (base_asset_amount_fulfilled_by_maker, quote_asset_amount) =
         calculate_fill_for_matched_orders(
             maker_base_asset_amount,
-            maker_price,
+            fill_price,
             taker_base_asset_amount,
             PERP_DECIMALS,
             maker_direction,
@@ -2272,11 +3485,7 @@ pub fn fulfill_perp_order_with_match(
         maker.orders[maker_order_index].direction,
     )?;
 
-    update_position_and_market(
-        &mut maker.perp_positions[maker_position_index],
-        market,
-        &maker_position_delta,
-    )?;
+    update_user_position_and_market(maker, maker_position_index, market, &maker_position_delta)?;
 
     // if maker is none, makes maker and taker authority was the same
     if let Some(maker_stats) = maker_stats {
@@ -2296,11 +3505,7 @@ pub fn fulfill_perp_order_with_match(
         taker.orders[taker_order_index].direction,
     )?;
 
-    update_position_and_market(
-        &mut taker.perp_positions[taker_position_index],
-        market,
-        &taker_position_delta,
-    )?;
+    update_user_position_and_market(taker, taker_position_index, market, &taker_position_delta)?;
 
     taker_stats.update_taker_volume_30d(quote_asset_amount, now)?;
 
@@ -2308,7 +3513,7 @@ pub fn fulfill_perp_order_with_match(
     let reward_filler = can_reward_user_with_perp_pnl(filler, market.market_index);
 
     let filler_multiplier = if reward_filler {
-        calculate_filler_multiplier_for_matched_orders(maker_price, maker_direction, oracle_price)?
+        calculate_filler_multiplier_for_matched_orders(fill_price, maker_direction, oracle_price)?
     } else {
         0
     };
@@ -2336,19 +3541,7 @@ pub fn fulfill_perp_order_with_match(
     )?;
 
     // Increment the markets house's total fee variables
-    market.amm.total_fee = market.amm.total_fee.safe_add(fee_to_market.cast()?)?;
-    market.amm.total_exchange_fee = market
-        .amm
-        .total_exchange_fee
-        .safe_add(fee_to_market.cast()?)?;
-    market.amm.total_fee_minus_distributions = market
-        .amm
-        .total_fee_minus_distributions
-        .safe_add(fee_to_market.cast()?)?;
-    market.amm.net_revenue_since_last_funding = market
-        .amm
-        .net_revenue_since_last_funding
-        .safe_add(fee_to_market)?;
+    update_amm_fee_pool_for_match_fill(&mut market.amm, fee_to_market)?;
 
     controller::position::update_quote_asset_and_break_even_amount(
         &mut taker.perp_positions[taker_position_index],
@@ -2549,6 +3742,12 @@ pub fn trigger_order(
         "Order is already triggered"
     )?;
 
+    validate!(
+        !user.is_bracket_exit_pending(&user.orders[order_index]),
+        ErrorCode::BracketExitPending,
+        "Bracket entry must fill before exit can be triggered"
+    )?;
+
     validate!(
         market_type == MarketType::Perp,
         ErrorCode::InvalidOrderMarketType,
@@ -2566,14 +3765,14 @@ pub fn trigger_order(
     validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
 
     let mut perp_market = perp_market_map.get_ref_mut(&market_index)?;
-    let oracle_price_data = &oracle_map.get_price_data(&perp_market.amm.oracle)?;
+    let oracle_price_data = *oracle_map.get_price_data(&perp_market.amm.oracle)?;
 
     let oracle_validity = oracle::oracle_validity(
         perp_market
             .amm
             .historical_oracle_data
             .last_oracle_price_twap,
-        oracle_price_data,
+        &oracle_price_data,
         &state.oracle_guard_rails.validity,
     )?;
     let is_oracle_valid =
@@ -2583,9 +3782,45 @@ pub fn trigger_order(
 
     let oracle_price = oracle_price_data.price;
 
-    let can_trigger = order_satisfies_trigger_condition(
+    if let Some((trigger_price, trigger_condition)) = calculate_trailing_trigger_update(
         &user.orders[order_index],
         oracle_price.unsigned_abs().cast()?,
+        perp_market.amm.order_tick_size,
+    )? {
+        let mut filler = if user_key != filler_key {
+            Some(load_mut!(filler)?)
+        } else {
+            None
+        };
+
+        let filler_reward = pay_keeper_flat_reward_for_perps(
+            user,
+            filler.as_deref_mut(),
+            &mut perp_market,
+            state.perp_fee_structure.flat_filler_fee,
+            slot,
+        )?;
+
+        return update_trailing_trigger_price(
+            user,
+            &user_key,
+            order_index,
+            &filler_key,
+            filler_reward,
+            trigger_price,
+            trigger_condition,
+            oracle_price,
+            now,
+        );
+    }
+
+    let can_trigger = order_satisfies_trigger_source_condition(
+        &user.orders[order_index],
+        &perp_market,
+        &oracle_price_data,
+        state,
+        perp_market_map,
+        oracle_map,
     )?;
     validate!(can_trigger, ErrorCode::OrderDidNotSatisfyTriggerCondition)?;
 
@@ -2596,7 +3831,7 @@ pub fn trigger_order(
     {
         update_trigger_order_params(
             &mut user.orders[order_index],
-            oracle_price_data,
+            &oracle_price_data,
             slot,
             30,
             Some(&perp_market),
@@ -2683,13 +3918,594 @@ pub fn trigger_order(
         }
     }
 
-    user.update_last_active_slot(slot);
-
-    Ok(())
-}
-
-fn update_trigger_order_params(
-    order: &mut Order,
+    if user.orders[order_index].status == OrderStatus::Open {
+        let order = user.orders[order_index];
+        cancel_order_group_siblings(
+            &order,
+            user,
+            &user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            Some(&filler_key),
+        )?;
+    }
+
+    user.update_last_active_slot(slot);
+
+    Ok(())
+}
+
+fn order_satisfies_trigger_source_condition(
+    order: &Order,
+    perp_market: &PerpMarket,
+    oracle_price_data: &OraclePriceData,
+    state: &State,
+    perp_market_map: &PerpMarketMap,
+    oracle_map: &mut OracleMap,
+) -> DriftResult<bool> {
+    match order.get_trigger_source()? {
+        TriggerSource::Oracle => {
+            order_satisfies_trigger_condition(order, oracle_price_data.price.unsigned_abs())
+        }
+        TriggerSource::MarketOracle => {
+            let trigger_market = perp_market_map.get_ref(&order.get_trigger_market_index()?)?;
+            let trigger_oracle_price_data =
+                oracle_map.get_price_data(&trigger_market.amm.oracle)?;
+
+            let trigger_oracle_validity = oracle::oracle_validity(
+                trigger_market
+                    .amm
+                    .historical_oracle_data
+                    .last_oracle_price_twap,
+                trigger_oracle_price_data,
+                &state.oracle_guard_rails.validity,
+            )?;
+            validate!(
+                is_oracle_valid_for_action(
+                    trigger_oracle_validity,
+                    Some(DriftAction::TriggerOrder)
+                )?,
+                ErrorCode::InvalidOracle,
+                "trigger market {} oracle invalid",
+                trigger_market.market_index
+            )?;
+
+            order_satisfies_trigger_condition(order, trigger_oracle_price_data.price.unsigned_abs())
+        }
+        TriggerSource::FundingRate => {
+            order_satisfies_signed_trigger_condition(order, perp_market.amm.last_funding_rate)
+        }
+        TriggerSource::MarkOracleDivergence => {
+            let mark_oracle_divergence = amm::calculate_oracle_reserve_price_spread_pct(
+                &perp_market.amm,
+                oracle_price_data,
+                None,
+            )?;
+
+            order_satisfies_signed_trigger_condition(order, mark_oracle_divergence)
+        }
+    }
+}
+
+pub fn trigger_perp_position_take_profit_or_stop_loss(
+    state: &State,
+    user: &AccountLoader<User>,
+    take_profit_stop_loss: &mut PerpPositionTakeProfitStopLoss,
+    spot_market_map: &SpotMarketMap,
+    perp_market_map: &PerpMarketMap,
+    oracle_map: &mut OracleMap,
+    filler: &AccountLoader<User>,
+    clock: &Clock,
+) -> DriftResult {
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+    let market_index = take_profit_stop_loss.market_index;
+
+    let filler_key = filler.key();
+    let user_key = user.key();
+    let user = &mut load_mut!(user)?;
+
+    // the position the prices were set for was closed or flipped since
+    if !take_profit_stop_loss.is_for_position(user) {
+        msg!(
+            "perp market={} no longer has the {:?} position take profit / stop loss was set for",
+            market_index,
+            take_profit_stop_loss.position_direction
+        );
+        take_profit_stop_loss.reset();
+        return Ok(());
+    }
+
+    validate_user_not_being_liquidated(
+        user,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        state.liquidation_margin_buffer_ratio,
+    )?;
+
+    validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
+
+    let (oracle_price, explanation, params) = {
+        let perp_market = perp_market_map.get_ref(&market_index)?;
+        let oracle_price_data = oracle_map.get_price_data(&perp_market.amm.oracle)?;
+
+        let oracle_validity = oracle::oracle_validity(
+            perp_market
+                .amm
+                .historical_oracle_data
+                .last_oracle_price_twap,
+            oracle_price_data,
+            &state.oracle_guard_rails.validity,
+        )?;
+        let is_oracle_valid =
+            is_oracle_valid_for_action(oracle_validity, Some(DriftAction::TriggerOrder))?;
+
+        validate!(is_oracle_valid, ErrorCode::InvalidOracle)?;
+
+        let oracle_price = oracle_price_data.price;
+
+        let explanation =
+            match take_profit_stop_loss.get_triggered(user, oracle_price.unsigned_abs()) {
+                Some(explanation) => explanation,
+                None => {
+                    msg!(
+                        "oracle price {} does not satisfy take profit {} or stop loss {}",
+                        oracle_price,
+                        take_profit_stop_loss.take_profit_price,
+                        take_profit_stop_loss.stop_loss_price
+                    );
+                    return Err(ErrorCode::OrderDidNotSatisfyTriggerCondition);
+                }
+            };
+
+        // closes the whole position at the time of the trigger
+        let position = user.get_perp_position(market_index)?;
+        let params = OrderParams {
+            order_type: OrderType::Market,
+            market_type: MarketType::Perp,
+            direction: position.get_direction_to_close(),
+            base_asset_amount: position.base_asset_amount.unsigned_abs(),
+            market_index,
+            reduce_only: true,
+            ..OrderParams::default()
+        };
+
+        (oracle_price, explanation, params)
+    };
+
+    // take profit / stop loss is consumed once the closing order is placed
+    take_profit_stop_loss.reset();
+
+    let order_id = user.next_order_id;
+
+    // reduce only order can't increase risk so the margin check is skipped
+    place_perp_order(
+        state,
+        user,
+        user_key,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        clock,
+        params,
+        PlaceOrderOptions {
+            enforce_margin_check: false,
+            ..PlaceOrderOptions::default()
+        }
+        .explanation(explanation),
+    )?;
+
+    let order_index = user
+        .orders
+        .iter()
+        .position(|order| order.order_id == order_id && order.status == OrderStatus::Open)
+        .ok_or_else(print_error!(ErrorCode::OrderDoesNotExist))?;
+
+    let is_filler_taker = user_key == filler_key;
+    let mut filler = if !is_filler_taker {
+        Some(load_mut!(filler)?)
+    } else {
+        None
+    };
+
+    let mut perp_market = perp_market_map.get_ref_mut(&market_index)?;
+
+    let filler_reward = pay_keeper_flat_reward_for_perps(
+        user,
+        filler.as_deref_mut(),
+        &mut perp_market,
+        state.perp_fee_structure.flat_filler_fee,
+        slot,
+    )?;
+
+    let order_action_record = get_order_action_record(
+        now,
+        OrderAction::Trigger,
+        explanation,
+        market_index,
+        Some(filler_key),
+        None,
+        Some(filler_reward),
+        None,
+        None,
+        Some(filler_reward),
+        None,
+        None,
+        None,
+        None,
+        Some(user_key),
+        Some(user.orders[order_index]),
+        None,
+        None,
+        oracle_price,
+    )?;
+    emit!(order_action_record);
+
+    user.update_last_active_slot(slot);
+
+    Ok(())
+}
+
+pub fn release_twap_order_slice(
+    order_id: u32,
+    state: &State,
+    user: &AccountLoader<User>,
+    spot_market_map: &SpotMarketMap,
+    perp_market_map: &PerpMarketMap,
+    oracle_map: &mut OracleMap,
+    filler: &AccountLoader<User>,
+    clock: &Clock,
+) -> DriftResult {
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+
+    let filler_key = filler.key();
+    let user_key = user.key();
+    let user = &mut load_mut!(user)?;
+
+    let order_index = user
+        .orders
+        .iter()
+        .position(|order| order.order_id == order_id)
+        .ok_or_else(print_error!(ErrorCode::OrderDoesNotExist))?;
+
+    let twap_order = user.orders[order_index];
+
+    validate!(
+        twap_order.status == OrderStatus::Open,
+        ErrorCode::OrderNotOpen,
+        "Order not open"
+    )?;
+
+    validate!(
+        twap_order.order_type == OrderType::Twap,
+        ErrorCode::OrderNotTriggerable,
+        "Order is not a twap order"
+    )?;
+
+    validate!(
+        twap_order.slices_remaining > 0,
+        ErrorCode::OrderNotTriggerable,
+        "Twap order has no slices remaining"
+    )?;
+
+    validate!(
+        now >= twap_order.get_twap_next_release_ts()? && now <= twap_order.max_ts,
+        ErrorCode::OrderDidNotSatisfyTriggerCondition,
+        "Next twap slice can be released from {} until {}, now {}",
+        twap_order.get_twap_next_release_ts()?,
+        twap_order.max_ts,
+        now
+    )?;
+
+    validate_user_not_being_liquidated(
+        user,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        state.liquidation_margin_buffer_ratio,
+    )?;
+
+    validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
+
+    // the unfilled part of the previous slice is spread over the remaining slices
+    for slice_index in 0..user.orders.len() {
+        let slice = &user.orders[slice_index];
+        if slice.status != OrderStatus::Open
+            || slice.group_id != twap_order.group_id
+            || !slice.is_bit_flag_set(OrderBitFlag::TwapSlice)
+        {
+            continue;
+        }
+
+        cancel_single_order(
+            slice_index,
+            user,
+            &user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            OrderActionExplanation::TwapSliceRolledOver,
+            Some(&filler_key),
+            0,
+            false,
+        )?;
+    }
+
+    let order_step_size = match twap_order.market_type {
+        MarketType::Perp => {
+            perp_market_map
+                .get_ref(&twap_order.market_index)?
+                .amm
+                .order_step_size
+        }
+        MarketType::Spot => {
+            spot_market_map
+                .get_ref(&twap_order.market_index)?
+                .order_step_size
+        }
+    };
+
+    let base_asset_amount_unfilled = twap_order.get_base_asset_amount_unfilled(None)?;
+    let slice_base_asset_amount = if twap_order.slices_remaining == 1 {
+        base_asset_amount_unfilled
+    } else {
+        standardize_base_asset_amount(
+            base_asset_amount_unfilled.safe_div(twap_order.slices_remaining.cast()?)?,
+            order_step_size,
+        )?
+        .max(order_step_size)
+        .min(base_asset_amount_unfilled)
+    };
+
+    let params = OrderParams {
+        order_type: OrderType::Market,
+        market_type: twap_order.market_type,
+        direction: twap_order.direction,
+        base_asset_amount: slice_base_asset_amount,
+        price: twap_order.price,
+        market_index: twap_order.market_index,
+        reduce_only: twap_order.reduce_only,
+        bit_flags: OrderBitFlag::TwapSlice as u8,
+        group_id: twap_order.group_id,
+        ..OrderParams::default()
+    };
+    let options =
+        PlaceOrderOptions::default().explanation(OrderActionExplanation::TwapSliceReleased);
+
+    match twap_order.market_type {
+        MarketType::Perp => place_perp_order(
+            state,
+            user,
+            user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            clock,
+            params,
+            options,
+        )?,
+        MarketType::Spot => place_spot_order(
+            state,
+            user,
+            user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            clock,
+            params,
+            options,
+        )?,
+    }
+
+    // the rest of the window is split evenly between the remaining slices
+    {
+        let twap_order = &mut user.orders[order_index];
+        let next_release_ts = now.safe_add(
+            twap_order
+                .max_ts
+                .safe_sub(now)?
+                .safe_div(twap_order.slices_remaining.cast()?)?,
+        )?;
+        twap_order.trigger_price = next_release_ts.cast()?;
+        twap_order.slices_remaining -= 1;
+    }
+
+    let is_filler_taker = user_key == filler_key;
+    let mut filler = if !is_filler_taker {
+        Some(load_mut!(filler)?)
+    } else {
+        None
+    };
+
+    let (filler_reward, oracle_price) = match twap_order.market_type {
+        MarketType::Perp => {
+            let mut perp_market = perp_market_map.get_ref_mut(&twap_order.market_index)?;
+            let oracle_price = oracle_map.get_price_data(&perp_market.amm.oracle)?.price;
+            let filler_reward = pay_keeper_flat_reward_for_perps(
+                user,
+                filler.as_deref_mut(),
+                &mut perp_market,
+                state.perp_fee_structure.flat_filler_fee,
+                slot,
+            )?;
+            (filler_reward, oracle_price)
+        }
+        MarketType::Spot => {
+            let oracle_price = oracle_map
+                .get_price_data(&spot_market_map.get_ref(&twap_order.market_index)?.oracle)?
+                .price;
+            let filler_reward = pay_keeper_flat_reward_for_spot(
+                user,
+                filler.as_deref_mut(),
+                &mut spot_market_map.get_quote_spot_market_mut()?,
+                state.spot_fee_structure.flat_filler_fee,
+                slot,
+            )?;
+            (filler_reward, oracle_price)
+        }
+    };
+
+    let order_action_record = get_order_action_record(
+        now,
+        OrderAction::Trigger,
+        OrderActionExplanation::TwapSliceReleased,
+        twap_order.market_index,
+        Some(filler_key),
+        None,
+        Some(filler_reward),
+        None,
+        None,
+        Some(filler_reward),
+        None,
+        None,
+        None,
+        None,
+        Some(user_key),
+        Some(user.orders[order_index]),
+        None,
+        None,
+        oracle_price,
+    )?;
+    emit!(order_action_record);
+
+    user.update_last_active_slot(slot);
+
+    Ok(())
+}
+
+/// Trigger orders keep a trigger source other than their oracle in the auction params until
+/// triggered. Only oracle prices are standardized to the tick size
+fn init_trigger_source(
+    order: &mut Order,
+    trigger_source: TriggerSource,
+    trigger_market_index: u16,
+    trigger_price: u64,
+) -> DriftResult {
+    validate!(
+        matches!(
+            order.order_type,
+            OrderType::TriggerMarket | OrderType::TriggerLimit
+        ),
+        ErrorCode::InvalidOrderTrigger,
+        "trigger source only supported for trigger market and trigger limit orders"
+    )?;
+
+    if trigger_source == TriggerSource::MarketOracle {
+        validate!(
+            trigger_market_index != order.market_index,
+            ErrorCode::InvalidOrderTrigger,
+            "trigger market must be a different market than the order's"
+        )?;
+
+        order.auction_end_price = trigger_market_index.cast()?;
+    }
+
+    order.auction_start_price = trigger_source as i64;
+    order.trigger_price = trigger_price;
+
+    Ok(())
+}
+
+/// The max slippage is kept on the order and checked against the oracle at fill time. Market
+/// orders keep it in oracle_price_offset, oracle orders need that for their offset and keep it in
+/// trigger_price
+pub(crate) fn init_max_slippage_order(order: &mut Order, max_slippage_bps: u16) -> DriftResult {
+    match order.order_type {
+        OrderType::Market | OrderType::TriggerMarket => {
+            order.oracle_price_offset = max_slippage_bps.cast()?;
+        }
+        OrderType::Oracle => {
+            order.trigger_price = max_slippage_bps.cast()?;
+        }
+        _ => {
+            msg!("Max slippage only supported for market and oracle orders");
+            return Err(ErrorCode::InvalidOrder);
+        }
+    }
+
+    order.bit_flags |= OrderBitFlag::MaxSlippage as u8;
+
+    Ok(())
+}
+
+/// Iceberg orders keep their display amount in trigger_price
+fn init_iceberg_order(order: &mut Order, display_base_asset_amount: u64) {
+    order.bit_flags |= OrderBitFlag::Iceberg as u8;
+    order.trigger_price = display_base_asset_amount;
+}
+
+/// Links the twap order to a group for its slices. The first slice can be released right away
+fn init_twap_order(order: &mut Order, user: &User, twap_slices: u8, now: i64) -> DriftResult {
+    order.trigger_price = now.cast()?;
+    order.slices_remaining = twap_slices;
+
+    if !order.is_in_order_group() {
+        order.group_id = user
+            .get_unused_order_group_id()
+            .ok_or(ErrorCode::InvalidOrderGroup)?;
+    }
+
+    Ok(())
+}
+
+fn update_trailing_trigger_price(
+    user: &mut User,
+    user_key: &Pubkey,
+    order_index: usize,
+    filler_key: &Pubkey,
+    filler_reward: u64,
+    trigger_price: u64,
+    trigger_condition: OrderTriggerCondition,
+    oracle_price: i64,
+    now: i64,
+) -> DriftResult {
+    let order = &mut user.orders[order_index];
+
+    msg!(
+        "trailing trigger price {} -> {} condition {:?}",
+        order.trigger_price,
+        trigger_price,
+        trigger_condition
+    );
+
+    order.trigger_price = trigger_price;
+    order.trigger_condition = trigger_condition;
+
+    let order_action_record = get_order_action_record(
+        now,
+        OrderAction::UpdateTrigger,
+        OrderActionExplanation::TrailingTriggerPriceUpdated,
+        order.market_index,
+        Some(*filler_key),
+        None,
+        Some(filler_reward),
+        None,
+        None,
+        Some(filler_reward),
+        None,
+        None,
+        None,
+        None,
+        Some(*user_key),
+        Some(*order),
+        None,
+        None,
+        oracle_price,
+    )?;
+    emit!(order_action_record);
+
+    Ok(())
+}
+
+fn update_trigger_order_params(
+    order: &mut Order,
     oracle_price_data: &OraclePriceData,
     slot: u64,
     min_auction_duration: u8,
@@ -2762,8 +4578,115 @@ pub fn force_cancel_orders(
     let meets_initial_margin_requirement = margin_calc.meets_margin_requirement();
 
     validate!(
-        !meets_initial_margin_requirement,
-        ErrorCode::SufficientCollateral
+        !meets_initial_margin_requirement,
+        ErrorCode::SufficientCollateral
+    )?;
+
+    let mut total_fee = 0_u64;
+
+    for order_index in 0..user.orders.len() {
+        if user.orders[order_index].status != OrderStatus::Open {
+            continue;
+        }
+
+        let market_index = user.orders[order_index].market_index;
+        let market_type = user.orders[order_index].market_type;
+
+        let fee = match market_type {
+            MarketType::Spot => {
+                let spot_market = spot_market_map.get_ref(&market_index)?;
+                let token_amount = user
+                    .get_spot_position(market_index)?
+                    .get_signed_token_amount(&spot_market)?
+                    .cast::<i64>()?;
+                let is_position_reducing = is_order_position_reducing(
+                    &user.orders[order_index].direction,
+                    user.orders[order_index].get_base_asset_amount_unfilled(Some(token_amount))?,
+                    token_amount,
+                )?;
+                if is_position_reducing {
+                    continue;
+                }
+
+                state.spot_fee_structure.flat_filler_fee
+            }
+            MarketType::Perp => {
+                let base_asset_amount = user.get_perp_position(market_index)?.base_asset_amount;
+                let is_position_reducing = is_order_position_reducing(
+                    &user.orders[order_index].direction,
+                    user.orders[order_index]
+                        .get_base_asset_amount_unfilled(Some(base_asset_amount))?,
+                    base_asset_amount,
+                )?;
+                if is_position_reducing {
+                    continue;
+                }
+
+                state.perp_fee_structure.flat_filler_fee
+            }
+        };
+
+        total_fee = total_fee.safe_add(fee)?;
+
+        cancel_order(
+            order_index,
+            user,
+            &user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            OrderActionExplanation::InsufficientFreeCollateral,
+            Some(&filler_key),
+            fee,
+            false,
+        )?;
+    }
+
+    pay_keeper_flat_reward_for_spot(
+        user,
+        Some(filler),
+        spot_market_map.get_quote_spot_market_mut()?.deref_mut(),
+        total_fee,
+        slot,
+    )?;
+
+    user.update_last_active_slot(slot);
+
+    Ok(())
+}
+
+pub fn cancel_orders_after_deadline(
+    state: &State,
+    user_account_loader: &AccountLoader<User>,
+    spot_market_map: &SpotMarketMap,
+    perp_market_map: &PerpMarketMap,
+    oracle_map: &mut OracleMap,
+    filler: &AccountLoader<User>,
+    clock: &Clock,
+) -> DriftResult {
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+
+    let filler_key = filler.key();
+    let user_key = user_account_loader.key();
+    let user = &mut load_mut!(user_account_loader)?;
+    let filler = &mut load_mut!(filler)?;
+
+    validate!(
+        !user.is_being_liquidated(),
+        ErrorCode::UserIsBeingLiquidated
+    )?;
+
+    validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
+
+    validate!(
+        user.cancel_after_ts != 0 && now >= user.cancel_after_ts,
+        ErrorCode::CancelAfterDeadlineNotReached,
+        "cancel after ts {} now {}",
+        user.cancel_after_ts,
+        now
     )?;
 
     let mut total_fee = 0_u64;
@@ -2773,41 +4696,9 @@ pub fn force_cancel_orders(
             continue;
         }
 
-        let market_index = user.orders[order_index].market_index;
-        let market_type = user.orders[order_index].market_type;
-
-        let fee = match market_type {
-            MarketType::Spot => {
-                let spot_market = spot_market_map.get_ref(&market_index)?;
-                let token_amount = user
-                    .get_spot_position(market_index)?
-                    .get_signed_token_amount(&spot_market)?
-                    .cast::<i64>()?;
-                let is_position_reducing = is_order_position_reducing(
-                    &user.orders[order_index].direction,
-                    user.orders[order_index].get_base_asset_amount_unfilled(Some(token_amount))?,
-                    token_amount,
-                )?;
-                if is_position_reducing {
-                    continue;
-                }
-
-                state.spot_fee_structure.flat_filler_fee
-            }
-            MarketType::Perp => {
-                let base_asset_amount = user.get_perp_position(market_index)?.base_asset_amount;
-                let is_position_reducing = is_order_position_reducing(
-                    &user.orders[order_index].direction,
-                    user.orders[order_index]
-                        .get_base_asset_amount_unfilled(Some(base_asset_amount))?,
-                    base_asset_amount,
-                )?;
-                if is_position_reducing {
-                    continue;
-                }
-
-                state.perp_fee_structure.flat_filler_fee
-            }
+        let fee = match user.orders[order_index].market_type {
+            MarketType::Spot => state.spot_fee_structure.flat_filler_fee,
+            MarketType::Perp => state.perp_fee_structure.flat_filler_fee,
         };
 
         total_fee = total_fee.safe_add(fee)?;
@@ -2821,13 +4712,16 @@ pub fn force_cancel_orders(
             oracle_map,
             now,
             slot,
-            OrderActionExplanation::InsufficientFreeCollateral,
+            OrderActionExplanation::CancelAfterDeadline,
             Some(&filler_key),
             fee,
             false,
         )?;
     }
 
+    // the switch fires once, the user has to set a new deadline
+    user.cancel_after_ts = 0;
+
     pay_keeper_flat_reward_for_spot(
         user,
         Some(filler),
@@ -2836,8 +4730,6 @@ pub fn force_cancel_orders(
         slot,
     )?;
 
-    user.update_last_active_slot(slot);
-
     Ok(())
 }
 
@@ -2923,12 +4815,14 @@ pub fn burn_user_lp_shares_for_risk_reduction(
             user_custom_margin_ratio,
         )?;
 
+    let base_asset_amount_before = user.perp_positions[position_index].base_asset_amount;
     let (position_delta, pnl) = burn_lp_shares(
         &mut user.perp_positions[position_index],
         &mut market,
         lp_shares_to_burn,
         oracle_price,
     )?;
+    user.update_perp_position_open_count(position_index, base_asset_amount_before);
 
     // emit LP record for shares removed
     emit_stack::<_, { LPRecord::SIZE }>(LPRecord {
@@ -3078,7 +4972,7 @@ pub fn place_spot_order(
 
     if user.is_reduce_only() {
         validate!(
-            params.reduce_only,
+            params.is_reduce_only(),
             ErrorCode::UserReduceOnly,
             "order must be reduce only"
         )?;
@@ -3199,7 +5093,13 @@ pub fn place_spot_order(
         "must be spot order"
     )?;
 
-    let new_order = Order {
+    validate!(
+        params.trigger_source == TriggerSource::Oracle,
+        ErrorCode::InvalidOrderTrigger,
+        "spot orders can only trigger on their oracle"
+    )?;
+
+    let mut new_order = Order {
         status: OrderStatus::Open,
         order_type: params.order_type,
         market_type: params.market_type,
@@ -3213,7 +5113,7 @@ pub fn place_spot_order(
         base_asset_amount_filled: 0,
         quote_asset_amount_filled: 0,
         direction: params.direction,
-        reduce_only: params.reduce_only || force_reduce_only,
+        reduce_only: params.is_reduce_only() || force_reduce_only,
         trigger_price: standardize_price(
             params.trigger_price.unwrap_or(0),
             spot_market.order_tick_size,
@@ -3227,15 +5127,47 @@ pub fn place_spot_order(
         auction_end_price,
         auction_duration,
         max_ts,
-        padding: [0; 3],
+        bit_flags: params.bit_flags,
+        group_id: params.group_id,
+        slices_remaining: 0,
     };
 
+    // trailing stop without a trigger price starts trailing from the current oracle price
+    if new_order.order_type == OrderType::TrailingStop && new_order.trigger_price == 0 {
+        new_order.trigger_price = calculate_trailing_trigger_price(
+            &new_order,
+            oracle_price_data.price.unsigned_abs(),
+            spot_market.order_tick_size,
+        )?;
+    }
+
+    if new_order.order_type == OrderType::Twap {
+        init_twap_order(&mut new_order, user, params.twap_slices, now)?;
+    }
+
+    if params.display_base_asset_amount > 0 || new_order.is_bit_flag_set(OrderBitFlag::Iceberg) {
+        init_iceberg_order(&mut new_order, params.display_base_asset_amount);
+    }
+
+    if params.max_slippage_bps > 0 || new_order.is_bit_flag_set(OrderBitFlag::MaxSlippage) {
+        init_max_slippage_order(&mut new_order, params.max_slippage_bps)?;
+    }
+
     validate_spot_order(
         &new_order,
         spot_market.order_step_size,
         spot_market.min_order_size,
     )?;
 
+    // slices are only placed by release_twap_order_slice, which sizes them from the twap order
+    validate!(
+        !new_order.is_bit_flag_set(OrderBitFlag::TwapSlice) || options.is_twap_slice_release(),
+        ErrorCode::InvalidOrderGroup,
+        "twap slice can only be released by its twap order"
+    )?;
+
+    validate_order_group(&new_order, &user.orders)?;
+
     let risk_increasing = is_new_order_risk_increasing(
         &new_order,
         signed_token_amount.cast()?,
@@ -3329,6 +5261,45 @@ pub fn fill_spot_order(
     clock: &Clock,
     fulfillment_params: &mut dyn SpotFulfillmentParams,
 ) -> DriftResult<u64> {
+    let (base_asset_amount, _) = fill_spot_order_and_get_fill_amounts(
+        order_id,
+        state,
+        user,
+        user_stats,
+        spot_market_map,
+        perp_market_map,
+        oracle_map,
+        filler,
+        filler_stats,
+        maker,
+        maker_stats,
+        maker_order_id,
+        clock,
+        fulfillment_params,
+        FillMode::Fill,
+    )?;
+
+    Ok(base_asset_amount)
+}
+
+/// Fills the order like fill_spot_order, returning both the base and quote asset amount filled
+pub fn fill_spot_order_and_get_fill_amounts(
+    order_id: u32,
+    state: &State,
+    user: &AccountLoader<User>,
+    user_stats: &AccountLoader<UserStats>,
+    spot_market_map: &SpotMarketMap,
+    perp_market_map: &PerpMarketMap,
+    oracle_map: &mut OracleMap,
+    filler: &AccountLoader<User>,
+    filler_stats: &AccountLoader<UserStats>,
+    maker: Option<&AccountLoader<User>>,
+    maker_stats: Option<&AccountLoader<UserStats>>,
+    maker_order_id: Option<u32>,
+    clock: &Clock,
+    fulfillment_params: &mut dyn SpotFulfillmentParams,
+    fill_mode: FillMode,
+) -> DriftResult<(u64, u64)> {
     let now = clock.unix_timestamp;
     let slot = clock.slot;
 
@@ -3380,7 +5351,7 @@ pub fn fill_spot_order(
 
     if user.is_bankrupt() {
         msg!("User is bankrupt");
-        return Ok(0);
+        return Ok((0, 0));
     }
 
     match validate_user_not_being_liquidated(
@@ -3393,7 +5364,7 @@ pub fn fill_spot_order(
         Ok(_) => {}
         Err(_) => {
             msg!("User is being liquidated");
-            return Ok(0);
+            return Ok((0, 0));
         }
     }
 
@@ -3453,7 +5424,7 @@ pub fn fill_spot_order(
                 filler.update_last_active_slot(slot);
             }
 
-            return Ok(0);
+            return Ok((0, 0));
         }
     }
 
@@ -3506,7 +5477,64 @@ pub fn fill_spot_order(
             filler_reward,
             false,
         )?;
-        return Ok(0);
+        return Ok((0, 0));
+    }
+
+    let self_trade_prevented = match (maker.as_deref_mut(), maker_key, maker_order_index) {
+        (Some(maker), Some(maker_key), Some(maker_order_index))
+            if user_stats.self_trade_prevention_mode != SelfTradePreventionMode::None
+                && maker.authority == user.authority =>
+        {
+            let orders_cross = {
+                let spot_market = spot_market_map.get_ref(&order_market_index)?;
+                let oracle_price = oracle_map.get_price_data(&spot_market.oracle)?.price;
+                match user.orders[order_index].get_limit_price(
+                    Some(oracle_price),
+                    None,
+                    slot,
+                    spot_market.order_tick_size,
+                )? {
+                    Some(taker_price) => do_orders_cross(
+                        maker.orders[maker_order_index].direction,
+                        maker.orders[maker_order_index].force_get_limit_price(
+                            Some(oracle_price),
+                            None,
+                            slot,
+                            spot_market.order_tick_size,
+                        )?,
+                        taker_price,
+                    ),
+                    None => false,
+                }
+            };
+
+            orders_cross
+                && apply_self_trade_prevention(
+                    user_stats.self_trade_prevention_mode,
+                    user,
+                    order_index,
+                    &user_key,
+                    maker,
+                    maker_order_index,
+                    &maker_key,
+                    perp_market_map,
+                    spot_market_map,
+                    oracle_map,
+                    now,
+                    slot,
+                    &filler_key,
+                )?
+        }
+        _ => false,
+    };
+
+    if self_trade_prevented {
+        if user.orders[order_index].status != OrderStatus::Open {
+            return Ok((0, 0));
+        }
+
+        maker = None;
+        maker_stats = None;
     }
 
     let (base_asset_amount, quote_asset_amount) = fulfill_spot_order(
@@ -3528,6 +5556,7 @@ pub fn fill_spot_order(
         slot,
         &state.spot_fee_structure,
         fulfillment_params,
+        fill_mode,
     )?;
 
     if base_asset_amount != 0 {
@@ -3616,13 +5645,41 @@ pub fn fill_spot_order(
         )?
     }
 
+    if should_cancel_max_slippage_order(&user.orders[order_index], slot)? {
+        let filler_reward = {
+            let mut quote_market = spot_market_map.get_quote_spot_market_mut()?;
+            pay_keeper_flat_reward_for_spot(
+                user,
+                filler.as_deref_mut(),
+                &mut quote_market,
+                state.spot_fee_structure.flat_filler_fee,
+                slot,
+            )?
+        };
+
+        cancel_order(
+            order_index,
+            user,
+            &user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            OrderActionExplanation::MaxSlippageExceeded,
+            Some(&filler_key),
+            filler_reward,
+            false,
+        )?
+    }
+
     spot_market_map
         .get_ref(&order_market_index)?
         .validate_max_token_deposits()?;
 
     user.update_last_active_slot(slot);
 
-    Ok(base_asset_amount)
+    Ok((base_asset_amount, quote_asset_amount))
 }
 
 #[allow(clippy::type_complexity)]
@@ -3801,6 +5858,7 @@ fn fulfill_spot_order(
     slot: u64,
     fee_structure: &FeeStructure,
     fulfillment_params: &mut dyn SpotFulfillmentParams,
+    fill_mode: FillMode,
 ) -> DriftResult<(u64, u64)> {
     let base_market_index = user.orders[user_order_index].market_index;
     let order_direction = user.orders[user_order_index].direction;
@@ -3821,33 +5879,48 @@ fn fulfill_spot_order(
         .force_get_spot_position_mut(base_market_index)?
         .get_signed_token_amount(&base_market)?;
 
+    // orders can be reset once fully filled, so keep copies to apply their order groups after
+    let user_order_before_fill = user.orders[user_order_index];
+    let maker_order_before_fill = match (maker.as_deref(), maker_order_index) {
+        (Some(maker), Some(maker_order_index)) => Some(maker.orders[maker_order_index]),
+        _ => None,
+    };
+
     let mut base_asset_amount = 0_u64;
     let mut quote_asset_amount = 0_u64;
+    let mut maker_base_asset_amount_filled = 0_u64;
     for fulfillment_method in fulfillment_methods.iter() {
         if user.orders[user_order_index].status != OrderStatus::Open {
             break;
         }
 
         let (base_filled, quote_filled) = match fulfillment_method {
-            SpotFulfillmentMethod::Match => fulfill_spot_order_with_match(
-                &mut base_market,
-                &mut quote_market,
-                user,
-                user_stats,
-                user_order_index,
-                user_key,
-                maker.as_deref_mut().safe_unwrap()?,
-                maker_stats,
-                maker_order_index.safe_unwrap()?,
-                maker_key.safe_unwrap()?,
-                filler.as_deref_mut(),
-                filler_stats.as_deref_mut(),
-                filler_key,
-                now,
-                slot,
-                oracle_map,
-                fee_structure,
-            )?,
+            SpotFulfillmentMethod::Match => {
+                let (base_filled, quote_filled) = fulfill_spot_order_with_match(
+                    &mut base_market,
+                    &mut quote_market,
+                    user,
+                    user_stats,
+                    user_order_index,
+                    user_key,
+                    maker.as_deref_mut().safe_unwrap()?,
+                    maker_stats,
+                    maker_order_index.safe_unwrap()?,
+                    maker_key.safe_unwrap()?,
+                    filler.as_deref_mut(),
+                    filler_stats.as_deref_mut(),
+                    filler_key,
+                    now,
+                    slot,
+                    oracle_map,
+                    fee_structure,
+                )?;
+
+                maker_base_asset_amount_filled =
+                    maker_base_asset_amount_filled.safe_add(base_filled)?;
+
+                (base_filled, quote_filled)
+            }
             SpotFulfillmentMethod::ExternalMarket => fulfill_spot_order_with_external_market(
                 &mut base_market,
                 &mut quote_market,
@@ -3878,6 +5951,8 @@ fn fulfill_spot_order(
         quote_asset_amount
     )?;
 
+    validate_fill_or_kill(&user_order_before_fill, base_asset_amount)?;
+
     let quote_token_amount_after = user
         .get_quote_spot_position()
         .get_signed_token_amount(&quote_market)?;
@@ -3934,22 +6009,73 @@ fn fulfill_spot_order(
     drop(base_market);
     drop(quote_market);
 
-    let taker_margin_calculation =
-        calculate_margin_requirement_and_total_collateral_and_liability_info(
+    if base_asset_amount != 0 {
+        cancel_order_group_siblings(
+            &user_order_before_fill,
             user,
+            user_key,
             perp_market_map,
             spot_market_map,
             oracle_map,
-            MarginContext::standard(margin_type),
+            now,
+            slot,
+            Some(filler_key),
         )?;
 
-    if !taker_margin_calculation.meets_margin_requirement() {
-        msg!(
-            "taker breached maintenance requirements (margin requirement {}) (total_collateral {})",
-            taker_margin_calculation.margin_requirement,
-            taker_margin_calculation.total_collateral
-        );
-        return Err(ErrorCode::InsufficientCollateral);
+        user.update_bracket_exits_for_entry_fill(&user_order_before_fill);
+
+        update_twap_order_for_slice_fill(
+            &user_order_before_fill,
+            user,
+            user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            base_asset_amount,
+            quote_asset_amount,
+        )?;
+    }
+
+    if let (Some(maker), Some(maker_order), Some(maker_key)) =
+        (maker.as_deref_mut(), maker_order_before_fill, maker_key)
+    {
+        if maker_base_asset_amount_filled != 0 {
+            cancel_order_group_siblings(
+                &maker_order,
+                maker,
+                maker_key,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                now,
+                slot,
+                Some(filler_key),
+            )?;
+
+            maker.update_bracket_exits_for_entry_fill(&maker_order);
+        }
+    }
+
+    if !fill_mode.is_combo_leg() {
+        let taker_margin_calculation =
+            calculate_margin_requirement_and_total_collateral_and_liability_info(
+                user,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                MarginContext::standard(margin_type),
+            )?;
+
+        if !taker_margin_calculation.meets_margin_requirement() {
+            msg!(
+                "taker breached maintenance requirements (margin requirement {}) (total_collateral {})",
+                taker_margin_calculation.margin_requirement,
+                taker_margin_calculation.total_collateral
+            );
+            return Err(ErrorCode::InsufficientCollateral);
+        }
     }
 
     if let Some(maker) = maker {
@@ -4037,11 +6163,11 @@ pub fn fulfill_spot_order_with_match(
     let maker_spot_position_index = maker.get_spot_position_index(market_index)?;
     let maker_token_amount =
         maker.spot_positions[maker_spot_position_index].get_signed_token_amount(base_market)?;
-    let maker_base_asset_amount = maker.orders[maker_order_index]
-        .get_standardized_base_asset_amount_unfilled(
-            Some(maker_token_amount.cast()?),
-            base_market.order_step_size,
-        )?;
+    let maker_base_asset_amount = standardize_base_asset_amount(
+        maker.orders[maker_order_index]
+            .get_visible_base_asset_amount_unfilled(Some(maker_token_amount.cast()?))?,
+        base_market.order_step_size,
+    )?;
 
     let orders_cross = do_orders_cross(maker_direction, maker_price, taker_price);
 
@@ -4648,6 +6774,12 @@ pub fn trigger_spot_order(
         "Order is already triggered"
     )?;
 
+    validate!(
+        !user.is_bracket_exit_pending(&user.orders[order_index]),
+        ErrorCode::BracketExitPending,
+        "Bracket entry must fill before exit can be triggered"
+    )?;
+
     validate!(
         market_type == MarketType::Spot,
         ErrorCode::InvalidOrderMarketType,
@@ -4687,6 +6819,41 @@ pub fn trigger_spot_order(
 
     let oracle_price = oracle_price_data.price;
 
+    if let Some((trigger_price, trigger_condition)) = calculate_trailing_trigger_update(
+        &user.orders[order_index],
+        oracle_price.unsigned_abs().cast()?,
+        spot_market.order_tick_size,
+    )? {
+        drop(spot_market);
+
+        let mut filler = if user_key != filler_key {
+            Some(load_mut!(filler)?)
+        } else {
+            None
+        };
+
+        let mut quote_market = spot_market_map.get_quote_spot_market_mut()?;
+        let filler_reward = pay_keeper_flat_reward_for_spot(
+            user,
+            filler.as_deref_mut(),
+            &mut quote_market,
+            state.spot_fee_structure.flat_filler_fee,
+            slot,
+        )?;
+
+        return update_trailing_trigger_price(
+            user,
+            &user_key,
+            order_index,
+            &filler_key,
+            filler_reward,
+            trigger_price,
+            trigger_condition,
+            oracle_price,
+            now,
+        );
+    }
+
     let can_trigger = order_satisfies_trigger_condition(
         &user.orders[order_index],
         oracle_price.unsigned_abs().cast()?,
@@ -4803,6 +6970,21 @@ pub fn trigger_spot_order(
         }
     }
 
+    if user.orders[order_index].status == OrderStatus::Open {
+        let order = user.orders[order_index];
+        cancel_order_group_siblings(
+            &order,
+            user,
+            &user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            Some(&filler_key),
+        )?;
+    }
+
     user.update_last_active_slot(slot);
 
     Ok(())
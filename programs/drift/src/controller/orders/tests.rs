@@ -130,6 +130,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -249,6 +251,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -368,6 +372,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -487,6 +493,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -606,6 +614,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -691,6 +701,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -777,6 +789,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -863,6 +877,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -949,6 +965,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -1055,6 +1073,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -1164,6 +1184,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -1280,6 +1302,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -1397,6 +1421,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -1538,6 +1564,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -1654,6 +1682,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -1769,6 +1799,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut oracle_map,
+            None,
+            None,
         )
         .unwrap();
 
@@ -1909,6 +1941,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut oracle_map,
+            None,
+            None,
         )
         .unwrap();
 
@@ -2042,6 +2076,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut oracle_map,
+            None,
+            None,
         )
         .unwrap();
 
@@ -2181,6 +2217,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut oracle_map,
+            None,
+            None,
         )
         .unwrap();
 
@@ -2308,6 +2346,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -2434,6 +2474,8 @@ pub mod fulfill_order_with_maker_order {
             slot,
             &fee_structure,
             &mut get_oracle_map(),
+            None,
+            None,
         )
         .unwrap();
 
@@ -4756,47 +4798,42 @@ pub mod fulfill_order {
     }
 }
 
-pub mod fill_order {
+pub mod self_trade_prevention {
     use std::str::FromStr;
 
-    use anchor_lang::prelude::{AccountLoader, Clock};
-
-    use crate::controller::orders::fill_perp_order;
+    use crate::controller::orders::fulfill_perp_order;
     use crate::controller::position::PositionDirection;
+    use crate::create_account_info;
     use crate::create_anchor_account_info;
     use crate::math::constants::{
         AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64, PEG_PRECISION,
-        PRICE_PRECISION_I64, PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION_U64,
+        PRICE_PRECISION, PRICE_PRECISION_U64, QUOTE_PRECISION_I64, SPOT_BALANCE_PRECISION_U64,
         SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
     };
-    use crate::state::oracle::HistoricalOracleData;
-    use crate::state::oracle::OracleSource;
+    use crate::state::fill_mode::FillMode;
+    use crate::state::oracle::{HistoricalOracleData, OracleSource};
     use crate::state::perp_market::{PerpMarket, AMM};
     use crate::state::perp_market_map::PerpMarketMap;
     use crate::state::spot_market::{SpotBalanceType, SpotMarket};
     use crate::state::spot_market_map::SpotMarketMap;
-    use crate::state::state::State;
-    use crate::state::user::{MarketType, OrderStatus, OrderType, SpotPosition, User, UserStats};
-    use crate::test_utils::*;
-    use crate::test_utils::{
-        create_account_info, get_orders, get_positions, get_pyth_price, get_spot_positions,
+    use crate::state::user::{
+        OrderStatus, OrderType, SelfTradePreventionMode, SpotPosition, User, UserStats,
     };
-    use crate::{create_account_info, QUOTE_PRECISION_I64};
+    use crate::state::user_map::{UserMap, UserStatsMap};
+    use crate::test_utils::*;
+    use crate::test_utils::{get_orders, get_positions, get_pyth_price, get_spot_positions};
 
     use super::*;
-    use crate::error::ErrorCode;
-    use crate::state::fill_mode::FillMode;
-    use crate::state::user_map::{UserMap, UserStatsMap};
 
-    #[test]
-    fn maker_order_canceled_for_breaching_oracle_price_band() {
-        let clock = Clock {
-            slot: 56,
-            epoch_start_timestamp: 0,
-            epoch: 0,
-            leader_schedule_epoch: 0,
-            unix_timestamp: 0,
-        };
+    /// Fills a long market taker order against a short limit maker order from another sub account
+    /// of the same authority. Returns the base filled, the taker and the maker
+    fn fulfill_with_self_trade_prevention(
+        self_trade_prevention_mode: SelfTradePreventionMode,
+        taker_base_asset_amount: u64,
+        maker_base_asset_amount: u64,
+    ) -> (u64, User, User) {
+        let now = 0_i64;
+        let slot = 0_u64;
 
         let mut oracle_price = get_pyth_price(100, 6);
         let oracle_price_key =
@@ -4808,28 +4845,27 @@ pub mod fill_order {
             &pyth_program,
             oracle_account_info
         );
-        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
 
         let mut market = PerpMarket {
             amm: AMM {
                 base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
                 quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
-                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                ask_base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                ask_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
                 sqrt_k: 100 * AMM_RESERVE_PRECISION,
                 peg_multiplier: 100 * PEG_PRECISION,
-                max_slippage_ratio: 100,
+                max_slippage_ratio: 50,
                 max_fill_reserve_fraction: 100,
                 order_step_size: 1000,
                 order_tick_size: 1,
                 oracle: oracle_price_key,
-                max_spread: 1000,
-                base_spread: 0,
-                long_spread: 0,
-                short_spread: 0,
                 historical_oracle_data: HistoricalOracleData {
-                    last_oracle_price_twap: oracle_price.twap as i64,
-                    last_oracle_price_twap_5min: oracle_price.twap as i64,
-                    last_oracle_price: oracle_price.agg.price as i64,
+                    last_oracle_price: (100 * PRICE_PRECISION) as i64,
+                    last_oracle_price_twap: (100 * PRICE_PRECISION) as i64,
+                    last_oracle_price_twap_5min: (100 * PRICE_PRECISION) as i64,
                     ..HistoricalOracleData::default()
                 },
                 ..AMM::default()
@@ -4837,27 +4873,11 @@ pub mod fill_order {
             margin_ratio_initial: 1000,
             margin_ratio_maintenance: 500,
             status: MarketStatus::Initialized,
-            ..PerpMarket::default()
+            ..PerpMarket::default_test()
         };
-        market.status = MarketStatus::Active;
         market.amm.max_base_asset_reserve = u128::MAX;
         market.amm.min_base_asset_reserve = 0;
-        let (new_ask_base_asset_reserve, new_ask_quote_asset_reserve) =
-            crate::math::amm_spread::calculate_spread_reserves(
-                &market.amm,
-                PositionDirection::Long,
-            )
-            .unwrap();
-        let (new_bid_base_asset_reserve, new_bid_quote_asset_reserve) =
-            crate::math::amm_spread::calculate_spread_reserves(
-                &market.amm,
-                PositionDirection::Short,
-            )
-            .unwrap();
-        market.amm.ask_base_asset_reserve = new_ask_base_asset_reserve;
-        market.amm.bid_base_asset_reserve = new_bid_base_asset_reserve;
-        market.amm.ask_quote_asset_reserve = new_ask_quote_asset_reserve;
-        market.amm.bid_quote_asset_reserve = new_bid_quote_asset_reserve;
+
         create_anchor_account_info!(market, PerpMarket, market_account_info);
         let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
 
@@ -4874,26 +4894,23 @@ pub mod fill_order {
         create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
         let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
 
-        let mut user = User {
-            authority: Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap(), // different authority than filler
+        let authority = Pubkey::new_unique();
+
+        let mut taker = User {
+            authority,
             orders: get_orders(Order {
                 market_index: 0,
-                order_id: 1,
                 status: OrderStatus::Open,
                 order_type: OrderType::Market,
                 direction: PositionDirection::Long,
-                base_asset_amount: BASE_PRECISION_U64,
-                slot: 0,
-                auction_start_price: 0,
-                auction_end_price: 50 * PRICE_PRECISION_I64,
-                auction_duration: 5,
-                price: 50 * PRICE_PRECISION_U64,
+                base_asset_amount: taker_base_asset_amount,
+                price: 150 * PRICE_PRECISION_U64,
                 ..Order::default()
             }),
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 open_orders: 1,
-                open_bids: BASE_PRECISION_I64,
+                open_bids: taker_base_asset_amount as i64,
                 ..PerpPosition::default()
             }),
             spot_positions: get_spot_positions(SpotPosition {
@@ -4902,37 +4919,28 @@ pub mod fill_order {
                 scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
                 ..SpotPosition::default()
             }),
+            open_orders: 1,
             ..User::default()
         };
-        create_anchor_account_info!(user, User, user_account_info);
-        let user_account_loader: AccountLoader<User> =
-            AccountLoader::try_from(&user_account_info).unwrap();
-
-        create_anchor_account_info!(UserStats::default(), UserStats, user_stats_account_info);
-        let user_stats_account_loader: AccountLoader<UserStats> =
-            AccountLoader::try_from(&user_stats_account_info).unwrap();
 
-        let maker_key = Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
-        let maker_authority =
-            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let maker_key = Pubkey::default();
         let mut maker = User {
-            authority: maker_authority,
+            authority,
+            sub_account_id: 1,
             orders: get_orders(Order {
                 market_index: 0,
-                order_id: 1,
                 status: OrderStatus::Open,
+                post_only: true,
                 order_type: OrderType::Limit,
                 direction: PositionDirection::Short,
-                base_asset_amount: BASE_PRECISION_U64,
-                slot: 0,
-                price: 50 * PRICE_PRECISION_U64,
-                post_only: true,
+                base_asset_amount: maker_base_asset_amount,
+                price: 90 * PRICE_PRECISION_U64,
                 ..Order::default()
             }),
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
                 open_orders: 1,
-                open_asks: -BASE_PRECISION_I64,
+                open_asks: -(maker_base_asset_amount as i64),
                 ..PerpPosition::default()
             }),
             spot_positions: get_spot_positions(SpotPosition {
@@ -4941,89 +4949,280 @@ pub mod fill_order {
                 scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
                 ..SpotPosition::default()
             }),
+            open_orders: 1,
             ..User::default()
         };
-        create_anchor_account_info!(maker, &maker_key, User, maker_account_info);
+        create_anchor_account_info!(maker, User, maker_account_info);
         let makers_and_referrers = UserMap::load_one(&maker_account_info).unwrap();
 
         let mut maker_stats = UserStats {
-            authority: maker_authority,
+            authority,
             ..UserStats::default()
         };
         create_anchor_account_info!(maker_stats, UserStats, maker_stats_account_info);
         let maker_and_referrer_stats = UserStatsMap::load_one(&maker_stats_account_info).unwrap();
 
-        let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
-        create_anchor_account_info!(User::default(), &filler_key, User, user_account_info);
-        let filler_account_loader: AccountLoader<User> =
-            AccountLoader::try_from(&user_account_info).unwrap();
+        let mut taker_stats = UserStats {
+            authority,
+            self_trade_prevention_mode,
+            ..UserStats::default()
+        };
 
-        create_anchor_account_info!(UserStats::default(), UserStats, filler_stats_account_info);
-        let filler_stats_account_loader: AccountLoader<UserStats> =
-            AccountLoader::try_from(&filler_stats_account_info).unwrap();
+        let mut filler = User::default();
+        let mut filler_stats = UserStats::default();
 
-        let state = State {
-            min_perp_auction_duration: 1,
-            default_market_order_time_in_force: 10,
-            ..State::default()
-        };
+        let fee_structure = get_fee_structure();
 
-        let base_asset_amount = fill_perp_order(
-            1,
-            &state,
-            &user_account_loader,
-            &user_stats_account_loader,
-            &spot_market_map,
-            &market_map,
-            &mut oracle_map,
-            &filler_account_loader,
-            &filler_stats_account_loader,
+        let (taker_key, _, filler_key) = get_user_keys();
+
+        let (base_asset_amount, _) = fulfill_perp_order(
+            &mut taker,
+            0,
+            &taker_key,
+            &mut taker_stats,
             &makers_and_referrers,
             &maker_and_referrer_stats,
+            &[(maker_key, 0, 90 * PRICE_PRECISION_U64)],
+            &mut Some(&mut filler),
+            &filler_key,
+            &mut Some(&mut filler_stats),
             None,
-            &clock,
+            &spot_market_map,
+            &market_map,
+            &mut oracle_map,
+            &fee_structure,
+            100 * PRICE_PRECISION_U64,
+            Some(market.amm.historical_oracle_data.last_oracle_price),
+            now,
+            slot,
+            0,
+            false,
             FillMode::Fill,
         )
         .unwrap();
 
+        let maker = *makers_and_referrers.get_ref(&maker_key).unwrap();
+
+        (base_asset_amount, taker, maker)
+    }
+
+    #[test]
+    fn none() {
+        let (base_asset_amount, taker, maker) = fulfill_with_self_trade_prevention(
+            SelfTradePreventionMode::None,
+            BASE_PRECISION_U64,
+            BASE_PRECISION_U64 / 2,
+        );
+
+        assert_eq!(base_asset_amount, BASE_PRECISION_U64 / 2);
+
+        assert_eq!(taker.orders[0].status, OrderStatus::Open);
+        assert_eq!(
+            taker.orders[0].base_asset_amount_filled,
+            BASE_PRECISION_U64 / 2
+        );
+        assert_eq!(
+            taker.perp_positions[0].base_asset_amount,
+            BASE_PRECISION_I64 / 2
+        );
+
+        assert_eq!(maker.orders[0].status, OrderStatus::Filled);
+        assert_eq!(
+            maker.perp_positions[0].base_asset_amount,
+            -BASE_PRECISION_I64 / 2
+        );
+        assert_eq!(maker.perp_positions[0].open_orders, 0);
+    }
+
+    #[test]
+    fn expire_maker() {
+        let (base_asset_amount, taker, maker) = fulfill_with_self_trade_prevention(
+            SelfTradePreventionMode::ExpireMaker,
+            BASE_PRECISION_U64,
+            BASE_PRECISION_U64 / 2,
+        );
+
         assert_eq!(base_asset_amount, 0);
 
-        // order canceled
-        let maker = makers_and_referrers.get_ref_mut(&maker_key).unwrap();
+        assert_eq!(taker.orders[0].status, OrderStatus::Open);
+        assert_eq!(taker.orders[0].base_asset_amount, BASE_PRECISION_U64);
+        assert_eq!(taker.orders[0].base_asset_amount_filled, 0);
+        assert_eq!(taker.perp_positions[0].open_bids, BASE_PRECISION_I64);
+        assert_eq!(taker.perp_positions[0].base_asset_amount, 0);
+
         assert_eq!(maker.orders[0], Order::default());
+        assert_eq!(maker.perp_positions[0].open_asks, 0);
+        assert_eq!(maker.perp_positions[0].open_orders, 0);
+        assert_eq!(maker.perp_positions[0].base_asset_amount, 0);
     }
 
     #[test]
-    fn fallback_maker_order_id() {
-        let clock = Clock {
-            slot: 56,
-            epoch_start_timestamp: 0,
-            epoch: 0,
-            leader_schedule_epoch: 0,
-            unix_timestamp: 0,
-        };
+    fn expire_taker() {
+        let (base_asset_amount, taker, maker) = fulfill_with_self_trade_prevention(
+            SelfTradePreventionMode::ExpireTaker,
+            BASE_PRECISION_U64,
+            BASE_PRECISION_U64 / 2,
+        );
 
-        let mut oracle_price = get_pyth_price(100, 6);
-        let oracle_price_key =
-            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
-        let pyth_program = crate::ids::pyth_program::id();
-        create_account_info!(
-            oracle_price,
-            &oracle_price_key,
-            &pyth_program,
-            oracle_account_info
+        assert_eq!(base_asset_amount, 0);
+
+        assert_eq!(taker.orders[0], Order::default());
+        assert_eq!(taker.perp_positions[0].open_bids, 0);
+        assert_eq!(taker.perp_positions[0].open_orders, 0);
+        assert_eq!(taker.perp_positions[0].base_asset_amount, 0);
+
+        assert_eq!(maker.orders[0].status, OrderStatus::Open);
+        assert_eq!(maker.orders[0].base_asset_amount, BASE_PRECISION_U64 / 2);
+        assert_eq!(maker.orders[0].base_asset_amount_filled, 0);
+        assert_eq!(maker.perp_positions[0].open_asks, -BASE_PRECISION_I64 / 2);
+        assert_eq!(maker.perp_positions[0].base_asset_amount, 0);
+    }
+
+    #[test]
+    fn expire_both() {
+        let (base_asset_amount, taker, maker) = fulfill_with_self_trade_prevention(
+            SelfTradePreventionMode::ExpireBoth,
+            BASE_PRECISION_U64,
+            BASE_PRECISION_U64 / 2,
         );
-        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
 
-        let mut market = PerpMarket {
-            amm: AMM {
-                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
-                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
-                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
-                sqrt_k: 100 * AMM_RESERVE_PRECISION,
-                peg_multiplier: 100 * PEG_PRECISION,
-                max_slippage_ratio: 100,
-                max_fill_reserve_fraction: 100,
+        assert_eq!(base_asset_amount, 0);
+
+        assert_eq!(taker.orders[0], Order::default());
+        assert_eq!(taker.perp_positions[0].open_bids, 0);
+        assert_eq!(taker.perp_positions[0].open_orders, 0);
+
+        assert_eq!(maker.orders[0], Order::default());
+        assert_eq!(maker.perp_positions[0].open_asks, 0);
+        assert_eq!(maker.perp_positions[0].open_orders, 0);
+    }
+
+    #[test]
+    fn decrement_and_cancel() {
+        // maker is smaller, maker canceled and taker decremented by the maker size
+        let (base_asset_amount, taker, maker) = fulfill_with_self_trade_prevention(
+            SelfTradePreventionMode::DecrementAndCancel,
+            BASE_PRECISION_U64,
+            BASE_PRECISION_U64 / 2,
+        );
+
+        assert_eq!(base_asset_amount, 0);
+
+        assert_eq!(taker.orders[0].status, OrderStatus::Open);
+        assert_eq!(taker.orders[0].base_asset_amount, BASE_PRECISION_U64 / 2);
+        assert_eq!(taker.orders[0].base_asset_amount_filled, 0);
+        assert_eq!(taker.perp_positions[0].open_bids, BASE_PRECISION_I64 / 2);
+        assert_eq!(taker.perp_positions[0].open_orders, 1);
+
+        assert_eq!(maker.orders[0], Order::default());
+        assert_eq!(maker.perp_positions[0].open_asks, 0);
+        assert_eq!(maker.perp_positions[0].open_orders, 0);
+
+        // taker is smaller, taker canceled and maker decremented by the taker size
+        let (base_asset_amount, taker, maker) = fulfill_with_self_trade_prevention(
+            SelfTradePreventionMode::DecrementAndCancel,
+            BASE_PRECISION_U64 / 2,
+            2 * BASE_PRECISION_U64,
+        );
+
+        assert_eq!(base_asset_amount, 0);
+
+        assert_eq!(taker.orders[0], Order::default());
+        assert_eq!(taker.perp_positions[0].open_bids, 0);
+        assert_eq!(taker.perp_positions[0].open_orders, 0);
+
+        assert_eq!(maker.orders[0].status, OrderStatus::Open);
+        assert_eq!(
+            maker.orders[0].base_asset_amount,
+            3 * BASE_PRECISION_U64 / 2
+        );
+        assert_eq!(
+            maker.perp_positions[0].open_asks,
+            -3 * BASE_PRECISION_I64 / 2
+        );
+        assert_eq!(maker.perp_positions[0].open_orders, 1);
+
+        // same size, both canceled
+        let (base_asset_amount, taker, maker) = fulfill_with_self_trade_prevention(
+            SelfTradePreventionMode::DecrementAndCancel,
+            BASE_PRECISION_U64,
+            BASE_PRECISION_U64,
+        );
+
+        assert_eq!(base_asset_amount, 0);
+        assert_eq!(taker.orders[0], Order::default());
+        assert_eq!(taker.perp_positions[0].open_bids, 0);
+        assert_eq!(maker.orders[0], Order::default());
+        assert_eq!(maker.perp_positions[0].open_asks, 0);
+    }
+}
+
+pub mod fill_order {
+    use std::str::FromStr;
+
+    use anchor_lang::prelude::{AccountLoader, Clock};
+
+    use crate::controller::orders::fill_perp_order;
+    use crate::controller::position::PositionDirection;
+    use crate::create_anchor_account_info;
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64, PEG_PRECISION,
+        PRICE_PRECISION_I64, PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION_U64,
+        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+    };
+    use crate::state::oracle::HistoricalOracleData;
+    use crate::state::oracle::OracleSource;
+    use crate::state::perp_market::{PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::state::State;
+    use crate::state::user::{MarketType, OrderStatus, OrderType, SpotPosition, User, UserStats};
+    use crate::test_utils::*;
+    use crate::test_utils::{
+        create_account_info, get_orders, get_positions, get_pyth_price, get_spot_positions,
+    };
+    use crate::{create_account_info, QUOTE_PRECISION_I64};
+
+    use super::*;
+    use crate::controller::orders::fill_perp_order_and_get_fill_amounts;
+    use crate::error::ErrorCode;
+    use crate::math::orders::calculate_fill_price;
+    use crate::state::fill_mode::FillMode;
+    use crate::state::perp_market::AuctionClearingMode;
+    use crate::state::user_map::{UserMap, UserStatsMap};
+
+    #[test]
+    fn maker_order_canceled_for_breaching_oracle_price_band() {
+        let clock = Clock {
+            slot: 56,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
                 order_step_size: 1000,
                 order_tick_size: 1,
                 oracle: oracle_price_key,
@@ -5087,13 +5286,12 @@ pub mod fill_order {
                 status: OrderStatus::Open,
                 order_type: OrderType::Market,
                 direction: PositionDirection::Long,
-                market_type: MarketType::Perp,
                 base_asset_amount: BASE_PRECISION_U64,
                 slot: 0,
                 auction_start_price: 0,
-                auction_end_price: 100 * PRICE_PRECISION_I64,
+                auction_end_price: 50 * PRICE_PRECISION_I64,
                 auction_duration: 5,
-                price: 100 * PRICE_PRECISION_U64,
+                price: 50 * PRICE_PRECISION_U64,
                 ..Order::default()
             }),
             perp_positions: get_positions(PerpPosition {
@@ -5121,19 +5319,17 @@ pub mod fill_order {
         let maker_key = Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
         let maker_authority =
             Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
-        let maker_order_id = 1;
         let mut maker = User {
             authority: maker_authority,
             orders: get_orders(Order {
                 market_index: 0,
-                order_id: maker_order_id,
+                order_id: 1,
                 status: OrderStatus::Open,
                 order_type: OrderType::Limit,
-                market_type: MarketType::Perp,
                 direction: PositionDirection::Short,
                 base_asset_amount: BASE_PRECISION_U64,
                 slot: 0,
-                price: 100 * PRICE_PRECISION_U64,
+                price: 50 * PRICE_PRECISION_U64,
                 post_only: true,
                 ..Order::default()
             }),
@@ -5194,29 +5390,57 @@ pub mod fill_order {
         )
         .unwrap();
 
-        assert_eq!(base_asset_amount, 1000000000);
+        assert_eq!(base_asset_amount, 0);
+
+        // order canceled
+        let maker = makers_and_referrers.get_ref_mut(&maker_key).unwrap();
+        assert_eq!(maker.orders[0], Order::default());
     }
 
     #[test]
-    fn expire_order() {
+    fn fallback_maker_order_id() {
+        let clock = Clock {
+            slot: 56,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
         let mut market = PerpMarket {
             amm: AMM {
-                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
                 base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
                 quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
-                bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
-                bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
-                ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
-                ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
                 sqrt_k: 100 * AMM_RESERVE_PRECISION,
                 peg_multiplier: 100 * PEG_PRECISION,
                 max_slippage_ratio: 100,
                 max_fill_reserve_fraction: 100,
-                order_step_size: 10000000,
+                order_step_size: 1000,
                 order_tick_size: 1,
-                max_base_asset_reserve: 200 * AMM_RESERVE_PRECISION,
-                min_base_asset_reserve: 50 * AMM_RESERVE_PRECISION,
-                historical_oracle_data: HistoricalOracleData::default_price(PRICE_PRECISION_I64),
+                oracle: oracle_price_key,
+                max_spread: 1000,
+                base_spread: 0,
+                long_spread: 0,
+                short_spread: 0,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: oracle_price.twap as i64,
+                    last_oracle_price_twap_5min: oracle_price.twap as i64,
+                    last_oracle_price: oracle_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
                 ..AMM::default()
             },
             margin_ratio_initial: 1000,
@@ -5224,9 +5448,25 @@ pub mod fill_order {
             status: MarketStatus::Initialized,
             ..PerpMarket::default()
         };
-
         market.status = MarketStatus::Active;
-
+        market.amm.max_base_asset_reserve = u128::MAX;
+        market.amm.min_base_asset_reserve = 0;
+        let (new_ask_base_asset_reserve, new_ask_quote_asset_reserve) =
+            crate::math::amm_spread::calculate_spread_reserves(
+                &market.amm,
+                PositionDirection::Long,
+            )
+            .unwrap();
+        let (new_bid_base_asset_reserve, new_bid_quote_asset_reserve) =
+            crate::math::amm_spread::calculate_spread_reserves(
+                &market.amm,
+                PositionDirection::Short,
+            )
+            .unwrap();
+        market.amm.ask_base_asset_reserve = new_ask_base_asset_reserve;
+        market.amm.bid_base_asset_reserve = new_bid_base_asset_reserve;
+        market.amm.ask_quote_asset_reserve = new_ask_quote_asset_reserve;
+        market.amm.bid_quote_asset_reserve = new_bid_quote_asset_reserve;
         create_anchor_account_info!(market, PerpMarket, market_account_info);
         let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
 
@@ -5236,28 +5476,28 @@ pub mod fill_order {
             cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
             decimals: 6,
             initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
             ..SpotMarket::default()
         };
         create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
         let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
 
-        let mut oracle_map = get_oracle_map();
-
         let mut user = User {
-            authority: Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap(),
+            authority: Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap(), // different authority than filler
             orders: get_orders(Order {
                 market_index: 0,
                 order_id: 1,
                 status: OrderStatus::Open,
                 order_type: OrderType::Market,
                 direction: PositionDirection::Long,
+                market_type: MarketType::Perp,
                 base_asset_amount: BASE_PRECISION_U64,
                 slot: 0,
                 auction_start_price: 0,
-                auction_end_price: 102 * PRICE_PRECISION_I64,
+                auction_end_price: 100 * PRICE_PRECISION_I64,
                 auction_duration: 5,
-                price: 102 * PRICE_PRECISION_U64,
-                max_ts: 10,
+                price: 100 * PRICE_PRECISION_U64,
                 ..Order::default()
             }),
             perp_positions: get_positions(PerpPosition {
@@ -5282,6 +5522,49 @@ pub mod fill_order {
         let user_stats_account_loader: AccountLoader<UserStats> =
             AccountLoader::try_from(&user_stats_account_info).unwrap();
 
+        let maker_key = Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
+        let maker_authority =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let maker_order_id = 1;
+        let mut maker = User {
+            authority: maker_authority,
+            orders: get_orders(Order {
+                market_index: 0,
+                order_id: maker_order_id,
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                market_type: MarketType::Perp,
+                direction: PositionDirection::Short,
+                base_asset_amount: BASE_PRECISION_U64,
+                slot: 0,
+                price: 100 * PRICE_PRECISION_U64,
+                post_only: true,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_asks: -BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        create_anchor_account_info!(maker, &maker_key, User, maker_account_info);
+        let makers_and_referrers = UserMap::load_one(&maker_account_info).unwrap();
+
+        let mut maker_stats = UserStats {
+            authority: maker_authority,
+            ..UserStats::default()
+        };
+        create_anchor_account_info!(maker_stats, UserStats, maker_stats_account_info);
+        let maker_and_referrer_stats = UserStatsMap::load_one(&maker_stats_account_info).unwrap();
+
         let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
         create_anchor_account_info!(User::default(), &filler_key, User, user_account_info);
         let filler_account_loader: AccountLoader<User> =
@@ -5297,14 +5580,6 @@ pub mod fill_order {
             ..State::default()
         };
 
-        let clock = Clock {
-            slot: 11,
-            epoch_start_timestamp: 0,
-            epoch: 0,
-            leader_schedule_epoch: 0,
-            unix_timestamp: 11,
-        };
-
         let base_asset_amount = fill_perp_order(
             1,
             &state,
@@ -5315,28 +5590,157 @@ pub mod fill_order {
             &mut oracle_map,
             &filler_account_loader,
             &filler_stats_account_loader,
-            &UserMap::empty(),
-            &UserStatsMap::empty(),
+            &makers_and_referrers,
+            &maker_and_referrer_stats,
             None,
             &clock,
             FillMode::Fill,
         )
         .unwrap();
 
-        let user_after = user_account_loader.load().unwrap();
-        assert_eq!(base_asset_amount, 0);
-        assert_eq!(user_after.perp_positions[0].open_orders, 0);
-        assert_eq!(user_after.perp_positions[0].open_bids, 0);
-        assert_eq!(user_after.perp_positions[0].quote_asset_amount, -10000);
-        assert_eq!(user_after.orders[0], Order::default()); // order canceled
-
-        let filler_after = filler_account_loader.load().unwrap();
-        assert_eq!(filler_after.perp_positions[0].quote_asset_amount, 10000);
+        assert_eq!(base_asset_amount, 1000000000);
     }
 
     #[test]
-    fn max_open_interest() {
-        let clock = Clock {
+    fn expire_order() {
+        let mut market = PerpMarket {
+            amm: AMM {
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 10000000,
+                order_tick_size: 1,
+                max_base_asset_reserve: 200 * AMM_RESERVE_PRECISION,
+                min_base_asset_reserve: 50 * AMM_RESERVE_PRECISION,
+                historical_oracle_data: HistoricalOracleData::default_price(PRICE_PRECISION_I64),
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Initialized,
+            ..PerpMarket::default()
+        };
+
+        market.status = MarketStatus::Active;
+
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let mut oracle_map = get_oracle_map();
+
+        let mut user = User {
+            authority: Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap(),
+            orders: get_orders(Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Market,
+                direction: PositionDirection::Long,
+                base_asset_amount: BASE_PRECISION_U64,
+                slot: 0,
+                auction_start_price: 0,
+                auction_end_price: 102 * PRICE_PRECISION_I64,
+                auction_duration: 5,
+                price: 102 * PRICE_PRECISION_U64,
+                max_ts: 10,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_bids: BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        create_anchor_account_info!(user, User, user_account_info);
+        let user_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&user_account_info).unwrap();
+
+        create_anchor_account_info!(UserStats::default(), UserStats, user_stats_account_info);
+        let user_stats_account_loader: AccountLoader<UserStats> =
+            AccountLoader::try_from(&user_stats_account_info).unwrap();
+
+        let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        create_anchor_account_info!(User::default(), &filler_key, User, user_account_info);
+        let filler_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&user_account_info).unwrap();
+
+        create_anchor_account_info!(UserStats::default(), UserStats, filler_stats_account_info);
+        let filler_stats_account_loader: AccountLoader<UserStats> =
+            AccountLoader::try_from(&filler_stats_account_info).unwrap();
+
+        let state = State {
+            min_perp_auction_duration: 1,
+            default_market_order_time_in_force: 10,
+            ..State::default()
+        };
+
+        let clock = Clock {
+            slot: 11,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 11,
+        };
+
+        let base_asset_amount = fill_perp_order(
+            1,
+            &state,
+            &user_account_loader,
+            &user_stats_account_loader,
+            &spot_market_map,
+            &market_map,
+            &mut oracle_map,
+            &filler_account_loader,
+            &filler_stats_account_loader,
+            &UserMap::empty(),
+            &UserStatsMap::empty(),
+            None,
+            &clock,
+            FillMode::Fill,
+        )
+        .unwrap();
+
+        let user_after = user_account_loader.load().unwrap();
+        assert_eq!(base_asset_amount, 0);
+        assert_eq!(user_after.perp_positions[0].open_orders, 0);
+        assert_eq!(user_after.perp_positions[0].open_bids, 0);
+        assert_eq!(user_after.perp_positions[0].quote_asset_amount, -10000);
+        assert_eq!(user_after.orders[0], Order::default()); // order canceled
+
+        let filler_after = filler_account_loader.load().unwrap();
+        assert_eq!(filler_after.perp_positions[0].quote_asset_amount, 10000);
+    }
+
+    #[test]
+    fn max_open_interest() {
+        let clock = Clock {
             slot: 6,
             epoch_start_timestamp: 0,
             epoch: 0,
@@ -5492,49 +5896,271 @@ pub mod fill_order {
 
         assert_eq!(err, Err(ErrorCode::MaxOpenInterest));
     }
-}
 
-#[cfg(test)]
-pub mod fulfill_spot_order_with_match {
-    use crate::controller::orders::fulfill_spot_order_with_match;
-    use crate::controller::position::PositionDirection;
-    use crate::math::constants::{
-        LAMPORTS_PER_SOL_I64, LAMPORTS_PER_SOL_U64, PRICE_PRECISION_I64, PRICE_PRECISION_U64,
-        QUOTE_PRECISION_U64, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
-    };
-    use crate::math::spot_balance::calculate_utilization;
-    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
-    use crate::state::user::{MarketType, Order, OrderType, SpotPosition, User, UserStats};
-    use crate::test_utils::get_orders;
-    use crate::SPOT_UTILIZATION_PRECISION;
+    #[test]
+    fn batch_auction_makers_and_amm_clear_at_one_price() {
+        let clock = Clock {
+            slot: 56,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
 
-    use super::*;
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
 
-    #[test]
-    fn long_taker_order_fulfilled_start_of_auction() {
-        let mut taker_spot_positions = [SpotPosition::default(); 8];
-        taker_spot_positions[0] = SpotPosition {
-            market_index: 0,
-            scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
-            balance_type: SpotBalanceType::Deposit,
-            ..SpotPosition::default()
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                max_spread: 1000,
+                base_spread: 0,
+                long_spread: 0,
+                short_spread: 0,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: oracle_price.twap as i64,
+                    last_oracle_price_twap_5min: oracle_price.twap as i64,
+                    last_oracle_price: oracle_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            auction_clearing_mode: AuctionClearingMode::Batch,
+            ..PerpMarket::default()
         };
-        taker_spot_positions[1] = SpotPosition {
-            market_index: 1,
-            open_orders: 1,
-            open_bids: LAMPORTS_PER_SOL_I64,
-            ..SpotPosition::default()
+        market.amm.max_base_asset_reserve = u128::MAX;
+        market.amm.min_base_asset_reserve = 0;
+        let (new_ask_base_asset_reserve, new_ask_quote_asset_reserve) =
+            crate::math::amm_spread::calculate_spread_reserves(
+                &market.amm,
+                PositionDirection::Long,
+            )
+            .unwrap();
+        let (new_bid_base_asset_reserve, new_bid_quote_asset_reserve) =
+            crate::math::amm_spread::calculate_spread_reserves(
+                &market.amm,
+                PositionDirection::Short,
+            )
+            .unwrap();
+        market.amm.ask_base_asset_reserve = new_ask_base_asset_reserve;
+        market.amm.bid_base_asset_reserve = new_bid_base_asset_reserve;
+        market.amm.ask_quote_asset_reserve = new_ask_quote_asset_reserve;
+        market.amm.bid_quote_asset_reserve = new_bid_quote_asset_reserve;
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
+            ..SpotMarket::default()
         };
-        let mut taker = User {
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        // auction is over, the taker buys 1.5 up to 102
+        let taker_base_asset_amount = 3 * BASE_PRECISION_U64 / 2;
+        let mut user = User {
+            authority: Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap(), // different authority than filler
             orders: get_orders(Order {
-                market_index: 1,
-                market_type: MarketType::Spot,
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
                 order_type: OrderType::Market,
                 direction: PositionDirection::Long,
-                base_asset_amount: LAMPORTS_PER_SOL_U64,
+                base_asset_amount: taker_base_asset_amount,
                 slot: 0,
                 auction_start_price: 100 * PRICE_PRECISION_I64,
-                auction_end_price: 200 * PRICE_PRECISION_I64,
+                auction_end_price: 102 * PRICE_PRECISION_I64,
+                auction_duration: 10,
+                price: 102 * PRICE_PRECISION_U64,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_bids: taker_base_asset_amount as i64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        create_anchor_account_info!(user, User, user_account_info);
+        let user_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&user_account_info).unwrap();
+
+        create_anchor_account_info!(UserStats::default(), UserStats, user_stats_account_info);
+        let user_stats_account_loader: AccountLoader<UserStats> =
+            AccountLoader::try_from(&user_stats_account_info).unwrap();
+
+        // the maker only covers one of the 1.5
+        let maker_key = Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
+        let maker_authority =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let mut maker = User {
+            authority: maker_authority,
+            orders: get_orders(Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount: BASE_PRECISION_U64,
+                slot: 0,
+                price: 100 * PRICE_PRECISION_U64 + PRICE_PRECISION_U64 / 2,
+                post_only: true,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_asks: -BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        create_anchor_account_info!(maker, &maker_key, User, maker_account_info);
+        let makers_and_referrers = UserMap::load_one(&maker_account_info).unwrap();
+
+        let mut maker_stats = UserStats {
+            authority: maker_authority,
+            ..UserStats::default()
+        };
+        create_anchor_account_info!(maker_stats, UserStats, maker_stats_account_info);
+        let maker_and_referrer_stats = UserStatsMap::load_one(&maker_stats_account_info).unwrap();
+
+        let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        create_anchor_account_info!(User::default(), &filler_key, User, user_account_info);
+        let filler_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&user_account_info).unwrap();
+
+        create_anchor_account_info!(UserStats::default(), UserStats, filler_stats_account_info);
+        let filler_stats_account_loader: AccountLoader<UserStats> =
+            AccountLoader::try_from(&filler_stats_account_info).unwrap();
+
+        let state = State {
+            min_perp_auction_duration: 1,
+            default_market_order_time_in_force: 10,
+            ..State::default()
+        };
+
+        let (base_asset_amount, quote_asset_amount) = fill_perp_order_and_get_fill_amounts(
+            1,
+            &state,
+            &user_account_loader,
+            &user_stats_account_loader,
+            &spot_market_map,
+            &market_map,
+            &mut oracle_map,
+            &filler_account_loader,
+            &filler_stats_account_loader,
+            &makers_and_referrers,
+            &maker_and_referrer_stats,
+            None,
+            &clock,
+            FillMode::Fill,
+        )
+        .unwrap();
+
+        // the maker fills one and the amm the other half
+        assert_eq!(base_asset_amount, taker_base_asset_amount);
+        let maker = makers_and_referrers.get_ref(&maker_key).unwrap();
+        assert_eq!(
+            maker.perp_positions[0].base_asset_amount,
+            -BASE_PRECISION_I64
+        );
+        let market = market_map.get_ref(&0).unwrap();
+        assert_eq!(
+            market.amm.base_asset_amount_with_amm,
+            (BASE_PRECISION_U64 / 2) as i128
+        );
+
+        // the amm covers the last half around 101, well under the taker's limit of 102
+        let fill_price =
+            calculate_fill_price(quote_asset_amount, base_asset_amount, BASE_PRECISION_U64)
+                .unwrap();
+        assert!(fill_price > 100 * PRICE_PRECISION_U64 + PRICE_PRECISION_U64 / 2);
+        assert!(fill_price < 101 * PRICE_PRECISION_U64 + PRICE_PRECISION_U64 / 10);
+    }
+}
+
+#[cfg(test)]
+pub mod fulfill_spot_order_with_match {
+    use crate::controller::orders::fulfill_spot_order_with_match;
+    use crate::controller::position::PositionDirection;
+    use crate::math::constants::{
+        LAMPORTS_PER_SOL_I64, LAMPORTS_PER_SOL_U64, PRICE_PRECISION_I64, PRICE_PRECISION_U64,
+        QUOTE_PRECISION_U64, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
+    };
+    use crate::math::spot_balance::calculate_utilization;
+    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+    use crate::state::user::{MarketType, Order, OrderType, SpotPosition, User, UserStats};
+    use crate::test_utils::get_orders;
+    use crate::SPOT_UTILIZATION_PRECISION;
+
+    use super::*;
+
+    #[test]
+    fn long_taker_order_fulfilled_start_of_auction() {
+        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        taker_spot_positions[0] = SpotPosition {
+            market_index: 0,
+            scaled_balance: 101 * SPOT_BALANCE_PRECISION_U64,
+            balance_type: SpotBalanceType::Deposit,
+            ..SpotPosition::default()
+        };
+        taker_spot_positions[1] = SpotPosition {
+            market_index: 1,
+            open_orders: 1,
+            open_bids: LAMPORTS_PER_SOL_I64,
+            ..SpotPosition::default()
+        };
+        let mut taker = User {
+            orders: get_orders(Order {
+                market_index: 1,
+                market_type: MarketType::Spot,
+                order_type: OrderType::Market,
+                direction: PositionDirection::Long,
+                base_asset_amount: LAMPORTS_PER_SOL_U64,
+                slot: 0,
+                auction_start_price: 100 * PRICE_PRECISION_I64,
+                auction_end_price: 200 * PRICE_PRECISION_I64,
                 auction_duration: 5,
                 ..Order::default()
             }),
@@ -9149,61 +9775,20 @@ pub mod force_cancel_orders {
     }
 }
 
-pub mod insert_maker_order_info {
-    use crate::controller::orders::insert_maker_order_info;
-    use crate::controller::position::PositionDirection;
-    use solana_program::pubkey::Pubkey;
-
-    #[test]
-    fn bids() {
-        let mut bids = Vec::with_capacity(3);
-        bids.push((Pubkey::default(), 1, 10));
-        bids.push((Pubkey::default(), 0, 1));
-        let maker_direction = PositionDirection::Long;
-
-        insert_maker_order_info(&mut bids, (Pubkey::default(), 2, 100), maker_direction);
-
-        assert_eq!(
-            bids,
-            vec![
-                (Pubkey::default(), 2, 100),
-                (Pubkey::default(), 1, 10),
-                (Pubkey::default(), 0, 1),
-            ]
-        );
-    }
-
-    #[test]
-    fn asks() {
-        let mut asks = Vec::with_capacity(3);
-        asks.push((Pubkey::default(), 0, 1));
-        asks.push((Pubkey::default(), 1, 10));
-        let maker_direction = PositionDirection::Short;
-
-        insert_maker_order_info(&mut asks, (Pubkey::default(), 2, 100), maker_direction);
-
-        assert_eq!(
-            asks,
-            vec![
-                (Pubkey::default(), 0, 1),
-                (Pubkey::default(), 1, 10),
-                (Pubkey::default(), 2, 100)
-            ]
-        );
-    }
-}
-
-pub mod get_maker_orders_info {
+pub mod cancel_orders_after_deadline {
     use std::str::FromStr;
 
     use anchor_lang::prelude::{AccountLoader, Clock};
 
-    use crate::controller::orders::get_maker_orders_info;
+    use crate::controller::orders::cancel_orders_after_deadline;
     use crate::controller::position::PositionDirection;
+    use crate::create_account_info;
+    use crate::create_anchor_account_info;
+    use crate::error::ErrorCode;
     use crate::math::constants::{
-        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64, PEG_PRECISION,
-        PRICE_PRECISION_I64, PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION_U64,
-        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64, LAMPORTS_PER_SOL_I64,
+        LAMPORTS_PER_SOL_U64, PEG_PRECISION, PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION,
+        SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
     };
     use crate::state::oracle::HistoricalOracleData;
     use crate::state::oracle::OracleSource;
@@ -9211,34 +9796,31 @@ pub mod get_maker_orders_info {
     use crate::state::perp_market_map::PerpMarketMap;
     use crate::state::spot_market::{SpotBalanceType, SpotMarket};
     use crate::state::spot_market_map::SpotMarketMap;
-    use crate::state::user::{OrderStatus, OrderType, SpotPosition, User};
-    use crate::state::user_map::UserMap;
+    use crate::state::state::State;
+    use crate::state::user::{MarketType, OrderStatus, OrderType, SpotPosition, User, UserStats};
     use crate::test_utils::*;
     use crate::test_utils::{
-        create_account_info, get_orders, get_positions, get_pyth_price, get_spot_positions,
+        create_account_info, get_positions, get_pyth_price, get_spot_positions,
     };
-    use crate::{create_account_info, get_orders};
-    use crate::{create_anchor_account_info, QUOTE_PRECISION_I64};
 
     use super::*;
 
     #[test]
-    fn one_maker_order_canceled_for_breaching_oracle_price_band() {
-        let clock = Clock {
-            slot: 56,
+    fn cancel_all_orders_once_deadline_passed() {
+        let mut clock = Clock {
+            slot: 6,
             epoch_start_timestamp: 0,
             epoch: 0,
             leader_schedule_epoch: 0,
-            unix_timestamp: 0,
+            unix_timestamp: 10,
         };
 
-        let mut pyth_price = get_pyth_price(100, 6);
-        let oracle_price = 100 * PRICE_PRECISION_I64;
+        let mut oracle_price = get_pyth_price(100, 6);
         let oracle_price_key =
             Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
         let pyth_program = crate::ids::pyth_program::id();
         create_account_info!(
-            pyth_price,
+            oracle_price,
             &oracle_price_key,
             &pyth_program,
             oracle_account_info
@@ -9250,6 +9832,10 @@ pub mod get_maker_orders_info {
                 base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
                 quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
                 terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                // bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                // bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                // ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                // ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
                 sqrt_k: 100 * AMM_RESERVE_PRECISION,
                 peg_multiplier: 100 * PEG_PRECISION,
                 max_slippage_ratio: 100,
@@ -9262,9 +9848,9 @@ pub mod get_maker_orders_info {
                 long_spread: 0,
                 short_spread: 0,
                 historical_oracle_data: HistoricalOracleData {
-                    last_oracle_price_twap: pyth_price.twap as i64,
-                    last_oracle_price_twap_5min: pyth_price.twap as i64,
-                    last_oracle_price: pyth_price.agg.price as i64,
+                    last_oracle_price_twap: oracle_price.twap as i64,
+                    last_oracle_price_twap_5min: oracle_price.twap as i64,
+                    last_oracle_price: oracle_price.agg.price as i64,
                     ..HistoricalOracleData::default()
                 },
                 ..AMM::default()
@@ -9296,134 +9882,250 @@ pub mod get_maker_orders_info {
         create_anchor_account_info!(market, PerpMarket, market_account_info);
         let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
 
-        let mut spot_market = SpotMarket {
+        let mut usdc_spot_market = SpotMarket {
             market_index: 0,
             oracle_source: OracleSource::QuoteAsset,
+            deposit_balance: SPOT_BALANCE_PRECISION,
             cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
             decimals: 6,
             initial_asset_weight: SPOT_WEIGHT_PRECISION,
             maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
-            historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
             ..SpotMarket::default()
         };
-        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
-        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+        create_anchor_account_info!(usdc_spot_market, SpotMarket, usdc_spot_market_account_info);
 
-        let taker_key = Pubkey::default();
-        let taker_authority =
-            Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
-        let user = User {
-            authority: taker_authority,
-            orders: get_orders(Order {
-                market_index: 0,
-                order_id: 1,
-                status: OrderStatus::Open,
-                order_type: OrderType::Market,
-                direction: PositionDirection::Long,
-                base_asset_amount: BASE_PRECISION_U64,
-                slot: 0,
-                auction_start_price: 0,
-                auction_end_price: 50 * PRICE_PRECISION_I64,
-                auction_duration: 5,
-                price: 50 * PRICE_PRECISION_U64,
-                ..Order::default()
-            }),
-            perp_positions: get_positions(PerpPosition {
-                market_index: 0,
-                open_orders: 1,
-                open_bids: BASE_PRECISION_I64,
-                ..PerpPosition::default()
-            }),
-            spot_positions: get_spot_positions(SpotPosition {
-                market_index: 0,
-                balance_type: SpotBalanceType::Deposit,
-                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
-                ..SpotPosition::default()
-            }),
-            ..User::default()
+        let mut sol_spot_market = SpotMarket {
+            market_index: 1,
+            deposit_balance: SPOT_BALANCE_PRECISION,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            oracle: oracle_price_key,
+            ..SpotMarket::default_base_market()
         };
+        create_anchor_account_info!(sol_spot_market, SpotMarket, sol_spot_market_account_info);
 
-        let mut maker_orders = [Order::default(); 32];
-        maker_orders[0] = Order {
+        let spot_market_map = SpotMarketMap::load_multiple(
+            vec![
+                &usdc_spot_market_account_info,
+                &sol_spot_market_account_info,
+            ],
+            true,
+        )
+        .unwrap();
+
+        let mut orders = [Order::default(); 32];
+        orders[0] = Order {
             market_index: 0,
             order_id: 1,
             status: OrderStatus::Open,
             order_type: OrderType::Limit,
-            direction: PositionDirection::Short,
-            base_asset_amount: BASE_PRECISION_U64,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            base_asset_amount: 100 * BASE_PRECISION_U64,
             slot: 0,
-            price: 50 * PRICE_PRECISION_U64,
-            post_only: true,
+            price: 102 * PRICE_PRECISION_U64,
             ..Order::default()
         };
-        maker_orders[1] = Order {
+        orders[1] = Order {
             market_index: 0,
             order_id: 2,
             status: OrderStatus::Open,
             order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
             direction: PositionDirection::Short,
             base_asset_amount: BASE_PRECISION_U64,
             slot: 0,
-            price: 100 * PRICE_PRECISION_U64,
-            post_only: true,
+            price: 102 * PRICE_PRECISION_U64,
+            ..Order::default()
+        };
+        orders[2] = Order {
+            market_index: 1,
+            order_id: 1,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Spot,
+            direction: PositionDirection::Long,
+            base_asset_amount: 100 * LAMPORTS_PER_SOL_U64,
+            slot: 0,
+            price: 102 * PRICE_PRECISION_U64,
+            ..Order::default()
+        };
+        orders[3] = Order {
+            market_index: 1,
+            order_id: 1,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Spot,
+            direction: PositionDirection::Short,
+            base_asset_amount: LAMPORTS_PER_SOL_U64,
+            slot: 0,
+            price: 102 * PRICE_PRECISION_U64,
             ..Order::default()
         };
 
-        let mut maker = User {
-            orders: maker_orders,
+        let mut user = User {
+            authority: Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap(), // different authority than filler
+            orders,
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
+                base_asset_amount: BASE_PRECISION_I64,
                 open_orders: 2,
-                open_asks: -2 * BASE_PRECISION_I64,
+                open_bids: 100 * BASE_PRECISION_I64,
+                open_asks: -BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
             spot_positions: get_spot_positions(SpotPosition {
-                market_index: 0,
+                market_index: 1,
                 balance_type: SpotBalanceType::Deposit,
-                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                scaled_balance: SPOT_BALANCE_PRECISION_U64,
+                open_orders: 2,
+                open_bids: 100 * LAMPORTS_PER_SOL_I64,
+                open_asks: -LAMPORTS_PER_SOL_I64,
                 ..SpotPosition::default()
             }),
+            cancel_after_ts: 11,
             ..User::default()
         };
-        let maker_key = Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
-        create_anchor_account_info!(maker, &maker_key, User, maker_account_info);
+        create_anchor_account_info!(user, User, user_account_info);
+        let user_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&user_account_info).unwrap();
 
-        let makers_and_referrers = UserMap::load_one(&maker_account_info).unwrap();
+        create_anchor_account_info!(UserStats::default(), UserStats, user_stats_account_info);
+        let _user_stats_account_loader: AccountLoader<UserStats> =
+            AccountLoader::try_from(&user_stats_account_info).unwrap();
 
         let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
-        let mut filler = User::default();
+        create_anchor_account_info!(User::default(), &filler_key, User, user_account_info);
+        let filler_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&user_account_info).unwrap();
 
-        let maker_order_price_and_indexes = get_maker_orders_info(
+        create_anchor_account_info!(UserStats::default(), UserStats, filler_stats_account_info);
+        let _filler_stats_account_loader: AccountLoader<UserStats> =
+            AccountLoader::try_from(&filler_stats_account_info).unwrap();
+
+        let state = State {
+            min_perp_auction_duration: 1,
+            default_market_order_time_in_force: 10,
+            ..State::default()
+        };
+
+        let result = cancel_orders_after_deadline(
+            &state,
+            &user_account_loader,
+            &spot_market_map,
             &market_map,
+            &mut oracle_map,
+            &filler_account_loader,
+            &clock,
+        );
+        assert_eq!(result, Err(ErrorCode::CancelAfterDeadlineNotReached));
+
+        clock.unix_timestamp = 11;
+
+        cancel_orders_after_deadline(
+            &state,
+            &user_account_loader,
             &spot_market_map,
+            &market_map,
             &mut oracle_map,
-            &makers_and_referrers,
-            &taker_key,
-            &user.orders[0],
-            &mut Some(&mut filler),
-            &filler_key,
-            0,
-            oracle_price,
-            None,
-            clock.unix_timestamp,
-            clock.slot,
+            &filler_account_loader,
+            &clock,
         )
         .unwrap();
 
+        let user = user_account_loader.load().unwrap();
+        assert_eq!(user.orders[0], Order::default());
+        assert_eq!(user.orders[1], Order::default());
+        assert_eq!(user.orders[2], Order::default());
+        assert_eq!(user.orders[3], Order::default());
+        assert_eq!(user.cancel_after_ts, 0);
+        assert_eq!(user.perp_positions[0].open_orders, 0);
+        assert_eq!(user.spot_positions[1].open_orders, 0);
+    }
+}
+
+pub mod insert_maker_order_info {
+    use crate::controller::orders::insert_maker_order_info;
+    use crate::controller::position::PositionDirection;
+    use solana_program::pubkey::Pubkey;
+
+    #[test]
+    fn bids() {
+        let mut bids = Vec::with_capacity(3);
+        bids.push((Pubkey::default(), 1, 10));
+        bids.push((Pubkey::default(), 0, 1));
+        let maker_direction = PositionDirection::Long;
+
+        insert_maker_order_info(&mut bids, (Pubkey::default(), 2, 100), maker_direction);
+
         assert_eq!(
-            maker_order_price_and_indexes,
-            vec![(maker_key, 1, 100 * PRICE_PRECISION_U64)]
+            bids,
+            vec![
+                (Pubkey::default(), 2, 100),
+                (Pubkey::default(), 1, 10),
+                (Pubkey::default(), 0, 1),
+            ]
         );
     }
 
     #[test]
-    fn one_maker_order_canceled_for_being_expired() {
+    fn asks() {
+        let mut asks = Vec::with_capacity(3);
+        asks.push((Pubkey::default(), 0, 1));
+        asks.push((Pubkey::default(), 1, 10));
+        let maker_direction = PositionDirection::Short;
+
+        insert_maker_order_info(&mut asks, (Pubkey::default(), 2, 100), maker_direction);
+
+        assert_eq!(
+            asks,
+            vec![
+                (Pubkey::default(), 0, 1),
+                (Pubkey::default(), 1, 10),
+                (Pubkey::default(), 2, 100)
+            ]
+        );
+    }
+}
+
+pub mod get_maker_orders_info {
+    use std::str::FromStr;
+
+    use anchor_lang::prelude::{AccountLoader, Clock};
+
+    use crate::controller::orders::get_maker_orders_info;
+    use crate::controller::position::PositionDirection;
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64, PEG_PRECISION,
+        PRICE_PRECISION_I64, PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION_U64,
+        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+    };
+    use crate::state::oracle::HistoricalOracleData;
+    use crate::state::oracle::OracleSource;
+    use crate::state::perp_market::{PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::user::{OrderStatus, OrderType, SpotPosition, User};
+    use crate::state::user_map::UserMap;
+    use crate::test_utils::*;
+    use crate::test_utils::{
+        create_account_info, get_orders, get_positions, get_pyth_price, get_spot_positions,
+    };
+    use crate::{create_account_info, get_orders};
+    use crate::{create_anchor_account_info, QUOTE_PRECISION_I64};
+
+    use super::*;
+
+    #[test]
+    fn one_maker_order_canceled_for_breaching_oracle_price_band() {
         let clock = Clock {
             slot: 56,
             epoch_start_timestamp: 0,
             epoch: 0,
             leader_schedule_epoch: 0,
-            unix_timestamp: 6,
+            unix_timestamp: 0,
         };
 
         let mut pyth_price = get_pyth_price(100, 6);
@@ -9519,7 +10221,7 @@ pub mod get_maker_orders_info {
                 auction_start_price: 0,
                 auction_end_price: 50 * PRICE_PRECISION_I64,
                 auction_duration: 5,
-                price: 100 * PRICE_PRECISION_U64,
+                price: 50 * PRICE_PRECISION_U64,
                 ..Order::default()
             }),
             perp_positions: get_positions(PerpPosition {
@@ -9546,8 +10248,7 @@ pub mod get_maker_orders_info {
             direction: PositionDirection::Short,
             base_asset_amount: BASE_PRECISION_U64,
             slot: 0,
-            price: 100 * PRICE_PRECISION_U64,
-            max_ts: 1,
+            price: 50 * PRICE_PRECISION_U64,
             post_only: true,
             ..Order::default()
         };
@@ -9612,13 +10313,13 @@ pub mod get_maker_orders_info {
     }
 
     #[test]
-    fn one_maker_order_canceled_for_being_reduce_only() {
+    fn one_maker_order_canceled_for_being_expired() {
         let clock = Clock {
-            slot: 6,
+            slot: 56,
             epoch_start_timestamp: 0,
             epoch: 0,
             leader_schedule_epoch: 0,
-            unix_timestamp: 0,
+            unix_timestamp: 6,
         };
 
         let mut pyth_price = get_pyth_price(100, 6);
@@ -9715,7 +10416,6 @@ pub mod get_maker_orders_info {
                 auction_end_price: 50 * PRICE_PRECISION_I64,
                 auction_duration: 5,
                 price: 100 * PRICE_PRECISION_U64,
-                max_ts: 1,
                 ..Order::default()
             }),
             perp_positions: get_positions(PerpPosition {
@@ -9743,7 +10443,20 @@ pub mod get_maker_orders_info {
             base_asset_amount: BASE_PRECISION_U64,
             slot: 0,
             price: 100 * PRICE_PRECISION_U64,
-            reduce_only: true,
+            max_ts: 1,
+            post_only: true,
+            ..Order::default()
+        };
+        maker_orders[1] = Order {
+            market_index: 0,
+            order_id: 2,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION_U64,
+            slot: 0,
+            price: 100 * PRICE_PRECISION_U64,
+            post_only: true,
             ..Order::default()
         };
 
@@ -9751,9 +10464,8 @@ pub mod get_maker_orders_info {
             orders: maker_orders,
             perp_positions: get_positions(PerpPosition {
                 market_index: 0,
-                base_asset_amount: -BASE_PRECISION_I64,
-                open_orders: 1,
-                open_asks: -BASE_PRECISION_I64,
+                open_orders: 2,
+                open_asks: -2 * BASE_PRECISION_I64,
                 ..PerpPosition::default()
             }),
             spot_positions: get_spot_positions(SpotPosition {
@@ -9789,11 +10501,195 @@ pub mod get_maker_orders_info {
         )
         .unwrap();
 
-        assert_eq!(maker_order_price_and_indexes, vec![],);
+        assert_eq!(
+            maker_order_price_and_indexes,
+            vec![(maker_key, 1, 100 * PRICE_PRECISION_U64)]
+        );
     }
 
     #[test]
-    fn two_makers() {
+    fn one_maker_order_canceled_for_being_reduce_only() {
+        let clock = Clock {
+            slot: 6,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut pyth_price = get_pyth_price(100, 6);
+        let oracle_price = 100 * PRICE_PRECISION_I64;
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            pyth_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                max_spread: 1000,
+                base_spread: 0,
+                long_spread: 0,
+                short_spread: 0,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: pyth_price.twap as i64,
+                    last_oracle_price_twap_5min: pyth_price.twap as i64,
+                    last_oracle_price: pyth_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Initialized,
+            ..PerpMarket::default()
+        };
+        market.status = MarketStatus::Active;
+        market.amm.max_base_asset_reserve = u128::MAX;
+        market.amm.min_base_asset_reserve = 0;
+        let (new_ask_base_asset_reserve, new_ask_quote_asset_reserve) =
+            crate::math::amm_spread::calculate_spread_reserves(
+                &market.amm,
+                PositionDirection::Long,
+            )
+            .unwrap();
+        let (new_bid_base_asset_reserve, new_bid_quote_asset_reserve) =
+            crate::math::amm_spread::calculate_spread_reserves(
+                &market.amm,
+                PositionDirection::Short,
+            )
+            .unwrap();
+        market.amm.ask_base_asset_reserve = new_ask_base_asset_reserve;
+        market.amm.bid_base_asset_reserve = new_bid_base_asset_reserve;
+        market.amm.ask_quote_asset_reserve = new_ask_quote_asset_reserve;
+        market.amm.bid_quote_asset_reserve = new_bid_quote_asset_reserve;
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let taker_key = Pubkey::default();
+        let taker_authority =
+            Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        let user = User {
+            authority: taker_authority,
+            orders: get_orders(Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::Market,
+                direction: PositionDirection::Long,
+                base_asset_amount: BASE_PRECISION_U64,
+                slot: 0,
+                auction_start_price: 0,
+                auction_end_price: 50 * PRICE_PRECISION_I64,
+                auction_duration: 5,
+                price: 100 * PRICE_PRECISION_U64,
+                max_ts: 1,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_bids: BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+
+        let mut maker_orders = [Order::default(); 32];
+        maker_orders[0] = Order {
+            market_index: 0,
+            order_id: 1,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION_U64,
+            slot: 0,
+            price: 100 * PRICE_PRECISION_U64,
+            reduce_only: true,
+            ..Order::default()
+        };
+
+        let mut maker = User {
+            orders: maker_orders,
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                base_asset_amount: -BASE_PRECISION_I64,
+                open_orders: 1,
+                open_asks: -BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        let maker_key = Pubkey::from_str("My11111111111111111111111111111111111111113").unwrap();
+        create_anchor_account_info!(maker, &maker_key, User, maker_account_info);
+
+        let makers_and_referrers = UserMap::load_one(&maker_account_info).unwrap();
+
+        let filler_key = Pubkey::from_str("My11111111111111111111111111111111111111111").unwrap();
+        let mut filler = User::default();
+
+        let maker_order_price_and_indexes = get_maker_orders_info(
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            &makers_and_referrers,
+            &taker_key,
+            &user.orders[0],
+            &mut Some(&mut filler),
+            &filler_key,
+            0,
+            oracle_price,
+            None,
+            clock.unix_timestamp,
+            clock.slot,
+        )
+        .unwrap();
+
+        assert_eq!(maker_order_price_and_indexes, vec![],);
+    }
+
+    #[test]
+    fn two_makers() {
         let clock = Clock {
             slot: 6,
             epoch_start_timestamp: 0,
@@ -10466,6 +11362,265 @@ pub mod get_maker_orders_info {
     }
 }
 
+pub mod trigger_order {
+    use std::str::FromStr;
+
+    use anchor_lang::prelude::{AccountLoader, Clock};
+
+    use crate::controller::orders::trigger_order;
+    use crate::controller::position::PositionDirection;
+    use crate::create_anchor_account_info;
+    use crate::error::{DriftResult, ErrorCode};
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64, PEG_PRECISION,
+        SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+    };
+    use crate::state::oracle::{HistoricalOracleData, OracleSource};
+    use crate::state::perp_market::{PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::state::State;
+    use crate::state::user::{
+        OrderStatus, OrderTriggerCondition, OrderType, SpotPosition, TriggerSource, User,
+    };
+    use crate::test_utils::{
+        create_account_info, get_orders, get_positions, get_pyth_price, get_spot_positions,
+    };
+    use crate::{create_account_info, QUOTE_PRECISION_I64};
+
+    use super::*;
+
+    /// Triggers a reduce only trigger market order on a market with an oracle price of 100
+    fn trigger_with_source(
+        trigger_source: TriggerSource,
+        trigger_condition: OrderTriggerCondition,
+        trigger_price: u64,
+        last_funding_rate: i64,
+        peg_multiplier: u128,
+    ) -> DriftResult<Order> {
+        let clock = Clock {
+            slot: 56,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                terminal_quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier,
+                max_slippage_ratio: 100,
+                max_fill_reserve_fraction: 100,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                max_spread: 1000,
+                last_funding_rate,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: oracle_price.twap as i64,
+                    last_oracle_price_twap_5min: oracle_price.twap as i64,
+                    last_oracle_price: oracle_price.agg.price as i64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            ..PerpMarket::default()
+        };
+        market.amm.max_base_asset_reserve = u128::MAX;
+        market.amm.min_base_asset_reserve = 0;
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+        let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+        let mut user = User {
+            orders: get_orders(Order {
+                market_index: 0,
+                order_id: 1,
+                status: OrderStatus::Open,
+                order_type: OrderType::TriggerMarket,
+                direction: PositionDirection::Long,
+                base_asset_amount: BASE_PRECISION_U64,
+                reduce_only: true,
+                trigger_price,
+                trigger_condition,
+                // untriggered orders keep the trigger source in the auction params
+                auction_start_price: trigger_source as i64,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                base_asset_amount: -BASE_PRECISION_I64,
+                quote_asset_amount: 100 * QUOTE_PRECISION_I64,
+                open_orders: 1,
+                ..PerpPosition::default()
+            }),
+            spot_positions: get_spot_positions(SpotPosition {
+                market_index: 0,
+                balance_type: SpotBalanceType::Deposit,
+                scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+                ..SpotPosition::default()
+            }),
+            ..User::default()
+        };
+        create_anchor_account_info!(user, User, user_account_info);
+        let user_account_loader: AccountLoader<User> =
+            AccountLoader::try_from(&user_account_info).unwrap();
+
+        trigger_order(
+            1,
+            &State::default(),
+            &user_account_loader,
+            &spot_market_map,
+            &market_map,
+            &mut oracle_map,
+            &user_account_loader,
+            &clock,
+        )?;
+
+        let order = user_account_loader.load().unwrap().orders[0];
+        Ok(order)
+    }
+
+    #[test]
+    fn funding_rate() {
+        // funding above the threshold
+        let order = trigger_with_source(
+            TriggerSource::FundingRate,
+            OrderTriggerCondition::Above,
+            500_000,
+            1_000_000,
+            100 * PEG_PRECISION,
+        )
+        .unwrap();
+        assert_eq!(
+            order.trigger_condition,
+            OrderTriggerCondition::TriggeredAbove
+        );
+        assert!(order.has_auction());
+
+        assert_eq!(
+            trigger_with_source(
+                TriggerSource::FundingRate,
+                OrderTriggerCondition::Above,
+                500_000,
+                100_000,
+                100 * PEG_PRECISION,
+            ),
+            Err(ErrorCode::OrderDidNotSatisfyTriggerCondition)
+        );
+
+        // below triggers once funding is under the negative threshold, not the threshold itself
+        assert_eq!(
+            trigger_with_source(
+                TriggerSource::FundingRate,
+                OrderTriggerCondition::Below,
+                500_000,
+                100_000,
+                100 * PEG_PRECISION,
+            ),
+            Err(ErrorCode::OrderDidNotSatisfyTriggerCondition)
+        );
+
+        let order = trigger_with_source(
+            TriggerSource::FundingRate,
+            OrderTriggerCondition::Below,
+            500_000,
+            -1_000_000,
+            100 * PEG_PRECISION,
+        )
+        .unwrap();
+        assert_eq!(
+            order.trigger_condition,
+            OrderTriggerCondition::TriggeredBelow
+        );
+    }
+
+    #[test]
+    fn mark_oracle_divergence() {
+        // mark of 101 is ~0.99% over the oracle of 100
+        let order = trigger_with_source(
+            TriggerSource::MarkOracleDivergence,
+            OrderTriggerCondition::Above,
+            5_000, // 0.5%
+            0,
+            101 * PEG_PRECISION,
+        )
+        .unwrap();
+        assert_eq!(
+            order.trigger_condition,
+            OrderTriggerCondition::TriggeredAbove
+        );
+
+        assert_eq!(
+            trigger_with_source(
+                TriggerSource::MarkOracleDivergence,
+                OrderTriggerCondition::Above,
+                20_000,
+                0,
+                101 * PEG_PRECISION,
+            ),
+            Err(ErrorCode::OrderDidNotSatisfyTriggerCondition)
+        );
+
+        // mark of 99 is ~1% under the oracle
+        assert_eq!(
+            trigger_with_source(
+                TriggerSource::MarkOracleDivergence,
+                OrderTriggerCondition::Below,
+                5_000,
+                0,
+                101 * PEG_PRECISION,
+            ),
+            Err(ErrorCode::OrderDidNotSatisfyTriggerCondition)
+        );
+
+        let order = trigger_with_source(
+            TriggerSource::MarkOracleDivergence,
+            OrderTriggerCondition::Below,
+            5_000,
+            0,
+            99 * PEG_PRECISION,
+        )
+        .unwrap();
+        assert_eq!(
+            order.trigger_condition,
+            OrderTriggerCondition::TriggeredBelow
+        );
+    }
+}
+
 pub mod update_trigger_order_params {
     use crate::controller::orders::update_trigger_order_params;
     use crate::state::oracle::OraclePriceData;
@@ -10597,3 +11752,148 @@ mod update_maker_fills_map {
         assert_eq!(*map.get(&maker_key).unwrap(), -2 * fill as i64);
     }
 }
+
+pub mod fulfill_spot_order_with_external_market {
+    use std::str::FromStr;
+
+    use anchor_lang::prelude::Clock;
+
+    use crate::controller::orders::fulfill_spot_order_with_external_market;
+    use crate::controller::position::PositionDirection;
+    use crate::create_account_info;
+    use crate::math::constants::{
+        LAMPORTS_PER_SOL_I64, LAMPORTS_PER_SOL_U64, PRICE_PRECISION_I64, PRICE_PRECISION_U64,
+        QUOTE_PRECISION_U64, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
+    };
+    use crate::state::events::OrderActionExplanation;
+    use crate::state::fulfillment_params::mock::MockVenue;
+    use crate::state::oracle::HistoricalOracleData;
+    use crate::state::spot_fulfillment_params::SpotFulfillmentParams;
+    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+    use crate::state::user::{MarketType, OrderStatus, OrderType, SpotPosition, User, UserStats};
+    use crate::test_utils::get_pyth_price;
+    use crate::test_utils::*;
+
+    use super::*;
+
+    #[test]
+    fn long_fills_across_mock_venue_levels() {
+        let clock = Clock {
+            slot: 6,
+            epoch_start_timestamp: 0,
+            epoch: 0,
+            leader_schedule_epoch: 0,
+            unix_timestamp: 0,
+        };
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, clock.slot, None).unwrap();
+
+        let mut base_market = SpotMarket {
+            market_index: 1,
+            deposit_balance: SPOT_BALANCE_PRECISION,
+            oracle: oracle_price_key,
+            historical_oracle_data: HistoricalOracleData::default_price(100 * PRICE_PRECISION_I64),
+            ..SpotMarket::default_base_market()
+        };
+        let mut quote_market = SpotMarket {
+            market_index: 0,
+            deposit_balance: 200 * SPOT_BALANCE_PRECISION,
+            ..SpotMarket::default_quote_market()
+        };
+
+        let mut taker_spot_positions = [SpotPosition::default(); 8];
+        taker_spot_positions[0] = SpotPosition {
+            market_index: 0,
+            scaled_balance: 200 * SPOT_BALANCE_PRECISION_U64,
+            balance_type: SpotBalanceType::Deposit,
+            ..SpotPosition::default()
+        };
+        taker_spot_positions[1] = SpotPosition {
+            market_index: 1,
+            open_orders: 1,
+            open_bids: LAMPORTS_PER_SOL_I64,
+            ..SpotPosition::default()
+        };
+        let mut taker_orders = [Order::default(); 32];
+        taker_orders[0] = Order {
+            order_id: 1,
+            market_index: 1,
+            market_type: MarketType::Spot,
+            order_type: OrderType::Limit,
+            status: OrderStatus::Open,
+            direction: PositionDirection::Long,
+            base_asset_amount: LAMPORTS_PER_SOL_U64,
+            price: 101 * PRICE_PRECISION_U64,
+            ..Order::default()
+        };
+        let mut taker = User {
+            orders: taker_orders,
+            spot_positions: taker_spot_positions,
+            open_orders: 1,
+            ..User::default()
+        };
+        let mut taker_stats = UserStats::default();
+
+        let mut mock_venue = MockVenue {
+            asks: vec![
+                (100 * PRICE_PRECISION_U64, LAMPORTS_PER_SOL_U64 / 2),
+                (101 * PRICE_PRECISION_U64, LAMPORTS_PER_SOL_U64),
+            ],
+            taker_fee_bps: 10,
+            base_decimals: 9,
+            quote_vault_amount: 200 * QUOTE_PRECISION_U64,
+            ..MockVenue::default()
+        };
+
+        let (base_asset_amount, quote_asset_amount) = fulfill_spot_order_with_external_market(
+            &mut base_market,
+            &mut quote_market,
+            &mut taker,
+            &mut taker_stats,
+            0,
+            &Pubkey::default(),
+            None,
+            None,
+            &Pubkey::default(),
+            clock.unix_timestamp,
+            clock.slot,
+            &mut oracle_map,
+            &FeeStructure::test_default(),
+            &mut mock_venue,
+        )
+        .unwrap();
+
+        // half at 100 and half at 101
+        assert_eq!(base_asset_amount, LAMPORTS_PER_SOL_U64);
+        assert_eq!(quote_asset_amount, 100_500_000);
+
+        assert_eq!(taker.orders[0], Order::default());
+        assert_eq!(
+            taker.spot_positions[1].scaled_balance,
+            SPOT_BALANCE_PRECISION_U64
+        );
+        // 100.5 paid plus the 10bps taker fee
+        assert_eq!(taker.spot_positions[0].scaled_balance, 99_399_500_000);
+        assert_eq!(taker_stats.fees.total_fee_paid, 100_500);
+
+        assert_eq!(
+            mock_venue.asks,
+            vec![(101 * PRICE_PRECISION_U64, LAMPORTS_PER_SOL_U64 / 2)]
+        );
+        assert_eq!(mock_venue.base_vault_amount, LAMPORTS_PER_SOL_U64);
+        assert!(
+            mock_venue.get_order_action_explanation().unwrap()
+                == OrderActionExplanation::OrderFillWithMockVenue
+        );
+    }
+}
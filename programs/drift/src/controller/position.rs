@@ -81,6 +81,20 @@ pub struct PositionDelta {
     pub base_asset_amount: i64,
 }
 
+/// Same as update_position_and_market but also counts the position being opened or flipped
+/// so take profit / stop loss set for an earlier position in the slot goes stale
+pub fn update_user_position_and_market(
+    user: &mut User,
+    position_index: usize,
+    market: &mut PerpMarket,
+    delta: &PositionDelta,
+) -> DriftResult<i64> {
+    let base_asset_amount_before = user.perp_positions[position_index].base_asset_amount;
+    let pnl = update_position_and_market(&mut user.perp_positions[position_index], market, delta)?;
+    user.update_perp_position_open_count(position_index, base_asset_amount_before);
+    Ok(pnl)
+}
+
 pub fn update_position_and_market(
     position: &mut PerpPosition,
     market: &mut PerpMarket,
@@ -433,8 +447,7 @@ pub fn update_position_with_base_asset_amount(
     base_asset_amount: u64,
     direction: PositionDirection,
     market: &mut PerpMarket,
-    user: &mut User,
-    position_index: usize,
+    position: &mut PerpPosition,
     fill_price: Option<u64>,
 ) -> DriftResult<(u64, i64, i64)> {
     let swap_direction = match direction {
@@ -458,11 +471,7 @@ pub fn update_position_with_base_asset_amount(
     let position_delta =
         get_position_delta_for_fill(base_asset_amount, quote_asset_amount, direction)?;
 
-    let pnl = update_position_and_market(
-        &mut user.perp_positions[position_index],
-        market,
-        &position_delta,
-    )?;
+    let pnl = update_position_and_market(position, market, &position_delta)?;
 
     market.amm.base_asset_amount_with_amm = market
         .amm
@@ -481,7 +490,7 @@ pub fn update_position_with_base_asset_amount(
     Ok((quote_asset_amount, quote_asset_amount_surplus, pnl))
 }
 
-fn calculate_quote_asset_amount_surplus(
+pub(crate) fn calculate_quote_asset_amount_surplus(
     position_direction: PositionDirection,
     quote_asset_swapped: u64,
     base_asset_amount: u64,
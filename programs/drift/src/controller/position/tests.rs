@@ -2,7 +2,8 @@ use crate::controller::amm::{
     calculate_base_swap_output_with_spread, move_price, recenter_perp_market_amm, swap_base_asset,
 };
 use crate::controller::position::{
-    update_lp_market_position, update_position_and_market, PositionDelta,
+    update_lp_market_position, update_position_and_market, update_user_position_and_market,
+    PositionDelta,
 };
 
 use crate::controller::lp::{apply_lp_rebase_to_perp_market, settle_lp_position};
@@ -13,13 +14,14 @@ use crate::math::constants::{
     PRICE_PRECISION_I64, PRICE_PRECISION_U64, QUOTE_PRECISION_I128,
 };
 use crate::math::position::swap_direction_to_close_position;
+use crate::state::events::OrderActionExplanation;
 use crate::state::oracle::OraclePriceData;
 use crate::state::oracle_map::OracleMap;
 use crate::state::perp_market::{AMMLiquiditySplit, PerpMarket, AMM};
 use crate::state::perp_market_map::PerpMarketMap;
 use crate::state::state::State;
-use crate::state::user::PerpPosition;
-use crate::test_utils::{create_account_info, get_account_bytes};
+use crate::state::user::{PerpPosition, PerpPositionTakeProfitStopLoss, User};
+use crate::test_utils::{create_account_info, get_account_bytes, get_positions};
 
 use crate::bn::U192;
 use crate::math::cp_curve::{adjust_k_cost, get_update_k_result, update_k};
@@ -1720,3 +1722,154 @@ fn test_move_amm() {
     assert_eq!(perp_market.amm.sqrt_k, new_k);
     assert_eq!(perp_market.amm.peg_multiplier, 5); // still same
 }
+
+#[test]
+fn take_profit_and_stop_loss_stale_after_close_and_flip() {
+    let mut user = User {
+        perp_positions: get_positions(PerpPosition {
+            base_asset_amount: 10,
+            quote_asset_amount: -10,
+            quote_entry_amount: -10,
+            quote_break_even_amount: -12,
+            last_cumulative_funding_rate: 1,
+            ..PerpPosition::default()
+        }),
+        ..User::default()
+    };
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_amount_with_amm: 10,
+            base_asset_amount_long: 10,
+            base_asset_amount_short: 0,
+            quote_asset_amount: -10,
+            quote_entry_amount_long: -10,
+            quote_break_even_amount_long: -12,
+            cumulative_funding_rate_long: 1,
+            cumulative_funding_rate_short: 1,
+            ..AMM::default_test()
+        },
+        number_of_users: 1,
+        number_of_users_with_base: 1,
+        ..PerpMarket::default_test()
+    };
+    let mut take_profit_stop_loss = PerpPositionTakeProfitStopLoss {
+        take_profit_price: 2 * PRICE_PRECISION_U64,
+        stop_loss_price: PRICE_PRECISION_U64 / 2,
+        ..PerpPositionTakeProfitStopLoss::default()
+    };
+    take_profit_stop_loss.set_position(&user).unwrap();
+
+    // reduce keeps them
+    let position_delta = PositionDelta {
+        base_asset_amount: -1,
+        quote_asset_amount: 5,
+    };
+    update_user_position_and_market(&mut user, 0, &mut market, &position_delta).unwrap();
+
+    assert_eq!(user.perp_positions[0].base_asset_amount, 9);
+    assert!(take_profit_stop_loss.is_for_position(&user));
+    assert!(
+        take_profit_stop_loss.get_triggered(&user, 2 * PRICE_PRECISION_U64)
+            == Some(OrderActionExplanation::PositionTakeProfit)
+    );
+
+    // flip leaves them for the old direction
+    let position_delta = PositionDelta {
+        base_asset_amount: -18,
+        quote_asset_amount: 18,
+    };
+    update_user_position_and_market(&mut user, 0, &mut market, &position_delta).unwrap();
+
+    assert_eq!(user.perp_positions[0].base_asset_amount, -9);
+    assert!(!take_profit_stop_loss.is_for_position(&user));
+    assert!(take_profit_stop_loss
+        .get_triggered(&user, 2 * PRICE_PRECISION_U64)
+        .is_none());
+
+    // flipping back to a long doesn't bring them back
+    let position_delta = PositionDelta {
+        base_asset_amount: 18,
+        quote_asset_amount: -18,
+    };
+    update_user_position_and_market(&mut user, 0, &mut market, &position_delta).unwrap();
+
+    assert_eq!(user.perp_positions[0].base_asset_amount, 9);
+    assert!(!take_profit_stop_loss.is_for_position(&user));
+    assert!(take_profit_stop_loss
+        .get_triggered(&user, 2 * PRICE_PRECISION_U64)
+        .is_none());
+
+    // close leaves them without a position
+    take_profit_stop_loss.set_position(&user).unwrap();
+    assert!(take_profit_stop_loss.is_for_position(&user));
+
+    let position_delta = PositionDelta {
+        base_asset_amount: -9,
+        quote_asset_amount: 9,
+    };
+    update_user_position_and_market(&mut user, 0, &mut market, &position_delta).unwrap();
+
+    assert_eq!(user.perp_positions[0].base_asset_amount, 0);
+    assert!(!take_profit_stop_loss.is_for_position(&user));
+    assert!(take_profit_stop_loss
+        .get_triggered(&user, PRICE_PRECISION_U64 / 2)
+        .is_none());
+}
+
+#[test]
+fn take_profit_and_stop_loss_stale_after_close_and_reopen() {
+    let mut user = User {
+        perp_positions: get_positions(PerpPosition {
+            base_asset_amount: 10,
+            quote_asset_amount: -10,
+            quote_entry_amount: -10,
+            quote_break_even_amount: -10,
+            ..PerpPosition::default()
+        }),
+        ..User::default()
+    };
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_amount_with_amm: 10,
+            base_asset_amount_long: 10,
+            quote_asset_amount: -10,
+            quote_entry_amount_long: -10,
+            quote_break_even_amount_long: -10,
+            ..AMM::default_test()
+        },
+        number_of_users: 1,
+        number_of_users_with_base: 1,
+        ..PerpMarket::default_test()
+    };
+    let mut take_profit_stop_loss = PerpPositionTakeProfitStopLoss {
+        take_profit_price: 2 * PRICE_PRECISION_U64,
+        stop_loss_price: PRICE_PRECISION_U64 / 2,
+        ..PerpPositionTakeProfitStopLoss::default()
+    };
+    take_profit_stop_loss.set_position(&user).unwrap();
+    assert!(take_profit_stop_loss.is_for_position(&user));
+
+    let position_delta = PositionDelta {
+        base_asset_amount: -10,
+        quote_asset_amount: 10,
+    };
+    update_user_position_and_market(&mut user, 0, &mut market, &position_delta).unwrap();
+    assert_eq!(user.perp_positions[0].base_asset_amount, 0);
+
+    // new long in the same slot
+    let position_delta = PositionDelta {
+        base_asset_amount: 10,
+        quote_asset_amount: -10,
+    };
+    update_user_position_and_market(&mut user, 0, &mut market, &position_delta).unwrap();
+
+    assert_eq!(user.perp_positions[0].base_asset_amount, 10);
+    assert_eq!(user.perp_position_open_counts[0], 1);
+    assert!(!take_profit_stop_loss.is_for_position(&user));
+    assert!(take_profit_stop_loss
+        .get_triggered(&user, 2 * PRICE_PRECISION_U64)
+        .is_none());
+    assert!(take_profit_stop_loss
+        .get_triggered(&user, PRICE_PRECISION_U64 / 2)
+        .is_none());
+}
@@ -1,13 +1,14 @@
-use std::cmp::min;
+use std::cmp::{max, min};
 
 use anchor_lang::prelude::AccountInfo;
 use anchor_lang::prelude::*;
 use solana_program::msg;
 
-use crate::controller::amm::update_spreads;
+use crate::controller::amm::{update_k_with_budget, update_spreads};
 use crate::controller::spot_balance::update_spot_balances;
 use crate::error::ErrorCode;
 use crate::error::*;
+use crate::get_then_update_id;
 use crate::load_mut;
 use crate::math::amm;
 use crate::math::bn;
@@ -18,15 +19,17 @@ use crate::math::constants::{
 use crate::math::cp_curve;
 use crate::math::cp_curve::get_update_k_result;
 use crate::math::cp_curve::UpdateKResult;
+use crate::math::funding::update_premium_index_twap;
 use crate::math::oracle;
 use crate::math::oracle::{is_oracle_valid_for_action, oracle_validity, DriftAction};
 use crate::math::repeg;
 use crate::math::safe_math::SafeMath;
 use crate::math::spot_balance::get_token_amount;
 
+use crate::state::events::CurveRecord;
 use crate::state::oracle::OraclePriceData;
 use crate::state::oracle_map::OracleMap;
-use crate::state::perp_market::{MarketStatus, PerpMarket};
+use crate::state::perp_market::{FundingFormula, MarketStatus, PerpMarket};
 use crate::state::perp_market_map::PerpMarketMap;
 use crate::state::spot_market::SpotBalanceType;
 use crate::state::spot_market_map::SpotMarketMap;
@@ -191,6 +194,10 @@ pub fn _update_amm(
     let reserve_price_after = market.amm.reserve_price()?;
 
     if is_oracle_valid_for_action(oracle_validity, Some(DriftAction::UpdateTwap))? {
+        if market.amm.funding_formula == FundingFormula::PremiumIndex {
+            update_premium_index_twap(market, now)?;
+        }
+
         let sanitize_clamp_denominator = market.get_sanitize_clamp_denominator()?;
 
         amm::update_oracle_price_twap(
@@ -216,6 +223,137 @@ pub fn _update_amm(
     Ok(amm_update_cost)
 }
 
+pub fn scheduled_curve_update(
+    market: &mut PerpMarket,
+    oracle_price_data: &OraclePriceData,
+    state: &State,
+    now: i64,
+) -> DriftResult<i128> {
+    validate!(
+        market.curve_update_interval > 0,
+        ErrorCode::InvalidScheduledCurveUpdate,
+        "scheduled curve updates disabled for perp market {}",
+        market.market_index
+    )?;
+
+    let next_scheduled_curve_update_ts = market
+        .last_scheduled_curve_update_ts
+        .safe_add(market.curve_update_interval.cast()?)?;
+    validate!(
+        now >= next_scheduled_curve_update_ts,
+        ErrorCode::InvalidScheduledCurveUpdate,
+        "next scheduled curve update for perp market {} at {}",
+        market.market_index,
+        next_scheduled_curve_update_ts
+    )?;
+
+    let oracle_validity = oracle_validity(
+        market.amm.historical_oracle_data.last_oracle_price_twap,
+        oracle_price_data,
+        &state.oracle_guard_rails.validity,
+    )?;
+    validate!(
+        is_oracle_valid_for_action(oracle_validity, Some(DriftAction::UpdateAMMCurve))?,
+        ErrorCode::InvalidOracle,
+        "oracle invalid for scheduled curve update ({:?})",
+        oracle_validity
+    )?;
+
+    // start a new budget period
+    if now
+        >= market
+            .curve_update_budget_period_start_ts
+            .safe_add(market.curve_update_budget_period.cast()?)?
+    {
+        market.curve_update_budget_period_start_ts = now;
+        market.curve_update_budget_spent = 0;
+    }
+
+    let peg_multiplier_before = market.amm.peg_multiplier;
+    let base_asset_reserve_before = market.amm.base_asset_reserve;
+    let quote_asset_reserve_before = market.amm.quote_asset_reserve;
+    let sqrt_k_before = market.amm.sqrt_k;
+
+    let budget_remaining = market
+        .curve_update_budget
+        .saturating_sub(market.curve_update_budget_spent);
+
+    // repeg towards the oracle with at most the budget left this period
+    let (optimal_peg, fee_budget, check_lower_bound) =
+        repeg::calculate_optimal_peg_and_budget(market, oracle_price_data)?;
+
+    let (repegged_market, repegged_cost) = repeg::adjust_amm(
+        market,
+        optimal_peg,
+        fee_budget.min(budget_remaining.cast()?),
+        true,
+    )?;
+
+    let mut adjustment_cost = 0_i128;
+    if apply_cost_to_market(market, repegged_cost, check_lower_bound)? {
+        cp_curve::update_k(
+            market,
+            &UpdateKResult {
+                sqrt_k: repegged_market.amm.sqrt_k,
+                base_asset_reserve: repegged_market.amm.base_asset_reserve,
+                quote_asset_reserve: repegged_market.amm.quote_asset_reserve,
+            },
+        )?;
+        market.amm.peg_multiplier = repegged_market.amm.peg_multiplier;
+        adjustment_cost = repegged_cost;
+    }
+
+    // if spread is low, spend half of what's left on a k increase
+    let budget_remaining = budget_remaining.saturating_sub(max(adjustment_cost, 0).cast()?);
+    let k_budget = if max(market.amm.long_spread, market.amm.short_spread) <= market.amm.base_spread
+    {
+        budget_remaining.safe_div(2)?.cast::<i64>()?
+    } else {
+        0
+    };
+
+    if let Some(k_adjustment_cost) = update_k_with_budget(market, k_budget)? {
+        adjustment_cost = adjustment_cost.safe_add(k_adjustment_cost)?;
+    }
+
+    market.curve_update_budget_spent = market
+        .curve_update_budget_spent
+        .safe_add(max(adjustment_cost, 0).cast()?)?;
+    market.last_scheduled_curve_update_ts = now;
+
+    let reserve_price_after = market.amm.reserve_price()?;
+    update_spreads(&mut market.amm, reserve_price_after)?;
+
+    emit!(CurveRecord {
+        ts: now,
+        record_id: get_then_update_id!(market, next_curve_record_id),
+        market_index: market.market_index,
+        peg_multiplier_before,
+        base_asset_reserve_before,
+        quote_asset_reserve_before,
+        sqrt_k_before,
+        peg_multiplier_after: market.amm.peg_multiplier,
+        base_asset_reserve_after: market.amm.base_asset_reserve,
+        quote_asset_reserve_after: market.amm.quote_asset_reserve,
+        sqrt_k_after: market.amm.sqrt_k,
+        base_asset_amount_long: market.amm.base_asset_amount_long.unsigned_abs(),
+        base_asset_amount_short: market.amm.base_asset_amount_short.unsigned_abs(),
+        base_asset_amount_with_amm: market.amm.base_asset_amount_with_amm,
+        number_of_users: market.number_of_users,
+        adjustment_cost,
+        total_fee: market.amm.total_fee,
+        total_fee_minus_distributions: market.amm.total_fee_minus_distributions,
+        oracle_price: oracle_price_data.price,
+        fill_record: market.next_fill_record_id as u128,
+        curve_update_budget_spent: market.curve_update_budget_spent,
+        curve_update_budget_remaining: market
+            .curve_update_budget
+            .saturating_sub(market.curve_update_budget_spent),
+    });
+
+    Ok(adjustment_cost)
+}
+
 pub fn update_amm_and_check_validity(
     market: &mut PerpMarket,
     oracle_price_data: &OraclePriceData,
@@ -532,3 +532,93 @@ pub fn update_amm_larg_conf_w_neg_tfmd_test() {
     assert_eq!((oracle_price_data.price as u64) > bid, true);
     assert_eq!((oracle_price_data.price as u64) < ask, true);
 }
+
+#[test]
+pub fn scheduled_curve_update_test() {
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_reserve: 65 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 63015384615,
+            terminal_quote_asset_reserve: 64 * AMM_RESERVE_PRECISION,
+            sqrt_k: 64 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 19_400 * PEG_PRECISION,
+            base_asset_amount_with_amm: -(AMM_RESERVE_PRECISION as i128),
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price_twap: 18_800 * PRICE_PRECISION_I64,
+                ..HistoricalOracleData::default()
+            },
+            total_fee_minus_distributions: 1000 * QUOTE_PRECISION as i128,
+            base_spread: 250,
+            curve_update_intensity: 100,
+            max_spread: 55500,
+            concentration_coef: 31020710,
+            ..AMM::default()
+        },
+        status: MarketStatus::Active,
+        contract_tier: ContractTier::B,
+        margin_ratio_initial: 555,
+        ..PerpMarket::default()
+    };
+    let (_, new_terminal_base_reserve) = amm::calculate_terminal_reserves(&market.amm).unwrap();
+    let (min_base_asset_reserve, max_base_asset_reserve) =
+        amm::calculate_bid_ask_bounds(market.amm.concentration_coef, new_terminal_base_reserve)
+            .unwrap();
+    market.amm.min_base_asset_reserve = min_base_asset_reserve;
+    market.amm.max_base_asset_reserve = max_base_asset_reserve;
+
+    let state = State {
+        oracle_guard_rails: OracleGuardRails {
+            validity: ValidityGuardRails {
+                slots_before_stale_for_amm: 10,
+                slots_before_stale_for_margin: 120,
+                confidence_interval_max_size: 1000,
+                too_volatile_ratio: 5,
+            },
+            ..OracleGuardRails::default()
+        },
+        ..State::default()
+    };
+
+    let oracle_price_data = OraclePriceData {
+        price: 18_500 * PRICE_PRECISION_I64,
+        confidence: 0,
+        delay: 2,
+        has_sufficient_number_of_data_points: true,
+    };
+
+    // disabled by default
+    let now = 10000;
+    assert!(scheduled_curve_update(&mut market, &oracle_price_data, &state, now).is_err());
+
+    market.curve_update_interval = 60;
+    market.curve_update_budget = QUOTE_PRECISION as u64;
+    market.curve_update_budget_period = 3600;
+
+    let peg_before = market.amm.peg_multiplier;
+    let cost = scheduled_curve_update(&mut market, &oracle_price_data, &state, now).unwrap();
+
+    // budget is too small to reach the oracle, peg moves part of the way
+    assert!(cost > 0);
+    assert!(market.amm.peg_multiplier < peg_before);
+    assert!(market.amm.reserve_price().unwrap() > oracle_price_data.price as u64);
+    assert_eq!(market.curve_update_budget_period_start_ts, now);
+    assert_eq!(market.curve_update_budget_spent, cost as u64);
+    assert_eq!(market.last_scheduled_curve_update_ts, now);
+    assert_eq!(market.next_curve_record_id, 1);
+
+    // too soon
+    assert!(scheduled_curve_update(&mut market, &oracle_price_data, &state, now + 30).is_err());
+
+    // same period, the budget left keeps shrinking
+    let spent_before = market.curve_update_budget_spent;
+    let peg_before = market.amm.peg_multiplier;
+    scheduled_curve_update(&mut market, &oracle_price_data, &state, now + 60).unwrap();
+    assert!(market.curve_update_budget_spent >= spent_before);
+    assert!(market.amm.peg_multiplier <= peg_before);
+    assert_eq!(market.curve_update_budget_period_start_ts, now);
+
+    // new period resets the budget
+    let cost = scheduled_curve_update(&mut market, &oracle_price_data, &state, now + 3600).unwrap();
+    assert_eq!(market.curve_update_budget_period_start_ts, now + 3600);
+    assert_eq!(market.curve_update_budget_spent, cost.max(0) as u64);
+}
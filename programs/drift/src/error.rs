@@ -521,6 +521,32 @@ pub enum ErrorCode {
     CantPayUserInitFee,
     #[msg("CantReclaimRent")]
     CantReclaimRent,
+    #[msg("InvalidOrderGroup")]
+    InvalidOrderGroup,
+    #[msg("BracketExitPending")]
+    BracketExitPending,
+    #[msg("InvalidPositionTakeProfitStopLoss")]
+    InvalidPositionTakeProfitStopLoss,
+    #[msg("InvalidScaleOrderParams")]
+    InvalidScaleOrderParams,
+    #[msg("CancelAfterDeadlineNotReached")]
+    CancelAfterDeadlineNotReached,
+    #[msg("FillOrKillOrderNotFilled")]
+    FillOrKillOrderNotFilled,
+    #[msg("MinFillNotMet")]
+    MinFillNotMet,
+    #[msg("PostOnlySlideTooFar")]
+    PostOnlySlideTooFar,
+    #[msg("InvalidComboOrderParams")]
+    InvalidComboOrderParams,
+    #[msg("ComboOrderNotFilled")]
+    ComboOrderNotFilled,
+    #[msg("ComboOrderNetPriceBreached")]
+    ComboOrderNetPriceBreached,
+    #[msg("InvalidScheduledCurveUpdate")]
+    InvalidScheduledCurveUpdate,
+    #[msg("TooManyAmmL2Levels")]
+    TooManyAmmL2Levels,
 }
 
 #[macro_export]
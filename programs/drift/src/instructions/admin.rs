@@ -12,14 +12,17 @@ use crate::instructions::constraints::*;
 use crate::load_mut;
 use crate::math::casting::Cast;
 use crate::math::constants::{
-    DEFAULT_BASE_ASSET_AMOUNT_STEP_SIZE, DEFAULT_LIQUIDATION_MARGIN_BUFFER_RATIO,
-    DEFAULT_QUOTE_ASSET_AMOUNT_TICK_SIZE, FEE_POOL_TO_REVENUE_POOL_THRESHOLD, IF_FACTOR_PRECISION,
-    INSURANCE_A_MAX, INSURANCE_B_MAX, INSURANCE_C_MAX, INSURANCE_SPECULATIVE_MAX,
-    LIQUIDATION_FEE_PRECISION, MAX_CONCENTRATION_COEFFICIENT, MAX_SQRT_K,
-    MAX_UPDATE_K_PRICE_CHANGE, QUOTE_SPOT_MARKET_INDEX, SPOT_CUMULATIVE_INTEREST_PRECISION,
-    SPOT_IMF_PRECISION, SPOT_WEIGHT_PRECISION, THIRTEEN_DAY, TWENTY_FOUR_HOUR,
+    BPS_PRECISION_U64, DEFAULT_BASE_ASSET_AMOUNT_STEP_SIZE,
+    DEFAULT_LIQUIDATION_MARGIN_BUFFER_RATIO, DEFAULT_QUOTE_ASSET_AMOUNT_TICK_SIZE,
+    FEE_POOL_TO_REVENUE_POOL_THRESHOLD, IF_FACTOR_PRECISION, INSURANCE_A_MAX, INSURANCE_B_MAX,
+    INSURANCE_C_MAX, INSURANCE_SPECULATIVE_MAX, LIQUIDATION_FEE_PRECISION,
+    MAX_AMM_JIT_INVENTORY_SCALE, MAX_CONCENTRATION_COEFFICIENT, MAX_FUNDING_INTEREST_RATE,
+    MAX_SQRT_K, MAX_UPDATE_K_PRICE_CHANGE, QUOTE_SPOT_MARKET_INDEX,
+    SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_IMF_PRECISION, SPOT_WEIGHT_PRECISION, THIRTEEN_DAY,
+    TWENTY_FOUR_HOUR,
 };
 use crate::math::cp_curve::get_update_k_result;
+use crate::math::funding::calculate_premium_index;
 use crate::math::oracle::{is_oracle_valid_for_action, DriftAction};
 use crate::math::orders::is_multiple_of_step_size;
 use crate::math::repeg::get_total_fee_lower_bound;
@@ -39,7 +42,8 @@ use crate::state::oracle::{
 };
 use crate::state::paused_operations::{PerpOperation, SpotOperation};
 use crate::state::perp_market::{
-    ContractTier, ContractType, InsuranceClaim, MarketStatus, PerpMarket, PoolBalance, AMM,
+    AuctionClearingMode, ContractTier, ContractType, FundingFormula, InsuranceClaim, MarketStatus,
+    MatchingMode, PerpMarket, PoolBalance, AMM,
 };
 use crate::state::spot_market::{
     AssetTier, InsuranceFund, SpotBalanceType, SpotFulfillmentConfigStatus, SpotMarket,
@@ -637,7 +641,15 @@ pub fn handle_initialize_perp_market(
         paused_operations: 0,
         quote_spot_market_index: 0,
         fee_adjustment: 0,
-        padding: [0; 46],
+        matching_mode: MatchingMode::PriceTime,
+        auction_clearing_mode: AuctionClearingMode::Continuous,
+        curve_update_interval: 0,
+        curve_update_budget: 0,
+        curve_update_budget_spent: 0,
+        curve_update_budget_period_start_ts: 0,
+        last_scheduled_curve_update_ts: 0,
+        curve_update_budget_period: 0,
+        premium_index_twap: 0,
         amm: AMM {
             oracle: *ctx.accounts.oracle.key,
             oracle_source,
@@ -726,13 +738,17 @@ pub fn handle_initialize_perp_market(
             last_oracle_valid: false,
             target_base_asset_amount_per_lp: 0,
             per_lp_base: 0,
-            padding1: 0,
-            padding2: 0,
+            funding_formula: FundingFormula::MarkOracleTwap,
+            funding_interest_rate: 0,
             total_fee_earned_per_lp: 0,
             net_unsettled_funding_pnl: 0,
             quote_asset_amount_with_unsettled_lp: 0,
             reference_price_offset: 0,
-            padding: [0; 12],
+            realized_volatility: 0,
+            realized_volatility_window: 0,
+            realized_volatility_spread_multiplier: 0,
+            amm_jit_inventory_scale: 0,
+            max_funding_premium: 0,
         },
     };
 
@@ -1083,6 +1099,8 @@ pub fn handle_repeg_amm_curve(ctx: Context<RepegCurve>, new_peg_candidate: u128)
         adjustment_cost,
         oracle_price,
         fill_record: 0,
+        curve_update_budget_spent: 0,
+        curve_update_budget_remaining: 0,
     });
 
     Ok(())
@@ -1318,6 +1336,8 @@ pub fn handle_update_k(ctx: Context<AdminUpdateK>, sqrt_k: u128) -> Result<()> {
         total_fee_minus_distributions,
         oracle_price,
         fill_record: 0,
+        curve_update_budget_spent: 0,
+        curve_update_budget_remaining: 0,
     });
 
     Ok(())
@@ -1808,6 +1828,40 @@ pub fn handle_update_perp_market_contract_tier(
     Ok(())
 }
 
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_matching_mode(
+    ctx: Context<AdminUpdatePerpMarket>,
+    matching_mode: MatchingMode,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    msg!(
+        "perp_market.matching_mode: {:?} -> {:?}",
+        perp_market.matching_mode,
+        matching_mode
+    );
+    perp_market.matching_mode = matching_mode;
+    Ok(())
+}
+
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_auction_clearing_mode(
+    ctx: Context<AdminUpdatePerpMarket>,
+    auction_clearing_mode: AuctionClearingMode,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    msg!(
+        "perp_market.auction_clearing_mode: {:?} -> {:?}",
+        perp_market.auction_clearing_mode,
+        auction_clearing_mode
+    );
+    perp_market.auction_clearing_mode = auction_clearing_mode;
+    Ok(())
+}
+
 #[access_control(
     perp_market_valid(&ctx.accounts.perp_market)
 )]
@@ -2091,6 +2145,31 @@ pub fn handle_update_amm_jit_intensity(
     Ok(())
 }
 
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_amm_jit_inventory_scale(
+    ctx: Context<AdminUpdatePerpMarket>,
+    amm_jit_inventory_scale: u16,
+) -> Result<()> {
+    validate!(
+        amm_jit_inventory_scale <= MAX_AMM_JIT_INVENTORY_SCALE,
+        ErrorCode::DefaultError,
+        "invalid amm_jit_inventory_scale",
+    )?;
+
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    msg!(
+        "perp_market.amm.amm_jit_inventory_scale: {} -> {}",
+        perp_market.amm.amm_jit_inventory_scale,
+        amm_jit_inventory_scale
+    );
+
+    perp_market.amm.amm_jit_inventory_scale = amm_jit_inventory_scale;
+
+    Ok(())
+}
+
 #[access_control(
     perp_market_valid(&ctx.accounts.perp_market)
 )]
@@ -2116,6 +2195,142 @@ pub fn handle_update_perp_market_max_spread(
     Ok(())
 }
 
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_curve_update_schedule(
+    ctx: Context<AdminUpdatePerpMarket>,
+    curve_update_interval: u32,
+    curve_update_budget: u64,
+    curve_update_budget_period: u32,
+) -> Result<()> {
+    validate!(
+        curve_update_interval == 0 || curve_update_budget_period >= curve_update_interval,
+        ErrorCode::DefaultError,
+        "curve_update_budget_period must be at least curve_update_interval",
+    )?;
+
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    msg!(
+        "perp_market.curve_update_interval: {} -> {}",
+        perp_market.curve_update_interval,
+        curve_update_interval
+    );
+    msg!(
+        "perp_market.curve_update_budget: {} -> {}",
+        perp_market.curve_update_budget,
+        curve_update_budget
+    );
+    msg!(
+        "perp_market.curve_update_budget_period: {} -> {}",
+        perp_market.curve_update_budget_period,
+        curve_update_budget_period
+    );
+
+    perp_market.curve_update_interval = curve_update_interval;
+    perp_market.curve_update_budget = curve_update_budget;
+    perp_market.curve_update_budget_period = curve_update_budget_period;
+
+    Ok(())
+}
+
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_realized_volatility(
+    ctx: Context<AdminUpdatePerpMarket>,
+    realized_volatility_window: u16,
+    realized_volatility_spread_multiplier: u16,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    msg!(
+        "perp_market.amm.realized_volatility_window: {} -> {}",
+        perp_market.amm.realized_volatility_window,
+        realized_volatility_window
+    );
+    msg!(
+        "perp_market.amm.realized_volatility_spread_multiplier: {} -> {}",
+        perp_market.amm.realized_volatility_spread_multiplier,
+        realized_volatility_spread_multiplier
+    );
+
+    if realized_volatility_window == 0 {
+        perp_market.amm.realized_volatility = 0;
+    }
+
+    perp_market.amm.realized_volatility_window = realized_volatility_window;
+    perp_market.amm.realized_volatility_spread_multiplier = realized_volatility_spread_multiplier;
+
+    Ok(())
+}
+
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_funding_formula(
+    ctx: Context<AdminUpdatePerpMarket>,
+    funding_formula: FundingFormula,
+    funding_interest_rate: i16,
+    max_funding_premium: u16,
+) -> Result<()> {
+    validate!(
+        funding_interest_rate.unsigned_abs() <= MAX_FUNDING_INTEREST_RATE.unsigned_abs(),
+        ErrorCode::DefaultError,
+        "funding_interest_rate must be between -{} and {}",
+        MAX_FUNDING_INTEREST_RATE,
+        MAX_FUNDING_INTEREST_RATE
+    )?;
+
+    validate!(
+        max_funding_premium.cast::<u64>()? <= BPS_PRECISION_U64,
+        ErrorCode::DefaultError,
+        "max_funding_premium must be <= {}",
+        BPS_PRECISION_U64
+    )?;
+
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    msg!(
+        "perp_market.amm.funding_formula: {:?} -> {:?}",
+        perp_market.amm.funding_formula,
+        funding_formula
+    );
+    msg!(
+        "perp_market.amm.funding_interest_rate: {} -> {}",
+        perp_market.amm.funding_interest_rate,
+        funding_interest_rate
+    );
+    msg!(
+        "perp_market.amm.max_funding_premium: {} -> {}",
+        perp_market.amm.max_funding_premium,
+        max_funding_premium
+    );
+
+    // start the twap from the current premium rather than 0
+    if funding_formula == FundingFormula::PremiumIndex
+        && perp_market.amm.funding_formula != FundingFormula::PremiumIndex
+    {
+        perp_market.premium_index_twap = calculate_premium_index(
+            perp_market.amm.last_bid_price_twap,
+            perp_market.amm.last_ask_price_twap,
+            perp_market
+                .amm
+                .historical_oracle_data
+                .last_oracle_price_twap,
+        )?
+        .cast()?;
+        msg!(
+            "perp_market.premium_index_twap: {}",
+            perp_market.premium_index_twap
+        );
+    }
+
+    perp_market.amm.funding_formula = funding_formula;
+    perp_market.amm.funding_interest_rate = funding_interest_rate;
+    perp_market.amm.max_funding_premium = max_funding_premium;
+
+    Ok(())
+}
+
 #[access_control(
     perp_market_valid(&ctx.accounts.perp_market)
 )]
@@ -18,6 +18,13 @@ pub fn can_sign_for_user(user: &AccountLoader<User>, signer: &Signer) -> anchor_
     })
 }
 
+pub fn is_authority_for_user(
+    user: &AccountLoader<User>,
+    authority: &AccountInfo,
+) -> anchor_lang::Result<bool> {
+    user.load().map(|user| user.authority.eq(authority.key))
+}
+
 pub fn is_stats_for_user(
     user: &AccountLoader<User>,
     user_stats: &AccountLoader<UserStats>,
@@ -1,6 +1,7 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Token, TokenAccount};
 
+use crate::controller::amm::{AMML2, MAX_AMM_L2_LEVELS};
 use crate::error::ErrorCode;
 use crate::instructions::constraints::*;
 use crate::instructions::optional_accounts::{
@@ -12,9 +13,7 @@ use crate::math::margin::calculate_user_equity;
 use crate::math::orders::{estimate_price_from_side, find_bids_and_asks_from_users};
 use crate::math::spot_withdraw::validate_spot_market_vault_amount;
 use crate::state::fill_mode::FillMode;
-use crate::state::fulfillment_params::drift::MatchFulfillmentParams;
-use crate::state::fulfillment_params::phoenix::PhoenixFulfillmentParams;
-use crate::state::fulfillment_params::serum::SerumFulfillmentParams;
+use crate::state::fulfillment_params::load_spot_fulfillment_params;
 use crate::state::insurance_fund_stake::InsuranceFundStake;
 use crate::state::oracle_map::OracleMap;
 use crate::state::paused_operations::PerpOperation;
@@ -23,13 +22,14 @@ use crate::state::perp_market_map::{
     get_market_set_for_user_positions, get_market_set_from_list, get_writable_perp_market_set,
     MarketSet, PerpMarketMap,
 };
-use crate::state::spot_fulfillment_params::SpotFulfillmentParams;
 use crate::state::spot_market::SpotMarket;
 use crate::state::spot_market_map::{
     get_writable_spot_market_set, get_writable_spot_market_set_from_many,
 };
 use crate::state::state::State;
-use crate::state::user::{MarketType, OrderStatus, User, UserStats};
+use crate::state::user::{
+    MarketType, OrderStatus, OrderType, PerpPositionTakeProfitStopLoss, User, UserStats,
+};
 use crate::state::user_map::load_user_maps;
 use crate::validation::user::validate_user_is_idle;
 use crate::{controller, load, math};
@@ -216,37 +216,17 @@ fn fill_spot_order<'info>(
 
     let (_referrer, _referrer_stats) = get_referrer_and_referrer_stats(remaining_accounts_iter)?;
 
-    let mut fulfillment_params: Box<dyn SpotFulfillmentParams> = match fulfillment_type {
-        SpotFulfillmentType::SerumV3 => {
-            let base_market = spot_market_map.get_ref(&market_index)?;
-            let quote_market = spot_market_map.get_quote_spot_market()?;
-            Box::new(SerumFulfillmentParams::new(
-                remaining_accounts_iter,
-                &ctx.accounts.state,
-                &base_market,
-                &quote_market,
-                clock.unix_timestamp,
-            )?)
-        }
-        SpotFulfillmentType::PhoenixV1 => {
-            let base_market = spot_market_map.get_ref(&market_index)?;
-            let quote_market = spot_market_map.get_quote_spot_market()?;
-            Box::new(PhoenixFulfillmentParams::new(
-                remaining_accounts_iter,
-                &ctx.accounts.state,
-                &base_market,
-                &quote_market,
-            )?)
-        }
-        SpotFulfillmentType::Match => {
-            let base_market = spot_market_map.get_ref(&market_index)?;
-            let quote_market = spot_market_map.get_quote_spot_market()?;
-            Box::new(MatchFulfillmentParams::new(
-                remaining_accounts_iter,
-                &base_market,
-                &quote_market,
-            )?)
-        }
+    let mut fulfillment_params = {
+        let base_market = spot_market_map.get_ref(&market_index)?;
+        let quote_market = spot_market_map.get_quote_spot_market()?;
+        load_spot_fulfillment_params(
+            fulfillment_type,
+            remaining_accounts_iter,
+            &ctx.accounts.state,
+            &base_market,
+            &quote_market,
+            clock.unix_timestamp,
+        )?
     };
 
     controller::orders::fill_spot_order(
@@ -289,14 +269,30 @@ pub fn handle_trigger_order<'info>(ctx: Context<TriggerOrder>, order_id: u32) ->
         None,
     )?;
 
-    let market_type = match load!(ctx.accounts.user)?.get_order(order_id) {
-        Some(order) => order.market_type,
+    let (market_type, order_type) = match load!(ctx.accounts.user)?.get_order(order_id) {
+        Some(order) => (order.market_type, order.order_type),
         None => {
             msg!("order_id not found {}", order_id);
             return Ok(());
         }
     };
 
+    // twap orders are never triggered, each trigger releases their next slice
+    if order_type == OrderType::Twap {
+        controller::orders::release_twap_order_slice(
+            order_id,
+            &ctx.accounts.state,
+            &ctx.accounts.user,
+            &spot_market_map,
+            &perp_market_map,
+            &mut oracle_map,
+            &ctx.accounts.filler,
+            &Clock::get()?,
+        )?;
+
+        return Ok(());
+    }
+
     match market_type {
         MarketType::Perp => controller::orders::trigger_order(
             order_id,
@@ -323,6 +319,44 @@ pub fn handle_trigger_order<'info>(ctx: Context<TriggerOrder>, order_id: u32) ->
     Ok(())
 }
 
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_trigger_perp_position_take_profit_or_stop_loss<'info>(
+    ctx: Context<TriggerPerpPositionTakeProfitStopLoss>,
+    _market_index: u16,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &MarketSet::new(),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let take_profit_stop_loss = &mut load_mut!(ctx.accounts.take_profit_stop_loss)?;
+
+    controller::orders::trigger_perp_position_take_profit_or_stop_loss(
+        state,
+        &ctx.accounts.user,
+        take_profit_stop_loss,
+        &spot_market_map,
+        &perp_market_map,
+        &mut oracle_map,
+        &ctx.accounts.filler,
+        clock,
+    )?;
+
+    Ok(())
+}
+
 #[access_control(
     exchange_not_paused(&ctx.accounts.state)
 )]
@@ -352,6 +386,35 @@ pub fn handle_force_cancel_orders<'info>(ctx: Context<ForceCancelOrder>) -> Resu
     Ok(())
 }
 
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_cancel_orders_after_deadline<'info>(ctx: Context<ForceCancelOrder>) -> Result<()> {
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &MarketSet::new(),
+        &MarketSet::new(),
+        Clock::get()?.slot,
+        None,
+    )?;
+
+    controller::orders::cancel_orders_after_deadline(
+        &ctx.accounts.state,
+        &ctx.accounts.user,
+        &spot_market_map,
+        &perp_market_map,
+        &mut oracle_map,
+        &ctx.accounts.filler,
+        &Clock::get()?,
+    )?;
+
+    Ok(())
+}
+
 #[access_control(
     exchange_not_paused(&ctx.accounts.state)
 )]
@@ -1426,6 +1489,75 @@ pub fn handle_update_amms(ctx: Context<UpdateAMM>, market_indexes: [u16; 5]) ->
     Ok(())
 }
 
+/// Read only, meant to be simulated to get the amm's book. The amm is updated on a copy of the
+/// market so the ladder reflects the current oracle price and spreads
+pub fn handle_view_amm_l2(ctx: Context<ViewAMML2>, num_levels: u8) -> Result<AMML2> {
+    validate!(
+        num_levels <= MAX_AMM_L2_LEVELS,
+        ErrorCode::TooManyAmmL2Levels,
+        "num_levels {} > max {}",
+        num_levels,
+        MAX_AMM_L2_LEVELS
+    )?;
+
+    let clock = Clock::get()?;
+    let state = &ctx.accounts.state;
+    let mut oracle_map = OracleMap::load_one(
+        &ctx.accounts.oracle,
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let mut perp_market = *load!(ctx.accounts.perp_market)?;
+    let oracle_price_data = oracle_map.get_price_data(&perp_market.amm.oracle)?;
+    controller::repeg::_update_amm(
+        &mut perp_market,
+        oracle_price_data,
+        state,
+        clock.unix_timestamp,
+        clock.slot,
+    )?;
+
+    Ok(controller::amm::calculate_amm_l2(
+        &perp_market.amm,
+        num_levels,
+    )?)
+}
+
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+    valid_oracle_for_perp_market(&ctx.accounts.oracle, &ctx.accounts.perp_market)
+)]
+pub fn handle_scheduled_curve_update(ctx: Context<ScheduledCurveUpdate>) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    let clock = Clock::get()?;
+    let state = &ctx.accounts.state;
+    let mut oracle_map = OracleMap::load_one(
+        &ctx.accounts.oracle,
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    validate!(
+        matches!(
+            perp_market.status,
+            MarketStatus::Active | MarketStatus::ReduceOnly
+        ),
+        ErrorCode::MarketActionPaused,
+        "Market curve updates are paused",
+    )?;
+
+    let oracle_price_data = oracle_map.get_price_data(&perp_market.amm.oracle)?;
+    controller::repeg::scheduled_curve_update(
+        perp_market,
+        oracle_price_data,
+        state,
+        clock.unix_timestamp,
+    )?;
+
+    Ok(())
+}
+
 pub fn handle_update_user_quote_asset_insurance_stake(
     ctx: Context<UpdateUserQuoteAssetInsuranceStake>,
 ) -> Result<()> {
@@ -1500,6 +1632,26 @@ pub struct TriggerOrder<'info> {
     pub user: AccountLoader<'info, User>,
 }
 
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct TriggerPerpPositionTakeProfitStopLoss<'info> {
+    pub state: Box<Account<'info, State>>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = can_sign_for_user(&filler, &authority)?
+    )]
+    pub filler: AccountLoader<'info, User>,
+    #[account(mut)]
+    pub user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        seeds = [b"take_profit_stop_loss", user.key().as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub take_profit_stop_loss: AccountLoader<'info, PerpPositionTakeProfitStopLoss>,
+}
+
 #[derive(Accounts)]
 pub struct ForceCancelOrder<'info> {
     pub state: Box<Account<'info, State>>,
@@ -1762,6 +1914,23 @@ pub struct UpdateAMM<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct ViewAMML2<'info> {
+    pub state: Box<Account<'info, State>>,
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    /// CHECK: checked when loading the oracle price
+    pub oracle: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ScheduledCurveUpdate<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(mut)]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    /// CHECK: checked in `scheduled_curve_update` ix constraint
+    pub oracle: AccountInfo<'info>,
+}
+
 #[derive(Accounts)]
 pub struct UpdateFundingRate<'info> {
     pub state: Box<Account<'info, State>>,
@@ -5,7 +5,7 @@ use solana_program::program::invoke;
 use solana_program::system_instruction::transfer;
 
 use crate::controller::orders::{cancel_orders, ModifyOrderId};
-use crate::controller::position::PositionDirection;
+use crate::controller::position::{get_position_index, PositionDirection};
 use crate::controller::spot_balance::update_revenue_pool_balances;
 use crate::controller::spot_position::{
     charge_withdraw_fee, update_spot_balances_and_cumulative_deposits,
@@ -22,7 +22,9 @@ use crate::instructions::optional_accounts::{
 };
 use crate::instructions::SpotFulfillmentType;
 use crate::load_mut;
+use crate::math::auction::is_batch_auction_in_progress;
 use crate::math::casting::Cast;
+use crate::math::fulfillment::validate_min_fill;
 use crate::math::liquidation::is_user_being_liquidated;
 use crate::math::margin::{
     calculate_max_withdrawable_amount, meets_initial_margin_requirement,
@@ -41,17 +43,15 @@ use crate::state::events::{
     OrderActionExplanation, SwapRecord,
 };
 use crate::state::fill_mode::FillMode;
-use crate::state::fulfillment_params::drift::MatchFulfillmentParams;
-use crate::state::fulfillment_params::phoenix::PhoenixFulfillmentParams;
-use crate::state::fulfillment_params::serum::SerumFulfillmentParams;
+use crate::state::fulfillment_params::load_spot_fulfillment_params;
 use crate::state::oracle::StrictOraclePrice;
 use crate::state::order_params::{
-    ModifyOrderParams, OrderParams, PlaceOrderOptions, PostOnlyParam,
+    ComboOrderParams, ModifyOrderParams, OrderParams, PlaceOrderOptions, PostOnlyParam,
+    ScaleOrderParams,
 };
 use crate::state::paused_operations::PerpOperation;
 use crate::state::perp_market::MarketStatus;
 use crate::state::perp_market_map::{get_writable_perp_market_set, MarketSet};
-use crate::state::spot_fulfillment_params::SpotFulfillmentParams;
 use crate::state::spot_market::SpotBalanceType;
 use crate::state::spot_market::SpotMarket;
 use crate::state::spot_market_map::{
@@ -59,9 +59,13 @@ use crate::state::spot_market_map::{
 };
 use crate::state::state::State;
 use crate::state::traits::Size;
-use crate::state::user::{MarketType, OrderType, ReferrerName, User, UserStats};
+use crate::state::user::{
+    MarketType, OrderBitFlag, OrderType, PerpPositionTakeProfitStopLoss, ReferrerName,
+    SelfTradePreventionMode, User, UserStats,
+};
 use crate::state::user_map::load_user_maps;
 use crate::validate;
+use crate::validation::position::validate_perp_position_take_profit_and_stop_loss;
 use crate::validation::user::validate_user_deletion;
 use crate::validation::whitelist::validate_whitelist_token;
 use crate::{controller, math};
@@ -245,6 +249,29 @@ pub fn handle_initialize_referrer_name(
     Ok(())
 }
 
+pub fn handle_initialize_perp_position_take_profit_stop_loss(
+    ctx: Context<InitializePerpPositionTakeProfitStopLoss>,
+    market_index: u16,
+) -> Result<()> {
+    let mut take_profit_stop_loss = ctx
+        .accounts
+        .take_profit_stop_loss
+        .load_init()
+        .or(Err(ErrorCode::UnableToLoadAccountLoader))?;
+
+    take_profit_stop_loss.user = ctx.accounts.user.key();
+    take_profit_stop_loss.market_index = market_index;
+
+    Ok(())
+}
+
+pub fn handle_delete_perp_position_take_profit_stop_loss(
+    _ctx: Context<DeletePerpPositionTakeProfitStopLoss>,
+    _market_index: u16,
+) -> Result<()> {
+    Ok(())
+}
+
 #[access_control(
     deposit_not_paused(&ctx.accounts.state)
 )]
@@ -795,6 +822,11 @@ pub fn handle_place_perp_order(ctx: Context<PlaceOrder>, params: OrderParams) ->
         return Err(print_error!(ErrorCode::InvalidOrderIOC)().into());
     }
 
+    if params.is_place_and_take_only() {
+        msg!("fill or kill and min fill orders must be in place_and_take");
+        return Err(print_error!(ErrorCode::InvalidOrder)().into());
+    }
+
     let user_key = ctx.accounts.user.key();
     let mut user = load_mut!(ctx.accounts.user)?;
 
@@ -813,6 +845,69 @@ pub fn handle_place_perp_order(ctx: Context<PlaceOrder>, params: OrderParams) ->
     Ok(())
 }
 
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_set_perp_position_take_profit_and_stop_loss(
+    ctx: Context<SetPerpPositionTakeProfitStopLoss>,
+    market_index: u16,
+    take_profit_price: u64,
+    stop_loss_price: u64,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let AccountMaps {
+        perp_market_map,
+        mut oracle_map,
+        ..
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &MarketSet::new(),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let mut user = load_mut!(ctx.accounts.user)?;
+    let mut take_profit_stop_loss = load_mut!(ctx.accounts.take_profit_stop_loss)?;
+
+    let perp_market = perp_market_map.get_ref(&market_index)?;
+    let oracle_price = oracle_map.get_price_data(&perp_market.amm.oracle)?.price;
+
+    take_profit_stop_loss.take_profit_price = take_profit_price;
+    take_profit_stop_loss.stop_loss_price = stop_loss_price;
+    take_profit_stop_loss.set_position(&user)?;
+
+    validate_perp_position_take_profit_and_stop_loss(
+        &take_profit_stop_loss,
+        &user,
+        oracle_price.unsigned_abs(),
+    )?;
+
+    user.update_last_active_slot(clock.slot);
+
+    Ok(())
+}
+
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_set_cancel_after(ctx: Context<PlaceOrder>, seconds: u32) -> Result<()> {
+    let clock = &Clock::get()?;
+    let mut user = load_mut!(ctx.accounts.user)?;
+
+    user.cancel_after_ts = if seconds == 0 {
+        0
+    } else {
+        clock.unix_timestamp.safe_add(seconds.cast()?)?
+    };
+
+    user.update_last_active_slot(clock.slot);
+
+    Ok(())
+}
+
 #[access_control(
     exchange_not_paused(&ctx.accounts.state)
 )]
@@ -1040,7 +1135,11 @@ pub fn handle_modify_order_by_user_order_id(
 #[access_control(
     exchange_not_paused(&ctx.accounts.state)
 )]
-pub fn handle_place_orders(ctx: Context<PlaceOrder>, params: Vec<OrderParams>) -> Result<()> {
+pub fn handle_modify_orders(
+    ctx: Context<CancelOrder>,
+    order_ids: Vec<u32>,
+    modify_order_params: Vec<ModifyOrderParams>,
+) -> Result<()> {
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
@@ -1057,56 +1156,161 @@ pub fn handle_place_orders(ctx: Context<PlaceOrder>, params: Vec<OrderParams>) -
     )?;
 
     validate!(
-        params.len() <= 32,
+        order_ids.len() == modify_order_params.len(),
         ErrorCode::DefaultError,
-        "max 32 order params"
+        "{} order ids for {} modify order params",
+        order_ids.len(),
+        modify_order_params.len()
+    )?;
+
+    let orders = order_ids
+        .into_iter()
+        .zip(modify_order_params.into_iter())
+        .collect::<Vec<_>>();
+
+    controller::orders::modify_orders(
+        &orders,
+        &ctx.accounts.user,
+        state,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        clock,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_place_orders(ctx: Context<PlaceOrder>, params: Vec<OrderParams>) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &MarketSet::new(),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
     )?;
 
     let user_key = ctx.accounts.user.key();
     let mut user = load_mut!(ctx.accounts.user)?;
 
-    let num_orders = params.len();
-    for (i, params) in params.iter().enumerate() {
-        validate!(
-            !params.immediate_or_cancel,
-            ErrorCode::InvalidOrderIOC,
-            "immediate_or_cancel order must be in place_and_make or place_and_take"
-        )?;
+    controller::orders::place_orders(
+        state,
+        &mut user,
+        user_key,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        clock,
+        &params,
+    )?;
 
-        // only enforce margin on last order and only try to expire on first order
-        let options = PlaceOrderOptions {
-            enforce_margin_check: i == num_orders - 1,
-            try_expire_orders: i == 0,
-            risk_increasing: false,
-            explanation: OrderActionExplanation::None,
-        };
+    Ok(())
+}
 
-        if params.market_type == MarketType::Perp {
-            controller::orders::place_perp_order(
-                &ctx.accounts.state,
-                &mut user,
-                user_key,
-                &perp_market_map,
-                &spot_market_map,
-                &mut oracle_map,
-                clock,
-                *params,
-                options,
-            )?;
-        } else {
-            controller::orders::place_spot_order(
-                &ctx.accounts.state,
-                &mut user,
-                user_key,
-                &perp_market_map,
-                &spot_market_map,
-                &mut oracle_map,
-                clock,
-                *params,
-                options,
-            )?;
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_replace_orders(
+    ctx: Context<PlaceOrder>,
+    market_type: MarketType,
+    market_index: u16,
+    params: Vec<OrderParams>,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &MarketSet::new(),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let user_key = ctx.accounts.user.key();
+    let mut user = load_mut!(ctx.accounts.user)?;
+
+    controller::orders::replace_orders(
+        state,
+        &mut user,
+        user_key,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        clock,
+        market_type,
+        market_index,
+        &params,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_place_scale_orders(ctx: Context<PlaceOrder>, params: ScaleOrderParams) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &MarketSet::new(),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let order_params = match params.market_type {
+        MarketType::Perp => {
+            let market = perp_market_map.get_ref(&params.market_index)?;
+            params.get_order_params(
+                market.amm.order_tick_size,
+                market.amm.order_step_size,
+                market.amm.min_order_size,
+            )?
         }
-    }
+        MarketType::Spot => {
+            let market = spot_market_map.get_ref(&params.market_index)?;
+            params.get_order_params(
+                market.order_tick_size,
+                market.order_step_size,
+                market.min_order_size,
+            )?
+        }
+    };
+
+    let user_key = ctx.accounts.user.key();
+    let mut user = load_mut!(ctx.accounts.user)?;
+
+    controller::orders::place_orders(
+        state,
+        &mut user,
+        user_key,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        clock,
+        &order_params,
+    )?;
 
     Ok(())
 }
@@ -1144,6 +1348,8 @@ pub fn handle_place_and_take_perp_order<'info>(
         load_user_maps(remaining_accounts_iter, true)?;
 
     let is_immediate_or_cancel = params.immediate_or_cancel;
+    let is_fill_or_kill = params.bit_flags & OrderBitFlag::FillOrKill as u8 != 0;
+    let min_fill_base_asset_amount = params.min_fill_base_asset_amount;
 
     controller::repeg::update_amm(
         params.market_index,
@@ -1173,7 +1379,7 @@ pub fn handle_place_and_take_perp_order<'info>(
     let user = &mut ctx.accounts.user;
     let order_id = load!(user)?.get_last_order_id();
 
-    controller::orders::fill_perp_order(
+    let base_asset_amount_filled = controller::orders::fill_perp_order(
         order_id,
         &ctx.accounts.state,
         user,
@@ -1190,12 +1396,15 @@ pub fn handle_place_and_take_perp_order<'info>(
         FillMode::PlaceAndTake,
     )?;
 
+    validate_min_fill(base_asset_amount_filled, min_fill_base_asset_amount)?;
+
     let order_exists = load!(ctx.accounts.user)?
         .orders
         .iter()
         .any(|order| order.order_id == order_id);
 
-    if is_immediate_or_cancel && order_exists {
+    // fill or kill orders that didn't fill are killed
+    if (is_immediate_or_cancel || is_fill_or_kill) && order_exists {
         controller::orders::cancel_order_by_order_id(
             order_id,
             &ctx.accounts.user,
@@ -1209,6 +1418,110 @@ pub fn handle_place_and_take_perp_order<'info>(
     Ok(())
 }
 
+#[access_control(
+    fill_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_place_and_take_combo_order(
+    ctx: Context<PlaceAndTake>,
+    params: ComboOrderParams,
+    spot_fulfillment_type: Option<SpotFulfillmentType>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let is_spot_combo = params.second_market_type == MarketType::Spot;
+
+    let mut writable_perp_markets = get_writable_perp_market_set(params.first_market_index);
+    let writable_spot_markets = if is_spot_combo {
+        get_writable_spot_market_set_from_many(vec![
+            QUOTE_SPOT_MARKET_INDEX,
+            params.second_market_index,
+        ])
+    } else {
+        writable_perp_markets.insert(params.second_market_index);
+        MarketSet::new()
+    };
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &writable_perp_markets,
+        &writable_spot_markets,
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let (makers_and_referrer, makers_and_referrer_stats) =
+        load_user_maps(remaining_accounts_iter, true)?;
+
+    let mut spot_fulfillment_params = if is_spot_combo {
+        let spot_fulfillment_type = match spot_fulfillment_type {
+            Some(spot_fulfillment_type) => spot_fulfillment_type,
+            None => {
+                msg!("spot combo legs need a spot_fulfillment_type");
+                return Err(ErrorCode::InvalidComboOrderParams.into());
+            }
+        };
+
+        validate!(
+            spot_fulfillment_type != SpotFulfillmentType::Match,
+            ErrorCode::InvalidComboOrderParams,
+            "spot combo legs fill against serum or phoenix"
+        )?;
+
+        let base_market = spot_market_map.get_ref(&params.second_market_index)?;
+        let quote_market = spot_market_map.get_quote_spot_market()?;
+        Some(load_spot_fulfillment_params(
+            spot_fulfillment_type,
+            remaining_accounts_iter,
+            state,
+            &base_market,
+            &quote_market,
+            clock.unix_timestamp,
+        )?)
+    } else {
+        None
+    };
+
+    controller::repeg::update_amm(
+        params.first_market_index,
+        &perp_market_map,
+        &mut oracle_map,
+        state,
+        &clock,
+    )?;
+
+    if !is_spot_combo {
+        controller::repeg::update_amm(
+            params.second_market_index,
+            &perp_market_map,
+            &mut oracle_map,
+            state,
+            &clock,
+        )?;
+    }
+
+    controller::orders::place_and_take_combo_order(
+        state,
+        &ctx.accounts.user,
+        &ctx.accounts.user_stats,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &makers_and_referrer,
+        &makers_and_referrer_stats,
+        &clock,
+        params,
+        spot_fulfillment_params.as_deref_mut(),
+    )?;
+
+    Ok(())
+}
+
 #[access_control(
     fill_not_paused(&ctx.accounts.state)
 )]
@@ -1241,6 +1554,11 @@ pub fn handle_place_and_make_perp_order<'a, 'b, 'c, 'info>(
         return Err(print_error!(ErrorCode::InvalidOrderIOCPostOnly)().into());
     }
 
+    if params.is_place_and_take_only() {
+        msg!("fill or kill and min fill orders must be in place_and_take");
+        return Err(print_error!(ErrorCode::InvalidOrder)().into());
+    }
+
     controller::repeg::update_amm(
         params.market_index,
         &perp_market_map,
@@ -1249,6 +1567,23 @@ pub fn handle_place_and_make_perp_order<'a, 'b, 'c, 'info>(
         clock,
     )?;
 
+    let is_taker_in_batch_auction = match load!(ctx.accounts.taker)?.get_order(taker_order_id) {
+        Some(taker_order) if taker_order.market_index == params.market_index => {
+            is_batch_auction_in_progress(
+                taker_order,
+                &perp_market_map.get_ref(&params.market_index)?,
+                clock.slot,
+            )?
+        }
+        _ => false,
+    };
+
+    // the order rests until the batch auction clears, max_ts expires it if it's never cleared
+    if is_taker_in_batch_auction && params.max_ts.is_none() {
+        msg!("place_and_make into a batch auction must set max_ts");
+        return Err(print_error!(ErrorCode::InvalidOrderMaxTs)().into());
+    }
+
     let user_key = ctx.accounts.user.key();
     let mut user = load_mut!(ctx.accounts.user)?;
 
@@ -1268,6 +1603,15 @@ pub fn handle_place_and_make_perp_order<'a, 'b, 'c, 'info>(
 
     drop(user);
 
+    if is_taker_in_batch_auction {
+        msg!(
+            "Order {} registered for the batch auction of order {}",
+            order_id,
+            taker_order_id
+        );
+        return Ok(());
+    }
+
     let (mut makers_and_referrer, mut makers_and_referrer_stats) =
         load_user_maps(remaining_accounts_iter, true)?;
     makers_and_referrer.insert(ctx.accounts.user.key(), ctx.accounts.user.clone())?;
@@ -1327,6 +1671,11 @@ pub fn handle_place_spot_order(ctx: Context<PlaceOrder>, params: OrderParams) ->
         return Err(print_error!(ErrorCode::InvalidOrderIOC)().into());
     }
 
+    if params.is_place_and_take_only() {
+        msg!("fill or kill and min fill orders must be in place_and_take");
+        return Err(print_error!(ErrorCode::InvalidOrder)().into());
+    }
+
     let user_key = ctx.accounts.user.key();
     let mut user = load_mut!(ctx.accounts.user)?;
 
@@ -1386,38 +1735,20 @@ pub fn handle_place_and_take_spot_order<'info>(
     let (_referrer, _referrer_stats) = get_referrer_and_referrer_stats(remaining_accounts_iter)?;
 
     let is_immediate_or_cancel = params.immediate_or_cancel;
-
-    let mut fulfillment_params: Box<dyn SpotFulfillmentParams> = match fulfillment_type {
-        SpotFulfillmentType::SerumV3 => {
-            let base_market = spot_market_map.get_ref(&market_index)?;
-            let quote_market = spot_market_map.get_quote_spot_market()?;
-            Box::new(SerumFulfillmentParams::new(
-                remaining_accounts_iter,
-                &ctx.accounts.state,
-                &base_market,
-                &quote_market,
-                clock.unix_timestamp,
-            )?)
-        }
-        SpotFulfillmentType::PhoenixV1 => {
-            let base_market = spot_market_map.get_ref(&market_index)?;
-            let quote_market = spot_market_map.get_quote_spot_market()?;
-            Box::new(PhoenixFulfillmentParams::new(
-                remaining_accounts_iter,
-                &ctx.accounts.state,
-                &base_market,
-                &quote_market,
-            )?)
-        }
-        SpotFulfillmentType::Match => {
-            let base_market = spot_market_map.get_ref(&market_index)?;
-            let quote_market = spot_market_map.get_quote_spot_market()?;
-            Box::new(MatchFulfillmentParams::new(
-                remaining_accounts_iter,
-                &base_market,
-                &quote_market,
-            )?)
-        }
+    let is_fill_or_kill = params.bit_flags & OrderBitFlag::FillOrKill as u8 != 0;
+    let min_fill_base_asset_amount = params.min_fill_base_asset_amount;
+
+    let mut fulfillment_params = {
+        let base_market = spot_market_map.get_ref(&market_index)?;
+        let quote_market = spot_market_map.get_quote_spot_market()?;
+        load_spot_fulfillment_params(
+            fulfillment_type,
+            remaining_accounts_iter,
+            &ctx.accounts.state,
+            &base_market,
+            &quote_market,
+            clock.unix_timestamp,
+        )?
     };
 
     let user_key = ctx.accounts.user.key();
@@ -1440,7 +1771,7 @@ pub fn handle_place_and_take_spot_order<'info>(
     let user = &mut ctx.accounts.user;
     let order_id = load!(user)?.get_last_order_id();
 
-    controller::orders::fill_spot_order(
+    let base_asset_amount_filled = controller::orders::fill_spot_order(
         order_id,
         &ctx.accounts.state,
         user,
@@ -1457,12 +1788,15 @@ pub fn handle_place_and_take_spot_order<'info>(
         fulfillment_params.as_mut(),
     )?;
 
+    validate_min_fill(base_asset_amount_filled, min_fill_base_asset_amount)?;
+
     let order_exists = load!(ctx.accounts.user)?
         .orders
         .iter()
         .any(|order| order.order_id == order_id);
 
-    if is_immediate_or_cancel && order_exists {
+    // fill or kill orders that didn't fill are killed
+    if (is_immediate_or_cancel || is_fill_or_kill) && order_exists {
         controller::orders::cancel_order_by_order_id(
             order_id,
             &ctx.accounts.user,
@@ -1515,39 +1849,24 @@ pub fn handle_place_and_make_spot_order<'info>(
         return Err(print_error!(ErrorCode::InvalidOrderIOCPostOnly)().into());
     }
 
+    if params.is_place_and_take_only() {
+        msg!("fill or kill and min fill orders must be in place_and_take");
+        return Err(print_error!(ErrorCode::InvalidOrder)().into());
+    }
+
     let market_index = params.market_index;
 
-    let mut fulfillment_params: Box<dyn SpotFulfillmentParams> = match fulfillment_type {
-        SpotFulfillmentType::SerumV3 => {
-            let base_market = spot_market_map.get_ref(&market_index)?;
-            let quote_market = spot_market_map.get_quote_spot_market()?;
-            Box::new(SerumFulfillmentParams::new(
-                remaining_accounts_iter,
-                &ctx.accounts.state,
-                &base_market,
-                &quote_market,
-                clock.unix_timestamp,
-            )?)
-        }
-        SpotFulfillmentType::PhoenixV1 => {
-            let base_market = spot_market_map.get_ref(&market_index)?;
-            let quote_market = spot_market_map.get_quote_spot_market()?;
-            Box::new(PhoenixFulfillmentParams::new(
-                remaining_accounts_iter,
-                &ctx.accounts.state,
-                &base_market,
-                &quote_market,
-            )?)
-        }
-        SpotFulfillmentType::Match => {
-            let base_market = spot_market_map.get_ref(&market_index)?;
-            let quote_market = spot_market_map.get_quote_spot_market()?;
-            Box::new(MatchFulfillmentParams::new(
-                remaining_accounts_iter,
-                &base_market,
-                &quote_market,
-            )?)
-        }
+    let mut fulfillment_params = {
+        let base_market = spot_market_map.get_ref(&market_index)?;
+        let quote_market = spot_market_map.get_quote_spot_market()?;
+        load_spot_fulfillment_params(
+            fulfillment_type,
+            remaining_accounts_iter,
+            &ctx.accounts.state,
+            &base_market,
+            &quote_market,
+            clock.unix_timestamp,
+        )?
     };
 
     let user_key = ctx.accounts.user.key();
@@ -1676,12 +1995,19 @@ pub fn handle_add_perp_lp_shares<'info>(
         )?
         .cast::<u64>()?;
 
+        let base_asset_amount_before = user
+            .get_perp_position(market_index)
+            .map_or(0, |position| position.base_asset_amount);
+
         controller::lp::mint_lp_shares(
             user.force_get_perp_position_mut(market_index)?,
             &mut market,
             n_shares,
         )?;
 
+        let position_index = get_position_index(&user.perp_positions, market_index)?;
+        user.update_perp_position_open_count(position_index, base_asset_amount_before);
+
         user.last_add_perp_lp_shares_ts = now;
     }
 
@@ -1887,6 +2213,15 @@ pub fn handle_update_user_advanced_lp(
     Ok(())
 }
 
+pub fn handle_update_user_self_trade_prevention_mode(
+    ctx: Context<UpdateUserStats>,
+    self_trade_prevention_mode: SelfTradePreventionMode,
+) -> Result<()> {
+    let mut user_stats = load_mut!(ctx.accounts.user_stats)?;
+    user_stats.self_trade_prevention_mode = self_trade_prevention_mode;
+    Ok(())
+}
+
 pub fn handle_delete_user(ctx: Context<DeleteUser>) -> Result<()> {
     let user = &load!(ctx.accounts.user)?;
     let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
@@ -2066,6 +2401,69 @@ pub struct InitializeReferrerName<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct InitializePerpPositionTakeProfitStopLoss<'info> {
+    #[account(
+        init,
+        seeds = [b"take_profit_stop_loss", user.key().as_ref(), market_index.to_le_bytes().as_ref()],
+        space = PerpPositionTakeProfitStopLoss::SIZE,
+        bump,
+        payer = payer
+    )]
+    pub take_profit_stop_loss: AccountLoader<'info, PerpPositionTakeProfitStopLoss>,
+    #[account(
+        constraint = can_sign_for_user(&user, &authority)?
+    )]
+    pub user: AccountLoader<'info, User>,
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct SetPerpPositionTakeProfitStopLoss<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        constraint = can_sign_for_user(&user, &authority)?
+    )]
+    pub user: AccountLoader<'info, User>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"take_profit_stop_loss", user.key().as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub take_profit_stop_loss: AccountLoader<'info, PerpPositionTakeProfitStopLoss>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct DeletePerpPositionTakeProfitStopLoss<'info> {
+    #[account(
+        constraint = can_sign_for_user(&user, &authority)?
+    )]
+    pub user: AccountLoader<'info, User>,
+    pub authority: Signer<'info>,
+    /// CHECK: rent goes back to the user authority, not a delegate
+    #[account(
+        mut,
+        constraint = is_authority_for_user(&user, &user_authority)?
+    )]
+    pub user_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"take_profit_stop_loss", user.key().as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+        close = user_authority
+    )]
+    pub take_profit_stop_loss: AccountLoader<'info, PerpPositionTakeProfitStopLoss>,
+}
+
 #[derive(Accounts)]
 #[instruction(market_index: u16,)]
 pub struct Deposit<'info> {
@@ -2272,6 +2670,16 @@ pub struct UpdateUser<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct UpdateUserStats<'info> {
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    pub authority: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct DeleteUser<'info> {
     #[account(
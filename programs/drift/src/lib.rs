@@ -10,15 +10,22 @@ use math::amm;
 use math::{bn, constants::*};
 use state::oracle::OracleSource;
 
+use crate::controller::amm::AMML2;
 use crate::controller::position::PositionDirection;
-use crate::state::order_params::{ModifyOrderParams, OrderParams};
-use crate::state::perp_market::{ContractTier, MarketStatus};
+use crate::state::order_params::{
+    ComboOrderParams, ModifyOrderParams, OrderParams, ScaleOrderParams,
+};
+use crate::state::perp_market::{
+    AuctionClearingMode, ContractTier, FundingFormula, MarketStatus, MatchingMode,
+};
 use crate::state::spot_market::AssetTier;
 use crate::state::spot_market::SpotFulfillmentConfigStatus;
 use crate::state::state::FeeStructure;
 use crate::state::state::*;
-use crate::state::user::MarketType;
+use crate::state::user::{MarketType, SelfTradePreventionMode};
 
+#[cfg(any(test, feature = "backtest"))]
+pub mod backtest;
 pub mod controller;
 pub mod error;
 pub mod ids;
@@ -92,6 +99,38 @@ pub mod drift {
         handle_place_perp_order(ctx, params)
     }
 
+    pub fn initialize_perp_position_take_profit_stop_loss(
+        ctx: Context<InitializePerpPositionTakeProfitStopLoss>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_initialize_perp_position_take_profit_stop_loss(ctx, market_index)
+    }
+
+    pub fn set_perp_position_take_profit_and_stop_loss(
+        ctx: Context<SetPerpPositionTakeProfitStopLoss>,
+        market_index: u16,
+        take_profit_price: u64,
+        stop_loss_price: u64,
+    ) -> Result<()> {
+        handle_set_perp_position_take_profit_and_stop_loss(
+            ctx,
+            market_index,
+            take_profit_price,
+            stop_loss_price,
+        )
+    }
+
+    pub fn delete_perp_position_take_profit_stop_loss(
+        ctx: Context<DeletePerpPositionTakeProfitStopLoss>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_delete_perp_position_take_profit_stop_loss(ctx, market_index)
+    }
+
+    pub fn set_cancel_after(ctx: Context<PlaceOrder>, seconds: u32) -> Result<()> {
+        handle_set_cancel_after(ctx, seconds)
+    }
+
     pub fn cancel_order(ctx: Context<CancelOrder>, order_id: Option<u32>) -> Result<()> {
         handle_cancel_order(ctx, order_id)
     }
@@ -121,6 +160,14 @@ pub mod drift {
         handle_modify_order(ctx, order_id, modify_order_params)
     }
 
+    pub fn modify_orders(
+        ctx: Context<CancelOrder>,
+        order_ids: Vec<u32>,
+        modify_order_params: Vec<ModifyOrderParams>,
+    ) -> Result<()> {
+        handle_modify_orders(ctx, order_ids, modify_order_params)
+    }
+
     pub fn modify_order_by_user_id(
         ctx: Context<CancelOrder>,
         user_order_id: u8,
@@ -137,6 +184,14 @@ pub mod drift {
         handle_place_and_take_perp_order(ctx, params, maker_order_id)
     }
 
+    pub fn place_and_take_combo_order(
+        ctx: Context<PlaceAndTake>,
+        params: ComboOrderParams,
+        spot_fulfillment_type: Option<SpotFulfillmentType>,
+    ) -> Result<()> {
+        handle_place_and_take_combo_order(ctx, params, spot_fulfillment_type)
+    }
+
     pub fn place_and_make_perp_order<'info>(
         ctx: Context<'_, '_, '_, 'info, PlaceAndMake<'info>>,
         params: OrderParams,
@@ -181,6 +236,19 @@ pub mod drift {
         handle_place_orders(ctx, params)
     }
 
+    pub fn replace_orders(
+        ctx: Context<PlaceOrder>,
+        market_type: MarketType,
+        market_index: u16,
+        params: Vec<OrderParams>,
+    ) -> Result<()> {
+        handle_replace_orders(ctx, market_type, market_index, params)
+    }
+
+    pub fn place_scale_orders(ctx: Context<PlaceOrder>, params: ScaleOrderParams) -> Result<()> {
+        handle_place_scale_orders(ctx, params)
+    }
+
     pub fn begin_swap(
         ctx: Context<Swap>,
         in_market_index: u16,
@@ -278,6 +346,13 @@ pub mod drift {
         handle_update_user_advanced_lp(ctx, _sub_account_id, advanced_lp)
     }
 
+    pub fn update_user_self_trade_prevention_mode(
+        ctx: Context<UpdateUserStats>,
+        self_trade_prevention_mode: SelfTradePreventionMode,
+    ) -> Result<()> {
+        handle_update_user_self_trade_prevention_mode(ctx, self_trade_prevention_mode)
+    }
+
     pub fn delete_user(ctx: Context<DeleteUser>) -> Result<()> {
         handle_delete_user(ctx)
     }
@@ -313,10 +388,21 @@ pub mod drift {
         handle_trigger_order(ctx, order_id)
     }
 
+    pub fn trigger_perp_position_take_profit_or_stop_loss(
+        ctx: Context<TriggerPerpPositionTakeProfitStopLoss>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_trigger_perp_position_take_profit_or_stop_loss(ctx, market_index)
+    }
+
     pub fn force_cancel_orders(ctx: Context<ForceCancelOrder>) -> Result<()> {
         handle_force_cancel_orders(ctx)
     }
 
+    pub fn cancel_orders_after_deadline(ctx: Context<ForceCancelOrder>) -> Result<()> {
+        handle_cancel_orders_after_deadline(ctx)
+    }
+
     pub fn update_user_idle(ctx: Context<UpdateUserIdle>) -> Result<()> {
         handle_update_user_idle(ctx)
     }
@@ -448,6 +534,14 @@ pub mod drift {
         handle_update_perp_bid_ask_twap(ctx)
     }
 
+    pub fn view_amm_l2(ctx: Context<ViewAMML2>, num_levels: u8) -> Result<AMML2> {
+        handle_view_amm_l2(ctx, num_levels)
+    }
+
+    pub fn scheduled_curve_update(ctx: Context<ScheduledCurveUpdate>) -> Result<()> {
+        handle_scheduled_curve_update(ctx)
+    }
+
     pub fn update_spot_market_cumulative_interest(
         ctx: Context<UpdateSpotMarketCumulativeInterest>,
     ) -> Result<()> {
@@ -881,6 +975,20 @@ pub mod drift {
         handle_update_perp_market_contract_tier(ctx, contract_tier)
     }
 
+    pub fn update_perp_market_matching_mode(
+        ctx: Context<AdminUpdatePerpMarket>,
+        matching_mode: MatchingMode,
+    ) -> Result<()> {
+        handle_update_perp_market_matching_mode(ctx, matching_mode)
+    }
+
+    pub fn update_perp_market_auction_clearing_mode(
+        ctx: Context<AdminUpdatePerpMarket>,
+        auction_clearing_mode: AuctionClearingMode,
+    ) -> Result<()> {
+        handle_update_perp_market_auction_clearing_mode(ctx, auction_clearing_mode)
+    }
+
     pub fn update_perp_market_imf_factor(
         ctx: Context<AdminUpdatePerpMarket>,
         imf_factor: u32,
@@ -1024,6 +1132,13 @@ pub mod drift {
         handle_update_amm_jit_intensity(ctx, amm_jit_intensity)
     }
 
+    pub fn update_amm_jit_inventory_scale(
+        ctx: Context<AdminUpdatePerpMarket>,
+        amm_jit_inventory_scale: u16,
+    ) -> Result<()> {
+        handle_update_amm_jit_inventory_scale(ctx, amm_jit_inventory_scale)
+    }
+
     pub fn update_perp_market_max_spread(
         ctx: Context<AdminUpdatePerpMarket>,
         max_spread: u32,
@@ -1031,6 +1146,46 @@ pub mod drift {
         handle_update_perp_market_max_spread(ctx, max_spread)
     }
 
+    pub fn update_perp_market_curve_update_schedule(
+        ctx: Context<AdminUpdatePerpMarket>,
+        curve_update_interval: u32,
+        curve_update_budget: u64,
+        curve_update_budget_period: u32,
+    ) -> Result<()> {
+        handle_update_perp_market_curve_update_schedule(
+            ctx,
+            curve_update_interval,
+            curve_update_budget,
+            curve_update_budget_period,
+        )
+    }
+
+    pub fn update_perp_market_realized_volatility(
+        ctx: Context<AdminUpdatePerpMarket>,
+        realized_volatility_window: u16,
+        realized_volatility_spread_multiplier: u16,
+    ) -> Result<()> {
+        handle_update_perp_market_realized_volatility(
+            ctx,
+            realized_volatility_window,
+            realized_volatility_spread_multiplier,
+        )
+    }
+
+    pub fn update_perp_market_funding_formula(
+        ctx: Context<AdminUpdatePerpMarket>,
+        funding_formula: FundingFormula,
+        funding_interest_rate: i16,
+        max_funding_premium: u16,
+    ) -> Result<()> {
+        handle_update_perp_market_funding_formula(
+            ctx,
+            funding_formula,
+            funding_interest_rate,
+            max_funding_premium,
+        )
+    }
+
     pub fn update_perp_market_step_size_and_tick_size(
         ctx: Context<AdminUpdatePerpMarket>,
         step_size: u64,
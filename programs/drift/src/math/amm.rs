@@ -1,5 +1,7 @@
 use std::cmp::{max, min};
 
+use num_integer::Roots;
+
 use solana_program::msg;
 
 use crate::controller::amm::SwapDirection;
@@ -10,8 +12,9 @@ use crate::math::casting::Cast;
 use crate::math::constants::{
     BID_ASK_SPREAD_PRECISION_I128, CONCENTRATION_PRECISION,
     DEFAULT_MAX_TWAP_UPDATE_PRICE_BAND_DENOMINATOR, FIVE_MINUTE, ONE_HOUR, ONE_MINUTE,
-    PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO, PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO_I128,
-    PRICE_TO_PEG_PRECISION_RATIO, QUOTE_PRECISION_I64,
+    PERCENTAGE_PRECISION, PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO,
+    PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO_I128, PRICE_TO_PEG_PRECISION_RATIO,
+    QUOTE_PRECISION_I64,
 };
 use crate::math::orders::standardize_base_asset_amount;
 use crate::math::quote_asset::reserve_to_asset_amount;
@@ -426,6 +429,8 @@ pub fn update_oracle_price_twap(
             TwapPeriod::FiveMin,
         )?;
 
+        update_amm_realized_volatility(amm, now, capped_oracle_update_price)?;
+
         amm.last_oracle_normalised_price = capped_oracle_update_price;
         amm.historical_oracle_data.last_oracle_price = oracle_price_data.price;
 
@@ -552,6 +557,51 @@ pub fn update_amm_oracle_std(amm: &mut AMM, now: i64, price: u64, ewma: u64) ->
     Ok(true)
 }
 
+/// Updates the ewma of the oracle's variance per second and stores the root of it over the window.
+/// Each squared return is normalized by the time since the last update, so the estimate doesn't
+/// depend on how often the oracle updates. A new return's weight grows with the time since the
+/// last update, up to the full window
+pub fn update_amm_realized_volatility(amm: &mut AMM, now: i64, price: i64) -> DriftResult {
+    let window = amm.realized_volatility_window.cast::<u128>()?;
+    let last_price = amm.last_oracle_normalised_price;
+    if window == 0 || last_price <= 0 || price <= 0 {
+        return Ok(());
+    }
+
+    // nothing has elapsed since the last update, so there's no return to add or variance to decay
+    let since_last = now.safe_sub(amm.historical_oracle_data.last_oracle_price_twap_ts)?;
+    if since_last <= 0 {
+        return Ok(());
+    }
+    let since_last = since_last.cast::<u128>()?.min(window);
+
+    let price_return = price
+        .safe_sub(last_price)?
+        .unsigned_abs()
+        .cast::<u128>()?
+        .safe_mul(PERCENTAGE_PRECISION)?
+        .safe_div(last_price.cast()?)?;
+
+    let squared_return = price_return.safe_mul(price_return)?.min(i64::MAX as u128);
+
+    // variance per second times the window
+    let variance = squared_return.safe_mul(window)?.safe_div(since_last)?;
+
+    let last_variance = amm
+        .realized_volatility
+        .cast::<u128>()?
+        .safe_mul(amm.realized_volatility.cast()?)?;
+
+    let variance = variance
+        .safe_mul(since_last)?
+        .safe_add(last_variance.safe_mul(window.safe_sub(since_last)?)?)?
+        .safe_div(window)?;
+
+    amm.realized_volatility = variance.nth_root(2).min(u32::MAX as u128).cast()?;
+
+    Ok(())
+}
+
 pub fn update_amm_long_short_intensity(
     amm: &mut AMM,
     now: i64,
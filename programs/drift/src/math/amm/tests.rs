@@ -867,3 +867,50 @@ fn test_last_oracle_conf_update() {
 
     assert_eq!(amm.last_oracle_conf_pct, 7307 - 7307 / 5 + 1); //5847
 }
+
+#[test]
+fn update_amm_realized_volatility_test() {
+    let mut amm = AMM {
+        last_oracle_normalised_price: 100 * PRICE_PRECISION_I64,
+        historical_oracle_data: HistoricalOracleData {
+            last_oracle_price_twap_ts: 0,
+            ..HistoricalOracleData::default()
+        },
+        ..AMM::default()
+    };
+
+    // disabled without a window
+    update_amm_realized_volatility(&mut amm, 60, 101 * PRICE_PRECISION_I64).unwrap();
+    assert_eq!(amm.realized_volatility, 0);
+
+    amm.realized_volatility_window = 600;
+
+    // a 1% move a full window later replaces the estimate
+    update_amm_realized_volatility(&mut amm, 600, 101 * PRICE_PRECISION_I64).unwrap();
+    assert_eq!(amm.realized_volatility, 10000);
+
+    // another update in the same second doesn't decay it
+    amm.last_oracle_normalised_price = 101 * PRICE_PRECISION_I64;
+    amm.historical_oracle_data.last_oracle_price_twap_ts = 600;
+    update_amm_realized_volatility(&mut amm, 600, 101 * PRICE_PRECISION_I64).unwrap();
+    assert_eq!(amm.realized_volatility, 10000);
+
+    // a flat update a tenth of the window later decays the variance by a tenth
+    amm.last_oracle_normalised_price = 101 * PRICE_PRECISION_I64;
+    amm.historical_oracle_data.last_oracle_price_twap_ts = 600;
+    update_amm_realized_volatility(&mut amm, 660, 101 * PRICE_PRECISION_I64).unwrap();
+    assert_eq!(amm.realized_volatility, 9486);
+
+    // a 5% jump a second later counts in full, it's 5% over the window however fast it was
+    amm.historical_oracle_data.last_oracle_price_twap_ts = 660;
+    let jump_price = 101 * PRICE_PRECISION_I64 * 105 / 100;
+    update_amm_realized_volatility(&mut amm, 661, jump_price).unwrap();
+    assert_eq!(amm.realized_volatility, 50890);
+
+    // a 1% move a tenth of the window after the last update is the same 1% over the window
+    amm.realized_volatility = 0;
+    amm.last_oracle_normalised_price = 100 * PRICE_PRECISION_I64;
+    amm.historical_oracle_data.last_oracle_price_twap_ts = 0;
+    update_amm_realized_volatility(&mut amm, 60, 101 * PRICE_PRECISION_I64).unwrap();
+    assert_eq!(amm.realized_volatility, 10000);
+}
@@ -1,7 +1,9 @@
 use crate::controller::position::PositionDirection;
 use crate::error::DriftResult;
+use crate::math::amm_spread::calculate_inventory_liquidity_ratio;
 use crate::math::casting::Cast;
-use crate::math::constants::AMM_RESERVE_PRECISION;
+use crate::math::constants::{AMM_RESERVE_PRECISION, PERCENTAGE_PRECISION};
+use crate::math::helpers::get_proportion_i128;
 use crate::math::orders::standardize_base_asset_amount;
 use crate::math::safe_math::SafeMath;
 use crate::state::perp_market::{AMMLiquiditySplit, PerpMarket};
@@ -80,6 +82,22 @@ pub fn calculate_jit_base_asset_amount(
 
     jit_base_asset_amount = jit_base_asset_amount.min(max_jit_amount);
 
+    // take more when the fill reduces the amm's inventory, less when it adds to it. scaling the
+    // capped amount lets the scale move the amm past the 50% cap, but never past the maker's size
+    // or flipping its inventory
+    let jit_inventory_scale =
+        calculate_jit_inventory_scale(market, taker_direction, liquidity_split)?;
+    jit_base_asset_amount = jit_base_asset_amount
+        .cast::<u128>()?
+        .safe_mul(jit_inventory_scale)?
+        .safe_div(PERCENTAGE_PRECISION)?
+        .cast::<u64>()?
+        .min(maker_base_asset_amount)
+        .min(calculate_max_jit_base_asset_amount(
+            market,
+            liquidity_split,
+        )?);
+
     // last step we always standardize
     jit_base_asset_amount =
         standardize_base_asset_amount(jit_base_asset_amount, market.amm.order_step_size)?;
@@ -87,6 +105,60 @@ pub fn calculate_jit_base_asset_amount(
     Ok(jit_base_asset_amount)
 }
 
+// returns the multiplier (precision: PERCENTAGE_PRECISION) applied to the jit size
+// e.g. amm_jit_inventory_scale = 100 and inventory at 50% of min side liquidity
+//     fill reduces base_asset_amount_with_amm => 1.5x
+//     fill adds to base_asset_amount_with_amm => .5x
+// lp owned fills only move the lps' inventory, so it's their base_asset_amount_per_lp away from
+// the target, scaled up as if every share was an lp's
+pub fn calculate_jit_inventory_scale(
+    market: &PerpMarket,
+    taker_direction: PositionDirection,
+    liquidity_split: AMMLiquiditySplit,
+) -> DriftResult<u128> {
+    let base_asset_amount_with_amm = if liquidity_split == AMMLiquiditySplit::LPOwned {
+        // lps hold the opposite side of what users hold with the amm
+        get_proportion_i128(
+            market
+                .amm
+                .get_target_base_asset_amount_per_lp()?
+                .safe_sub(market.amm.base_asset_amount_per_lp)?,
+            market.amm.sqrt_k,
+            market.amm.get_per_lp_base_unit()?.cast()?,
+        )?
+    } else {
+        market.amm.base_asset_amount_with_amm
+    };
+
+    if market.amm.amm_jit_inventory_scale == 0 || base_asset_amount_with_amm == 0 {
+        return Ok(PERCENTAGE_PRECISION);
+    }
+
+    let amm_inventory_pct = calculate_inventory_liquidity_ratio(
+        base_asset_amount_with_amm,
+        market.amm.base_asset_reserve,
+        market.amm.min_base_asset_reserve,
+        market.amm.max_base_asset_reserve,
+    )?
+    .unsigned_abs();
+
+    let inventory_adjustment = amm_inventory_pct
+        .safe_mul(market.amm.amm_jit_inventory_scale.cast()?)?
+        .safe_div(100)?;
+
+    // taker going long means the amm goes short
+    let fill_reduces_inventory = match taker_direction {
+        PositionDirection::Long => base_asset_amount_with_amm < 0,
+        PositionDirection::Short => base_asset_amount_with_amm > 0,
+    };
+
+    if fill_reduces_inventory {
+        PERCENTAGE_PRECISION.safe_add(inventory_adjustment)
+    } else {
+        PERCENTAGE_PRECISION.safe_sub(inventory_adjustment.min(PERCENTAGE_PRECISION))
+    }
+}
+
 // assumption: taker_baa will improve market balance (see orders.rs & amm_wants_to_jit_make)
 // note: we split it into two (calc and clamp) bc its easier to maintain tests
 pub fn calculate_clamped_jit_base_asset_amount(
@@ -103,23 +175,32 @@ pub fn calculate_clamped_jit_base_asset_amount(
         .cast::<u64>()?;
 
     // bound it; dont flip the net_baa
-    let max_amm_base_asset_amount = if liquidity_split != AMMLiquiditySplit::LPOwned {
+    let jit_base_asset_amount = jit_base_asset_amount.min(calculate_max_jit_base_asset_amount(
+        market,
+        liquidity_split,
+    )?);
+
+    Ok(jit_base_asset_amount)
+}
+
+// the most the amm can jit without flipping its (or the lps') inventory
+fn calculate_max_jit_base_asset_amount(
+    market: &PerpMarket,
+    liquidity_split: AMMLiquiditySplit,
+) -> DriftResult<u64> {
+    if liquidity_split != AMMLiquiditySplit::LPOwned {
         market
             .amm
             .base_asset_amount_with_amm
             .unsigned_abs()
-            .cast::<u64>()?
+            .cast::<u64>()
     } else {
         market
             .amm
             .imbalanced_base_asset_amount_with_lp()?
             .unsigned_abs()
-            .cast::<u64>()?
-    };
-
-    let jit_base_asset_amount = jit_base_asset_amount.min(max_amm_base_asset_amount);
-
-    Ok(jit_base_asset_amount)
+            .cast::<u64>()
+    }
 }
 
 pub fn calculate_amm_jit_liquidity(
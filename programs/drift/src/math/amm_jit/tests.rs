@@ -1,4 +1,5 @@
 use crate::math::amm_jit::*;
+use crate::math::constants::{AMM_RESERVE_PRECISION, PERCENTAGE_PRECISION};
 use crate::state::perp_market::AMM;
 
 #[test]
@@ -84,3 +85,92 @@ fn balanced_market_half_intensity() {
     .unwrap();
     assert_eq!(jit_amount, 50);
 }
+
+#[test]
+fn inventory_scaled_jit() {
+    let mut market = PerpMarket {
+        amm: AMM {
+            // inventory is 50% of the min side liquidity
+            base_asset_amount_with_amm: 50 * AMM_RESERVE_PRECISION as i128,
+            amm_jit_intensity: 100,
+            ..AMM::default_test()
+        },
+        ..PerpMarket::default()
+    };
+
+    // static when disabled
+    let scale = calculate_jit_inventory_scale(
+        &market,
+        PositionDirection::Short,
+        AMMLiquiditySplit::ProtocolOwned,
+    )
+    .unwrap();
+    assert_eq!(scale, PERCENTAGE_PRECISION);
+
+    market.amm.amm_jit_inventory_scale = 100;
+
+    // amm goes long, reducing its short inventory
+    let scale = calculate_jit_inventory_scale(
+        &market,
+        PositionDirection::Short,
+        AMMLiquiditySplit::ProtocolOwned,
+    )
+    .unwrap();
+    assert_eq!(scale, 3 * PERCENTAGE_PRECISION / 2);
+
+    let scale = calculate_jit_inventory_scale(
+        &market,
+        PositionDirection::Long,
+        AMMLiquiditySplit::ProtocolOwned,
+    )
+    .unwrap();
+    assert_eq!(scale, PERCENTAGE_PRECISION / 2);
+
+    market.amm.amm_jit_inventory_scale = 300;
+
+    let scale = calculate_jit_inventory_scale(
+        &market,
+        PositionDirection::Short,
+        AMMLiquiditySplit::ProtocolOwned,
+    )
+    .unwrap();
+    assert_eq!(scale, 5 * PERCENTAGE_PRECISION / 2);
+
+    // never below zero
+    let scale = calculate_jit_inventory_scale(
+        &market,
+        PositionDirection::Long,
+        AMMLiquiditySplit::ProtocolOwned,
+    )
+    .unwrap();
+    assert_eq!(scale, 0);
+
+    // balanced amm is unscaled
+    market.amm.base_asset_amount_with_amm = 0;
+    let scale = calculate_jit_inventory_scale(
+        &market,
+        PositionDirection::Long,
+        AMMLiquiditySplit::ProtocolOwned,
+    )
+    .unwrap();
+    assert_eq!(scale, PERCENTAGE_PRECISION);
+
+    // lp owned jit scales by the lps' inventory, here short .5 per lp share
+    market.amm.amm_jit_inventory_scale = 100;
+    market.amm.base_asset_amount_per_lp = -(AMM_RESERVE_PRECISION as i128) / 2;
+    let scale = calculate_jit_inventory_scale(
+        &market,
+        PositionDirection::Short,
+        AMMLiquiditySplit::LPOwned,
+    )
+    .unwrap();
+    assert_eq!(scale, 3 * PERCENTAGE_PRECISION / 2);
+
+    let scale = calculate_jit_inventory_scale(
+        &market,
+        PositionDirection::Short,
+        AMMLiquiditySplit::ProtocolOwned,
+    )
+    .unwrap();
+    assert_eq!(scale, PERCENTAGE_PRECISION);
+}
@@ -163,6 +163,17 @@ pub fn calculate_long_short_vol_spread(
     ))
 }
 
+/// The multiplier is in percent, so 100 adds the realized volatility to the spread as is
+pub fn calculate_realized_volatility_spread(
+    realized_volatility: u32,
+    realized_volatility_spread_multiplier: u16,
+) -> DriftResult<u64> {
+    realized_volatility
+        .cast::<u64>()?
+        .safe_mul(realized_volatility_spread_multiplier.cast()?)?
+        .safe_div(100)
+}
+
 pub fn calculate_inventory_liquidity_ratio(
     base_asset_amount_with_amm: i128,
     base_asset_reserve: u128,
@@ -316,6 +327,8 @@ pub fn calculate_spread(
     long_intensity_volume: u64,
     short_intensity_volume: u64,
     volume_24h: u64,
+    realized_volatility: u32,
+    realized_volatility_spread_multiplier: u16,
 ) -> DriftResult<(u32, u32)> {
     let (long_vol_spread, short_vol_spread) = calculate_long_short_vol_spread(
         last_oracle_conf_pct,
@@ -327,6 +340,13 @@ pub fn calculate_spread(
         volume_24h,
     )?;
 
+    let realized_volatility_spread = calculate_realized_volatility_spread(
+        realized_volatility,
+        realized_volatility_spread_multiplier,
+    )?;
+    let long_vol_spread = long_vol_spread.safe_add(realized_volatility_spread)?;
+    let short_vol_spread = short_vol_spread.safe_add(realized_volatility_spread)?;
+
     let half_base_spread_u64 = (base_spread / 2) as u64;
 
     let mut long_spread = max(half_base_spread_u64, long_vol_spread);
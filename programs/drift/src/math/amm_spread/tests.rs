@@ -236,6 +236,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread1, (base_spread * 10 / 2));
@@ -265,6 +267,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread2, 16667);
@@ -294,6 +298,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
 
@@ -329,6 +335,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
         assert!(short_spread4 < long_spread4);
@@ -358,6 +366,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
 
@@ -479,6 +489,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
 
@@ -505,6 +517,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
 
@@ -558,6 +572,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
 
@@ -606,6 +622,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread1, 500);
@@ -633,6 +651,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread1, 345);
@@ -659,6 +679,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread1, 110);
@@ -708,6 +730,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread1, 199926);
@@ -733,6 +757,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread1, 199951);
@@ -758,6 +784,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread1, 199815);
@@ -1114,6 +1142,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
 
@@ -1154,6 +1184,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
 
@@ -1180,6 +1212,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread, 195556);
@@ -1205,6 +1239,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread, 1639);
@@ -1304,6 +1340,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
 
@@ -1344,6 +1382,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
 
@@ -1370,6 +1410,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread, 197138); // big cause of oracel pct
@@ -1395,6 +1437,8 @@ mod test {
             long_intensity_volume,
             short_intensity_volume,
             volume_24h,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread, 1639);
@@ -1425,6 +1469,8 @@ mod test {
             12358265776,
             72230366233,
             432067603632,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread, 4262);
@@ -1451,6 +1497,8 @@ mod test {
             12358265776,
             72230366233,
             432067603632,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread, 4262);
@@ -1477,6 +1525,8 @@ mod test {
             12358265776,
             72230366233,
             432067603632,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread, 4262);
@@ -1504,9 +1554,57 @@ mod test {
             9520659647,
             53979922148,
             427588331503,
+            0,
+            0,
         )
         .unwrap();
         assert_eq!(long_spread, 4390);
         assert_eq!(short_spread, 43110);
     }
+
+    #[test]
+    fn calculate_spread_with_realized_volatility() {
+        let base_spread = 1000;
+        let max_spread = 200000;
+        let reserve_price = 34562304;
+
+        let get_spreads = |realized_volatility: u32, realized_volatility_spread_multiplier: u16| {
+            calculate_spread(
+                base_spread,
+                0,
+                0,
+                max_spread,
+                AMM_RESERVE_PRECISION * 10,
+                AMM_RESERVE_PRECISION * 10,
+                34000000,
+                0,
+                reserve_price,
+                QUOTE_PRECISION_I128,
+                0,
+                AMM_RESERVE_PRECISION * 10,
+                0,
+                AMM_RESERVE_PRECISION * 100000,
+                0,
+                0,
+                0,
+                0,
+                0,
+                realized_volatility,
+                realized_volatility_spread_multiplier,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(get_spreads(0, 100), (500, 500));
+
+        // disabled with a zero multiplier
+        assert_eq!(get_spreads(2000, 0), (500, 500));
+
+        assert_eq!(get_spreads(2000, 100), (2000, 2000));
+        assert_eq!(get_spreads(2000, 50), (1000, 1000));
+        assert_eq!(get_spreads(2000, 300), (6000, 6000));
+
+        // still capped by the max spread
+        assert_eq!(get_spreads(200000, 100), (100000, 100000));
+    }
 }
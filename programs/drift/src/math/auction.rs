@@ -8,7 +8,7 @@ use crate::state::oracle::OraclePriceData;
 use crate::state::user::{Order, OrderType};
 use solana_program::msg;
 
-use crate::state::perp_market::PerpMarket;
+use crate::state::perp_market::{AuctionClearingMode, PerpMarket};
 use crate::OrderParams;
 use std::cmp::min;
 
@@ -90,9 +90,10 @@ pub fn calculate_auction_price(
         OrderType::Market
         | OrderType::TriggerMarket
         | OrderType::Limit
-        | OrderType::TriggerLimit => {
-            calculate_auction_price_for_fixed_auction(order, slot, tick_size)
-        }
+        | OrderType::TriggerLimit
+        | OrderType::TrailingStop
+        | OrderType::TrailingTakeProfit
+        | OrderType::Twap => calculate_auction_price_for_fixed_auction(order, slot, tick_size),
         OrderType::Oracle => calculate_auction_price_for_oracle_offset_auction(
             order,
             slot,
@@ -208,6 +209,23 @@ pub fn is_auction_complete(order_slot: u64, auction_duration: u8, slot: u64) ->
     Ok(slots_elapsed > auction_duration.cast()?)
 }
 
+/// Market and oracle orders with an auction in batch auction markets only fill once their auction
+/// is over. Until then, place_and_make registers maker orders to be cleared with the auction
+pub fn is_batch_auction_order(order: &Order, market: &PerpMarket) -> bool {
+    order.is_market_order()
+        && order.has_auction()
+        && market.auction_clearing_mode == AuctionClearingMode::Batch
+}
+
+pub fn is_batch_auction_in_progress(
+    order: &Order,
+    market: &PerpMarket,
+    slot: u64,
+) -> DriftResult<bool> {
+    Ok(is_batch_auction_order(order, market)
+        && !is_auction_complete(order.slot, order.auction_duration, slot)?)
+}
+
 pub fn is_amm_available_liquidity_source(
     order: &Order,
     min_auction_duration: u8,
@@ -216,6 +234,95 @@ pub fn is_amm_available_liquidity_source(
     is_auction_complete(order.slot, min_auction_duration, slot)
 }
 
+/// Finds the uniform price a batch auction clears at. Candidate prices are the maker prices and
+/// the amm's best and limit price, walked from best to worst for the taker. The amm offers more
+/// the further its price moves, so between the last candidate that doesn't cover the taker and
+/// the first that does, the lowest price (in ticks) the amm and makers cover the taker at is
+/// searched for. If no candidate covers the taker, everything offered clears at the worst one
+pub fn calculate_batch_auction_clearing_price<F>(
+    taker_direction: PositionDirection,
+    taker_base_asset_amount: u64,
+    maker_levels: &[(u64, u64)],
+    amm_price_range: Option<(u64, u64)>,
+    tick_size: u64,
+    amm_base_asset_amount_at_price: F,
+) -> DriftResult<Option<u64>>
+where
+    F: Fn(u64) -> DriftResult<u64>,
+{
+    let is_at_or_better = |price: u64, clearing_price: u64| match taker_direction {
+        PositionDirection::Long => price <= clearing_price,
+        PositionDirection::Short => price >= clearing_price,
+    };
+
+    let mut candidate_prices: Vec<u64> = maker_levels.iter().map(|(price, _)| *price).collect();
+    if let Some((amm_best_price, amm_limit_price)) = amm_price_range {
+        candidate_prices.push(amm_limit_price);
+        if is_at_or_better(amm_best_price, amm_limit_price) {
+            candidate_prices.push(amm_best_price);
+        }
+    }
+
+    match taker_direction {
+        PositionDirection::Long => candidate_prices.sort_unstable(),
+        PositionDirection::Short => candidate_prices.sort_unstable_by(|a, b| b.cmp(a)),
+    }
+    candidate_prices.dedup();
+
+    let covers_taker = |clearing_price: u64| -> DriftResult<bool> {
+        let mut base_asset_amount = 0_u64;
+        for (price, maker_base_asset_amount) in maker_levels.iter() {
+            if is_at_or_better(*price, clearing_price) {
+                base_asset_amount = base_asset_amount.safe_add(*maker_base_asset_amount)?;
+            }
+        }
+
+        if amm_price_range.is_some() {
+            base_asset_amount =
+                base_asset_amount.safe_add(amm_base_asset_amount_at_price(clearing_price)?)?;
+        }
+
+        Ok(base_asset_amount >= taker_base_asset_amount)
+    };
+
+    let mut uncovered_price: Option<u64> = None;
+    for candidate_price in candidate_prices.iter() {
+        if !covers_taker(*candidate_price)? {
+            uncovered_price = Some(*candidate_price);
+            continue;
+        }
+
+        let mut clearing_price = *candidate_price;
+        if let (Some(mut uncovered_price), Some(_)) = (uncovered_price, amm_price_range) {
+            // binary search the ticks between the two candidates, only the amm changes there
+            while clearing_price.abs_diff(uncovered_price) > tick_size {
+                // rounded down to the tick size
+                let half_distance = standardize_price(
+                    clearing_price.abs_diff(uncovered_price) / 2,
+                    tick_size,
+                    PositionDirection::Long,
+                )?
+                .max(tick_size);
+
+                let price = match taker_direction {
+                    PositionDirection::Long => clearing_price.safe_sub(half_distance)?,
+                    PositionDirection::Short => clearing_price.safe_add(half_distance)?,
+                };
+
+                if covers_taker(price)? {
+                    clearing_price = price;
+                } else {
+                    uncovered_price = price;
+                }
+            }
+        }
+
+        return Ok(Some(clearing_price));
+    }
+
+    Ok(candidate_prices.last().copied())
+}
+
 pub fn calculate_auction_params_for_trigger_order(
     order: &Order,
     oracle_price_data: &OraclePriceData,
@@ -468,3 +468,228 @@ mod calculate_auction_params_for_trigger_order {
         assert_eq!(auction_end_price, 99500000);
     }
 }
+
+mod calculate_batch_auction_clearing_price {
+    use crate::controller::position::PositionDirection;
+    use crate::math::auction::calculate_batch_auction_clearing_price;
+    use crate::{BASE_PRECISION_U64, PRICE_PRECISION_U64};
+
+    #[test]
+    fn makers_only_long() {
+        let maker_levels = [
+            (101 * PRICE_PRECISION_U64, BASE_PRECISION_U64),
+            (100 * PRICE_PRECISION_U64, BASE_PRECISION_U64),
+            (102 * PRICE_PRECISION_U64, BASE_PRECISION_U64),
+        ];
+
+        let clearing_price = calculate_batch_auction_clearing_price(
+            PositionDirection::Long,
+            2 * BASE_PRECISION_U64,
+            &maker_levels,
+            None,
+            1,
+            |_| Ok(0),
+        )
+        .unwrap();
+
+        assert_eq!(clearing_price, Some(101 * PRICE_PRECISION_U64));
+
+        let clearing_price = calculate_batch_auction_clearing_price(
+            PositionDirection::Long,
+            5 * BASE_PRECISION_U64,
+            &maker_levels,
+            None,
+            1,
+            |_| Ok(0),
+        )
+        .unwrap();
+
+        assert_eq!(clearing_price, Some(102 * PRICE_PRECISION_U64));
+    }
+
+    #[test]
+    fn makers_only_short() {
+        let maker_levels = [
+            (99 * PRICE_PRECISION_U64, BASE_PRECISION_U64),
+            (100 * PRICE_PRECISION_U64, BASE_PRECISION_U64),
+        ];
+
+        let clearing_price = calculate_batch_auction_clearing_price(
+            PositionDirection::Short,
+            BASE_PRECISION_U64,
+            &maker_levels,
+            None,
+            1,
+            |_| Ok(0),
+        )
+        .unwrap();
+
+        assert_eq!(clearing_price, Some(100 * PRICE_PRECISION_U64));
+    }
+
+    #[test]
+    fn amm_adds_liquidity() {
+        let maker_levels = [
+            (100 * PRICE_PRECISION_U64, BASE_PRECISION_U64),
+            (102 * PRICE_PRECISION_U64, BASE_PRECISION_U64),
+        ];
+
+        // amm offers one base at 100 and two by 102
+        let amm_base_asset_amount_at_price = |price: u64| {
+            if price >= 102 * PRICE_PRECISION_U64 {
+                Ok(2 * BASE_PRECISION_U64)
+            } else if price >= 100 * PRICE_PRECISION_U64 {
+                Ok(BASE_PRECISION_U64)
+            } else {
+                Ok(0)
+            }
+        };
+
+        let clearing_price = calculate_batch_auction_clearing_price(
+            PositionDirection::Long,
+            2 * BASE_PRECISION_U64,
+            &maker_levels,
+            Some((100 * PRICE_PRECISION_U64, 103 * PRICE_PRECISION_U64)),
+            1,
+            amm_base_asset_amount_at_price,
+        )
+        .unwrap();
+
+        assert_eq!(clearing_price, Some(100 * PRICE_PRECISION_U64));
+
+        let clearing_price = calculate_batch_auction_clearing_price(
+            PositionDirection::Long,
+            10 * BASE_PRECISION_U64,
+            &maker_levels,
+            Some((100 * PRICE_PRECISION_U64, 103 * PRICE_PRECISION_U64)),
+            1,
+            amm_base_asset_amount_at_price,
+        )
+        .unwrap();
+
+        assert_eq!(clearing_price, Some(103 * PRICE_PRECISION_U64));
+    }
+
+    #[test]
+    fn amm_price_between_candidates() {
+        let maker_levels = [(100 * PRICE_PRECISION_U64, BASE_PRECISION_U64)];
+
+        // amm offers one base per dollar over 100
+        let amm_base_asset_amount_at_price = |price: u64| {
+            Ok(
+                price.saturating_sub(100 * PRICE_PRECISION_U64) * BASE_PRECISION_U64
+                    / PRICE_PRECISION_U64,
+            )
+        };
+
+        // makers fill one, the amm the other 2.5
+        let clearing_price = calculate_batch_auction_clearing_price(
+            PositionDirection::Long,
+            7 * BASE_PRECISION_U64 / 2,
+            &maker_levels,
+            Some((100 * PRICE_PRECISION_U64, 110 * PRICE_PRECISION_U64)),
+            PRICE_PRECISION_U64 / 1000,
+            amm_base_asset_amount_at_price,
+        )
+        .unwrap();
+
+        assert_eq!(clearing_price, Some(102_500_000));
+
+        // amm bid starts at 100 and offers one base per dollar under it
+        let maker_levels = [(100 * PRICE_PRECISION_U64, BASE_PRECISION_U64)];
+        let amm_base_asset_amount_at_price = |price: u64| {
+            Ok(
+                (100 * PRICE_PRECISION_U64).saturating_sub(price) * BASE_PRECISION_U64
+                    / PRICE_PRECISION_U64,
+            )
+        };
+
+        let clearing_price = calculate_batch_auction_clearing_price(
+            PositionDirection::Short,
+            3 * BASE_PRECISION_U64,
+            &maker_levels,
+            Some((100 * PRICE_PRECISION_U64, 90 * PRICE_PRECISION_U64)),
+            PRICE_PRECISION_U64 / 1000,
+            amm_base_asset_amount_at_price,
+        )
+        .unwrap();
+
+        assert_eq!(clearing_price, Some(98 * PRICE_PRECISION_U64));
+
+        // the amm's best price isn't a candidate if it's worse than its limit
+        let maker_levels = [(99 * PRICE_PRECISION_U64, BASE_PRECISION_U64)];
+        let clearing_price = calculate_batch_auction_clearing_price(
+            PositionDirection::Long,
+            2 * BASE_PRECISION_U64,
+            &maker_levels,
+            Some((101 * PRICE_PRECISION_U64, 100 * PRICE_PRECISION_U64)),
+            PRICE_PRECISION_U64 / 1000,
+            |_| Ok(0),
+        )
+        .unwrap();
+
+        assert_eq!(clearing_price, Some(100 * PRICE_PRECISION_U64));
+    }
+
+    #[test]
+    fn no_liquidity() {
+        let clearing_price = calculate_batch_auction_clearing_price(
+            PositionDirection::Long,
+            BASE_PRECISION_U64,
+            &[],
+            None,
+            1,
+            |_| Ok(0),
+        )
+        .unwrap();
+
+        assert_eq!(clearing_price, None);
+    }
+}
+
+mod is_batch_auction_in_progress {
+    use crate::math::auction::is_batch_auction_in_progress;
+    use crate::state::perp_market::{AuctionClearingMode, PerpMarket};
+    use crate::state::user::{Order, OrderStatus, OrderType};
+
+    #[test]
+    fn market_order_auction() {
+        let market = PerpMarket {
+            auction_clearing_mode: AuctionClearingMode::Batch,
+            ..PerpMarket::default()
+        };
+        let order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::Market,
+            slot: 1,
+            auction_duration: 10,
+            ..Order::default()
+        };
+
+        assert!(is_batch_auction_in_progress(&order, &market, 1).unwrap());
+        assert!(is_batch_auction_in_progress(&order, &market, 11).unwrap());
+        // auction over
+        assert!(!is_batch_auction_in_progress(&order, &market, 12).unwrap());
+
+        // no auction
+        let no_auction = Order {
+            auction_duration: 0,
+            ..order
+        };
+        assert!(!is_batch_auction_in_progress(&no_auction, &market, 1).unwrap());
+
+        // limit orders rest on the book
+        let limit = Order {
+            order_type: OrderType::Limit,
+            ..order
+        };
+        assert!(!is_batch_auction_in_progress(&limit, &market, 1).unwrap());
+
+        // continuous clearing
+        let market = PerpMarket {
+            auction_clearing_mode: AuctionClearingMode::Continuous,
+            ..market
+        };
+        assert!(!is_batch_auction_in_progress(&order, &market, 1).unwrap());
+    }
+}
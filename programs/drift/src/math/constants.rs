@@ -50,8 +50,11 @@ pub const SPOT_CUMULATIVE_INTEREST_PRECISION: u128 = 10_000_000_000; // expo = -
 pub const PERCENTAGE_PRECISION: u128 = 1_000_000; // expo -6 (represents 100%)
 pub const PERCENTAGE_PRECISION_I128: i128 = PERCENTAGE_PRECISION as i128;
 pub const PERCENTAGE_PRECISION_U64: u64 = PERCENTAGE_PRECISION as u64;
+pub const PERCENTAGE_PRECISION_I64: i64 = PERCENTAGE_PRECISION as i64;
 pub const TEN_BPS: i128 = PERCENTAGE_PRECISION_I128 / 1000;
 pub const TEN_BPS_I64: i64 = TEN_BPS as i64;
+pub const BPS_PRECISION_U64: u64 = 10_000; // expo = -4
+pub const BPS_PRECISION_I64: i64 = BPS_PRECISION_U64 as i64;
 pub const TWO_PT_TWO_PCT: i128 = 22_000;
 
 pub const BID_ASK_SPREAD_PRECISION: u64 = PERCENTAGE_PRECISION as u64; // expo = -6
@@ -180,6 +183,7 @@ pub const MAX_MARGIN_RATIO: u32 = MARGIN_PRECISION as u32; // 1x leverage
 pub const MIN_MARGIN_RATIO: u32 = MARGIN_PRECISION as u32 / 50; // 50x leverage
 
 pub const MAX_BID_ASK_INVENTORY_SKEW_FACTOR: u64 = 10 * BID_ASK_SPREAD_PRECISION;
+pub const MAX_AMM_JIT_INVENTORY_SCALE: u16 = 1000; // 10x
 
 pub const MAX_POSITIVE_UPNL_FOR_INITIAL_MARGIN: i128 = 100 * QUOTE_PRECISION_I128; // max upnl for initial margin calc
 pub const DEFAULT_MAX_TWAP_UPDATE_PRICE_BAND_DENOMINATOR: i64 = 3; // '3' here means clamp new data point to 33% (1/3) divergence from current twap (if twap > 0)
@@ -194,6 +198,8 @@ pub const DEFAULT_QUOTE_ASSET_AMOUNT_TICK_SIZE: u64 =
 
 // FUNDING
 pub const FUNDING_RATE_OFFSET_DENOMINATOR: i64 = 5000; // 5000 => 7.3% annualized rate for hourly funding
+pub const MAX_FUNDING_INTEREST_RATE: i16 = 5_000; // 50% annualized
+pub const PREMIUM_INDEX_INTEREST_CLAMP: i64 = 1_500; // .15% a day, 5 bps per 8h
 
 // ORDERS
 pub const AUCTION_DERIVE_PRICE_FRACTION: i64 = 200;
@@ -1,11 +1,19 @@
 use crate::controller::position::PositionDirection;
-use crate::error::DriftResult;
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::amm::calculate_amm_available_liquidity;
 use crate::math::auction::is_amm_available_liquidity_source;
-use crate::math::matching::do_orders_cross;
+use crate::math::casting::Cast;
+use crate::math::fees::determine_user_fee_tier;
+use crate::math::matching::{calculate_pro_rata_allocations, do_orders_cross};
+use crate::math::orders::calculate_base_asset_amount_for_amm_to_fulfill;
+use crate::math::safe_math::SafeMath;
 use crate::math::safe_unwrap::SafeUnwrap;
 use crate::state::fulfillment::{PerpFulfillmentMethod, SpotFulfillmentMethod};
-use crate::state::perp_market::AMM;
-use crate::state::user::Order;
+use crate::state::perp_market::{PerpMarket, AMM};
+use crate::state::state::FeeStructure;
+use crate::state::user::{MarketType, Order, OrderBitFlag, OrderStatus, UserStats};
+use crate::validate;
+use solana_program::msg;
 use solana_program::pubkey::Pubkey;
 
 #[cfg(test)]
@@ -94,6 +102,242 @@ pub fn determine_perp_fulfillment_methods(
     Ok(fulfillment_methods)
 }
 
+/// Turns runs of makers resting at the same price into pro-rata matches for that price level
+pub fn get_pro_rata_fulfillment_methods(
+    fulfillment_methods: Vec<PerpFulfillmentMethod>,
+    maker_orders_info: &[(Pubkey, usize, u64)],
+) -> DriftResult<Vec<PerpFulfillmentMethod>> {
+    let get_maker_price = |maker_key: &Pubkey, maker_order_index: u16| -> DriftResult<u64> {
+        maker_orders_info
+            .iter()
+            .find(|(key, order_index, _)| {
+                key == maker_key && *order_index == maker_order_index as usize
+            })
+            .map(|(_, _, price)| *price)
+            .safe_unwrap()
+    };
+
+    let mut maker_prices = Vec::with_capacity(fulfillment_methods.len());
+    for fulfillment_method in fulfillment_methods.iter() {
+        maker_prices.push(match fulfillment_method {
+            PerpFulfillmentMethod::Match(maker_key, maker_order_index) => {
+                Some(get_maker_price(maker_key, *maker_order_index)?)
+            }
+            _ => None,
+        });
+    }
+
+    let shares_price_level = |index: usize| -> bool {
+        let price = maker_prices[index];
+        price.is_some()
+            && ((index > 0 && maker_prices[index - 1] == price)
+                || maker_prices.get(index + 1).copied().flatten() == price)
+    };
+
+    Ok(fulfillment_methods
+        .into_iter()
+        .enumerate()
+        .map(|(index, fulfillment_method)| match fulfillment_method {
+            PerpFulfillmentMethod::Match(maker_key, maker_order_index)
+                if shares_price_level(index) =>
+            {
+                PerpFulfillmentMethod::ProRataMatch(
+                    maker_key,
+                    maker_order_index,
+                    maker_prices[index].unwrap_or_default(),
+                )
+            }
+            fulfillment_method => fulfillment_method,
+        })
+        .collect())
+}
+
+/// Allocates what's left of the taker order across the makers of the pro-rata price level the
+/// fulfillment methods start with. get_maker_order returns a maker's order and perp position
+/// base asset amount, makers get their share of what they show the taker
+pub fn get_pro_rata_allocations(
+    taker_order: &Order,
+    taker_position_base_asset_amount: i64,
+    fulfillment_methods: &[PerpFulfillmentMethod],
+    step_size: u64,
+    mut get_maker_order: impl FnMut(&Pubkey, u16) -> DriftResult<(Order, i64)>,
+) -> DriftResult<Vec<((Pubkey, u16), u64)>> {
+    let level_price = match fulfillment_methods.first() {
+        Some(PerpFulfillmentMethod::ProRataMatch(_, _, price)) => *price,
+        _ => return Ok(vec![]),
+    };
+
+    let taker_base_asset_amount =
+        taker_order.get_base_asset_amount_unfilled(Some(taker_position_base_asset_amount))?;
+
+    let mut makers = vec![];
+    let mut maker_base_asset_amounts = vec![];
+    for fulfillment_method in fulfillment_methods.iter() {
+        let (maker_key, maker_order_index) = match fulfillment_method {
+            PerpFulfillmentMethod::ProRataMatch(maker_key, maker_order_index, price)
+                if *price == level_price =>
+            {
+                (*maker_key, *maker_order_index)
+            }
+            _ => break,
+        };
+
+        let (maker_order, maker_position_base_asset_amount) =
+            get_maker_order(&maker_key, maker_order_index)?;
+        let maker_base_asset_amount = if maker_order.status == OrderStatus::Open {
+            maker_order
+                .get_visible_base_asset_amount_unfilled(Some(maker_position_base_asset_amount))?
+        } else {
+            0
+        };
+
+        makers.push((maker_key, maker_order_index));
+        maker_base_asset_amounts.push(maker_base_asset_amount);
+    }
+
+    let allocations = calculate_pro_rata_allocations(
+        taker_base_asset_amount,
+        &maker_base_asset_amounts,
+        step_size,
+    )?;
+
+    Ok(makers.into_iter().zip(allocations).collect())
+}
+
+/// The base amount the amm fills of the order, the limit price the fill is validated against and
+/// the price it fills at when it isn't the amm's own. The base amount is 0 when it's under the
+/// amm's min fill size
+pub fn calculate_amm_fill(
+    order: &Order,
+    market: &PerpMarket,
+    existing_base_asset_amount: i64,
+    user_stats: &UserStats,
+    fee_structure: &FeeStructure,
+    limit_price: Option<u64>,
+    override_base_asset_amount: Option<u64>,
+    override_fill_price: Option<u64>,
+) -> DriftResult<(u64, Option<u64>, Option<u64>)> {
+    let (base_asset_amount, limit_price, fill_price) = match override_base_asset_amount {
+        Some(override_base_asset_amount) => {
+            (override_base_asset_amount, limit_price, override_fill_price)
+        }
+        None => {
+            let fee_tier = determine_user_fee_tier(user_stats, fee_structure, &MarketType::Perp)?;
+            let (base_asset_amount, limit_price) = calculate_base_asset_amount_for_amm_to_fulfill(
+                order,
+                market,
+                limit_price,
+                override_fill_price,
+                existing_base_asset_amount,
+                fee_tier,
+            )?;
+
+            let fill_price = if order.post_only { limit_price } else { None };
+
+            (base_asset_amount, limit_price, fill_price)
+        }
+    };
+
+    // if user position is less than min order size, step size is the threshold
+    let amm_size_threshold =
+        if existing_base_asset_amount.unsigned_abs() > market.amm.min_order_size {
+            market.amm.min_order_size
+        } else {
+            market.amm.order_step_size
+        };
+
+    if base_asset_amount < amm_size_threshold {
+        return Ok((0, limit_price, fill_price));
+    }
+
+    Ok((base_asset_amount, limit_price, fill_price))
+}
+
+/// The price the taker order matches makers up to, the amm's fallback price if it has no limit
+pub fn calculate_taker_price_for_match(
+    amm: &AMM,
+    taker_order: &Order,
+    taker_limit_price: Option<u64>,
+    oracle_price: i64,
+    now: i64,
+) -> DriftResult<u64> {
+    match taker_limit_price {
+        Some(taker_limit_price) => Ok(taker_limit_price),
+        None => {
+            let amm_available_liquidity =
+                calculate_amm_available_liquidity(amm, &taker_order.direction)?;
+            amm.get_fallback_price(
+                &taker_order.direction,
+                amm_available_liquidity,
+                oracle_price,
+                taker_order.seconds_til_expiry(now),
+            )
+        }
+    }
+}
+
+/// Adds an amm fill's fees to the amm's fee pool
+pub fn update_amm_fee_pool_for_amm_fill(
+    amm: &mut AMM,
+    user_fee: u64,
+    fee_to_market: i64,
+    quote_asset_amount_surplus: i64,
+) -> DriftResult {
+    amm.total_fee = amm.total_fee.safe_add(fee_to_market.cast()?)?;
+    amm.total_exchange_fee = amm.total_exchange_fee.safe_add(user_fee.cast()?)?;
+    amm.total_mm_fee = amm
+        .total_mm_fee
+        .safe_add(quote_asset_amount_surplus.cast()?)?;
+    amm.total_fee_minus_distributions = amm
+        .total_fee_minus_distributions
+        .safe_add(fee_to_market.cast()?)?;
+    amm.net_revenue_since_last_funding =
+        amm.net_revenue_since_last_funding.safe_add(fee_to_market)?;
+
+    Ok(())
+}
+
+/// Adds a maker fill's fees to the amm's fee pool
+pub fn update_amm_fee_pool_for_match_fill(amm: &mut AMM, fee_to_market: i64) -> DriftResult {
+    amm.total_fee = amm.total_fee.safe_add(fee_to_market.cast()?)?;
+    amm.total_exchange_fee = amm.total_exchange_fee.safe_add(fee_to_market.cast()?)?;
+    amm.total_fee_minus_distributions = amm
+        .total_fee_minus_distributions
+        .safe_add(fee_to_market.cast()?)?;
+    amm.net_revenue_since_last_funding =
+        amm.net_revenue_since_last_funding.safe_add(fee_to_market)?;
+
+    Ok(())
+}
+
+/// Fill or kill orders revert unless whatever is filled is the whole order. order is the taker
+/// order as it was before the fill
+pub fn validate_fill_or_kill(order: &Order, base_asset_amount_filled: u64) -> DriftResult {
+    validate!(
+        base_asset_amount_filled == 0
+            || !order.is_bit_flag_set(OrderBitFlag::FillOrKill)
+            || base_asset_amount_filled == order.get_base_asset_amount_unfilled(None)?,
+        ErrorCode::FillOrKillOrderNotFilled,
+        "fill or kill order filled {} of {}",
+        base_asset_amount_filled,
+        order.get_base_asset_amount_unfilled(None)?
+    )
+}
+
+/// place_and_take reverts if less than the order's min fill is filled
+pub fn validate_min_fill(
+    base_asset_amount_filled: u64,
+    min_fill_base_asset_amount: u64,
+) -> DriftResult {
+    validate!(
+        base_asset_amount_filled >= min_fill_base_asset_amount,
+        ErrorCode::MinFillNotMet,
+        "filled {} < min fill {}",
+        base_asset_amount_filled,
+        min_fill_base_asset_amount
+    )
+}
+
 fn determine_perp_fulfillment_methods_for_maker(
     order: &Order,
     amm: &AMM,
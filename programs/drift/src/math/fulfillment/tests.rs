@@ -888,3 +888,160 @@ mod determine_perp_fulfillment_methods {
         assert_eq!(fulfillment_methods, vec![]);
     }
 }
+
+mod get_pro_rata_fulfillment_methods {
+    use crate::math::fulfillment::get_pro_rata_fulfillment_methods;
+    use crate::state::fulfillment::PerpFulfillmentMethod;
+    use crate::PRICE_PRECISION_U64;
+    use solana_program::pubkey::Pubkey;
+
+    #[test]
+    fn groups_makers_at_same_price() {
+        let maker_a = Pubkey::new_unique();
+        let maker_b = Pubkey::new_unique();
+        let maker_c = Pubkey::new_unique();
+
+        let maker_orders_info = vec![
+            (maker_a, 0, 100 * PRICE_PRECISION_U64),
+            (maker_b, 1, 100 * PRICE_PRECISION_U64),
+            (maker_c, 2, 101 * PRICE_PRECISION_U64),
+        ];
+
+        let fulfillment_methods = vec![
+            PerpFulfillmentMethod::AMM(Some(100 * PRICE_PRECISION_U64)),
+            PerpFulfillmentMethod::Match(maker_a, 0),
+            PerpFulfillmentMethod::Match(maker_b, 1),
+            PerpFulfillmentMethod::Match(maker_c, 2),
+            PerpFulfillmentMethod::AMM(None),
+        ];
+
+        let fulfillment_methods =
+            get_pro_rata_fulfillment_methods(fulfillment_methods, &maker_orders_info).unwrap();
+
+        assert_eq!(
+            fulfillment_methods,
+            vec![
+                PerpFulfillmentMethod::AMM(Some(100 * PRICE_PRECISION_U64)),
+                PerpFulfillmentMethod::ProRataMatch(maker_a, 0, 100 * PRICE_PRECISION_U64),
+                PerpFulfillmentMethod::ProRataMatch(maker_b, 1, 100 * PRICE_PRECISION_U64),
+                PerpFulfillmentMethod::Match(maker_c, 2),
+                PerpFulfillmentMethod::AMM(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn amm_between_makers_at_same_price() {
+        let maker_a = Pubkey::new_unique();
+        let maker_b = Pubkey::new_unique();
+
+        let maker_orders_info = vec![
+            (maker_a, 0, 100 * PRICE_PRECISION_U64),
+            (maker_b, 0, 100 * PRICE_PRECISION_U64),
+        ];
+
+        let fulfillment_methods = vec![
+            PerpFulfillmentMethod::Match(maker_a, 0),
+            PerpFulfillmentMethod::AMM(Some(100 * PRICE_PRECISION_U64)),
+            PerpFulfillmentMethod::Match(maker_b, 0),
+        ];
+
+        let fulfillment_methods =
+            get_pro_rata_fulfillment_methods(fulfillment_methods, &maker_orders_info).unwrap();
+
+        assert_eq!(
+            fulfillment_methods,
+            vec![
+                PerpFulfillmentMethod::Match(maker_a, 0),
+                PerpFulfillmentMethod::AMM(Some(100 * PRICE_PRECISION_U64)),
+                PerpFulfillmentMethod::Match(maker_b, 0),
+            ]
+        );
+    }
+}
+
+mod get_pro_rata_allocations {
+    use crate::controller::position::PositionDirection;
+    use crate::math::fulfillment::get_pro_rata_allocations;
+    use crate::state::fulfillment::PerpFulfillmentMethod;
+    use crate::state::user::{Order, OrderStatus, OrderType};
+    use crate::{BASE_PRECISION_U64, PRICE_PRECISION_U64};
+    use solana_program::pubkey::Pubkey;
+
+    #[test]
+    fn allocates_level_by_visible_amount() {
+        let taker_order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::Market,
+            direction: PositionDirection::Long,
+            base_asset_amount: 3 * BASE_PRECISION_U64,
+            ..Order::default()
+        };
+
+        let maker_orders = [
+            Order {
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount: 4 * BASE_PRECISION_U64,
+                price: 100 * PRICE_PRECISION_U64,
+                ..Order::default()
+            },
+            Order {
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount: 2 * BASE_PRECISION_U64,
+                price: 100 * PRICE_PRECISION_U64,
+                ..Order::default()
+            },
+            // already filled, gets nothing
+            Order::default(),
+            // next price level isn't allocated
+            Order {
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount: 2 * BASE_PRECISION_U64,
+                price: 101 * PRICE_PRECISION_U64,
+                ..Order::default()
+            },
+        ];
+
+        let fulfillment_methods = vec![
+            PerpFulfillmentMethod::ProRataMatch(Pubkey::default(), 0, 100 * PRICE_PRECISION_U64),
+            PerpFulfillmentMethod::ProRataMatch(Pubkey::default(), 1, 100 * PRICE_PRECISION_U64),
+            PerpFulfillmentMethod::ProRataMatch(Pubkey::default(), 2, 100 * PRICE_PRECISION_U64),
+            PerpFulfillmentMethod::ProRataMatch(Pubkey::default(), 3, 101 * PRICE_PRECISION_U64),
+        ];
+
+        let allocations = get_pro_rata_allocations(
+            &taker_order,
+            0,
+            &fulfillment_methods,
+            BASE_PRECISION_U64 / 10,
+            |_, maker_index| Ok((maker_orders[maker_index as usize], 0)),
+        )
+        .unwrap();
+
+        assert_eq!(
+            allocations,
+            vec![
+                ((Pubkey::default(), 0), 2 * BASE_PRECISION_U64),
+                ((Pubkey::default(), 1), BASE_PRECISION_U64),
+                ((Pubkey::default(), 2), 0),
+            ]
+        );
+
+        // nothing to allocate unless the methods start with a pro-rata level
+        let allocations = get_pro_rata_allocations(
+            &taker_order,
+            0,
+            &[PerpFulfillmentMethod::AMM(None)],
+            BASE_PRECISION_U64 / 10,
+            |_, maker_index| Ok((maker_orders[maker_index as usize], 0)),
+        )
+        .unwrap();
+        assert!(allocations.is_empty());
+    }
+}
@@ -6,11 +6,15 @@ use crate::error::{DriftResult, ErrorCode};
 use crate::math::bn;
 use crate::math::casting::Cast;
 use crate::math::constants::{
-    AMM_TO_QUOTE_PRECISION_RATIO, AMM_TO_QUOTE_PRECISION_RATIO_I128, FUNDING_RATE_BUFFER,
-    PRICE_PRECISION, QUOTE_TO_BASE_AMT_FUNDING_PRECISION,
+    AMM_TO_QUOTE_PRECISION_RATIO, AMM_TO_QUOTE_PRECISION_RATIO_I128, BPS_PRECISION_I64,
+    FUNDING_RATE_BUFFER, ONE_YEAR, PERCENTAGE_PRECISION_I128, PERCENTAGE_PRECISION_I64,
+    PREMIUM_INDEX_INTEREST_CLAMP, PRICE_PRECISION, QUOTE_TO_BASE_AMT_FUNDING_PRECISION,
+    TWENTY_FOUR_HOUR,
 };
 use crate::math::repeg::{calculate_fee_pool, get_total_fee_lower_bound};
 use crate::math::safe_math::SafeMath;
+use crate::math::stats::calculate_new_twap;
+use crate::validate;
 
 use crate::state::perp_market::PerpMarket;
 use crate::state::user::PerpPosition;
@@ -212,3 +216,85 @@ pub fn calculate_funding_payment_in_quote_precision(
 
     Ok(funding_payment_collateral)
 }
+
+/// The premium index is how far the bid twap sits above the oracle twap less how far the ask
+/// twap sits below it, so it's 0 while the oracle twap is inside the bid/ask twaps
+/// precision: PERCENTAGE_PRECISION
+pub fn calculate_premium_index(
+    bid_price_twap: u64,
+    ask_price_twap: u64,
+    oracle_price_twap: i64,
+) -> DriftResult<i64> {
+    validate!(
+        oracle_price_twap > 0,
+        ErrorCode::InvalidOracle,
+        "oracle_price_twap={} <= 0",
+        oracle_price_twap
+    )?;
+
+    let bid_premium = bid_price_twap
+        .cast::<i64>()?
+        .safe_sub(oracle_price_twap)?
+        .max(0);
+    let ask_discount = oracle_price_twap.safe_sub(ask_price_twap.cast()?)?.max(0);
+
+    bid_premium
+        .safe_sub(ask_discount)?
+        .cast::<i128>()?
+        .safe_mul(PERCENTAGE_PRECISION_I128)?
+        .safe_div(oracle_price_twap.cast()?)?
+        .cast()
+}
+
+/// Samples the premium index into the market's premium_index_twap. Must run before the oracle
+/// twap is updated since the last sample is taken to be at the oracle twap's ts
+pub fn update_premium_index_twap(market: &mut PerpMarket, now: i64) -> DriftResult<i64> {
+    let premium_index = calculate_premium_index(
+        market.amm.last_bid_price_twap,
+        market.amm.last_ask_price_twap,
+        market.amm.historical_oracle_data.last_oracle_price_twap,
+    )?;
+
+    let premium_index_twap = calculate_new_twap(
+        premium_index,
+        now,
+        market.premium_index_twap.cast()?,
+        market.amm.historical_oracle_data.last_oracle_price_twap_ts,
+        market.amm.funding_period,
+    )?;
+
+    market.premium_index_twap = premium_index_twap.cast()?;
+
+    Ok(premium_index_twap)
+}
+
+/// The price spread funding is paid on under the PremiumIndex formula: the premium index twap
+/// plus the interest rate, with the interest's pull on the premium capped at
+/// PREMIUM_INDEX_INTEREST_CLAMP. Like the mark/oracle twap spread it's a daily rate
+/// precision: PRICE_PRECISION
+pub fn calculate_premium_index_price_spread(
+    market: &PerpMarket,
+    oracle_price_twap: i64,
+) -> DriftResult<i64> {
+    let premium_index = market.premium_index_twap.cast::<i64>()?;
+
+    // annualized bps to a daily PERCENTAGE_PRECISION rate
+    let interest_rate = market
+        .amm
+        .funding_interest_rate
+        .cast::<i64>()?
+        .safe_mul(PERCENTAGE_PRECISION_I64 / BPS_PRECISION_I64)?
+        .safe_div(ONE_YEAR.cast::<i64>()?.safe_div(TWENTY_FOUR_HOUR)?)?;
+
+    let funding_premium = premium_index.safe_add(
+        interest_rate
+            .safe_sub(premium_index)?
+            .clamp(-PREMIUM_INDEX_INTEREST_CLAMP, PREMIUM_INDEX_INTEREST_CLAMP),
+    )?;
+
+    oracle_price_twap
+        .cast::<i128>()?
+        .safe_mul(funding_premium.cast()?)?
+        .safe_div(PERCENTAGE_PRECISION_I128)?
+        .cast()
+}
@@ -1,6 +1,7 @@
 use crate::controller::funding::update_funding_rate;
 use crate::math::constants::{
-    AMM_RESERVE_PRECISION, ONE_HOUR_I128, PRICE_PRECISION, PRICE_PRECISION_U64, QUOTE_PRECISION,
+    AMM_RESERVE_PRECISION, ONE_HOUR_I128, PRICE_PRECISION, PRICE_PRECISION_I64,
+    PRICE_PRECISION_U64, QUOTE_PRECISION,
 };
 use crate::math::funding::*;
 use std::cmp::min;
@@ -10,7 +11,7 @@ use crate::test_utils::get_pyth_price;
 // use crate::create_anchor_account_info;
 use crate::state::oracle::HistoricalOracleData;
 use crate::state::oracle_map::OracleMap;
-use crate::state::perp_market::{ContractTier, PerpMarket, AMM};
+use crate::state::perp_market::{ContractTier, FundingFormula, PerpMarket, AMM};
 use crate::state::state::{OracleGuardRails, State, ValidityGuardRails};
 use solana_program::pubkey::Pubkey;
 use std::str::FromStr;
@@ -573,3 +574,93 @@ fn max_funding_rates() {
 
     assert!(!did_succeed);
 }
+
+#[test]
+fn premium_index_funding() {
+    let oracle_price_twap = 100 * PRICE_PRECISION_I64;
+
+    // bid twap above the oracle twap
+    let premium_index = calculate_premium_index(
+        101 * PRICE_PRECISION_U64,
+        102 * PRICE_PRECISION_U64,
+        oracle_price_twap,
+    )
+    .unwrap();
+    assert_eq!(premium_index, 10000); // 1%
+
+    // oracle twap inside the bid/ask twaps
+    let premium_index = calculate_premium_index(
+        99 * PRICE_PRECISION_U64,
+        101 * PRICE_PRECISION_U64,
+        oracle_price_twap,
+    )
+    .unwrap();
+    assert_eq!(premium_index, 0);
+
+    // ask twap below the oracle twap
+    let premium_index = calculate_premium_index(
+        97 * PRICE_PRECISION_U64,
+        98 * PRICE_PRECISION_U64,
+        oracle_price_twap,
+    )
+    .unwrap();
+    assert_eq!(premium_index, -20000); // -2%
+
+    assert!(
+        calculate_premium_index(99 * PRICE_PRECISION_U64, 101 * PRICE_PRECISION_U64, 0).is_err()
+    );
+
+    let mut market = PerpMarket {
+        amm: AMM {
+            last_bid_price_twap: 101 * PRICE_PRECISION_U64,
+            last_ask_price_twap: 102 * PRICE_PRECISION_U64,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price_twap: oracle_price_twap,
+                last_oracle_price_twap_ts: 0,
+                ..HistoricalOracleData::default()
+            },
+            funding_period: 3600,
+            funding_formula: FundingFormula::PremiumIndex,
+            funding_interest_rate: 1095, // 10.95% a year, .03% a day
+            ..AMM::default()
+        },
+        ..PerpMarket::default()
+    };
+
+    // half the funding period at a 1% premium
+    let premium_index_twap = update_premium_index_twap(&mut market, 1800).unwrap();
+    assert_eq!(premium_index_twap, 4999);
+    assert_eq!(market.premium_index_twap, 4999);
+
+    // no premium, funding is just the interest rate
+    market.premium_index_twap = 0;
+    let price_spread = calculate_premium_index_price_spread(&market, oracle_price_twap).unwrap();
+    assert_eq!(price_spread, 30000); // .03%
+
+    // premium within the clamp of the interest rate, the interest rate wins
+    market.premium_index_twap = 1000;
+    let price_spread = calculate_premium_index_price_spread(&market, oracle_price_twap).unwrap();
+    assert_eq!(price_spread, 30000);
+
+    // premium beyond the clamp, interest only pulls it in by .15%
+    market.premium_index_twap = 10000;
+    let price_spread = calculate_premium_index_price_spread(&market, oracle_price_twap).unwrap();
+    assert_eq!(price_spread, 850000); // 1% - .15%
+
+    market.premium_index_twap = -20000;
+    let price_spread = calculate_premium_index_price_spread(&market, oracle_price_twap).unwrap();
+    assert_eq!(price_spread, -1850000); // -2% + .15%
+
+    // per market clamp overrides the contract tier's
+    market.contract_tier = ContractTier::A;
+    let max_price_spread = market
+        .get_max_price_divergence_for_funding_rate(oracle_price_twap)
+        .unwrap();
+    assert_eq!(max_price_spread, 3030303);
+
+    market.amm.max_funding_premium = 500; // 5%
+    let max_price_spread = market
+        .get_max_price_divergence_for_funding_rate(oracle_price_twap)
+        .unwrap();
+    assert_eq!(max_price_spread, 5 * PRICE_PRECISION_I64);
+}
@@ -4,10 +4,12 @@ use crate::controller::position::PositionDirection;
 use crate::error::DriftResult;
 use crate::math::casting::Cast;
 use crate::math::constants::{BID_ASK_SPREAD_PRECISION_I128, TEN_BPS_I64};
-use crate::math::orders::calculate_quote_asset_amount_for_maker_order;
+use crate::math::orders::{
+    calculate_quote_asset_amount_for_maker_order, standardize_base_asset_amount,
+};
 use crate::math::safe_math::SafeMath;
 
-use crate::state::user::Order;
+use crate::state::user::{Order, SelfTradePreventionMode};
 
 #[cfg(test)]
 mod tests;
@@ -59,6 +61,53 @@ pub fn do_orders_cross(
     }
 }
 
+#[derive(Clone, Copy, Default, PartialEq, Debug, Eq)]
+pub struct SelfTradePreventionOutcome {
+    pub expire_taker: bool,
+    pub expire_maker: bool,
+    /// amount removed from the order that isn't expired
+    pub base_asset_amount_decrement: u64,
+}
+
+pub fn calculate_self_trade_prevention_outcome(
+    self_trade_prevention_mode: SelfTradePreventionMode,
+    taker_base_asset_amount_unfilled: u64,
+    maker_base_asset_amount_unfilled: u64,
+) -> SelfTradePreventionOutcome {
+    match self_trade_prevention_mode {
+        SelfTradePreventionMode::None => SelfTradePreventionOutcome::default(),
+        SelfTradePreventionMode::ExpireMaker => SelfTradePreventionOutcome {
+            expire_maker: true,
+            ..SelfTradePreventionOutcome::default()
+        },
+        SelfTradePreventionMode::ExpireTaker => SelfTradePreventionOutcome {
+            expire_taker: true,
+            ..SelfTradePreventionOutcome::default()
+        },
+        SelfTradePreventionMode::ExpireBoth => SelfTradePreventionOutcome {
+            expire_taker: true,
+            expire_maker: true,
+            ..SelfTradePreventionOutcome::default()
+        },
+        SelfTradePreventionMode::DecrementAndCancel => {
+            let expire_taker = taker_base_asset_amount_unfilled <= maker_base_asset_amount_unfilled;
+            let expire_maker = maker_base_asset_amount_unfilled <= taker_base_asset_amount_unfilled;
+            SelfTradePreventionOutcome {
+                expire_taker,
+                expire_maker,
+                base_asset_amount_decrement: if expire_taker && expire_maker {
+                    0
+                } else {
+                    min(
+                        taker_base_asset_amount_unfilled,
+                        maker_base_asset_amount_unfilled,
+                    )
+                },
+            }
+        }
+    }
+}
+
 pub fn calculate_fill_for_matched_orders(
     maker_base_asset_amount: u64,
     maker_price: u64,
@@ -78,6 +127,55 @@ pub fn calculate_fill_for_matched_orders(
     Ok((base_asset_amount, quote_asset_amount))
 }
 
+/// Splits the taker's base amount across makers at one price level in proportion to their size,
+/// rounded down to the step size. What rounding leaves over goes to makers in order
+pub fn calculate_pro_rata_allocations(
+    taker_base_asset_amount: u64,
+    maker_base_asset_amounts: &[u64],
+    step_size: u64,
+) -> DriftResult<Vec<u64>> {
+    let total_maker_base_asset_amount = maker_base_asset_amounts.iter().sum::<u64>();
+
+    if taker_base_asset_amount >= total_maker_base_asset_amount {
+        return Ok(maker_base_asset_amounts.to_vec());
+    }
+
+    let mut allocations = maker_base_asset_amounts
+        .iter()
+        .map(|maker_base_asset_amount| {
+            standardize_base_asset_amount(
+                taker_base_asset_amount
+                    .cast::<u128>()?
+                    .safe_mul(maker_base_asset_amount.cast()?)?
+                    .safe_div(total_maker_base_asset_amount.cast()?)?
+                    .cast()?,
+                step_size,
+            )
+        })
+        .collect::<DriftResult<Vec<u64>>>()?;
+
+    let mut remaining_base_asset_amount =
+        taker_base_asset_amount.safe_sub(allocations.iter().sum::<u64>())?;
+
+    for (allocation, maker_base_asset_amount) in
+        allocations.iter_mut().zip(maker_base_asset_amounts.iter())
+    {
+        if remaining_base_asset_amount < step_size {
+            break;
+        }
+
+        let extra = standardize_base_asset_amount(
+            remaining_base_asset_amount.min(maker_base_asset_amount.safe_sub(*allocation)?),
+            step_size,
+        )?;
+
+        *allocation = allocation.safe_add(extra)?;
+        remaining_base_asset_amount = remaining_base_asset_amount.safe_sub(extra)?;
+    }
+
+    Ok(allocations)
+}
+
 pub fn calculate_filler_multiplier_for_matched_orders(
     maker_price: u64,
     maker_direction: PositionDirection,
@@ -255,3 +255,164 @@ fn filler_multiplier_maker_short() {
 
     assert_eq!(mult, 2100); // 2.1x
 }
+
+mod calculate_self_trade_prevention_outcome {
+    use crate::math::constants::BASE_PRECISION_U64;
+    use crate::math::matching::*;
+    use crate::state::user::SelfTradePreventionMode;
+
+    #[test]
+    fn expire_modes() {
+        let taker = BASE_PRECISION_U64;
+        let maker = 2 * BASE_PRECISION_U64;
+
+        let outcome =
+            calculate_self_trade_prevention_outcome(SelfTradePreventionMode::None, taker, maker);
+        assert_eq!(outcome, SelfTradePreventionOutcome::default());
+
+        let outcome = calculate_self_trade_prevention_outcome(
+            SelfTradePreventionMode::ExpireMaker,
+            taker,
+            maker,
+        );
+        assert_eq!(
+            outcome,
+            SelfTradePreventionOutcome {
+                expire_taker: false,
+                expire_maker: true,
+                base_asset_amount_decrement: 0,
+            }
+        );
+
+        let outcome = calculate_self_trade_prevention_outcome(
+            SelfTradePreventionMode::ExpireTaker,
+            taker,
+            maker,
+        );
+        assert_eq!(
+            outcome,
+            SelfTradePreventionOutcome {
+                expire_taker: true,
+                expire_maker: false,
+                base_asset_amount_decrement: 0,
+            }
+        );
+
+        let outcome = calculate_self_trade_prevention_outcome(
+            SelfTradePreventionMode::ExpireBoth,
+            taker,
+            maker,
+        );
+        assert_eq!(
+            outcome,
+            SelfTradePreventionOutcome {
+                expire_taker: true,
+                expire_maker: true,
+                base_asset_amount_decrement: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn decrement_and_cancel() {
+        // taker smaller, maker decremented
+        let outcome = calculate_self_trade_prevention_outcome(
+            SelfTradePreventionMode::DecrementAndCancel,
+            BASE_PRECISION_U64,
+            3 * BASE_PRECISION_U64,
+        );
+        assert_eq!(
+            outcome,
+            SelfTradePreventionOutcome {
+                expire_taker: true,
+                expire_maker: false,
+                base_asset_amount_decrement: BASE_PRECISION_U64,
+            }
+        );
+
+        // maker smaller, taker decremented
+        let outcome = calculate_self_trade_prevention_outcome(
+            SelfTradePreventionMode::DecrementAndCancel,
+            3 * BASE_PRECISION_U64,
+            2 * BASE_PRECISION_U64,
+        );
+        assert_eq!(
+            outcome,
+            SelfTradePreventionOutcome {
+                expire_taker: false,
+                expire_maker: true,
+                base_asset_amount_decrement: 2 * BASE_PRECISION_U64,
+            }
+        );
+
+        // same size, both canceled
+        let outcome = calculate_self_trade_prevention_outcome(
+            SelfTradePreventionMode::DecrementAndCancel,
+            BASE_PRECISION_U64,
+            BASE_PRECISION_U64,
+        );
+        assert_eq!(
+            outcome,
+            SelfTradePreventionOutcome {
+                expire_taker: true,
+                expire_maker: true,
+                base_asset_amount_decrement: 0,
+            }
+        );
+    }
+}
+
+mod calculate_pro_rata_allocations {
+    use crate::math::matching::calculate_pro_rata_allocations;
+    use crate::BASE_PRECISION_U64;
+
+    #[test]
+    fn taker_larger_than_level() {
+        let allocations = calculate_pro_rata_allocations(
+            10 * BASE_PRECISION_U64,
+            &[BASE_PRECISION_U64, 2 * BASE_PRECISION_U64],
+            BASE_PRECISION_U64 / 10,
+        )
+        .unwrap();
+
+        assert_eq!(
+            allocations,
+            vec![BASE_PRECISION_U64, 2 * BASE_PRECISION_U64]
+        );
+    }
+
+    #[test]
+    fn split_proportionally() {
+        let allocations = calculate_pro_rata_allocations(
+            3 * BASE_PRECISION_U64,
+            &[2 * BASE_PRECISION_U64, 4 * BASE_PRECISION_U64],
+            BASE_PRECISION_U64 / 10,
+        )
+        .unwrap();
+
+        assert_eq!(
+            allocations,
+            vec![BASE_PRECISION_U64, 2 * BASE_PRECISION_U64]
+        );
+    }
+
+    #[test]
+    fn rounding_leftover_goes_to_first_maker() {
+        let allocations = calculate_pro_rata_allocations(
+            BASE_PRECISION_U64,
+            &[BASE_PRECISION_U64, BASE_PRECISION_U64, BASE_PRECISION_U64],
+            BASE_PRECISION_U64 / 10,
+        )
+        .unwrap();
+
+        assert_eq!(
+            allocations,
+            vec![
+                4 * BASE_PRECISION_U64 / 10,
+                3 * BASE_PRECISION_U64 / 10,
+                3 * BASE_PRECISION_U64 / 10,
+            ]
+        );
+        assert_eq!(allocations.iter().sum::<u64>(), BASE_PRECISION_U64);
+    }
+}
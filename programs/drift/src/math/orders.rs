@@ -33,7 +33,8 @@ use crate::state::perp_market_map::PerpMarketMap;
 use crate::state::spot_market::SpotMarket;
 use crate::state::spot_market_map::SpotMarketMap;
 use crate::state::user::{
-    MarketType, Order, OrderFillSimulation, OrderStatus, OrderTriggerCondition, PerpPosition, User,
+    MarketType, Order, OrderBitFlag, OrderFillSimulation, OrderStatus, OrderTriggerCondition,
+    OrderType, PerpPosition, User,
 };
 use crate::state::user_map::UserMap;
 use crate::validate;
@@ -274,26 +275,46 @@ pub fn get_price_for_perp_order(
     price: u64,
     direction: PositionDirection,
     post_only: PostOnlyParam,
+    max_slide_ticks: u16,
     amm: &AMM,
 ) -> DriftResult<u64> {
     let mut limit_price = standardize_price(price, amm.order_tick_size, direction)?;
 
     if post_only == PostOnlyParam::Slide {
         let reserve_price = amm.reserve_price()?;
-        match direction {
+        let slid_price = match direction {
             PositionDirection::Long => {
                 let amm_ask = amm.ask_price(reserve_price)?;
                 if limit_price >= amm_ask {
-                    limit_price = amm_ask.safe_sub(amm.order_tick_size)?;
+                    amm_ask.safe_sub(amm.order_tick_size)?
+                } else {
+                    limit_price
                 }
             }
             PositionDirection::Short => {
                 let amm_bid = amm.bid_price(reserve_price)?;
                 if limit_price <= amm_bid {
-                    limit_price = amm_bid.safe_add(amm.order_tick_size)?;
+                    amm_bid.safe_add(amm.order_tick_size)?
+                } else {
+                    limit_price
                 }
             }
+        };
+
+        if max_slide_ticks != 0 {
+            let max_slide_distance = amm.order_tick_size.safe_mul(max_slide_ticks.cast()?)?;
+            let slide_distance = limit_price.abs_diff(slid_price);
+
+            validate!(
+                slide_distance <= max_slide_distance,
+                ErrorCode::PostOnlySlideTooFar,
+                "Post only slide ({}) greater than max slide ({})",
+                slide_distance,
+                max_slide_distance
+            )?;
         }
+
+        limit_price = slid_price;
     }
 
     Ok(limit_price)
@@ -359,7 +380,11 @@ pub fn should_expire_order_before_fill(
 #[inline(always)]
 pub fn should_expire_order(user: &User, user_order_index: usize, now: i64) -> DriftResult<bool> {
     let order = &user.orders[user_order_index];
-    if order.status != OrderStatus::Open || order.max_ts == 0 || order.must_be_triggered() {
+    // twap orders are never triggered but still end at max_ts
+    if order.status != OrderStatus::Open
+        || order.max_ts == 0
+        || (order.must_be_triggered() && order.order_type != OrderType::Twap)
+    {
         return Ok(false);
     }
 
@@ -378,6 +403,15 @@ pub fn should_cancel_reduce_only_order(
     Ok(should_cancel)
 }
 
+/// Once the auction is over, whatever a max slippage order couldn't fill within its bound is canceled
+pub fn should_cancel_max_slippage_order(order: &Order, slot: u64) -> DriftResult<bool> {
+    let should_cancel = order.status == OrderStatus::Open
+        && order.is_bit_flag_set(OrderBitFlag::MaxSlippage)
+        && order.is_auction_complete(slot)?;
+
+    Ok(should_cancel)
+}
+
 pub fn order_breaches_maker_oracle_price_bands(
     order: &Order,
     oracle_price: i64,
@@ -581,6 +615,81 @@ pub fn order_satisfies_trigger_condition(order: &Order, oracle_price: u64) -> Dr
     }
 }
 
+/// For signed trigger sources the unsigned trigger price is a threshold on either side of zero:
+/// Above triggers above +trigger_price and Below below -trigger_price
+pub fn order_satisfies_signed_trigger_condition(order: &Order, value: i64) -> DriftResult<bool> {
+    let trigger_value = order.trigger_price.cast::<i64>()?;
+    match order.trigger_condition {
+        OrderTriggerCondition::Above => Ok(value > trigger_value),
+        OrderTriggerCondition::Below => Ok(value < -trigger_value),
+        _ => Err(print_error!(ErrorCode::InvalidTriggerOrderCondition)()),
+    }
+}
+
+pub fn calculate_trailing_distance(order: &Order, oracle_price: u64) -> DriftResult<u64> {
+    let trailing_offset = order.oracle_price_offset.unsigned_abs().cast::<u64>()?;
+
+    if order.is_bit_flag_set(OrderBitFlag::TrailingPercentage) {
+        oracle_price
+            .safe_mul(trailing_offset)?
+            .safe_div(PERCENTAGE_PRECISION_U64)
+    } else {
+        Ok(trailing_offset)
+    }
+}
+
+pub fn calculate_trailing_trigger_price(
+    order: &Order,
+    oracle_price: u64,
+    tick_size: u64,
+) -> DriftResult<u64> {
+    let trailing_distance = calculate_trailing_distance(order, oracle_price)?;
+
+    let trigger_price = match order.direction {
+        PositionDirection::Long => oracle_price.safe_add(trailing_distance)?,
+        PositionDirection::Short => oracle_price.saturating_sub(trailing_distance),
+    };
+
+    // round away from the oracle so the trigger never lands on the wrong side of it
+    standardize_price(trigger_price, tick_size, order.direction.opposite())
+}
+
+/// Returns the new trigger price and condition if the oracle moved the trailing watermark in the user's favor
+/// Inactive trailing take profits only start trailing once the oracle crosses the activation price
+pub fn calculate_trailing_trigger_update(
+    order: &Order,
+    oracle_price: u64,
+    tick_size: u64,
+) -> DriftResult<Option<(u64, OrderTriggerCondition)>> {
+    if !order.is_trailing_order() || order.triggered() {
+        return Ok(None);
+    }
+
+    let trailing_trigger_condition = order.get_trailing_trigger_condition();
+
+    if !order.is_trailing_active() {
+        if !order_satisfies_trigger_condition(order, oracle_price)? {
+            return Ok(None);
+        }
+
+        let trigger_price = calculate_trailing_trigger_price(order, oracle_price, tick_size)?;
+        return Ok(Some((trigger_price, trailing_trigger_condition)));
+    }
+
+    let trigger_price = calculate_trailing_trigger_price(order, oracle_price, tick_size)?;
+
+    let watermark_moved = match order.direction {
+        PositionDirection::Long => trigger_price < order.trigger_price,
+        PositionDirection::Short => trigger_price > order.trigger_price,
+    };
+
+    if watermark_moved {
+        Ok(Some((trigger_price, trailing_trigger_condition)))
+    } else {
+        Ok(None)
+    }
+}
+
 pub fn is_new_order_risk_increasing(
     order: &Order,
     position_base_asset_amount: i64,
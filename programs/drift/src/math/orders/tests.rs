@@ -443,6 +443,25 @@ mod should_expire_order {
 
         assert!(!is_expired);
     }
+
+    #[test]
+    fn order_is_twap_order() {
+        let user = User {
+            orders: get_orders(Order {
+                status: OrderStatus::Open,
+                order_type: OrderType::Twap,
+                max_ts: 99,
+                ..Order::default()
+            }),
+            ..User::default()
+        };
+
+        let now = 100;
+
+        let is_expired = should_expire_order(&user, 0, now).unwrap();
+
+        assert!(is_expired);
+    }
 }
 
 mod get_max_fill_amounts {
@@ -3223,6 +3242,7 @@ pub mod is_new_order_risk_increasing {
 }
 
 pub mod get_price_for_perp_order {
+    use crate::error::ErrorCode;
     use crate::math::orders::get_price_for_perp_order;
 
     use crate::state::order_params::PostOnlyParam;
@@ -3250,13 +3270,13 @@ pub mod get_price_for_perp_order {
         let direction = PositionDirection::Short;
 
         let limit_price =
-            get_price_for_perp_order(ask, direction, PostOnlyParam::Slide, &amm).unwrap();
+            get_price_for_perp_order(ask, direction, PostOnlyParam::Slide, 0, &amm).unwrap();
 
         assert_eq!(limit_price, 99100000); // $99.1
 
         let ask = amm_bid_price;
         let limit_price =
-            get_price_for_perp_order(ask, direction, PostOnlyParam::Slide, &amm).unwrap();
+            get_price_for_perp_order(ask, direction, PostOnlyParam::Slide, 0, &amm).unwrap();
 
         assert_eq!(limit_price, 99100000); // $99.1
     }
@@ -3281,7 +3301,7 @@ pub mod get_price_for_perp_order {
         let direction = PositionDirection::Short;
 
         let limit_price =
-            get_price_for_perp_order(ask, direction, PostOnlyParam::Slide, &amm).unwrap();
+            get_price_for_perp_order(ask, direction, PostOnlyParam::Slide, 0, &amm).unwrap();
 
         assert_eq!(limit_price, ask); // $99.1
     }
@@ -3306,13 +3326,13 @@ pub mod get_price_for_perp_order {
         let direction = PositionDirection::Long;
 
         let limit_price =
-            get_price_for_perp_order(bid, direction, PostOnlyParam::Slide, &amm).unwrap();
+            get_price_for_perp_order(bid, direction, PostOnlyParam::Slide, 0, &amm).unwrap();
 
         assert_eq!(limit_price, 100900000); // $100.9
 
         let bid = amm_ask_price;
         let limit_price =
-            get_price_for_perp_order(bid, direction, PostOnlyParam::Slide, &amm).unwrap();
+            get_price_for_perp_order(bid, direction, PostOnlyParam::Slide, 0, &amm).unwrap();
 
         assert_eq!(limit_price, 100900000); // $100.9
     }
@@ -3337,10 +3357,42 @@ pub mod get_price_for_perp_order {
         let direction = PositionDirection::Long;
 
         let limit_price =
-            get_price_for_perp_order(bid, direction, PostOnlyParam::Slide, &amm).unwrap();
+            get_price_for_perp_order(bid, direction, PostOnlyParam::Slide, 0, &amm).unwrap();
 
         assert_eq!(limit_price, bid); // $100.1
     }
+
+    #[test]
+    fn max_slide_ticks() {
+        let amm = AMM {
+            base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 100 * PEG_PRECISION,
+            order_tick_size: 100000,
+            long_spread: BID_ASK_SPREAD_PRECISION_U128 as u32 / 100,
+            ..AMM::default()
+        };
+
+        let bid = 101100000; // $101.1
+        let direction = PositionDirection::Long;
+
+        // slides two ticks to $100.9
+        let limit_price =
+            get_price_for_perp_order(bid, direction, PostOnlyParam::Slide, 2, &amm).unwrap();
+
+        assert_eq!(limit_price, 100900000); // $100.9
+
+        let result = get_price_for_perp_order(bid, direction, PostOnlyParam::Slide, 1, &amm);
+
+        assert_eq!(result, Err(ErrorCode::PostOnlySlideTooFar));
+
+        // doesn't cross, no slide needed
+        let bid = 100100000; // $100.1
+        let limit_price =
+            get_price_for_perp_order(bid, direction, PostOnlyParam::Slide, 1, &amm).unwrap();
+
+        assert_eq!(limit_price, bid);
+    }
 }
 
 pub mod estimate_price_from_side {
@@ -3861,3 +3913,225 @@ mod fallback_price_logic {
         assert_eq!(result, 99500000);
     }
 }
+
+mod calculate_trailing_trigger_update {
+    use crate::controller::position::PositionDirection;
+    use crate::math::constants::{PERCENTAGE_PRECISION_U64, PRICE_PRECISION_U64};
+    use crate::math::orders::{
+        calculate_trailing_trigger_price, calculate_trailing_trigger_update,
+    };
+    use crate::state::user::{Order, OrderBitFlag, OrderStatus, OrderTriggerCondition, OrderType};
+
+    #[test]
+    fn trailing_stop_short_ratchets_up() {
+        let tick_size = PRICE_PRECISION_U64 / 100;
+        let order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::TrailingStop,
+            direction: PositionDirection::Short,
+            trigger_condition: OrderTriggerCondition::Below,
+            trigger_price: 95 * PRICE_PRECISION_U64,
+            oracle_price_offset: 5 * PRICE_PRECISION_U64 as i32,
+            ..Order::default()
+        };
+
+        // oracle moved up, trigger follows
+        let update =
+            calculate_trailing_trigger_update(&order, 101 * PRICE_PRECISION_U64, tick_size)
+                .unwrap();
+        assert_eq!(
+            update,
+            Some((96 * PRICE_PRECISION_U64, OrderTriggerCondition::Below))
+        );
+
+        // oracle moved down, trigger stays
+        let update =
+            calculate_trailing_trigger_update(&order, 99 * PRICE_PRECISION_U64, tick_size).unwrap();
+        assert_eq!(update, None);
+    }
+
+    #[test]
+    fn trailing_stop_long_ratchets_down() {
+        let tick_size = PRICE_PRECISION_U64 / 100;
+        let order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::TrailingStop,
+            direction: PositionDirection::Long,
+            trigger_condition: OrderTriggerCondition::Above,
+            trigger_price: 105 * PRICE_PRECISION_U64,
+            oracle_price_offset: 5 * PRICE_PRECISION_U64 as i32,
+            ..Order::default()
+        };
+
+        let update =
+            calculate_trailing_trigger_update(&order, 98 * PRICE_PRECISION_U64, tick_size).unwrap();
+        assert_eq!(
+            update,
+            Some((103 * PRICE_PRECISION_U64, OrderTriggerCondition::Above))
+        );
+
+        let update =
+            calculate_trailing_trigger_update(&order, 101 * PRICE_PRECISION_U64, tick_size)
+                .unwrap();
+        assert_eq!(update, None);
+    }
+
+    #[test]
+    fn trailing_stop_percentage() {
+        let tick_size = PRICE_PRECISION_U64 / 100;
+        let order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::TrailingStop,
+            direction: PositionDirection::Short,
+            trigger_condition: OrderTriggerCondition::Below,
+            trigger_price: 90 * PRICE_PRECISION_U64,
+            oracle_price_offset: (PERCENTAGE_PRECISION_U64 / 10) as i32, // 10%
+            bit_flags: OrderBitFlag::TrailingPercentage as u8,
+            ..Order::default()
+        };
+
+        let trigger_price =
+            calculate_trailing_trigger_price(&order, 200 * PRICE_PRECISION_U64, tick_size).unwrap();
+        assert_eq!(trigger_price, 180 * PRICE_PRECISION_U64);
+
+        let update =
+            calculate_trailing_trigger_update(&order, 200 * PRICE_PRECISION_U64, tick_size)
+                .unwrap();
+        assert_eq!(
+            update,
+            Some((180 * PRICE_PRECISION_U64, OrderTriggerCondition::Below))
+        );
+    }
+
+    #[test]
+    fn trailing_trigger_price_rounds_away_from_oracle() {
+        let tick_size = PRICE_PRECISION_U64 / 10;
+        let order = Order {
+            order_type: OrderType::TrailingStop,
+            direction: PositionDirection::Short,
+            oracle_price_offset: (PRICE_PRECISION_U64 / 100) as i32,
+            ..Order::default()
+        };
+
+        let trigger_price =
+            calculate_trailing_trigger_price(&order, 100 * PRICE_PRECISION_U64, tick_size).unwrap();
+        assert_eq!(trigger_price, 99 * PRICE_PRECISION_U64 + 9 * tick_size);
+
+        let order = Order {
+            direction: PositionDirection::Long,
+            ..order
+        };
+
+        let trigger_price =
+            calculate_trailing_trigger_price(&order, 100 * PRICE_PRECISION_U64, tick_size).unwrap();
+        assert_eq!(trigger_price, 100 * PRICE_PRECISION_U64 + tick_size);
+    }
+
+    #[test]
+    fn trailing_take_profit_activates() {
+        let tick_size = PRICE_PRECISION_U64 / 100;
+        // closing a long, activates once oracle above 110
+        let order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::TrailingTakeProfit,
+            direction: PositionDirection::Short,
+            trigger_condition: OrderTriggerCondition::Above,
+            trigger_price: 110 * PRICE_PRECISION_U64,
+            oracle_price_offset: 2 * PRICE_PRECISION_U64 as i32,
+            ..Order::default()
+        };
+        assert!(!order.is_trailing_active());
+
+        let update =
+            calculate_trailing_trigger_update(&order, 109 * PRICE_PRECISION_U64, tick_size)
+                .unwrap();
+        assert_eq!(update, None);
+
+        let update =
+            calculate_trailing_trigger_update(&order, 111 * PRICE_PRECISION_U64, tick_size)
+                .unwrap();
+        assert_eq!(
+            update,
+            Some((109 * PRICE_PRECISION_U64, OrderTriggerCondition::Below))
+        );
+
+        let (trigger_price, trigger_condition) = update.unwrap();
+        let order = Order {
+            trigger_price,
+            trigger_condition,
+            ..order
+        };
+        assert!(order.is_trailing_active());
+
+        let update =
+            calculate_trailing_trigger_update(&order, 115 * PRICE_PRECISION_U64, tick_size)
+                .unwrap();
+        assert_eq!(
+            update,
+            Some((113 * PRICE_PRECISION_U64, OrderTriggerCondition::Below))
+        );
+    }
+
+    #[test]
+    fn not_trailing_order() {
+        let order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Short,
+            trigger_condition: OrderTriggerCondition::Below,
+            trigger_price: 95 * PRICE_PRECISION_U64,
+            ..Order::default()
+        };
+
+        let update = calculate_trailing_trigger_update(
+            &order,
+            101 * PRICE_PRECISION_U64,
+            PRICE_PRECISION_U64 / 100,
+        )
+        .unwrap();
+        assert_eq!(update, None);
+    }
+}
+
+mod order_satisfies_signed_trigger_condition {
+    use crate::controller::position::PositionDirection;
+    use crate::math::constants::PERCENTAGE_PRECISION_U64;
+    use crate::math::orders::order_satisfies_signed_trigger_condition;
+    use crate::state::user::{Order, OrderStatus, OrderTriggerCondition, OrderType};
+
+    #[test]
+    fn above_and_below() {
+        // 1% mark oracle divergence
+        let order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Short,
+            trigger_condition: OrderTriggerCondition::Above,
+            trigger_price: PERCENTAGE_PRECISION_U64 / 100,
+            ..Order::default()
+        };
+
+        let trigger_value = (PERCENTAGE_PRECISION_U64 / 100) as i64;
+
+        assert!(!order_satisfies_signed_trigger_condition(&order, trigger_value).unwrap());
+        assert!(order_satisfies_signed_trigger_condition(&order, trigger_value + 1).unwrap());
+        assert!(!order_satisfies_signed_trigger_condition(&order, -2 * trigger_value).unwrap());
+
+        let order = Order {
+            direction: PositionDirection::Long,
+            trigger_condition: OrderTriggerCondition::Below,
+            ..order
+        };
+
+        assert!(!order_satisfies_signed_trigger_condition(&order, 2 * trigger_value).unwrap());
+        assert!(!order_satisfies_signed_trigger_condition(&order, -trigger_value).unwrap());
+        assert!(order_satisfies_signed_trigger_condition(&order, -trigger_value - 1).unwrap());
+
+        let order = Order {
+            trigger_condition: OrderTriggerCondition::TriggeredBelow,
+            ..order
+        };
+
+        assert!(order_satisfies_signed_trigger_condition(&order, 0).is_err());
+    }
+}
@@ -133,6 +133,11 @@ pub struct CurveRecord {
     pub fill_record: u128,
     pub number_of_users: u32,
     pub market_index: u16,
+    /// fees spent by scheduled repeg/k updates in the current budget period
+    /// 0 for updates that aren't scheduled
+    pub curve_update_budget_spent: u64,
+    /// fees left for scheduled repeg/k updates in the current budget period
+    pub curve_update_budget_remaining: u64,
 }
 
 #[event]
@@ -264,6 +269,8 @@ pub enum OrderAction {
     Fill,
     Trigger,
     Expire,
+    // a trailing order's trigger price moved without the order triggering
+    UpdateTrigger,
 }
 
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq)]
@@ -287,6 +294,21 @@ pub enum OrderActionExplanation {
     OrderFilledWithAMMJitLPSplit,
     OrderFilledWithLPJit,
     DeriskLp,
+    TrailingTriggerPriceUpdated,
+    OneCancelsOther,
+    BracketEntryCanceled,
+    PositionTakeProfit,
+    PositionStopLoss,
+    TwapSliceReleased,
+    TwapSliceRolledOver,
+    TwapCompleted,
+    TwapCanceled,
+    SelfTradePrevented,
+    CancelAfterDeadline,
+    MaxSlippageExceeded,
+    // test only MockVenue fills, kept last and out of the program build so it's never emitted
+    #[cfg(test)]
+    OrderFillWithMockVenue,
 }
 
 impl Default for OrderAction {
@@ -12,6 +12,10 @@ pub enum FillMode {
     Fill,
     PlaceAndMake,
     PlaceAndTake,
+    /// Leg of a combo order, the taker margin is checked once all legs are filled
+    ComboLeg,
+    /// Order whose auction is over in a batch auction market, makers clear at one uniform price
+    BatchAuction,
 }
 
 impl FillMode {
@@ -23,22 +27,35 @@ impl FillMode {
         tick_size: u64,
     ) -> DriftResult<Option<u64>> {
         match self {
-            FillMode::Fill | FillMode::PlaceAndMake => {
+            FillMode::Fill | FillMode::PlaceAndMake | FillMode::BatchAuction => {
                 order.get_limit_price(valid_oracle_price, None, slot, tick_size)
             }
-            FillMode::PlaceAndTake => {
+            FillMode::PlaceAndTake | FillMode::ComboLeg => {
                 if order.has_auction() {
-                    calculate_auction_price(
+                    let auction_price = calculate_auction_price(
                         order,
                         order.slot.safe_add(order.auction_duration.cast()?)?,
                         tick_size,
                         valid_oracle_price,
+                    )?;
+
+                    order.clamp_limit_price_to_max_slippage(
+                        Some(auction_price),
+                        valid_oracle_price,
+                        tick_size,
                     )
-                    .map(Some)
                 } else {
                     order.get_limit_price(valid_oracle_price, None, slot, tick_size)
                 }
             }
         }
     }
+
+    pub fn is_combo_leg(&self) -> bool {
+        *self == FillMode::ComboLeg
+    }
+
+    pub fn is_batch_auction(&self) -> bool {
+        *self == FillMode::BatchAuction
+    }
 }
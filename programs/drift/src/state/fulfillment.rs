@@ -1,9 +1,11 @@
 use solana_program::pubkey::Pubkey;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PerpFulfillmentMethod {
     AMM(Option<u64>),
     Match(Pubkey, u16),
+    /// Maker sharing a price level with other makers, filled pro-rata with them at the given price
+    ProRataMatch(Pubkey, u16, u64),
 }
 
 #[derive(Debug)]
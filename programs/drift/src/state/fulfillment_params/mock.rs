@@ -0,0 +1,206 @@
+use crate::controller::position::PositionDirection;
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::safe_math::SafeMath;
+use crate::state::events::OrderActionExplanation;
+use crate::state::spot_fulfillment_params::{ExternalSpotFill, ExternalSpotVenue};
+use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+use crate::state::state::State;
+use solana_program::account_info::AccountInfo;
+use std::iter::Peekable;
+use std::slice::Iter;
+
+/// In memory order book venue so external fills can be tested without the dex programs.
+///
+/// This is only a test double for the ExternalSpotVenue steps, not a deployable venue program: it
+/// isn't backed by accounts, so there's no SpotFulfillmentType for it and load_market always
+/// fails. Tests build it directly and pass it to fulfill_spot_order_with_external_market
+#[derive(Default)]
+pub struct MockVenue {
+    /// (price, base amount) resting bids, best first
+    pub bids: Vec<(u64, u64)>,
+    /// (price, base amount) resting asks, best first
+    pub asks: Vec<(u64, u64)>,
+    pub taker_fee_bps: u64,
+    pub base_decimals: u32,
+    pub base_vault_amount: u64,
+    pub quote_vault_amount: u64,
+}
+
+pub struct MockPlacedOrder {
+    pub base_before: u64,
+    pub quote_before: u64,
+    pub fee: u64,
+}
+
+impl<'a, 'b> ExternalSpotVenue<'a, 'b> for MockVenue {
+    type PlacedOrder = MockPlacedOrder;
+
+    /// The mock isn't backed by accounts, tests build it directly
+    #[allow(clippy::type_complexity)]
+    fn load_market<'c>(
+        _account_info_iter: &'a mut Peekable<Iter<'c, AccountInfo<'b>>>,
+        _state: &State,
+        _base_market: &SpotMarket,
+        _quote_market: &SpotMarket,
+        _now: i64,
+    ) -> DriftResult<Self> {
+        Err(ErrorCode::InvalidSpotFulfillmentParams)
+    }
+
+    fn quote_best_bid_and_ask(&self) -> DriftResult<(Option<u64>, Option<u64>)> {
+        Ok((
+            self.bids.first().map(|(price, _)| *price),
+            self.asks.first().map(|(price, _)| *price),
+        ))
+    }
+
+    fn place_ioc_order(
+        &mut self,
+        taker_direction: PositionDirection,
+        taker_price: u64,
+        taker_base_asset_amount: u64,
+        taker_max_quote_asset_amount: u64,
+    ) -> DriftResult<Option<MockPlacedOrder>> {
+        if taker_base_asset_amount == 0 {
+            return Ok(None);
+        }
+
+        let base_before = self.base_vault_amount;
+        let quote_before = self.quote_vault_amount;
+        let base_precision = 10_u64.pow(self.base_decimals);
+
+        let levels = match taker_direction {
+            PositionDirection::Long => &mut self.asks,
+            PositionDirection::Short => &mut self.bids,
+        };
+
+        let mut base_asset_amount_filled = 0_u64;
+        let mut quote_asset_amount_filled = 0_u64;
+        for (price, base_asset_amount) in levels.iter_mut() {
+            let crosses = match taker_direction {
+                PositionDirection::Long => *price <= taker_price,
+                PositionDirection::Short => *price >= taker_price,
+            };
+
+            if !crosses {
+                break;
+            }
+
+            // price and quote share the same precision
+            let quote_remaining =
+                taker_max_quote_asset_amount.safe_sub(quote_asset_amount_filled)?;
+            let base_affordable = quote_remaining
+                .cast::<u128>()?
+                .safe_mul(base_precision.cast()?)?
+                .safe_div(price.cast()?)?
+                .cast::<u64>()?;
+
+            let fill = (*base_asset_amount)
+                .min(taker_base_asset_amount.safe_sub(base_asset_amount_filled)?)
+                .min(base_affordable);
+
+            if fill == 0 {
+                break;
+            }
+
+            *base_asset_amount = base_asset_amount.safe_sub(fill)?;
+            base_asset_amount_filled = base_asset_amount_filled.safe_add(fill)?;
+            quote_asset_amount_filled = quote_asset_amount_filled.safe_add(
+                fill.cast::<u128>()?
+                    .safe_mul(price.cast()?)?
+                    .safe_div(base_precision.cast()?)?
+                    .cast()?,
+            )?;
+        }
+
+        levels.retain(|(_, base_asset_amount)| *base_asset_amount > 0);
+
+        let fee = quote_asset_amount_filled
+            .safe_mul(self.taker_fee_bps)?
+            .safe_div(10_000)?;
+
+        match taker_direction {
+            PositionDirection::Long => {
+                self.base_vault_amount =
+                    self.base_vault_amount.safe_add(base_asset_amount_filled)?;
+                self.quote_vault_amount = self
+                    .quote_vault_amount
+                    .safe_sub(quote_asset_amount_filled.safe_add(fee)?)?;
+            }
+            PositionDirection::Short => {
+                self.base_vault_amount =
+                    self.base_vault_amount.safe_sub(base_asset_amount_filled)?;
+                self.quote_vault_amount = self
+                    .quote_vault_amount
+                    .safe_add(quote_asset_amount_filled.safe_sub(fee)?)?;
+            }
+        }
+
+        Ok(Some(MockPlacedOrder {
+            base_before,
+            quote_before,
+            fee,
+        }))
+    }
+
+    fn settle(&mut self, placed_order: MockPlacedOrder) -> DriftResult<ExternalSpotFill> {
+        let MockPlacedOrder {
+            base_before,
+            quote_before,
+            fee,
+        } = placed_order;
+
+        let (base_update_direction, base_asset_amount_filled) =
+            if self.base_vault_amount > base_before {
+                (
+                    SpotBalanceType::Deposit,
+                    self.base_vault_amount.safe_sub(base_before)?,
+                )
+            } else {
+                (
+                    SpotBalanceType::Borrow,
+                    base_before.safe_sub(self.base_vault_amount)?,
+                )
+            };
+
+        if base_asset_amount_filled == 0 {
+            return Ok(ExternalSpotFill::empty());
+        }
+
+        let (quote_update_direction, quote_asset_amount_filled) =
+            if base_update_direction == SpotBalanceType::Borrow {
+                (
+                    SpotBalanceType::Deposit,
+                    self.quote_vault_amount
+                        .safe_sub(quote_before)?
+                        .safe_add(fee)?,
+                )
+            } else {
+                (
+                    SpotBalanceType::Borrow,
+                    quote_before
+                        .safe_sub(self.quote_vault_amount)?
+                        .safe_sub(fee)?,
+                )
+            };
+
+        Ok(ExternalSpotFill {
+            base_asset_amount_filled,
+            base_update_direction,
+            quote_asset_amount_filled,
+            quote_update_direction,
+            settled_referrer_rebate: 0,
+            unsettled_referrer_rebate: 0,
+            fee,
+        })
+    }
+
+    fn fill_explanation(&self) -> OrderActionExplanation {
+        OrderActionExplanation::OrderFillWithMockVenue
+    }
+
+    fn get_vault_amounts(&self) -> (u64, u64) {
+        (self.base_vault_amount, self.quote_vault_amount)
+    }
+}
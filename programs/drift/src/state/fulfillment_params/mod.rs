@@ -1,3 +1,54 @@
 pub mod drift;
+#[cfg(test)]
+pub mod mock;
 pub mod phoenix;
 pub mod serum;
+
+use crate::error::DriftResult;
+use crate::instructions::SpotFulfillmentType;
+use crate::state::fulfillment_params::drift::MatchFulfillmentParams;
+use crate::state::fulfillment_params::phoenix::PhoenixFulfillmentParams;
+use crate::state::fulfillment_params::serum::SerumFulfillmentParams;
+use crate::state::spot_fulfillment_params::{ExternalSpotVenue, SpotFulfillmentParams};
+use crate::state::spot_market::SpotMarket;
+use crate::state::state::State;
+use solana_program::account_info::AccountInfo;
+use std::iter::Peekable;
+use std::slice::Iter;
+
+/// Loads what a spot order is filled against for the fulfillment type. External venues only need
+/// an ExternalSpotVenue impl and a SpotFulfillmentType to be usable here. The test only MockVenue
+/// has no SpotFulfillmentType since it isn't loaded from accounts
+#[allow(clippy::type_complexity)]
+pub fn load_spot_fulfillment_params<'a, 'b, 'c>(
+    fulfillment_type: SpotFulfillmentType,
+    account_info_iter: &'a mut Peekable<Iter<'c, AccountInfo<'b>>>,
+    state: &State,
+    base_market: &SpotMarket,
+    quote_market: &SpotMarket,
+    now: i64,
+) -> DriftResult<Box<dyn SpotFulfillmentParams + 'a>> {
+    let fulfillment_params: Box<dyn SpotFulfillmentParams + 'a> = match fulfillment_type {
+        SpotFulfillmentType::SerumV3 => Box::new(SerumFulfillmentParams::load_market(
+            account_info_iter,
+            state,
+            base_market,
+            quote_market,
+            now,
+        )?),
+        SpotFulfillmentType::PhoenixV1 => Box::new(PhoenixFulfillmentParams::load_market(
+            account_info_iter,
+            state,
+            base_market,
+            quote_market,
+            now,
+        )?),
+        SpotFulfillmentType::Match => Box::new(MatchFulfillmentParams::new(
+            account_info_iter,
+            base_market,
+            quote_market,
+        )?),
+    };
+
+    Ok(fulfillment_params)
+}
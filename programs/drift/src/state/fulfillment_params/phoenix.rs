@@ -10,18 +10,18 @@ use phoenix::{
 };
 use solana_program::{msg, program::invoke_signed_unchecked};
 use std::convert::TryFrom;
-use std::{cell::Ref, convert::TryInto, mem::size_of, ops::Deref};
+use std::{convert::TryInto, mem::size_of, ops::Deref};
 
 use crate::{
     controller::position::PositionDirection,
     error::{DriftResult, ErrorCode},
     instructions::SpotFulfillmentType,
     load,
-    math::{casting::Cast, safe_math::SafeMath, spot_withdraw::validate_spot_market_vault_amount},
+    math::{casting::Cast, safe_math::SafeMath},
     signer::get_signer_seeds,
     state::{
         events::OrderActionExplanation,
-        spot_fulfillment_params::{ExternalSpotFill, SpotFulfillmentParams},
+        spot_fulfillment_params::{ExternalSpotFill, ExternalSpotVenue},
         spot_market::{SpotBalanceType, SpotFulfillmentConfigStatus, SpotMarket},
         state::State,
         traits::Size,
@@ -339,12 +339,28 @@ impl<'a, 'b> PhoenixFulfillmentParams<'a, 'b> {
     }
 }
 
-impl<'a, 'b> SpotFulfillmentParams for PhoenixFulfillmentParams<'a, 'b> {
-    fn is_external(&self) -> bool {
-        true
+/// Phoenix state from before the IOC order was placed
+pub struct PhoenixPlacedOrder {
+    pub base_before: u64,
+    pub quote_before: u64,
+    pub market_accrued_fees_before: u64,
+}
+
+impl<'a, 'b> ExternalSpotVenue<'a, 'b> for PhoenixFulfillmentParams<'a, 'b> {
+    type PlacedOrder = PhoenixPlacedOrder;
+
+    #[allow(clippy::type_complexity)]
+    fn load_market<'c>(
+        account_info_iter: &'a mut std::iter::Peekable<std::slice::Iter<'c, AccountInfo<'b>>>,
+        state: &State,
+        base_market: &SpotMarket,
+        quote_market: &SpotMarket,
+        _now: i64,
+    ) -> DriftResult<Self> {
+        PhoenixFulfillmentParams::new(account_info_iter, state, base_market, quote_market)
     }
 
-    fn get_best_bid_and_ask(&self) -> DriftResult<(Option<u64>, Option<u64>)> {
+    fn quote_best_bid_and_ask(&self) -> DriftResult<(Option<u64>, Option<u64>)> {
         let market_data = self.phoenix_market.data.borrow();
         let (_, market_bytes) = market_data.split_at(size_of::<MarketHeader>());
         let header = &self.phoenix_market.header;
@@ -381,13 +397,13 @@ impl<'a, 'b> SpotFulfillmentParams for PhoenixFulfillmentParams<'a, 'b> {
         Ok((best_bid, best_ask))
     }
 
-    fn fulfill_order(
+    fn place_ioc_order(
         &mut self,
         taker_direction: PositionDirection,
         taker_price: u64,
         taker_base_asset_amount: u64,
         taker_max_quote_asset_amount: u64,
-    ) -> DriftResult<ExternalSpotFill> {
+    ) -> DriftResult<Option<PhoenixPlacedOrder>> {
         let market_data = self.phoenix_market.data.borrow();
         let (_, market_bytes) = market_data.split_at(size_of::<MarketHeader>());
         let header = &self.phoenix_market.header;
@@ -421,7 +437,7 @@ impl<'a, 'b> SpotFulfillmentParams for PhoenixFulfillmentParams<'a, 'b> {
 
         if price_in_ticks == Ticks::ZERO {
             msg!("Price is too low");
-            return Ok(ExternalSpotFill::empty());
+            return Ok(None);
         }
 
         // This takes the minimum of
@@ -436,7 +452,7 @@ impl<'a, 'b> SpotFulfillmentParams for PhoenixFulfillmentParams<'a, 'b> {
 
         if num_base_lots == 0 {
             msg!("No base lots to fill");
-            return Ok(ExternalSpotFill::empty());
+            return Ok(None);
         }
 
         let phoenix_order = OrderPacket::ImmediateOrCancel {
@@ -469,9 +485,25 @@ impl<'a, 'b> SpotFulfillmentParams for PhoenixFulfillmentParams<'a, 'b> {
         drop(market_data);
         self.invoke_new_order(phoenix_order)?;
 
+        Ok(Some(PhoenixPlacedOrder {
+            base_before,
+            quote_before,
+            market_accrued_fees_before,
+        }))
+    }
+
+    /// Phoenix IOC orders move tokens when they're placed, so settling only reads the result
+    fn settle(&mut self, placed_order: PhoenixPlacedOrder) -> DriftResult<ExternalSpotFill> {
+        let PhoenixPlacedOrder {
+            base_before,
+            quote_before,
+            market_accrued_fees_before,
+        } = placed_order;
+
         // Reload market data
         let market_data = self.phoenix_market.data.borrow();
         let (_, market_bytes) = market_data.split_at(size_of::<MarketHeader>());
+        let market_size_params = self.phoenix_market.header.market_size_params;
 
         self.base_market_vault.reload().map_err(|_e| {
             msg!("Failed to reload base_market_vault");
@@ -532,19 +564,15 @@ impl<'a, 'b> SpotFulfillmentParams for PhoenixFulfillmentParams<'a, 'b> {
         })
     }
 
-    fn get_order_action_explanation(&self) -> DriftResult<OrderActionExplanation> {
-        Ok(OrderActionExplanation::OrderFillWithPhoenix)
+    fn fill_explanation(&self) -> OrderActionExplanation {
+        OrderActionExplanation::OrderFillWithPhoenix
     }
 
-    // Note: this trait method still feels a little out of place
-    fn validate_vault_amounts(
-        &self,
-        base_market: &Ref<SpotMarket>,
-        quote_market: &Ref<SpotMarket>,
-    ) -> DriftResult {
-        validate_spot_market_vault_amount(base_market, self.base_market_vault.amount)?;
-        validate_spot_market_vault_amount(quote_market, self.quote_market_vault.amount)?;
-        Ok(())
+    fn get_vault_amounts(&self) -> (u64, u64) {
+        (
+            self.base_market_vault.amount,
+            self.quote_market_vault.amount,
+        )
     }
 }
 
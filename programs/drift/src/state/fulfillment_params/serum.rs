@@ -7,10 +7,9 @@ use crate::math::serum::{
     calculate_price_from_serum_limit_price, calculate_serum_limit_price,
     calculate_serum_max_coin_qty, calculate_serum_max_native_pc_quantity,
 };
-use crate::math::spot_withdraw::validate_spot_market_vault_amount;
 use crate::signer::get_signer_seeds;
 use crate::state::events::OrderActionExplanation;
-use crate::state::spot_fulfillment_params::{ExternalSpotFill, SpotFulfillmentParams};
+use crate::state::spot_fulfillment_params::{ExternalSpotFill, ExternalSpotVenue};
 use crate::state::spot_market::{SpotBalanceType, SpotFulfillmentConfigStatus, SpotMarket};
 use crate::state::state::State;
 use crate::state::traits::Size;
@@ -463,12 +462,30 @@ impl<'a, 'b> SerumFulfillmentParams<'a, 'b> {
     }
 }
 
-impl<'a, 'b> SpotFulfillmentParams for SerumFulfillmentParams<'a, 'b> {
-    fn is_external(&self) -> bool {
-        true
+/// Serum state from before the IOC order was settled
+pub struct SerumPlacedOrder {
+    pub base_before: u64,
+    pub quote_before: u64,
+    pub market_rebates_accrued_before: u64,
+    pub market_rebates_accrued_after: u64,
+    pub unsettled_referrer_rebate_before: u64,
+}
+
+impl<'a, 'b> ExternalSpotVenue<'a, 'b> for SerumFulfillmentParams<'a, 'b> {
+    type PlacedOrder = SerumPlacedOrder;
+
+    #[allow(clippy::type_complexity)]
+    fn load_market<'c>(
+        account_info_iter: &'a mut std::iter::Peekable<std::slice::Iter<'c, AccountInfo<'b>>>,
+        state: &State,
+        base_market: &SpotMarket,
+        quote_market: &SpotMarket,
+        now: i64,
+    ) -> DriftResult<Self> {
+        SerumFulfillmentParams::new(account_info_iter, state, base_market, quote_market, now)
     }
 
-    fn get_best_bid_and_ask(&self) -> DriftResult<(Option<u64>, Option<u64>)> {
+    fn quote_best_bid_and_ask(&self) -> DriftResult<(Option<u64>, Option<u64>)> {
         let mut market = self.load_serum_market()?;
 
         let mut bids = market.load_bids_mut(self.serum_bids).map_err(|e| {
@@ -532,13 +549,13 @@ impl<'a, 'b> SpotFulfillmentParams for SerumFulfillmentParams<'a, 'b> {
         Ok((best_bid, best_ask))
     }
 
-    fn fulfill_order(
+    fn place_ioc_order(
         &mut self,
         taker_direction: PositionDirection,
         taker_price: u64,
         taker_base_asset_amount: u64,
         taker_max_quote_asset_amount: u64,
-    ) -> DriftResult<ExternalSpotFill> {
+    ) -> DriftResult<Option<SerumPlacedOrder>> {
         let market_state_before = self.load_serum_market()?;
 
         let serum_order_side = match taker_direction {
@@ -567,7 +584,7 @@ impl<'a, 'b> SpotFulfillmentParams for SerumFulfillmentParams<'a, 'b> {
         .min(taker_max_quote_asset_amount);
 
         if serum_max_coin_qty == 0 || serum_max_native_pc_qty == 0 {
-            return Ok(ExternalSpotFill::empty());
+            return Ok(None);
         }
 
         let serum_order = NewOrderInstructionV3 {
@@ -604,6 +621,24 @@ impl<'a, 'b> SpotFulfillmentParams for SerumFulfillmentParams<'a, 'b> {
 
         drop(open_orders_before);
 
+        Ok(Some(SerumPlacedOrder {
+            base_before,
+            quote_before,
+            market_rebates_accrued_before,
+            market_rebates_accrued_after,
+            unsettled_referrer_rebate_before,
+        }))
+    }
+
+    fn settle(&mut self, placed_order: SerumPlacedOrder) -> DriftResult<ExternalSpotFill> {
+        let SerumPlacedOrder {
+            base_before,
+            quote_before,
+            market_rebates_accrued_before,
+            market_rebates_accrued_after,
+            unsettled_referrer_rebate_before,
+        } = placed_order;
+
         self.invoke_settle_funds()?;
 
         self.base_market_vault.reload().map_err(|_e| {
@@ -679,17 +714,14 @@ impl<'a, 'b> SpotFulfillmentParams for SerumFulfillmentParams<'a, 'b> {
         })
     }
 
-    fn get_order_action_explanation(&self) -> DriftResult<OrderActionExplanation> {
-        Ok(OrderActionExplanation::OrderFillWithSerum)
+    fn fill_explanation(&self) -> OrderActionExplanation {
+        OrderActionExplanation::OrderFillWithSerum
     }
 
-    fn validate_vault_amounts(
-        &self,
-        base_market: &Ref<SpotMarket>,
-        quote_market: &Ref<SpotMarket>,
-    ) -> DriftResult {
-        validate_spot_market_vault_amount(base_market, self.base_market_vault.amount)?;
-        validate_spot_market_vault_amount(quote_market, self.quote_market_vault.amount)?;
-        Ok(())
+    fn get_vault_amounts(&self) -> (u64, u64) {
+        (
+            self.base_market_vault.amount,
+            self.quote_market_vault.amount,
+        )
     }
 }
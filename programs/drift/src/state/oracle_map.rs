@@ -256,7 +256,7 @@ impl<'a> OracleMap<'a> {
     }
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "backtest"))]
 impl<'a> OracleMap<'a> {
     pub fn empty() -> OracleMap<'a> {
         OracleMap {
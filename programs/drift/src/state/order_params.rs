@@ -1,12 +1,18 @@
 use crate::controller::position::PositionDirection;
-use crate::error::DriftResult;
+use crate::error::{DriftResult, ErrorCode};
 use crate::math::casting::Cast;
+use crate::math::constants::{MAX_OPEN_ORDERS, QUOTE_SPOT_MARKET_INDEX};
+use crate::math::orders::{standardize_base_asset_amount, standardize_price};
 use crate::math::safe_math::SafeMath;
 use crate::math::safe_unwrap::SafeUnwrap;
 use crate::state::events::OrderActionExplanation;
 use crate::state::perp_market::{ContractTier, PerpMarket};
-use crate::state::user::{MarketType, OrderTriggerCondition, OrderType};
-use crate::{PERCENTAGE_PRECISION_U64, PRICE_PRECISION_I64};
+use crate::state::spot_market::SpotMarket;
+use crate::state::user::{
+    MarketType, OrderBitFlag, OrderTriggerCondition, OrderType, TriggerSource,
+};
+use crate::validate;
+use crate::{BASE_PRECISION_U64, PERCENTAGE_PRECISION_U64, PRICE_PRECISION_I64};
 use anchor_lang::prelude::*;
 use borsh::{BorshDeserialize, BorshSerialize};
 use std::ops::Div;
@@ -27,15 +33,35 @@ pub struct OrderParams {
     pub post_only: PostOnlyParam,
     pub immediate_or_cancel: bool,
     pub max_ts: Option<i64>,
-    pub trigger_price: Option<u64>,
+    pub trigger_price: Option<u64>, // for signed trigger sources, Below triggers under -trigger_price
     pub trigger_condition: OrderTriggerCondition,
     pub oracle_price_offset: Option<i32>, // price offset from oracle for order (~ +/- 2147 max)
     pub auction_duration: Option<u8>,     // specified in slots
     pub auction_start_price: Option<i64>, // specified in price or oracle_price_offset
     pub auction_end_price: Option<i64>,   // specified in price or oracle_price_offset
+    pub bit_flags: u8,                    // see OrderBitFlag
+    pub group_id: u8,                     // links orders in an oco/bracket group, 0 for none
+    pub twap_slices: u8,                  // number of slices a twap order is released in
+    pub min_fill_base_asset_amount: u64,  // place_and_take reverts if less is filled, 0 for none
+    pub display_base_asset_amount: u64,   // iceberg amount shown at a time, 0 for none
+    pub max_slippage_bps: u16,            // max fill distance from oracle in bps, 0 for none
+    pub max_slide_ticks: u16,             // max ticks a post only slide moves the price, 0 for none
+    pub trigger_source: TriggerSource,    // what the trigger price is compared against (see Order)
+    pub trigger_market_index: u16,        // perp market for TriggerSource::MarketOracle
 }
 
 impl OrderParams {
+    /// Fill or kill and min fill are only checked when the order is filled as it's placed,
+    /// so they're only allowed in place_and_take
+    pub fn is_place_and_take_only(&self) -> bool {
+        self.bit_flags & OrderBitFlag::FillOrKill as u8 != 0 || self.min_fill_base_asset_amount != 0
+    }
+
+    /// Bracket exits only close what the entry filled, so they're always reduce only
+    pub fn is_reduce_only(&self) -> bool {
+        self.reduce_only || self.bit_flags & OrderBitFlag::BracketExit as u8 != 0
+    }
+
     pub fn update_perp_auction_params_limit_orders(
         &mut self,
         perp_market: &PerpMarket,
@@ -557,6 +583,7 @@ pub struct ModifyOrderParams {
     pub auction_start_price: Option<i64>,
     pub auction_end_price: Option<i64>,
     pub policy: Option<ModifyOrderPolicy>,
+    pub max_slide_ticks: Option<u16>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Eq, PartialEq)]
@@ -571,6 +598,236 @@ impl Default for ModifyOrderPolicy {
     }
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub struct ScaleOrderParams {
+    pub market_type: MarketType,
+    pub direction: PositionDirection,
+    pub market_index: u16,
+    pub total_base_asset_amount: u64,
+    pub start_price: u64, // price of the first order in the ladder
+    pub end_price: u64,   // price of the last order in the ladder
+    pub order_count: u8,
+    pub size_distribution: SizeDistribution,
+    pub reduce_only: bool,
+    pub post_only: PostOnlyParam,
+    pub max_ts: Option<i64>,
+}
+
+impl ScaleOrderParams {
+    pub fn get_order_params(
+        &self,
+        order_tick_size: u64,
+        order_step_size: u64,
+        min_order_size: u64,
+    ) -> DriftResult<Vec<OrderParams>> {
+        validate!(
+            self.order_count >= 2 && self.order_count <= MAX_OPEN_ORDERS,
+            ErrorCode::InvalidScaleOrderParams,
+            "order_count={} must be between 2 and {}",
+            self.order_count,
+            MAX_OPEN_ORDERS
+        )?;
+
+        validate!(
+            self.start_price != 0 && self.end_price != 0,
+            ErrorCode::InvalidScaleOrderParams,
+            "start_price={} and end_price={} must be non zero",
+            self.start_price,
+            self.end_price
+        )?;
+
+        let order_count = self.order_count.cast::<u64>()?;
+        let total_base_asset_amount =
+            standardize_base_asset_amount(self.total_base_asset_amount, order_step_size)?;
+
+        let weights = (0..order_count)
+            .map(|i| match self.size_distribution {
+                SizeDistribution::Flat => 1,
+                SizeDistribution::Increasing => i + 1,
+                SizeDistribution::Decreasing => order_count - i,
+            })
+            .collect::<Vec<u64>>();
+        let weight_sum = weights.iter().sum::<u64>();
+
+        let mut base_asset_amounts = weights
+            .iter()
+            .map(|weight| {
+                standardize_base_asset_amount(
+                    total_base_asset_amount
+                        .safe_mul(*weight)?
+                        .safe_div(weight_sum)?,
+                    order_step_size,
+                )
+            })
+            .collect::<DriftResult<Vec<u64>>>()?;
+
+        // rounding down to the step size leaves a remainder, add it to the largest order
+        let remainder = total_base_asset_amount.safe_sub(base_asset_amounts.iter().sum::<u64>())?;
+        let largest_order_index = match self.size_distribution {
+            SizeDistribution::Decreasing => 0,
+            SizeDistribution::Flat | SizeDistribution::Increasing => base_asset_amounts.len() - 1,
+        };
+        base_asset_amounts[largest_order_index] =
+            base_asset_amounts[largest_order_index].safe_add(remainder)?;
+
+        let min_base_asset_amount = min_order_size.max(order_step_size);
+        let price_range = self.start_price.abs_diff(self.end_price);
+
+        let mut order_params = Vec::with_capacity(base_asset_amounts.len());
+        for (i, base_asset_amount) in base_asset_amounts.iter().enumerate() {
+            validate!(
+                *base_asset_amount >= min_base_asset_amount,
+                ErrorCode::OrderAmountTooSmall,
+                "order {} base_asset_amount={} below min order size {}",
+                i,
+                base_asset_amount,
+                min_base_asset_amount
+            )?;
+
+            let price_offset = price_range
+                .safe_mul(i as u64)?
+                .safe_div(order_count.safe_sub(1)?)?;
+            let price = if self.end_price >= self.start_price {
+                self.start_price.safe_add(price_offset)?
+            } else {
+                self.start_price.safe_sub(price_offset)?
+            };
+
+            order_params.push(OrderParams {
+                order_type: OrderType::Limit,
+                market_type: self.market_type,
+                direction: self.direction,
+                base_asset_amount: *base_asset_amount,
+                price: standardize_price(price, order_tick_size, self.direction)?,
+                market_index: self.market_index,
+                reduce_only: self.reduce_only,
+                post_only: self.post_only,
+                max_ts: self.max_ts,
+                ..OrderParams::default()
+            });
+        }
+
+        Ok(order_params)
+    }
+}
+
+/// How the total size of a scale order is split across the ladder, from start price to end price
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum SizeDistribution {
+    Flat,
+    Increasing,
+    Decreasing,
+}
+
+impl Default for SizeDistribution {
+    fn default() -> Self {
+        SizeDistribution::Flat
+    }
+}
+
+/// Two legs of the same size filled together at a net price, e.g. a future against the perpetual
+/// or a perp against spot of the same asset. The first leg is always perp and takes the combo
+/// direction, the second leg takes the opposite side. Spot legs fill against serum/phoenix
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub struct ComboOrderParams {
+    pub direction: PositionDirection,
+    pub base_asset_amount: u64, // precision: BASE_PRECISION, converted to the spot market precision for spot legs
+    pub first_market_index: u16,
+    pub second_market_index: u16,
+    pub second_market_type: MarketType,
+    pub net_price_limit: i64, // first leg fill price minus second leg fill price
+    pub reduce_only: bool,
+}
+
+impl ComboOrderParams {
+    pub fn get_order_params(&self) -> DriftResult<[OrderParams; 2]> {
+        validate!(
+            self.second_market_type == MarketType::Spot
+                || self.first_market_index != self.second_market_index,
+            ErrorCode::InvalidComboOrderParams,
+            "combo legs must be in different markets (market_index={})",
+            self.first_market_index
+        )?;
+
+        validate!(
+            self.second_market_type == MarketType::Perp
+                || self.second_market_index != QUOTE_SPOT_MARKET_INDEX,
+            ErrorCode::InvalidComboOrderParams,
+            "spot combo leg can't be in the quote market"
+        )?;
+
+        validate!(
+            self.base_asset_amount > 0,
+            ErrorCode::InvalidComboOrderParams,
+            "base_asset_amount must be non zero"
+        )?;
+
+        let get_leg_params =
+            |direction: PositionDirection, market_type: MarketType, market_index: u16| {
+                OrderParams {
+                    order_type: OrderType::Market,
+                    market_type,
+                    direction,
+                    base_asset_amount: self.base_asset_amount,
+                    market_index,
+                    reduce_only: self.reduce_only,
+                    bit_flags: OrderBitFlag::FillOrKill as u8,
+                    ..OrderParams::default()
+                }
+            };
+
+        Ok([
+            get_leg_params(self.direction, MarketType::Perp, self.first_market_index),
+            get_leg_params(
+                self.direction.opposite(),
+                self.second_market_type,
+                self.second_market_index,
+            ),
+        ])
+    }
+
+    /// The perp leg's size in the spot market's precision, rounded down to its step size
+    pub fn get_spot_leg_base_asset_amount(&self, spot_market: &SpotMarket) -> DriftResult<u64> {
+        let base_asset_amount = standardize_base_asset_amount(
+            self.base_asset_amount
+                .safe_mul(spot_market.get_precision())?
+                .safe_div(BASE_PRECISION_U64)?,
+            spot_market.order_step_size,
+        )?;
+
+        validate!(
+            base_asset_amount > 0,
+            ErrorCode::OrderAmountTooSmall,
+            "spot leg is below spot market {} order_step_size={}",
+            spot_market.market_index,
+            spot_market.order_step_size
+        )?;
+
+        Ok(base_asset_amount)
+    }
+
+    pub fn validate_net_price(&self, first_fill_price: u64, second_fill_price: u64) -> DriftResult {
+        let net_price = first_fill_price
+            .cast::<i64>()?
+            .safe_sub(second_fill_price.cast()?)?;
+
+        let within_limit = match self.direction {
+            PositionDirection::Long => net_price <= self.net_price_limit,
+            PositionDirection::Short => net_price >= self.net_price_limit,
+        };
+
+        validate!(
+            within_limit,
+            ErrorCode::ComboOrderNetPriceBreached,
+            "combo net price {} breached limit {}",
+            net_price,
+            self.net_price_limit
+        )?;
+
+        Ok(())
+    }
+}
+
 pub struct PlaceOrderOptions {
     pub try_expire_orders: bool,
     pub enforce_margin_check: bool,
@@ -598,4 +855,8 @@ impl PlaceOrderOptions {
         self.explanation = explanation;
         self
     }
+
+    pub fn is_twap_slice_release(&self) -> bool {
+        self.explanation == OrderActionExplanation::TwapSliceReleased
+    }
 }
@@ -936,7 +936,351 @@ mod get_close_perp_params {
             auction_end_price: params.auction_end_price.unwrap_or(0),
             auction_duration: params.auction_duration.unwrap_or(0),
             max_ts: 100,
-            padding: [0; 3],
+            bit_flags: params.bit_flags,
+            group_id: params.group_id,
+            slices_remaining: 0,
         }
     }
 }
+
+mod get_scale_order_params {
+    use crate::state::order_params::{PostOnlyParam, ScaleOrderParams, SizeDistribution};
+    use crate::state::user::{MarketType, OrderType};
+    use crate::{PositionDirection, BASE_PRECISION_U64, PRICE_PRECISION_U64};
+
+    #[test]
+    fn flat() {
+        let params = ScaleOrderParams {
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            market_index: 0,
+            total_base_asset_amount: 10 * BASE_PRECISION_U64,
+            start_price: 100 * PRICE_PRECISION_U64,
+            end_price: 103 * PRICE_PRECISION_U64,
+            order_count: 4,
+            size_distribution: SizeDistribution::Flat,
+            post_only: PostOnlyParam::MustPostOnly,
+            ..ScaleOrderParams::default()
+        };
+
+        let order_params = params
+            .get_order_params(PRICE_PRECISION_U64 / 100, BASE_PRECISION_U64 / 10, 0)
+            .unwrap();
+
+        let prices = order_params.iter().map(|p| p.price).collect::<Vec<u64>>();
+        assert_eq!(
+            prices,
+            vec![
+                100 * PRICE_PRECISION_U64,
+                101 * PRICE_PRECISION_U64,
+                102 * PRICE_PRECISION_U64,
+                103 * PRICE_PRECISION_U64
+            ]
+        );
+
+        for order_params in order_params.iter() {
+            assert_eq!(order_params.base_asset_amount, 25 * BASE_PRECISION_U64 / 10);
+            assert_eq!(order_params.order_type, OrderType::Limit);
+            assert_eq!(order_params.direction, PositionDirection::Long);
+            assert_eq!(order_params.post_only, PostOnlyParam::MustPostOnly);
+        }
+    }
+
+    #[test]
+    fn increasing() {
+        let params = ScaleOrderParams {
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            total_base_asset_amount: 10 * BASE_PRECISION_U64,
+            start_price: 100 * PRICE_PRECISION_U64,
+            end_price: 103 * PRICE_PRECISION_U64,
+            order_count: 4,
+            size_distribution: SizeDistribution::Increasing,
+            ..ScaleOrderParams::default()
+        };
+
+        let order_params = params
+            .get_order_params(PRICE_PRECISION_U64 / 100, BASE_PRECISION_U64 / 10, 0)
+            .unwrap();
+
+        let base_asset_amounts = order_params
+            .iter()
+            .map(|p| p.base_asset_amount)
+            .collect::<Vec<u64>>();
+        assert_eq!(
+            base_asset_amounts,
+            vec![
+                BASE_PRECISION_U64,
+                2 * BASE_PRECISION_U64,
+                3 * BASE_PRECISION_U64,
+                4 * BASE_PRECISION_U64
+            ]
+        );
+    }
+
+    #[test]
+    fn decreasing_with_rounding() {
+        let params = ScaleOrderParams {
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Short,
+            total_base_asset_amount: 10 * BASE_PRECISION_U64,
+            start_price: 103 * PRICE_PRECISION_U64,
+            end_price: 100 * PRICE_PRECISION_U64,
+            order_count: 3,
+            size_distribution: SizeDistribution::Decreasing,
+            ..ScaleOrderParams::default()
+        };
+
+        let order_params = params
+            .get_order_params(PRICE_PRECISION_U64, BASE_PRECISION_U64 / 10, 0)
+            .unwrap();
+
+        let base_asset_amounts = order_params
+            .iter()
+            .map(|p| p.base_asset_amount)
+            .collect::<Vec<u64>>();
+        // remainder from rounding to step size goes to the largest order
+        assert_eq!(
+            base_asset_amounts,
+            vec![
+                51 * BASE_PRECISION_U64 / 10,
+                33 * BASE_PRECISION_U64 / 10,
+                16 * BASE_PRECISION_U64 / 10
+            ]
+        );
+
+        // 101.5 rounds up to the tick for a short
+        let prices = order_params.iter().map(|p| p.price).collect::<Vec<u64>>();
+        assert_eq!(
+            prices,
+            vec![
+                103 * PRICE_PRECISION_U64,
+                102 * PRICE_PRECISION_U64,
+                100 * PRICE_PRECISION_U64
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let params = ScaleOrderParams {
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            total_base_asset_amount: 10 * BASE_PRECISION_U64,
+            start_price: 100 * PRICE_PRECISION_U64,
+            end_price: 103 * PRICE_PRECISION_U64,
+            order_count: 4,
+            ..ScaleOrderParams::default()
+        };
+
+        // each order below min order size
+        assert!(params
+            .get_order_params(
+                PRICE_PRECISION_U64 / 100,
+                BASE_PRECISION_U64 / 10,
+                3 * BASE_PRECISION_U64
+            )
+            .is_err());
+
+        let single_order = ScaleOrderParams {
+            order_count: 1,
+            ..params
+        };
+        assert!(single_order
+            .get_order_params(PRICE_PRECISION_U64 / 100, BASE_PRECISION_U64 / 10, 0)
+            .is_err());
+
+        let no_price = ScaleOrderParams {
+            start_price: 0,
+            ..params
+        };
+        assert!(no_price
+            .get_order_params(PRICE_PRECISION_U64 / 100, BASE_PRECISION_U64 / 10, 0)
+            .is_err());
+    }
+}
+
+mod get_combo_order_params {
+    use crate::error::ErrorCode;
+    use crate::state::order_params::ComboOrderParams;
+    use crate::state::spot_market::SpotMarket;
+    use crate::state::user::{MarketType, OrderBitFlag, OrderType};
+    use crate::{PositionDirection, BASE_PRECISION_U64, PRICE_PRECISION_I64, PRICE_PRECISION_U64};
+
+    #[test]
+    fn legs() {
+        let params = ComboOrderParams {
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            first_market_index: 1,
+            second_market_index: 0,
+            second_market_type: MarketType::Perp,
+            net_price_limit: PRICE_PRECISION_I64,
+            ..ComboOrderParams::default()
+        };
+
+        let [first_leg, second_leg] = params.get_order_params().unwrap();
+
+        assert_eq!(first_leg.direction, PositionDirection::Long);
+        assert_eq!(first_leg.market_index, 1);
+        assert_eq!(second_leg.direction, PositionDirection::Short);
+        assert_eq!(second_leg.market_index, 0);
+
+        for leg in [first_leg, second_leg] {
+            assert_eq!(leg.order_type, OrderType::Market);
+            assert_eq!(leg.market_type, MarketType::Perp);
+            assert_eq!(leg.base_asset_amount, BASE_PRECISION_U64);
+            assert_eq!(leg.bit_flags, OrderBitFlag::FillOrKill as u8);
+        }
+
+        let params = ComboOrderParams {
+            second_market_index: 1,
+            ..params
+        };
+
+        assert_eq!(
+            params.get_order_params().map(|_| ()),
+            Err(ErrorCode::InvalidComboOrderParams)
+        );
+
+        // perp against spot of the same asset
+        let params = ComboOrderParams {
+            second_market_type: MarketType::Spot,
+            ..params
+        };
+
+        let [first_leg, second_leg] = params.get_order_params().unwrap();
+        assert_eq!(first_leg.market_type, MarketType::Perp);
+        assert_eq!(second_leg.market_type, MarketType::Spot);
+        assert_eq!(second_leg.market_index, 1);
+        assert_eq!(second_leg.direction, PositionDirection::Short);
+
+        let params = ComboOrderParams {
+            second_market_index: 0,
+            ..params
+        };
+
+        assert_eq!(
+            params.get_order_params().map(|_| ()),
+            Err(ErrorCode::InvalidComboOrderParams)
+        );
+    }
+
+    #[test]
+    fn spot_leg_base_asset_amount() {
+        let params = ComboOrderParams {
+            direction: PositionDirection::Long,
+            base_asset_amount: 1_234_567_000,
+            first_market_index: 1,
+            second_market_index: 1,
+            second_market_type: MarketType::Spot,
+            ..ComboOrderParams::default()
+        };
+
+        // 1.234567 in a 6 decimal market with a .01 step size
+        let spot_market = SpotMarket {
+            market_index: 1,
+            decimals: 6,
+            order_step_size: 10_000,
+            ..SpotMarket::default()
+        };
+        assert_eq!(
+            params.get_spot_leg_base_asset_amount(&spot_market),
+            Ok(1_230_000)
+        );
+
+        let params = ComboOrderParams {
+            base_asset_amount: 9_000_000,
+            ..params
+        };
+        assert_eq!(
+            params.get_spot_leg_base_asset_amount(&spot_market),
+            Err(ErrorCode::OrderAmountTooSmall)
+        );
+    }
+
+    #[test]
+    fn net_price() {
+        let params = ComboOrderParams {
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            first_market_index: 1,
+            second_market_index: 0,
+            second_market_type: MarketType::Perp,
+            net_price_limit: PRICE_PRECISION_I64,
+            ..ComboOrderParams::default()
+        };
+
+        // buying the spread at $1 or less
+        assert!(params
+            .validate_net_price(101 * PRICE_PRECISION_U64, 100 * PRICE_PRECISION_U64)
+            .is_ok());
+        assert!(params
+            .validate_net_price(99 * PRICE_PRECISION_U64, 100 * PRICE_PRECISION_U64)
+            .is_ok());
+        assert_eq!(
+            params.validate_net_price(102 * PRICE_PRECISION_U64, 100 * PRICE_PRECISION_U64),
+            Err(ErrorCode::ComboOrderNetPriceBreached)
+        );
+
+        // selling the spread at $1 or more
+        let params = ComboOrderParams {
+            direction: PositionDirection::Short,
+            ..params
+        };
+
+        assert!(params
+            .validate_net_price(102 * PRICE_PRECISION_U64, 100 * PRICE_PRECISION_U64)
+            .is_ok());
+        assert_eq!(
+            params.validate_net_price(100 * PRICE_PRECISION_U64, 100 * PRICE_PRECISION_U64),
+            Err(ErrorCode::ComboOrderNetPriceBreached)
+        );
+    }
+}
+
+mod is_place_and_take_only {
+    use crate::state::order_params::OrderParams;
+    use crate::state::user::OrderBitFlag;
+    use crate::BASE_PRECISION_U64;
+
+    #[test]
+    fn fill_or_kill_and_min_fill() {
+        let params = OrderParams {
+            base_asset_amount: BASE_PRECISION_U64,
+            ..OrderParams::default()
+        };
+        assert!(!params.is_place_and_take_only());
+
+        let params = OrderParams {
+            bit_flags: OrderBitFlag::FillOrKill as u8,
+            ..params
+        };
+        assert!(params.is_place_and_take_only());
+
+        let params = OrderParams {
+            bit_flags: 0,
+            min_fill_base_asset_amount: BASE_PRECISION_U64 / 2,
+            ..params
+        };
+        assert!(params.is_place_and_take_only());
+    }
+}
+
+mod is_twap_slice_release {
+    use crate::state::events::OrderActionExplanation;
+    use crate::state::order_params::PlaceOrderOptions;
+
+    #[test]
+    fn only_release_twap_order_slice() {
+        assert!(!PlaceOrderOptions::default().is_twap_slice_release());
+
+        assert!(!PlaceOrderOptions::default()
+            .explanation(OrderActionExplanation::OrderFilledWithMatch)
+            .is_twap_slice_release());
+
+        assert!(PlaceOrderOptions::default()
+            .explanation(OrderActionExplanation::TwapSliceReleased)
+            .is_twap_slice_release());
+    }
+}
@@ -8,7 +8,7 @@ use crate::math::amm;
 use crate::math::casting::Cast;
 #[cfg(test)]
 use crate::math::constants::{
-    AMM_RESERVE_PRECISION, MAX_CONCENTRATION_COEFFICIENT, PRICE_PRECISION_I64,
+    AMM_RESERVE_PRECISION, BPS_PRECISION_I64, MAX_CONCENTRATION_COEFFICIENT, PRICE_PRECISION_I64,
 };
 use crate::math::constants::{
     AMM_RESERVE_PRECISION_I128, BID_ASK_SPREAD_PRECISION, BID_ASK_SPREAD_PRECISION_U128,
@@ -95,6 +95,52 @@ impl Default for ContractType {
     }
 }
 
+/// How a taker is split across makers resting at the same price
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum MatchingMode {
+    /// Makers at the same price are filled in the order they're found
+    PriceTime,
+    /// Makers at the same price are filled in proportion to their size
+    ProRata,
+}
+
+impl Default for MatchingMode {
+    fn default() -> Self {
+        MatchingMode::PriceTime
+    }
+}
+
+/// How makers are matched against market and oracle orders during their auction
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum AuctionClearingMode {
+    /// Makers fill the auction as their fills land
+    Continuous,
+    /// Makers resting against the auction are cleared together at one price once it's over
+    Batch,
+}
+
+impl Default for AuctionClearingMode {
+    fn default() -> Self {
+        AuctionClearingMode::Continuous
+    }
+}
+
+/// How the funding rate is derived each funding period
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum FundingFormula {
+    /// The spread between the mark twap and oracle twap plus a fixed offset
+    MarkOracleTwap,
+    /// The twap of the premium index sampled from the bid/ask twaps, plus the market's interest
+    /// rate clamped around it
+    PremiumIndex,
+}
+
+impl Default for FundingFormula {
+    fn default() -> Self {
+        FundingFormula::MarkOracleTwap
+    }
+}
+
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, PartialOrd, Ord)]
 pub enum ContractTier {
     /// max insurance capped at A level
@@ -227,7 +273,28 @@ pub struct PerpMarket {
     /// E.g. if this is -50 and the fee is 5bps, the new fee will be 2.5bps
     /// if this is 50 and the fee is 5bps, the new fee will be 7.5bps
     pub fee_adjustment: i16,
-    pub padding: [u8; 46],
+    /// How takers are matched against makers resting at the same price
+    pub matching_mode: MatchingMode,
+    /// How makers are matched against market and oracle orders during their auction
+    pub auction_clearing_mode: AuctionClearingMode,
+    /// the min seconds between scheduled repeg/k updates. 0 disables them
+    pub curve_update_interval: u32,
+    /// the max fees scheduled repeg/k updates can spend each budget period
+    /// precision: QUOTE_PRECISION
+    pub curve_update_budget: u64,
+    /// the fees spent by scheduled repeg/k updates in the current budget period
+    /// precision: QUOTE_PRECISION
+    pub curve_update_budget_spent: u64,
+    /// the start of the current budget period
+    pub curve_update_budget_period_start_ts: i64,
+    /// the last time a scheduled repeg/k update ran
+    pub last_scheduled_curve_update_ts: i64,
+    /// the length of a budget period in seconds
+    pub curve_update_budget_period: u32,
+    /// twap of the premium index over the funding period. only sampled when the amm uses the
+    /// PremiumIndex funding formula
+    /// precision: PERCENTAGE_PRECISION
+    pub premium_index_twap: i32,
 }
 
 impl Default for PerpMarket {
@@ -261,7 +328,15 @@ impl Default for PerpMarket {
             paused_operations: 0,
             quote_spot_market_index: 0,
             fee_adjustment: 0,
-            padding: [0; 46],
+            matching_mode: MatchingMode::default(),
+            auction_clearing_mode: AuctionClearingMode::default(),
+            curve_update_interval: 0,
+            curve_update_budget: 0,
+            curve_update_budget_spent: 0,
+            curve_update_budget_period_start_ts: 0,
+            last_scheduled_curve_update_ts: 0,
+            curve_update_budget_period: 0,
+            premium_index_twap: 0,
         }
     }
 }
@@ -316,6 +391,12 @@ impl PerpMarket {
         self,
         oracle_price_twap: i64,
     ) -> DriftResult<i64> {
+        if self.amm.max_funding_premium > 0 {
+            return oracle_price_twap
+                .safe_mul(self.amm.max_funding_premium.cast()?)?
+                .safe_div(BPS_PRECISION_I64);
+        }
+
         // clamp to to 3% price divergence for safer markets and higher for lower contract tiers
         if self.contract_tier.is_as_safe_as_contract(&ContractTier::B) {
             oracle_price_twap.safe_div(33) // 3%
@@ -705,13 +786,30 @@ pub struct AMM {
     pub target_base_asset_amount_per_lp: i32,
     /// expo for unit of per_lp, base 10 (if per_lp_base=X, then per_lp unit is 10^X)
     pub per_lp_base: i8,
-    pub padding1: u8,
-    pub padding2: u16,
+    /// how the funding rate is derived
+    pub funding_formula: FundingFormula,
+    /// annualized interest rate added to funding under the PremiumIndex formula
+    /// precision: BPS_PRECISION
+    pub funding_interest_rate: i16,
     pub total_fee_earned_per_lp: u64,
     pub net_unsettled_funding_pnl: i64,
     pub quote_asset_amount_with_unsettled_lp: i64,
     pub reference_price_offset: i32,
-    pub padding: [u8; 12],
+    /// the oracle price's volatility over realized_volatility_window, the root of an ewma of its
+    /// variance per second times the window
+    /// precision: PERCENTAGE_PRECISION
+    pub realized_volatility: u32,
+    /// the window in seconds realized_volatility is averaged over. 0 disables it
+    pub realized_volatility_window: u16,
+    /// how much of realized_volatility is added to the spreads. 100 is 1x
+    pub realized_volatility_spread_multiplier: u16,
+    /// how much jit size scales with the amm's inventory. 100 is 1x, 0 keeps jit size static
+    /// jit takes more when the fill reduces base_asset_amount_with_amm and less when it adds to it
+    pub amm_jit_inventory_scale: u16,
+    /// the max premium funding is paid on, as a fraction of the oracle twap. 0 uses the contract
+    /// tier's default
+    /// precision: BPS_PRECISION
+    pub max_funding_premium: u16,
 }
 
 impl Default for AMM {
@@ -794,13 +892,17 @@ impl Default for AMM {
             last_oracle_valid: false,
             target_base_asset_amount_per_lp: 0,
             per_lp_base: 0,
-            padding1: 0,
-            padding2: 0,
+            funding_formula: FundingFormula::default(),
+            funding_interest_rate: 0,
             total_fee_earned_per_lp: 0,
             net_unsettled_funding_pnl: 0,
             quote_asset_amount_with_unsettled_lp: 0,
             reference_price_offset: 0,
-            padding: [0; 12],
+            realized_volatility: 0,
+            realized_volatility_window: 0,
+            realized_volatility_spread_multiplier: 0,
+            amm_jit_inventory_scale: 0,
+            max_funding_premium: 0,
         }
     }
 }
@@ -1,8 +1,13 @@
 use crate::error::DriftResult;
+use crate::math::spot_withdraw::validate_spot_market_vault_amount;
 use crate::state::events::OrderActionExplanation;
 use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+use crate::state::state::State;
 use crate::PositionDirection;
+use solana_program::account_info::AccountInfo;
 use std::cell::Ref;
+use std::iter::Peekable;
+use std::slice::Iter;
 
 pub trait SpotFulfillmentParams {
     /// Where or not the taker order is filled externally using another solana program
@@ -40,6 +45,95 @@ pub trait SpotFulfillmentParams {
     ) -> DriftResult<()>;
 }
 
+/// An external program that spot orders can be filled against, e.g. serum or phoenix. Every venue is
+/// a SpotFulfillmentParams through the impl below, so adding one doesn't touch the fill logic
+pub trait ExternalSpotVenue<'a, 'b>: Sized {
+    /// What the venue needs to remember between placing the IOC order and settling it
+    type PlacedOrder;
+
+    /// Loads the venue market and the accounts needed to trade on it from the remaining accounts
+    #[allow(clippy::type_complexity)]
+    fn load_market<'c>(
+        account_info_iter: &'a mut Peekable<Iter<'c, AccountInfo<'b>>>,
+        state: &State,
+        base_market: &SpotMarket,
+        quote_market: &SpotMarket,
+        now: i64,
+    ) -> DriftResult<Self>;
+
+    /// Returns the venue's best bid and ask price, in PRICE_PRECISION
+    fn quote_best_bid_and_ask(&self) -> DriftResult<(Option<u64>, Option<u64>)>;
+
+    /// Places an immediate or cancel order for the taker. Returns None if the order is too small
+    /// to place on the venue
+    ///
+    /// # Arguments
+    ///
+    /// *`taker_direction` - The direction of the taker order
+    /// *`taker_price` - The price of the taker order, in PRICE_PRECISION
+    /// *`taker_base_asset_amount` - The base amount for taker order, precision is 10^base_mint_decimals
+    /// *`taker_max_quote_asset_amount` - The max quote amount for taker order, precision is QUOTE_PRECISION (1e6)
+    fn place_ioc_order(
+        &mut self,
+        taker_direction: PositionDirection,
+        taker_price: u64,
+        taker_base_asset_amount: u64,
+        taker_max_quote_asset_amount: u64,
+    ) -> DriftResult<Option<Self::PlacedOrder>>;
+
+    /// Settles the IOC order's proceeds into the drift vaults and returns what was filled
+    fn settle(&mut self, placed_order: Self::PlacedOrder) -> DriftResult<ExternalSpotFill>;
+
+    /// The order action explanation for fills on this venue
+    fn fill_explanation(&self) -> OrderActionExplanation;
+
+    /// Returns the token amounts in the drift base and quote vaults
+    fn get_vault_amounts(&self) -> (u64, u64);
+}
+
+impl<'a, 'b, V: ExternalSpotVenue<'a, 'b>> SpotFulfillmentParams for V {
+    fn is_external(&self) -> bool {
+        true
+    }
+
+    fn get_best_bid_and_ask(&self) -> DriftResult<(Option<u64>, Option<u64>)> {
+        self.quote_best_bid_and_ask()
+    }
+
+    fn fulfill_order(
+        &mut self,
+        taker_direction: PositionDirection,
+        taker_price: u64,
+        taker_base_asset_amount: u64,
+        taker_max_quote_asset_amount: u64,
+    ) -> DriftResult<ExternalSpotFill> {
+        match self.place_ioc_order(
+            taker_direction,
+            taker_price,
+            taker_base_asset_amount,
+            taker_max_quote_asset_amount,
+        )? {
+            Some(placed_order) => self.settle(placed_order),
+            None => Ok(ExternalSpotFill::empty()),
+        }
+    }
+
+    fn get_order_action_explanation(&self) -> DriftResult<OrderActionExplanation> {
+        Ok(self.fill_explanation())
+    }
+
+    fn validate_vault_amounts(
+        &self,
+        base_market: &Ref<SpotMarket>,
+        quote_market: &Ref<SpotMarket>,
+    ) -> DriftResult<()> {
+        let (base_vault_amount, quote_vault_amount) = self.get_vault_amounts();
+        validate_spot_market_vault_amount(base_market, base_vault_amount)?;
+        validate_spot_market_vault_amount(quote_market, quote_vault_amount)?;
+        Ok(())
+    }
+}
+
 pub struct ExternalSpotFill {
     pub base_asset_amount_filled: u64,
     pub base_update_direction: SpotBalanceType,
@@ -6,7 +6,9 @@ mod size {
     use crate::state::spot_market::SpotMarket;
     use crate::state::state::State;
     use crate::state::traits::Size;
-    use crate::state::user::{User, UserStats};
+    use crate::state::user::{
+        Order, PerpPosition, PerpPositionTakeProfitStopLoss, SpotPosition, User, UserStats,
+    };
 
     #[test]
     fn order_action_records() {
@@ -50,6 +52,22 @@ mod size {
         assert_eq!(actual_size, expected_size);
     }
 
+    #[test]
+    fn user_layout() {
+        // user accounts are already allocated at this size, so positions and orders can't grow
+        assert_eq!(User::SIZE, 4376);
+        assert_eq!(std::mem::size_of::<SpotPosition>(), 40);
+        assert_eq!(std::mem::size_of::<PerpPosition>(), 96);
+        assert_eq!(std::mem::size_of::<Order>(), 96);
+    }
+
+    #[test]
+    fn perp_position_take_profit_stop_loss() {
+        let expected_size = std::mem::size_of::<PerpPositionTakeProfitStopLoss>() + 8;
+        let actual_size = PerpPositionTakeProfitStopLoss::SIZE;
+        assert_eq!(actual_size, expected_size);
+    }
+
     #[test]
     fn user_stats() {
         let expected_size = std::mem::size_of::<UserStats>() + 8;
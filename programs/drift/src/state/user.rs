@@ -4,9 +4,9 @@ use crate::error::{DriftResult, ErrorCode};
 use crate::math::auction::{calculate_auction_price, is_auction_complete};
 use crate::math::casting::Cast;
 use crate::math::constants::{
-    AMM_TO_QUOTE_PRECISION_RATIO_I128, EPOCH_DURATION, OPEN_ORDER_MARGIN_REQUIREMENT,
-    PRICE_PRECISION_I128, PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO, QUOTE_PRECISION,
-    QUOTE_SPOT_MARKET_INDEX, THIRTY_DAY,
+    AMM_TO_QUOTE_PRECISION_RATIO_I128, BPS_PRECISION_U64, EPOCH_DURATION,
+    OPEN_ORDER_MARGIN_REQUIREMENT, PRICE_PRECISION_I128, PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO,
+    QUOTE_PRECISION, QUOTE_SPOT_MARKET_INDEX, THIRTY_DAY,
 };
 use crate::math::lp::{calculate_lp_open_bids_asks, calculate_settle_lp_metrics};
 use crate::math::margin::MarginRequirementType;
@@ -20,6 +20,7 @@ use crate::math::spot_balance::{
     get_signed_token_amount, get_strict_token_value, get_token_amount, get_token_value,
 };
 use crate::math::stats::calculate_rolling_sum;
+use crate::state::events::OrderActionExplanation;
 use crate::state::oracle::StrictOraclePrice;
 use crate::state::perp_market::PerpMarket;
 use crate::state::spot_market::{SpotBalance, SpotBalanceType, SpotMarket};
@@ -117,7 +118,13 @@ pub struct User {
     pub open_auctions: u8,
     /// Whether or not user has open order with auction
     pub has_open_auction: bool,
-    pub padding: [u8; 21],
+    pub padding1: [u8; 5],
+    /// The unix timestamp after which any keeper can cancel all of the user's open orders
+    /// Set through set_cancel_after. 0 if not set
+    pub cancel_after_ts: i64,
+    /// How many positions have been opened (or flipped) in each perp position slot, wrapping at
+    /// 256. Lets position take profit / stop loss tell the position it was set for from a later one
+    pub perp_position_open_counts: [u8; 8],
 }
 
 impl User {
@@ -231,6 +238,24 @@ impl User {
         Ok(&mut self.perp_positions[get_position_index(&self.perp_positions, market_index)?])
     }
 
+    /// Counts the position in the slot as a new one if it was opened or flipped since it had
+    /// base_asset_amount_before
+    pub fn update_perp_position_open_count(
+        &mut self,
+        position_index: usize,
+        base_asset_amount_before: i64,
+    ) {
+        let base_asset_amount = self.perp_positions[position_index].base_asset_amount;
+        let opened = base_asset_amount != 0
+            && (base_asset_amount_before == 0
+                || base_asset_amount.signum() != base_asset_amount_before.signum());
+
+        if opened {
+            self.perp_position_open_counts[position_index] =
+                self.perp_position_open_counts[position_index].wrapping_add(1);
+        }
+    }
+
     pub fn force_get_perp_position_mut(
         &mut self,
         market_index: u16,
@@ -260,6 +285,58 @@ impl User {
         self.orders.iter().find(|order| order.order_id == order_id)
     }
 
+    /// Bracket exits can't be triggered until the group's entry order has filled
+    pub fn is_bracket_exit_pending(&self, order: &Order) -> bool {
+        order.is_bit_flag_set(OrderBitFlag::BracketExit)
+            && self.orders.iter().any(|other| {
+                other.status == OrderStatus::Open
+                    && other.group_id == order.group_id
+                    && other.is_bit_flag_set(OrderBitFlag::BracketEntry)
+                    && other.base_asset_amount_filled == 0
+            })
+    }
+
+    /// Sizes the untriggered exits of a bracket entry to the amount the entry has filled so far.
+    /// An entry that is no longer open was fully filled
+    pub fn update_bracket_exits_for_entry_fill(&mut self, entry_before_fill: &Order) {
+        if !entry_before_fill.is_bit_flag_set(OrderBitFlag::BracketEntry) {
+            return;
+        }
+
+        let entry_base_asset_amount_filled = self
+            .get_order(entry_before_fill.order_id)
+            .filter(|entry| entry.status == OrderStatus::Open)
+            .map_or(entry_before_fill.base_asset_amount, |entry| {
+                entry.base_asset_amount_filled
+            });
+
+        for exit in self.orders.iter_mut().filter(|exit| {
+            exit.status == OrderStatus::Open
+                && exit.group_id == entry_before_fill.group_id
+                && exit.is_bit_flag_set(OrderBitFlag::BracketExit)
+                && !exit.triggered()
+        }) {
+            exit.base_asset_amount = entry_base_asset_amount_filled;
+        }
+    }
+
+    pub fn get_twap_order_index(&self, group_id: u8) -> Option<usize> {
+        self.orders.iter().position(|order| {
+            order.status == OrderStatus::Open
+                && order.order_type == OrderType::Twap
+                && order.group_id == group_id
+        })
+    }
+
+    pub fn get_unused_order_group_id(&self) -> Option<u8> {
+        (1..=u8::MAX).find(|group_id| {
+            !self
+                .orders
+                .iter()
+                .any(|order| order.status == OrderStatus::Open && order.group_id == *group_id)
+        })
+    }
+
     pub fn get_last_order_id(&self) -> u32 {
         if self.next_order_id == 1 {
             u32::MAX
@@ -951,6 +1028,35 @@ impl PerpPosition {
 
 pub type PerpPositions = [PerpPosition; 8];
 
+/// Order layout v2. The sdk decoder (decode/user.ts) and the idl follow this layout.
+///
+/// Order is 96 bytes with no padding left: v2 is the 2.61 layout with its 3 bytes of padding
+/// used for bit_flags, group_id and slices_remaining. Order types and bit flags reuse the fields
+/// they don't need, each reuse with exactly one encoding. Reused fields are only read through the
+/// getters below, which the sdk mirrors with helpers of the same name in math/orders.ts:
+/// - trigger_price
+///   - Twap: the unix timestamp the next slice can be released at, get_twap_next_release_ts.
+///     The twap order is never filled itself, its TwapSlice market orders are
+///   - Limit with OrderBitFlag::Iceberg: the base asset amount shown at a time,
+///     get_iceberg_display_base_asset_amount
+///   - Oracle with OrderBitFlag::MaxSlippage: the max fill distance from the fill time oracle in
+///     bps, get_max_slippage_bps
+///   - TrailingStop/TrailingTakeProfit: the trigger price trailing the oracle. Inactive trailing
+///     take profits keep their activation price in it until the oracle crosses it
+///   - TriggerSource::FundingRate/MarkOracleDivergence: the unsigned threshold in the source's
+///     precision. Above triggers when the value rises above trigger_price and Below when it falls
+///     below -trigger_price, e.g. Below with 100 triggers once funding is under -100
+/// - oracle_price_offset
+///   - Market/TriggerMarket with OrderBitFlag::MaxSlippage: the max slippage in bps,
+///     get_max_slippage_bps
+///   - TrailingStop/TrailingTakeProfit: the trailing distance
+/// - auction_start_price: for untriggered TriggerMarket/TriggerLimit orders, the TriggerSource,
+///   get_trigger_source
+/// - auction_end_price: for untriggered TriggerSource::MarketOracle orders, the trigger perp market
+///   index, get_trigger_market_index. Triggering sets the real auction params
+///
+/// All 8 OrderBitFlag bits are used. New order settings don't reuse any more Order fields or
+/// bits, they go in a versioned order extension account (layout v3) instead
 #[zero_copy(unsafe)]
 #[repr(C)]
 #[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Debug, Eq)]
@@ -973,18 +1079,25 @@ pub struct Order {
     /// precision: QUOTE_PRECISION
     pub quote_asset_amount_filled: u64,
     /// At what price the order will be triggered. Only relevant for trigger orders
+    /// For twap orders, the unix timestamp the next slice can be released at (see Order)
+    /// For iceberg orders, the base asset amount shown at a time (see Order)
+    /// For oracle orders with OrderBitFlag::MaxSlippage set, the max fill distance from the oracle in bps (see Order)
     /// precision: PRICE_PRECISION
     pub trigger_price: u64,
     /// The start price for the auction. Only relevant for market/oracle orders
+    /// For untriggered trigger orders, the TriggerSource the trigger price is compared against (see Order)
     /// precision: PRICE_PRECISION
     pub auction_start_price: i64,
     /// The end price for the auction. Only relevant for market/oracle orders
+    /// For untriggered trigger orders with TriggerSource::MarketOracle, the perp market index (see Order)
     /// precision: PRICE_PRECISION
     pub auction_end_price: i64,
     /// The time when the order will expire
     pub max_ts: i64,
     /// If set, the order limit price is the oracle price + this offset
-    /// precision: PRICE_PRECISION
+    /// For trailing orders, this is the distance the trigger price trails the oracle by (see Order)
+    /// For market orders with OrderBitFlag::MaxSlippage set, the max fill distance from the oracle in bps (see Order)
+    /// precision: PRICE_PRECISION (PERCENTAGE_PRECISION if OrderBitFlag::TrailingPercentage set)
     pub oracle_price_offset: i32,
     /// The id for the order. Each users has their own order id space
     pub order_id: u32,
@@ -1012,7 +1125,12 @@ pub struct Order {
     pub trigger_condition: OrderTriggerCondition,
     /// How many slots the auction lasts
     pub auction_duration: u8,
-    pub padding: [u8; 3],
+    /// Bit flags for optional order settings. See OrderBitFlag
+    pub bit_flags: u8,
+    /// Orders with the same non-zero group id are linked. The role in the group is set in bit_flags
+    pub group_id: u8,
+    /// For twap orders, the number of slices left to release
+    pub slices_remaining: u8,
 }
 
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq, Debug)]
@@ -1027,7 +1145,62 @@ impl Order {
     }
 
     pub fn has_oracle_price_offset(self) -> bool {
+        // trailing orders use the offset as the trailing distance and max slippage market orders as
+        // the max slippage, not for the limit price
         self.oracle_price_offset != 0
+            && !self.is_trailing_order()
+            && (self.order_type == OrderType::Oracle
+                || !self.is_bit_flag_set(OrderBitFlag::MaxSlippage))
+    }
+
+    /// For max slippage orders, the max fill distance from the oracle in bps
+    pub fn get_max_slippage_bps(&self) -> Option<u64> {
+        if !self.is_bit_flag_set(OrderBitFlag::MaxSlippage) {
+            return None;
+        }
+
+        match self.order_type {
+            OrderType::Oracle => Some(self.trigger_price),
+            _ => Some(self.oracle_price_offset.unsigned_abs().into()),
+        }
+    }
+
+    /// For max slippage orders, the worst price the order can fill at given the oracle price
+    pub fn get_max_slippage_price(
+        &self,
+        valid_oracle_price: Option<i64>,
+        tick_size: u64,
+    ) -> DriftResult<Option<u64>> {
+        let max_slippage_bps = match self.get_max_slippage_bps() {
+            Some(max_slippage_bps) => max_slippage_bps,
+            None => return Ok(None),
+        };
+
+        let oracle_price = valid_oracle_price
+            .ok_or_else(|| {
+                msg!("Could not find oracle to calculate max slippage price");
+                ErrorCode::OracleNotFound
+            })?
+            .unsigned_abs();
+
+        let max_slippage = oracle_price
+            .safe_mul(max_slippage_bps)?
+            .safe_div(BPS_PRECISION_U64)?;
+
+        let max_slippage_price = match self.direction {
+            PositionDirection::Long => oracle_price.safe_add(max_slippage)?,
+            PositionDirection::Short => oracle_price.safe_sub(max_slippage)?,
+        };
+
+        Ok(Some(standardize_price(
+            max_slippage_price,
+            tick_size,
+            self.direction,
+        )?))
+    }
+
+    pub fn is_bit_flag_set(&self, flag: OrderBitFlag) -> bool {
+        self.bit_flags & flag as u8 != 0
     }
 
     pub fn get_limit_price(
@@ -1071,7 +1244,27 @@ impl Order {
             Some(self.price)
         };
 
-        Ok(price)
+        self.clamp_limit_price_to_max_slippage(price, valid_oracle_price, tick_size)
+    }
+
+    pub fn clamp_limit_price_to_max_slippage(
+        &self,
+        limit_price: Option<u64>,
+        valid_oracle_price: Option<i64>,
+        tick_size: u64,
+    ) -> DriftResult<Option<u64>> {
+        let max_slippage_price = match self.get_max_slippage_price(valid_oracle_price, tick_size)? {
+            Some(max_slippage_price) => max_slippage_price,
+            None => return Ok(limit_price),
+        };
+
+        let limit_price = match (limit_price, self.direction) {
+            (Some(limit_price), PositionDirection::Long) => limit_price.min(max_slippage_price),
+            (Some(limit_price), PositionDirection::Short) => limit_price.max(max_slippage_price),
+            (None, _) => max_slippage_price,
+        };
+
+        Ok(Some(limit_price))
     }
 
     #[track_caller]
@@ -1111,6 +1304,32 @@ impl Order {
         self.auction_duration != 0
     }
 
+    pub fn get_trigger_source(&self) -> DriftResult<TriggerSource> {
+        // the auction is only set once the order is triggered
+        let is_trigger_order = matches!(
+            self.order_type,
+            OrderType::TriggerMarket | OrderType::TriggerLimit
+        );
+        if !is_trigger_order || self.triggered() {
+            return Ok(TriggerSource::Oracle);
+        }
+
+        match self.auction_start_price {
+            0 => Ok(TriggerSource::Oracle),
+            1 => Ok(TriggerSource::MarketOracle),
+            2 => Ok(TriggerSource::FundingRate),
+            3 => Ok(TriggerSource::MarkOracleDivergence),
+            _ => {
+                msg!("Invalid trigger source {}", self.auction_start_price);
+                Err(ErrorCode::InvalidOrderTrigger)
+            }
+        }
+    }
+
+    pub fn get_trigger_market_index(&self) -> DriftResult<u16> {
+        self.auction_end_price.cast()
+    }
+
     pub fn has_auction_price(
         &self,
         order_slot: u64,
@@ -1178,13 +1397,97 @@ impl Order {
         )
     }
 
+    /// For twap orders, the unix timestamp the next slice can be released at. Kept in trigger_price (see Order)
+    pub fn get_twap_next_release_ts(&self) -> DriftResult<i64> {
+        self.trigger_price.cast()
+    }
+
+    /// For iceberg orders, the base asset amount shown at a time. Kept in trigger_price (see Order)
+    pub fn get_iceberg_display_base_asset_amount(&self) -> Option<u64> {
+        if self.is_bit_flag_set(OrderBitFlag::Iceberg) && self.trigger_price != 0 {
+            Some(self.trigger_price)
+        } else {
+            None
+        }
+    }
+
+    /// The unfilled amount that can be matched against. For iceberg orders, what's left of the slice on display.
+    ///
+    /// This only limits what takers can match at a time, it doesn't hide the order: base_asset_amount
+    /// in the user account and the position's open bids/asks still show the full size
+    pub fn get_visible_base_asset_amount_unfilled(
+        &self,
+        existing_position: Option<i64>,
+    ) -> DriftResult<u64> {
+        let base_asset_amount_unfilled = self.get_base_asset_amount_unfilled(existing_position)?;
+
+        let display_base_asset_amount = match self.get_iceberg_display_base_asset_amount() {
+            Some(display_base_asset_amount) => display_base_asset_amount,
+            None => return Ok(base_asset_amount_unfilled),
+        };
+
+        let visible_base_asset_amount = display_base_asset_amount
+            .safe_sub(self.base_asset_amount_filled % display_base_asset_amount)?;
+
+        Ok(visible_base_asset_amount.min(base_asset_amount_unfilled))
+    }
+
     pub fn must_be_triggered(&self) -> bool {
         matches!(
             self.order_type,
-            OrderType::TriggerMarket | OrderType::TriggerLimit
+            OrderType::TriggerMarket
+                | OrderType::TriggerLimit
+                | OrderType::TrailingStop
+                | OrderType::TrailingTakeProfit
+                | OrderType::Twap
         )
     }
 
+    pub fn is_trailing_order(&self) -> bool {
+        matches!(
+            self.order_type,
+            OrderType::TrailingStop | OrderType::TrailingTakeProfit
+        )
+    }
+
+    /// The condition a trailing order fires on once it is trailing the oracle
+    /// e.g. a trailing stop selling a long fires when the oracle drops below the trigger price
+    pub fn get_trailing_trigger_condition(&self) -> OrderTriggerCondition {
+        match self.direction {
+            PositionDirection::Long => OrderTriggerCondition::Above,
+            PositionDirection::Short => OrderTriggerCondition::Below,
+        }
+    }
+
+    pub fn is_in_order_group(&self) -> bool {
+        self.group_id != 0
+    }
+
+    /// Whether filling or triggering this order cancels the other order
+    pub fn cancels_group_sibling(&self, other: &Order) -> bool {
+        if !self.is_in_order_group()
+            || self.group_id != other.group_id
+            || self.order_id == other.order_id
+        {
+            return false;
+        }
+
+        if self.is_bit_flag_set(OrderBitFlag::OneCancelsOther) {
+            true
+        } else if self.is_bit_flag_set(OrderBitFlag::BracketExit) {
+            // an exit also stops the rest of a partly filled entry
+            other.is_bit_flag_set(OrderBitFlag::BracketExit)
+                || other.is_bit_flag_set(OrderBitFlag::BracketEntry)
+        } else {
+            false
+        }
+    }
+
+    /// Trailing take profits wait for the oracle to cross the activation price before trailing
+    pub fn is_trailing_active(&self) -> bool {
+        self.is_trailing_order() && self.trigger_condition == self.get_trailing_trigger_condition()
+    }
+
     pub fn triggered(&self) -> bool {
         matches!(
             self.trigger_condition,
@@ -1214,7 +1517,11 @@ impl Order {
     pub fn is_market_order(&self) -> bool {
         matches!(
             self.order_type,
-            OrderType::Market | OrderType::TriggerMarket | OrderType::Oracle
+            OrderType::Market
+                | OrderType::TriggerMarket
+                | OrderType::Oracle
+                | OrderType::TrailingStop
+                | OrderType::TrailingTakeProfit
         )
     }
 
@@ -1269,7 +1576,9 @@ impl Default for Order {
             auction_end_price: 0,
             auction_duration: 0,
             max_ts: 0,
-            padding: [0; 3],
+            bit_flags: 0,
+            group_id: 0,
+            slices_remaining: 0,
         }
     }
 }
@@ -1294,6 +1603,13 @@ pub enum OrderType {
     TriggerLimit,
     /// Market order where the auction prices are oracle offsets
     Oracle,
+    /// Market order triggered once the oracle retraces by the trailing distance
+    /// The trigger price only moves in the user's favor as the oracle moves
+    TrailingStop,
+    /// Trailing stop that only starts trailing once the oracle crosses the trigger price
+    TrailingTakeProfit,
+    /// Never filled itself. Keepers release it as market order slices spread out until max_ts
+    Twap,
 }
 
 impl Default for OrderType {
@@ -1316,6 +1632,52 @@ impl Default for OrderTriggerCondition {
     }
 }
 
+/// What a trigger order's trigger price is compared against. For signed sources, Above triggers
+/// when the value rises above the trigger price and Below when it falls below its negative (see Order)
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum TriggerSource {
+    /// The order market's oracle price
+    Oracle,
+    /// The oracle price of another perp market
+    MarketOracle,
+    /// The order market's last funding rate. precision: FUNDING_RATE_PRECISION
+    FundingRate,
+    /// The order market's mark price divergence from the oracle. precision: PERCENTAGE_PRECISION
+    MarkOracleDivergence,
+}
+
+impl Default for TriggerSource {
+    fn default() -> Self {
+        TriggerSource::Oracle
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Eq)]
+pub enum OrderBitFlag {
+    /// The trailing distance is a percentage of the oracle price instead of a fixed price offset
+    TrailingPercentage = 0b00000001,
+    /// Filling or triggering the order cancels the other orders in its group
+    OneCancelsOther = 0b00000010,
+    /// The group's exit orders can't be triggered until the entry fills and are sized to its fills
+    BracketEntry = 0b00000100,
+    /// Reduce only stop loss or take profit for the group's entry. Filling or triggering it cancels
+    /// the other exits and what is left of the entry
+    BracketExit = 0b00001000,
+    /// Market order released by the twap order in its group. Fills are added to the twap order
+    TwapSlice = 0b00010000,
+    /// The order can only be filled for its whole unfilled amount at once. Only allowed in
+    /// place_and_take, which cancels the order if it couldn't fill
+    FillOrKill = 0b00100000,
+    /// Limit order that only shows takers its display amount at a time, kept in trigger_price (see Order)
+    Iceberg = 0b01000000,
+    /// Market/oracle order that can't fill further from the oracle than its max slippage bps (see Order)
+    MaxSlippage = 0b10000000,
+}
+
+pub const ORDER_GROUP_ROLE_BIT_FLAGS: u8 = OrderBitFlag::OneCancelsOther as u8
+    | OrderBitFlag::BracketEntry as u8
+    | OrderBitFlag::BracketExit as u8;
+
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
 pub enum MarketType {
     Spot,
@@ -1328,6 +1690,26 @@ impl Default for MarketType {
     }
 }
 
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum SelfTradePreventionMode {
+    /// Orders from different sub accounts of the same authority can match
+    None,
+    /// The resting maker order is canceled
+    ExpireMaker,
+    /// The taker order is canceled
+    ExpireTaker,
+    /// Both orders are canceled
+    ExpireBoth,
+    /// Both orders are decremented by the smaller unfilled amount and the smaller order is canceled
+    DecrementAndCancel,
+}
+
+impl Default for SelfTradePreventionMode {
+    fn default() -> Self {
+        SelfTradePreventionMode::None
+    }
+}
+
 #[account(zero_copy(unsafe))]
 #[derive(Eq, PartialEq, Debug)]
 #[repr(C)]
@@ -1369,7 +1751,9 @@ pub struct UserStats {
     /// Whether the user is a referrer. Sub account 0 can not be deleted if user is a referrer
     pub is_referrer: bool,
     pub disable_update_perp_bid_ask_twap: bool,
-    pub padding: [u8; 50],
+    /// How orders from different sub accounts of the authority are kept from matching each other
+    pub self_trade_prevention_mode: SelfTradePreventionMode,
+    pub padding: [u8; 49],
 }
 
 impl Default for UserStats {
@@ -1390,7 +1774,8 @@ impl Default for UserStats {
             number_of_sub_accounts_created: 0,
             is_referrer: false,
             disable_update_perp_bid_ask_twap: false,
-            padding: [0; 50],
+            self_trade_prevention_mode: SelfTradePreventionMode::None,
+            padding: [0; 49],
         }
     }
 }
@@ -1514,3 +1899,92 @@ pub struct ReferrerName {
 impl Size for ReferrerName {
     const SIZE: usize = 136;
 }
+
+#[account(zero_copy(unsafe))]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct PerpPositionTakeProfitStopLoss {
+    /// The user account whose position is closed
+    pub user: Pubkey,
+    /// The oracle price at which the whole position is closed for a profit. 0 if not set
+    /// precision: PRICE_PRECISION
+    pub take_profit_price: u64,
+    /// The oracle price at which the whole position is closed to limit losses. 0 if not set
+    /// precision: PRICE_PRECISION
+    pub stop_loss_price: u64,
+    /// The perp market index of the position
+    pub market_index: u16,
+    /// The direction of the position when the prices were set
+    pub position_direction: PositionDirection,
+    /// The user's perp position slot and its open count when the prices were set (see
+    /// User::perp_position_open_counts). Once the position is closed or flipped, the prices no
+    /// longer apply, even to a new position in the same direction
+    pub position_index: u8,
+    pub position_open_count: u8,
+    pub padding: [u8; 3],
+}
+
+impl Size for PerpPositionTakeProfitStopLoss {
+    const SIZE: usize = 64;
+}
+
+impl PerpPositionTakeProfitStopLoss {
+    pub fn is_set(&self) -> bool {
+        self.take_profit_price != 0 || self.stop_loss_price != 0
+    }
+
+    /// Ties the prices to the user's current position in the market
+    pub fn set_position(&mut self, user: &User) -> DriftResult {
+        let position_index = get_position_index(&user.perp_positions, self.market_index)?;
+
+        self.position_direction = user.perp_positions[position_index].get_direction();
+        self.position_index = position_index.cast()?;
+        self.position_open_count = user.perp_position_open_counts[position_index];
+
+        Ok(())
+    }
+
+    /// Whether the prices were set for the position the user currently has
+    pub fn is_for_position(&self, user: &User) -> bool {
+        let position_index = self.position_index as usize;
+        let position = match user.perp_positions.get(position_index) {
+            Some(position) => position,
+            None => return false,
+        };
+
+        position.is_open_position()
+            && position.market_index == self.market_index
+            && position.get_direction() == self.position_direction
+            && user.perp_position_open_counts[position_index] == self.position_open_count
+    }
+
+    pub fn reset(&mut self) {
+        self.take_profit_price = 0;
+        self.stop_loss_price = 0;
+    }
+
+    pub fn get_triggered(&self, user: &User, oracle_price: u64) -> Option<OrderActionExplanation> {
+        if !self.is_for_position(user) {
+            return None;
+        }
+
+        let (take_profit_triggered, stop_loss_triggered) = match self.position_direction {
+            PositionDirection::Long => (
+                self.take_profit_price != 0 && oracle_price >= self.take_profit_price,
+                self.stop_loss_price != 0 && oracle_price <= self.stop_loss_price,
+            ),
+            PositionDirection::Short => (
+                self.take_profit_price != 0 && oracle_price <= self.take_profit_price,
+                self.stop_loss_price != 0 && oracle_price >= self.stop_loss_price,
+            ),
+        };
+
+        if stop_loss_triggered {
+            Some(OrderActionExplanation::PositionStopLoss)
+        } else if take_profit_triggered {
+            Some(OrderActionExplanation::PositionTakeProfit)
+        } else {
+            None
+        }
+    }
+}
@@ -1940,3 +1940,892 @@ mod get_user_stats_age_ts {
         assert_eq!(age, 0);
     }
 }
+
+mod order_groups {
+    use crate::controller::position::PositionDirection;
+    use crate::state::user::{
+        Order, OrderBitFlag, OrderStatus, OrderTriggerCondition, OrderType, User,
+    };
+    use crate::validation::order::validate_order_group;
+    use crate::{get_orders, BASE_PRECISION_U64, PRICE_PRECISION_U64};
+
+    #[test]
+    fn one_cancels_other() {
+        let take_profit = Order {
+            status: OrderStatus::Open,
+            order_id: 1,
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Short,
+            price: 110 * PRICE_PRECISION_U64,
+            bit_flags: OrderBitFlag::OneCancelsOther as u8,
+            group_id: 1,
+            ..Order::default()
+        };
+        let stop_loss = Order {
+            order_id: 2,
+            order_type: OrderType::TriggerMarket,
+            price: 0,
+            trigger_price: 90 * PRICE_PRECISION_U64,
+            ..take_profit
+        };
+        let other_group = Order {
+            order_id: 3,
+            group_id: 2,
+            ..take_profit
+        };
+
+        assert!(take_profit.cancels_group_sibling(&stop_loss));
+        assert!(stop_loss.cancels_group_sibling(&take_profit));
+        assert!(!take_profit.cancels_group_sibling(&take_profit));
+        assert!(!take_profit.cancels_group_sibling(&other_group));
+
+        let no_group = Order {
+            bit_flags: 0,
+            group_id: 0,
+            ..take_profit
+        };
+        assert!(!no_group.cancels_group_sibling(&Order::default()));
+    }
+
+    #[test]
+    fn bracket() {
+        let entry = Order {
+            status: OrderStatus::Open,
+            order_id: 1,
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            price: 100 * PRICE_PRECISION_U64,
+            bit_flags: OrderBitFlag::BracketEntry as u8,
+            group_id: 1,
+            ..Order::default()
+        };
+        let stop_loss = Order {
+            order_id: 2,
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Short,
+            price: 0,
+            trigger_price: 90 * PRICE_PRECISION_U64,
+            reduce_only: true,
+            bit_flags: OrderBitFlag::BracketExit as u8,
+            ..entry
+        };
+        let take_profit = Order {
+            order_id: 3,
+            trigger_price: 110 * PRICE_PRECISION_U64,
+            ..stop_loss
+        };
+
+        // exits cancel each other and the rest of the entry
+        assert!(stop_loss.cancels_group_sibling(&take_profit));
+        assert!(stop_loss.cancels_group_sibling(&entry));
+        assert!(!entry.cancels_group_sibling(&stop_loss));
+
+        let user = User {
+            orders: get_orders!(entry, stop_loss, take_profit),
+            ..User::default()
+        };
+
+        assert!(user.is_bracket_exit_pending(&stop_loss));
+        assert!(!user.is_bracket_exit_pending(&entry));
+
+        // entry partly filled
+        let partly_filled_entry = Order {
+            base_asset_amount_filled: BASE_PRECISION_U64 / 2,
+            ..entry
+        };
+        let user = User {
+            orders: get_orders!(partly_filled_entry, stop_loss, take_profit),
+            ..User::default()
+        };
+
+        assert!(!user.is_bracket_exit_pending(&stop_loss));
+
+        // entry filled
+        let user = User {
+            orders: get_orders!(stop_loss, take_profit),
+            ..User::default()
+        };
+
+        assert!(!user.is_bracket_exit_pending(&stop_loss));
+    }
+
+    #[test]
+    fn exits_sized_to_entry_fills() {
+        let entry = Order {
+            status: OrderStatus::Open,
+            order_id: 1,
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            base_asset_amount: 2 * BASE_PRECISION_U64,
+            price: 100 * PRICE_PRECISION_U64,
+            bit_flags: OrderBitFlag::BracketEntry as u8,
+            group_id: 1,
+            ..Order::default()
+        };
+        let stop_loss = Order {
+            order_id: 2,
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Short,
+            price: 0,
+            trigger_price: 90 * PRICE_PRECISION_U64,
+            trigger_condition: OrderTriggerCondition::Below,
+            reduce_only: true,
+            bit_flags: OrderBitFlag::BracketExit as u8,
+            ..entry
+        };
+        let take_profit = Order {
+            order_id: 3,
+            trigger_price: 110 * PRICE_PRECISION_U64,
+            trigger_condition: OrderTriggerCondition::Above,
+            ..stop_loss
+        };
+        let other_group = Order {
+            order_id: 4,
+            group_id: 2,
+            ..stop_loss
+        };
+
+        // entry partly filled
+        let mut user = User {
+            orders: get_orders!(
+                Order {
+                    base_asset_amount_filled: BASE_PRECISION_U64 / 2,
+                    ..entry
+                },
+                stop_loss,
+                take_profit,
+                other_group
+            ),
+            ..User::default()
+        };
+
+        user.update_bracket_exits_for_entry_fill(&entry);
+
+        assert_eq!(user.orders[1].base_asset_amount, BASE_PRECISION_U64 / 2);
+        assert_eq!(user.orders[2].base_asset_amount, BASE_PRECISION_U64 / 2);
+        assert_eq!(user.orders[3].base_asset_amount, 2 * BASE_PRECISION_U64);
+
+        // entry filled, exits sized to the whole entry
+        user.orders[0] = Order::default();
+        user.update_bracket_exits_for_entry_fill(&entry);
+
+        assert_eq!(user.orders[1].base_asset_amount, 2 * BASE_PRECISION_U64);
+        assert_eq!(user.orders[2].base_asset_amount, 2 * BASE_PRECISION_U64);
+
+        // triggered exits keep their size
+        let mut user = User {
+            orders: get_orders!(
+                Order {
+                    base_asset_amount_filled: BASE_PRECISION_U64,
+                    ..entry
+                },
+                Order {
+                    base_asset_amount: BASE_PRECISION_U64 / 2,
+                    trigger_condition: OrderTriggerCondition::TriggeredBelow,
+                    ..stop_loss
+                }
+            ),
+            ..User::default()
+        };
+
+        user.update_bracket_exits_for_entry_fill(&entry);
+
+        assert_eq!(user.orders[1].base_asset_amount, BASE_PRECISION_U64 / 2);
+
+        // not an entry
+        user.update_bracket_exits_for_entry_fill(&stop_loss);
+
+        assert_eq!(user.orders[1].base_asset_amount, BASE_PRECISION_U64 / 2);
+    }
+
+    #[test]
+    fn validate() {
+        let entry = Order {
+            status: OrderStatus::Open,
+            order_id: 1,
+            order_type: OrderType::Limit,
+            direction: PositionDirection::Long,
+            price: 100 * PRICE_PRECISION_U64,
+            bit_flags: OrderBitFlag::BracketEntry as u8,
+            group_id: 1,
+            ..Order::default()
+        };
+        let stop_loss = Order {
+            order_id: 2,
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Short,
+            price: 0,
+            trigger_price: 90 * PRICE_PRECISION_U64,
+            bit_flags: OrderBitFlag::BracketExit as u8,
+            ..entry
+        };
+        let orders = get_orders!(entry);
+
+        assert!(validate_order_group(&stop_loss, &orders).is_ok());
+
+        // role without group
+        let invalid = Order {
+            group_id: 0,
+            ..stop_loss
+        };
+        assert!(validate_order_group(&invalid, &orders).is_err());
+
+        // multiple roles
+        let invalid = Order {
+            bit_flags: OrderBitFlag::BracketExit as u8 | OrderBitFlag::OneCancelsOther as u8,
+            ..stop_loss
+        };
+        assert!(validate_order_group(&invalid, &orders).is_err());
+
+        // exit must be a trigger order
+        let invalid = Order {
+            order_type: OrderType::Limit,
+            price: 110 * PRICE_PRECISION_U64,
+            ..stop_loss
+        };
+        assert!(validate_order_group(&invalid, &orders).is_err());
+
+        // exit must be opposite side of entry
+        let invalid = Order {
+            direction: PositionDirection::Long,
+            ..stop_loss
+        };
+        assert!(validate_order_group(&invalid, &orders).is_err());
+
+        // only one entry
+        let invalid = Order {
+            order_id: 3,
+            ..entry
+        };
+        assert!(validate_order_group(&invalid, &orders).is_err());
+
+        // can't mix oco and bracket
+        let invalid = Order {
+            bit_flags: OrderBitFlag::OneCancelsOther as u8,
+            ..stop_loss
+        };
+        assert!(validate_order_group(&invalid, &orders).is_err());
+    }
+}
+
+mod get_triggered_take_profit_or_stop_loss {
+    use crate::state::events::OrderActionExplanation;
+    use crate::state::user::{PerpPosition, PerpPositionTakeProfitStopLoss, User};
+    use crate::test_utils::get_positions;
+    use crate::{BASE_PRECISION_I64, PRICE_PRECISION_U64};
+
+    #[test]
+    fn long() {
+        let user = User {
+            perp_positions: get_positions(PerpPosition {
+                base_asset_amount: BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            ..User::default()
+        };
+        let mut take_profit_stop_loss = PerpPositionTakeProfitStopLoss {
+            take_profit_price: 110 * PRICE_PRECISION_U64,
+            stop_loss_price: 90 * PRICE_PRECISION_U64,
+            ..PerpPositionTakeProfitStopLoss::default()
+        };
+        take_profit_stop_loss.set_position(&user).unwrap();
+
+        assert!(take_profit_stop_loss
+            .get_triggered(&user, 100 * PRICE_PRECISION_U64)
+            .is_none());
+        assert!(
+            take_profit_stop_loss.get_triggered(&user, 110 * PRICE_PRECISION_U64)
+                == Some(OrderActionExplanation::PositionTakeProfit)
+        );
+        assert!(
+            take_profit_stop_loss.get_triggered(&user, 90 * PRICE_PRECISION_U64)
+                == Some(OrderActionExplanation::PositionStopLoss)
+        );
+    }
+
+    #[test]
+    fn short() {
+        let user = User {
+            perp_positions: get_positions(PerpPosition {
+                base_asset_amount: -BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            ..User::default()
+        };
+        let mut take_profit_stop_loss = PerpPositionTakeProfitStopLoss {
+            take_profit_price: 90 * PRICE_PRECISION_U64,
+            stop_loss_price: 110 * PRICE_PRECISION_U64,
+            ..PerpPositionTakeProfitStopLoss::default()
+        };
+        take_profit_stop_loss.set_position(&user).unwrap();
+
+        assert!(take_profit_stop_loss
+            .get_triggered(&user, 100 * PRICE_PRECISION_U64)
+            .is_none());
+        assert!(
+            take_profit_stop_loss.get_triggered(&user, 89 * PRICE_PRECISION_U64)
+                == Some(OrderActionExplanation::PositionTakeProfit)
+        );
+        assert!(
+            take_profit_stop_loss.get_triggered(&user, 111 * PRICE_PRECISION_U64)
+                == Some(OrderActionExplanation::PositionStopLoss)
+        );
+    }
+
+    #[test]
+    fn unset_or_no_position() {
+        let mut user = User {
+            perp_positions: get_positions(PerpPosition {
+                base_asset_amount: BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            ..User::default()
+        };
+        let mut take_profit_stop_loss = PerpPositionTakeProfitStopLoss {
+            stop_loss_price: 90 * PRICE_PRECISION_U64,
+            ..PerpPositionTakeProfitStopLoss::default()
+        };
+        take_profit_stop_loss.set_position(&user).unwrap();
+
+        // take profit not set
+        assert!(take_profit_stop_loss
+            .get_triggered(&user, 1000 * PRICE_PRECISION_U64)
+            .is_none());
+
+        take_profit_stop_loss.take_profit_price = 110 * PRICE_PRECISION_U64;
+
+        // set for a long, position is now short
+        user.perp_positions[0].base_asset_amount = -BASE_PRECISION_I64;
+        assert!(take_profit_stop_loss
+            .get_triggered(&user, 50 * PRICE_PRECISION_U64)
+            .is_none());
+
+        // set for the long, a later long was opened in the slot
+        user.perp_positions[0].base_asset_amount = BASE_PRECISION_I64;
+        user.perp_position_open_counts[0] = 1;
+        assert!(take_profit_stop_loss
+            .get_triggered(&user, 50 * PRICE_PRECISION_U64)
+            .is_none());
+
+        // no position
+        assert!(take_profit_stop_loss
+            .get_triggered(&User::default(), 50 * PRICE_PRECISION_U64)
+            .is_none());
+    }
+}
+
+mod twap {
+    use crate::controller::position::PositionDirection;
+    use crate::state::user::{MarketType, Order, OrderBitFlag, OrderStatus, OrderType, User};
+    use crate::test_utils::get_orders;
+    use crate::validation::order::validate_order_group;
+    use crate::{get_orders, BASE_PRECISION_U64};
+
+    #[test]
+    fn get_unused_order_group_id() {
+        let user = User::default();
+        assert_eq!(user.get_unused_order_group_id(), Some(1));
+
+        let order = Order {
+            status: OrderStatus::Open,
+            order_id: 1,
+            group_id: 1,
+            ..Order::default()
+        };
+        let closed_order = Order {
+            status: OrderStatus::Init,
+            order_id: 2,
+            group_id: 2,
+            ..Order::default()
+        };
+        let user = User {
+            orders: get_orders!(order, closed_order),
+            ..User::default()
+        };
+        assert_eq!(user.get_unused_order_group_id(), Some(2));
+    }
+
+    #[test]
+    fn get_twap_order_index() {
+        let slice = Order {
+            status: OrderStatus::Open,
+            order_id: 2,
+            order_type: OrderType::Market,
+            bit_flags: OrderBitFlag::TwapSlice as u8,
+            group_id: 1,
+            ..Order::default()
+        };
+        let twap_order = Order {
+            order_id: 1,
+            order_type: OrderType::Twap,
+            bit_flags: 0,
+            ..slice
+        };
+        let user = User {
+            orders: get_orders!(slice, twap_order),
+            ..User::default()
+        };
+
+        assert_eq!(user.get_twap_order_index(1), Some(1));
+        assert_eq!(user.get_twap_order_index(2), None);
+    }
+
+    #[test]
+    fn validate() {
+        let twap_order = Order {
+            status: OrderStatus::Open,
+            order_id: 1,
+            order_type: OrderType::Twap,
+            market_type: MarketType::Spot,
+            market_index: 1,
+            direction: PositionDirection::Long,
+            base_asset_amount: 10 * BASE_PRECISION_U64,
+            group_id: 1,
+            slices_remaining: 10,
+            ..Order::default()
+        };
+        let slice = Order {
+            order_id: 2,
+            order_type: OrderType::Market,
+            base_asset_amount: BASE_PRECISION_U64,
+            bit_flags: OrderBitFlag::TwapSlice as u8,
+            slices_remaining: 0,
+            ..twap_order
+        };
+
+        // twap order needs an empty group
+        assert!(validate_order_group(&twap_order, &get_orders(Order::default())).is_ok());
+        assert!(validate_order_group(&twap_order, &get_orders(slice)).is_err());
+        assert!(validate_order_group(
+            &Order {
+                group_id: 0,
+                ..twap_order
+            },
+            &get_orders(Order::default())
+        )
+        .is_err());
+
+        // slice needs a matching twap order
+        assert!(validate_order_group(&slice, &get_orders(twap_order)).is_ok());
+        assert!(validate_order_group(&slice, &get_orders(Order::default())).is_err());
+        assert!(validate_order_group(
+            &Order {
+                direction: PositionDirection::Short,
+                ..slice
+            },
+            &get_orders(twap_order)
+        )
+        .is_err());
+
+        // twap group can't be shared with oco orders
+        let one_cancels_other = Order {
+            order_id: 3,
+            order_type: OrderType::Limit,
+            bit_flags: OrderBitFlag::OneCancelsOther as u8,
+            ..twap_order
+        };
+        assert!(validate_order_group(&one_cancels_other, &get_orders(twap_order)).is_err());
+    }
+}
+
+mod fill_or_kill {
+    use crate::controller::position::PositionDirection;
+    use crate::state::user::{MarketType, Order, OrderBitFlag, OrderStatus, OrderType};
+    use crate::validation::order::validate_spot_order;
+    use crate::{BASE_PRECISION_U64, PRICE_PRECISION_U64};
+
+    #[test]
+    fn validate() {
+        let order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Spot,
+            market_index: 1,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: 100 * PRICE_PRECISION_U64,
+            bit_flags: OrderBitFlag::FillOrKill as u8,
+            ..Order::default()
+        };
+
+        assert!(
+            validate_spot_order(&order, BASE_PRECISION_U64 / 10, BASE_PRECISION_U64 / 10).is_ok()
+        );
+
+        // post only orders never take, so they can't be fill or kill
+        assert!(validate_spot_order(
+            &Order {
+                post_only: true,
+                ..order
+            },
+            BASE_PRECISION_U64 / 10,
+            BASE_PRECISION_U64 / 10
+        )
+        .is_err());
+    }
+}
+
+mod iceberg {
+    use crate::controller::position::PositionDirection;
+    use crate::state::user::{MarketType, Order, OrderBitFlag, OrderStatus, OrderType};
+    use crate::validation::order::validate_spot_order;
+    use crate::{BASE_PRECISION_U64, PRICE_PRECISION_U64};
+
+    fn get_iceberg_order() -> Order {
+        Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Spot,
+            market_index: 1,
+            direction: PositionDirection::Long,
+            base_asset_amount: 10 * BASE_PRECISION_U64,
+            price: 100 * PRICE_PRECISION_U64,
+            trigger_price: 3 * BASE_PRECISION_U64,
+            bit_flags: OrderBitFlag::Iceberg as u8,
+            ..Order::default()
+        }
+    }
+
+    #[test]
+    fn get_visible_base_asset_amount_unfilled() {
+        let order = get_iceberg_order();
+        assert_eq!(
+            order.get_visible_base_asset_amount_unfilled(None).unwrap(),
+            3 * BASE_PRECISION_U64
+        );
+
+        // part of the slice on display filled
+        let order = Order {
+            base_asset_amount_filled: BASE_PRECISION_U64,
+            ..get_iceberg_order()
+        };
+        assert_eq!(
+            order.get_visible_base_asset_amount_unfilled(None).unwrap(),
+            2 * BASE_PRECISION_U64
+        );
+
+        // slice filled, next slice shown
+        let order = Order {
+            base_asset_amount_filled: 3 * BASE_PRECISION_U64,
+            ..get_iceberg_order()
+        };
+        assert_eq!(
+            order.get_visible_base_asset_amount_unfilled(None).unwrap(),
+            3 * BASE_PRECISION_U64
+        );
+
+        // last slice is what's left
+        let order = Order {
+            base_asset_amount_filled: 9 * BASE_PRECISION_U64,
+            ..get_iceberg_order()
+        };
+        assert_eq!(
+            order.get_visible_base_asset_amount_unfilled(None).unwrap(),
+            BASE_PRECISION_U64
+        );
+
+        // not an iceberg order
+        let order = Order {
+            trigger_price: 0,
+            bit_flags: 0,
+            ..get_iceberg_order()
+        };
+        assert_eq!(
+            order.get_visible_base_asset_amount_unfilled(None).unwrap(),
+            10 * BASE_PRECISION_U64
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let step_size = BASE_PRECISION_U64 / 10;
+        let min_order_size = BASE_PRECISION_U64 / 10;
+
+        assert!(validate_spot_order(&get_iceberg_order(), step_size, min_order_size).is_ok());
+
+        // display amount must be less than the order
+        assert!(validate_spot_order(
+            &Order {
+                trigger_price: 10 * BASE_PRECISION_U64,
+                ..get_iceberg_order()
+            },
+            step_size,
+            min_order_size
+        )
+        .is_err());
+
+        // display amount must be a step size multiple
+        assert!(validate_spot_order(
+            &Order {
+                trigger_price: BASE_PRECISION_U64 + 1,
+                ..get_iceberg_order()
+            },
+            step_size,
+            min_order_size
+        )
+        .is_err());
+
+        // must rest on the book
+        assert!(validate_spot_order(
+            &Order {
+                immediate_or_cancel: true,
+                ..get_iceberg_order()
+            },
+            step_size,
+            min_order_size
+        )
+        .is_err());
+    }
+}
+
+mod max_slippage {
+    use crate::controller::position::PositionDirection;
+    use crate::state::user::{MarketType, Order, OrderBitFlag, OrderStatus, OrderType};
+    use crate::validation::order::validate_spot_order;
+    use crate::{BASE_PRECISION_U64, PRICE_PRECISION_I64, PRICE_PRECISION_U64};
+
+    fn get_max_slippage_order(direction: PositionDirection) -> Order {
+        let (auction_start_price, auction_end_price) = match direction {
+            PositionDirection::Long => (100 * PRICE_PRECISION_I64, 110 * PRICE_PRECISION_I64),
+            PositionDirection::Short => (100 * PRICE_PRECISION_I64, 90 * PRICE_PRECISION_I64),
+        };
+
+        Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::Market,
+            market_type: MarketType::Spot,
+            market_index: 1,
+            direction,
+            base_asset_amount: BASE_PRECISION_U64,
+            slot: 0,
+            auction_duration: 10,
+            auction_start_price,
+            auction_end_price,
+            oracle_price_offset: 100, // 1%
+            bit_flags: OrderBitFlag::MaxSlippage as u8,
+            ..Order::default()
+        }
+    }
+
+    #[test]
+    fn get_limit_price() {
+        let oracle_price = Some(100 * PRICE_PRECISION_I64);
+        let tick_size = 1;
+
+        // auction price within max slippage
+        let order = get_max_slippage_order(PositionDirection::Long);
+        assert_eq!(
+            order
+                .get_limit_price(oracle_price, None, 0, tick_size)
+                .unwrap(),
+            Some(100 * PRICE_PRECISION_U64)
+        );
+
+        // auction price clamped to max slippage
+        assert_eq!(
+            order
+                .get_limit_price(oracle_price, None, 5, tick_size)
+                .unwrap(),
+            Some(101 * PRICE_PRECISION_U64)
+        );
+
+        // auction over, no price
+        assert_eq!(
+            order
+                .get_limit_price(oracle_price, None, 20, tick_size)
+                .unwrap(),
+            Some(101 * PRICE_PRECISION_U64)
+        );
+
+        // fallback price clamped
+        assert_eq!(
+            order
+                .get_limit_price(oracle_price, Some(120 * PRICE_PRECISION_U64), 20, tick_size)
+                .unwrap(),
+            Some(101 * PRICE_PRECISION_U64)
+        );
+
+        // tighter price kept
+        let order = Order {
+            price: 100 * PRICE_PRECISION_U64 + PRICE_PRECISION_U64 / 2,
+            ..get_max_slippage_order(PositionDirection::Long)
+        };
+        assert_eq!(
+            order
+                .get_limit_price(oracle_price, None, 20, tick_size)
+                .unwrap(),
+            Some(100 * PRICE_PRECISION_U64 + PRICE_PRECISION_U64 / 2)
+        );
+
+        let order = get_max_slippage_order(PositionDirection::Short);
+        assert_eq!(
+            order
+                .get_limit_price(oracle_price, None, 5, tick_size)
+                .unwrap(),
+            Some(99 * PRICE_PRECISION_U64)
+        );
+
+        assert_eq!(
+            order
+                .get_limit_price(oracle_price, None, 20, tick_size)
+                .unwrap(),
+            Some(99 * PRICE_PRECISION_U64)
+        );
+
+        // needs oracle
+        assert!(order.get_limit_price(None, None, 20, tick_size).is_err());
+    }
+
+    #[test]
+    fn has_oracle_price_offset() {
+        let order = get_max_slippage_order(PositionDirection::Long);
+        assert!(!order.has_oracle_price_offset());
+    }
+
+    #[test]
+    fn validate() {
+        let step_size = BASE_PRECISION_U64 / 10;
+        let min_order_size = BASE_PRECISION_U64 / 10;
+
+        let order = get_max_slippage_order(PositionDirection::Long);
+        assert!(validate_spot_order(&order, step_size, min_order_size).is_ok());
+
+        // must be a market order
+        let order = Order {
+            order_type: OrderType::Limit,
+            price: 100 * PRICE_PRECISION_U64,
+            auction_duration: 0,
+            auction_start_price: 0,
+            auction_end_price: 0,
+            ..get_max_slippage_order(PositionDirection::Long)
+        };
+        assert!(validate_spot_order(&order, step_size, min_order_size).is_err());
+
+        // max slippage must be less than 100%
+        let order = Order {
+            oracle_price_offset: 10_000,
+            ..get_max_slippage_order(PositionDirection::Long)
+        };
+        assert!(validate_spot_order(&order, step_size, min_order_size).is_err());
+
+        let order = Order {
+            oracle_price_offset: 0,
+            ..get_max_slippage_order(PositionDirection::Long)
+        };
+        assert!(validate_spot_order(&order, step_size, min_order_size).is_err());
+    }
+
+    #[test]
+    fn oracle_order() {
+        let tick_size = 1;
+        let step_size = BASE_PRECISION_U64 / 10;
+        let min_order_size = BASE_PRECISION_U64 / 10;
+
+        // 1% max slippage kept in trigger_price, oracle_price_offset is still the offset
+        let order = Order {
+            order_type: OrderType::Oracle,
+            auction_start_price: 0,
+            auction_end_price: 2 * PRICE_PRECISION_I64,
+            oracle_price_offset: 2 * PRICE_PRECISION_I64 as i32,
+            trigger_price: 100,
+            ..get_max_slippage_order(PositionDirection::Long)
+        };
+
+        assert!(order.has_oracle_price_offset());
+        assert_eq!(order.get_max_slippage_bps(), Some(100));
+        assert!(validate_spot_order(&order, step_size, min_order_size).is_ok());
+
+        // auction offset within max slippage
+        assert_eq!(
+            order
+                .get_limit_price(Some(100 * PRICE_PRECISION_I64), None, 0, tick_size)
+                .unwrap(),
+            Some(100 * PRICE_PRECISION_U64)
+        );
+
+        // offset clamped to max slippage from the oracle at fill time
+        assert_eq!(
+            order
+                .get_limit_price(Some(100 * PRICE_PRECISION_I64), None, 20, tick_size)
+                .unwrap(),
+            Some(101 * PRICE_PRECISION_U64)
+        );
+
+        assert_eq!(
+            order
+                .get_limit_price(Some(110 * PRICE_PRECISION_I64), None, 20, tick_size)
+                .unwrap(),
+            Some(111_100_000)
+        );
+
+        // no offset fills up to the max slippage
+        let order = Order {
+            oracle_price_offset: 0,
+            auction_end_price: 0,
+            ..order
+        };
+        assert_eq!(
+            order
+                .get_limit_price(Some(110 * PRICE_PRECISION_I64), None, 20, tick_size)
+                .unwrap(),
+            Some(111_100_000)
+        );
+
+        // oracle orders without the flag still can't have a trigger price
+        let order = Order {
+            bit_flags: 0,
+            ..order
+        };
+        assert!(validate_spot_order(&order, step_size, min_order_size).is_err());
+    }
+}
+
+mod get_trigger_source {
+    use crate::controller::position::PositionDirection;
+    use crate::state::user::{Order, OrderStatus, OrderTriggerCondition, OrderType, TriggerSource};
+    use crate::PRICE_PRECISION_U64;
+
+    #[test]
+    fn trigger_source() {
+        let order = Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Short,
+            trigger_condition: OrderTriggerCondition::Below,
+            trigger_price: 20_000 * PRICE_PRECISION_U64,
+            auction_start_price: TriggerSource::MarketOracle as i64,
+            auction_end_price: 1,
+            market_index: 2,
+            ..Order::default()
+        };
+
+        assert_eq!(
+            order.get_trigger_source().unwrap(),
+            TriggerSource::MarketOracle
+        );
+        assert_eq!(order.get_trigger_market_index().unwrap(), 1);
+
+        // auction params are the auction once triggered
+        let order = Order {
+            trigger_condition: OrderTriggerCondition::TriggeredBelow,
+            ..order
+        };
+        assert_eq!(order.get_trigger_source().unwrap(), TriggerSource::Oracle);
+
+        // auction params of non trigger orders are never a trigger source
+        let order = Order {
+            order_type: OrderType::Market,
+            trigger_condition: OrderTriggerCondition::Above,
+            auction_start_price: 3,
+            ..order
+        };
+        assert_eq!(order.get_trigger_source().unwrap(), TriggerSource::Oracle);
+
+        let order = Order {
+            order_type: OrderType::TriggerLimit,
+            auction_start_price: 4,
+            ..order
+        };
+        assert!(order.get_trigger_source().is_err());
+    }
+}
@@ -4,11 +4,15 @@ use crate::controller::position::PositionDirection;
 use crate::error::{DriftResult, ErrorCode};
 
 use crate::math::casting::Cast;
+use crate::math::constants::{BPS_PRECISION_U64, PERCENTAGE_PRECISION_U64};
 use crate::math::orders::{
     calculate_base_asset_amount_to_fill_up_to_limit_price, is_multiple_of_step_size,
 };
+use crate::math::safe_math::SafeMath;
 use crate::state::perp_market::PerpMarket;
-use crate::state::user::{Order, OrderTriggerCondition, OrderType};
+use crate::state::user::{
+    Order, OrderBitFlag, OrderStatus, OrderTriggerCondition, OrderType, ORDER_GROUP_ROLE_BIT_FLAGS,
+};
 use crate::validate;
 
 pub fn validate_order(
@@ -35,6 +39,24 @@ pub fn validate_order(
         OrderType::Oracle => {
             validate_oracle_order(order, market.amm.order_step_size, market.amm.min_order_size)?
         }
+        OrderType::TrailingStop | OrderType::TrailingTakeProfit => {
+            validate_trailing_order(order, market.amm.order_step_size, market.amm.min_order_size)?
+        }
+        OrderType::Twap => {
+            validate_twap_order(order, market.amm.order_step_size, market.amm.min_order_size)?
+        }
+    }
+
+    if order.is_bit_flag_set(OrderBitFlag::FillOrKill) {
+        validate_fill_or_kill_order(order)?;
+    }
+
+    if order.is_bit_flag_set(OrderBitFlag::Iceberg) {
+        validate_iceberg_order(order, market.amm.order_step_size, market.amm.min_order_size)?;
+    }
+
+    if order.is_bit_flag_set(OrderBitFlag::MaxSlippage) {
+        validate_max_slippage_order(order)?;
     }
 
     Ok(())
@@ -122,7 +144,7 @@ fn validate_oracle_order(order: &Order, step_size: u64, min_order_size: u64) ->
         }
     }
 
-    if order.trigger_price > 0 {
+    if order.trigger_price > 0 && !order.is_bit_flag_set(OrderBitFlag::MaxSlippage) {
         msg!("Oracle order should not have trigger price");
         return Err(ErrorCode::InvalidOrderTrigger);
     }
@@ -168,7 +190,7 @@ fn validate_limit_order(
         return Err(ErrorCode::InvalidOrderLimitPrice);
     }
 
-    if order.trigger_price > 0 {
+    if order.trigger_price > 0 && !order.is_bit_flag_set(OrderBitFlag::Iceberg) {
         msg!("Limit order should not have trigger price");
         return Err(ErrorCode::InvalidOrderTrigger);
     }
@@ -340,6 +362,114 @@ fn validate_trigger_market_order(
     Ok(())
 }
 
+fn validate_trailing_order(order: &Order, step_size: u64, min_order_size: u64) -> DriftResult {
+    validate_base_asset_amount(order, step_size, min_order_size, order.reduce_only)?;
+
+    // trailing take profits placed with the trailing condition are active immediately
+    let valid_trigger_condition = match order.order_type {
+        OrderType::TrailingStop => {
+            order.trigger_condition == order.get_trailing_trigger_condition()
+        }
+        _ => matches!(
+            order.trigger_condition,
+            OrderTriggerCondition::Above | OrderTriggerCondition::Below
+        ),
+    };
+
+    if !valid_trigger_condition {
+        msg!(
+            "Invalid trigger condition {:?} for {:?} {:?}",
+            order.trigger_condition,
+            order.order_type,
+            order.direction
+        );
+        return Err(ErrorCode::InvalidTriggerOrderCondition);
+    }
+
+    if order.price > 0 {
+        msg!("Trailing order should not have price");
+        return Err(ErrorCode::InvalidOrderLimitPrice);
+    }
+
+    if order.trigger_price == 0 {
+        msg!("Trailing order trigger_price == 0");
+        return Err(ErrorCode::InvalidOrderTrigger);
+    }
+
+    if order.post_only {
+        msg!("Trailing order can not be post only");
+        return Err(ErrorCode::InvalidOrderPostOnly);
+    }
+
+    if order.oracle_price_offset <= 0 {
+        msg!("Trailing order must have a positive trailing offset");
+        return Err(ErrorCode::InvalidOrderOracleOffset);
+    }
+
+    if order.is_bit_flag_set(OrderBitFlag::TrailingPercentage)
+        && order.oracle_price_offset.cast::<u64>()? >= PERCENTAGE_PRECISION_U64
+    {
+        msg!(
+            "Trailing percentage {} must be less than 100%",
+            order.oracle_price_offset
+        );
+        return Err(ErrorCode::InvalidOrderOracleOffset);
+    }
+
+    Ok(())
+}
+
+fn validate_twap_order(order: &Order, step_size: u64, min_order_size: u64) -> DriftResult {
+    validate_base_asset_amount(order, step_size, min_order_size, order.reduce_only)?;
+
+    if order.slices_remaining < 2 {
+        msg!(
+            "Twap order must have at least 2 slices, got {}",
+            order.slices_remaining
+        );
+        return Err(ErrorCode::InvalidOrder);
+    }
+
+    let slice_base_asset_amount = order
+        .base_asset_amount
+        .safe_div(order.slices_remaining.cast()?)?;
+    validate!(
+        slice_base_asset_amount >= step_size
+            && (order.reduce_only || slice_base_asset_amount >= min_order_size),
+        ErrorCode::InvalidOrderMinOrderSize,
+        "Twap slice base_asset_amount ({}) < min_order_size ({})",
+        slice_base_asset_amount,
+        min_order_size.max(step_size)
+    )?;
+
+    let first_release_ts = order.get_twap_next_release_ts()?;
+    if order.max_ts <= first_release_ts {
+        msg!(
+            "Twap order max_ts ({}) must be after the first slice ({})",
+            order.max_ts,
+            first_release_ts
+        );
+        return Err(ErrorCode::InvalidOrderMaxTs);
+    }
+
+    if order.oracle_price_offset != 0 {
+        msg!("Twap order can not have oracle offset");
+        return Err(ErrorCode::InvalidOrderOracleOffset);
+    }
+
+    if order.post_only {
+        msg!("Twap order can not be post only");
+        return Err(ErrorCode::InvalidOrderPostOnly);
+    }
+
+    if order.immediate_or_cancel {
+        msg!("Twap order can not be immediate or cancel");
+        return Err(ErrorCode::InvalidOrderIOC);
+    }
+
+    Ok(())
+}
+
 fn validate_base_asset_amount(
     order: &Order,
     step_size: u64,
@@ -436,8 +566,102 @@ pub fn validate_spot_order(order: &Order, step_size: u64, min_order_size: u64) -
         }
         OrderType::TriggerLimit => validate_trigger_limit_order(order, step_size, min_order_size)?,
         OrderType::Oracle => validate_oracle_order(order, step_size, min_order_size)?,
+        OrderType::TrailingStop | OrderType::TrailingTakeProfit => {
+            validate_trailing_order(order, step_size, min_order_size)?
+        }
+        OrderType::Twap => validate_twap_order(order, step_size, min_order_size)?,
+    }
+
+    if order.is_bit_flag_set(OrderBitFlag::FillOrKill) {
+        validate_fill_or_kill_order(order)?;
+    }
+
+    if order.is_bit_flag_set(OrderBitFlag::Iceberg) {
+        validate_iceberg_order(order, step_size, min_order_size)?;
+    }
+
+    if order.is_bit_flag_set(OrderBitFlag::MaxSlippage) {
+        validate_max_slippage_order(order)?;
+    }
+
+    Ok(())
+}
+
+fn validate_max_slippage_order(order: &Order) -> DriftResult {
+    validate!(
+        matches!(
+            order.order_type,
+            OrderType::Market | OrderType::TriggerMarket | OrderType::Oracle
+        ),
+        ErrorCode::InvalidOrder,
+        "max slippage flag only supported for market, trigger market and oracle orders"
+    )?;
+
+    let max_slippage_bps = order.get_max_slippage_bps().unwrap_or(0);
+    validate!(
+        max_slippage_bps > 0 && max_slippage_bps < BPS_PRECISION_U64,
+        ErrorCode::InvalidOrder,
+        "Max slippage ({}) must be between 0 and {} bps",
+        max_slippage_bps,
+        BPS_PRECISION_U64
+    )?;
+
+    Ok(())
+}
+
+fn validate_iceberg_order(order: &Order, step_size: u64, min_order_size: u64) -> DriftResult {
+    let display_base_asset_amount = order.get_iceberg_display_base_asset_amount().unwrap_or(0);
+
+    validate!(
+        order.order_type == OrderType::Limit,
+        ErrorCode::InvalidOrder,
+        "iceberg order must be a limit order"
+    )?;
+
+    if order.immediate_or_cancel || order.is_bit_flag_set(OrderBitFlag::FillOrKill) {
+        msg!("Iceberg order can not be immediate or cancel or fill or kill");
+        return Err(ErrorCode::InvalidOrderIOC);
     }
 
+    validate!(
+        is_multiple_of_step_size(display_base_asset_amount, step_size)?,
+        ErrorCode::InvalidOrderNotStepSizeMultiple,
+        "Iceberg display amount ({}) not a multiple of the step size ({})",
+        display_base_asset_amount,
+        step_size
+    )?;
+
+    validate!(
+        display_base_asset_amount > 0 && display_base_asset_amount >= min_order_size,
+        ErrorCode::InvalidOrderMinOrderSize,
+        "Iceberg display amount ({}) < min_order_size ({})",
+        display_base_asset_amount,
+        min_order_size
+    )?;
+
+    validate!(
+        display_base_asset_amount < order.base_asset_amount,
+        ErrorCode::InvalidOrder,
+        "Iceberg display amount ({}) must be less than base_asset_amount ({})",
+        display_base_asset_amount,
+        order.base_asset_amount
+    )?;
+
+    Ok(())
+}
+
+fn validate_fill_or_kill_order(order: &Order) -> DriftResult {
+    if order.post_only {
+        msg!("Fill or kill order can not be post only");
+        return Err(ErrorCode::InvalidOrderPostOnly);
+    }
+
+    validate!(
+        order.order_type != OrderType::Twap,
+        ErrorCode::InvalidOrder,
+        "twap order can not be fill or kill"
+    )?;
+
     Ok(())
 }
 
@@ -454,7 +678,7 @@ fn validate_spot_limit_order(order: &Order, step_size: u64, min_order_size: u64)
         return Err(ErrorCode::InvalidOrderOracleOffset);
     }
 
-    if order.trigger_price > 0 {
+    if order.trigger_price > 0 && !order.is_bit_flag_set(OrderBitFlag::Iceberg) {
         msg!("Limit order should not have trigger price");
         return Err(ErrorCode::InvalidOrderTrigger);
     }
@@ -499,3 +723,130 @@ pub fn validate_order_for_force_reduce_only(order: &Order, existing_position: i6
 
     Ok(())
 }
+
+pub fn validate_order_group(order: &Order, orders: &[Order]) -> DriftResult {
+    let role_bit_flags = order.bit_flags & ORDER_GROUP_ROLE_BIT_FLAGS;
+    let is_twap =
+        order.order_type == OrderType::Twap || order.is_bit_flag_set(OrderBitFlag::TwapSlice);
+
+    if !order.is_in_order_group() {
+        validate!(
+            role_bit_flags == 0 && !is_twap,
+            ErrorCode::InvalidOrderGroup,
+            "order with a group role must have a group id"
+        )?;
+
+        return Ok(());
+    }
+
+    if is_twap {
+        return validate_twap_order_group(order, orders);
+    }
+
+    validate!(
+        role_bit_flags.count_ones() == 1,
+        ErrorCode::InvalidOrderGroup,
+        "order in group {} must have exactly one group role",
+        order.group_id
+    )?;
+
+    let is_one_cancels_other = order.is_bit_flag_set(OrderBitFlag::OneCancelsOther);
+    let is_bracket_entry = order.is_bit_flag_set(OrderBitFlag::BracketEntry);
+    let is_bracket_exit = order.is_bit_flag_set(OrderBitFlag::BracketExit);
+
+    // exits rest untriggered until the entry fills
+    validate!(
+        !is_bracket_exit || order.must_be_triggered(),
+        ErrorCode::InvalidOrderGroup,
+        "bracket exit must be a trigger order"
+    )?;
+
+    for other in orders.iter().filter(|other| {
+        other.status == OrderStatus::Open
+            && other.group_id == order.group_id
+            && other.order_id != order.order_id
+    }) {
+        validate!(
+            other.order_type != OrderType::Twap && !other.is_bit_flag_set(OrderBitFlag::TwapSlice),
+            ErrorCode::InvalidOrderGroup,
+            "group {} is used by a twap order",
+            order.group_id
+        )?;
+
+        validate!(
+            is_one_cancels_other == other.is_bit_flag_set(OrderBitFlag::OneCancelsOther),
+            ErrorCode::InvalidOrderGroup,
+            "group {} can not mix one cancels other and bracket orders",
+            order.group_id
+        )?;
+
+        let other_is_bracket_entry = other.is_bit_flag_set(OrderBitFlag::BracketEntry);
+
+        validate!(
+            !(is_bracket_entry && other_is_bracket_entry),
+            ErrorCode::InvalidOrderGroup,
+            "group {} already has a bracket entry",
+            order.group_id
+        )?;
+
+        if (is_bracket_entry && other.is_bit_flag_set(OrderBitFlag::BracketExit))
+            || (is_bracket_exit && other_is_bracket_entry)
+        {
+            validate!(
+                order.market_index == other.market_index
+                    && order.market_type == other.market_type
+                    && order.direction != other.direction,
+                ErrorCode::InvalidOrderGroup,
+                "bracket exit must be for the opposite side of the entry's market"
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_twap_order_group(order: &Order, orders: &[Order]) -> DriftResult {
+    validate!(
+        order.bit_flags & ORDER_GROUP_ROLE_BIT_FLAGS == 0,
+        ErrorCode::InvalidOrderGroup,
+        "twap order or slice can not have a group role"
+    )?;
+
+    let mut group = orders.iter().filter(|other| {
+        other.status == OrderStatus::Open
+            && other.group_id == order.group_id
+            && other.order_id != order.order_id
+    });
+
+    if order.order_type == OrderType::Twap {
+        validate!(
+            !order.is_bit_flag_set(OrderBitFlag::TwapSlice),
+            ErrorCode::InvalidOrderGroup,
+            "twap order can not be a twap slice"
+        )?;
+
+        validate!(
+            group.next().is_none(),
+            ErrorCode::InvalidOrderGroup,
+            "group {} is already in use",
+            order.group_id
+        )?;
+    } else {
+        let is_valid_slice = group.any(|other| {
+            other.order_type == OrderType::Twap
+                && other.market_index == order.market_index
+                && other.market_type == order.market_type
+                && other.direction == order.direction
+                && other.reduce_only == order.reduce_only
+        });
+
+        validate!(
+            is_valid_slice,
+            ErrorCode::InvalidOrderGroup,
+            "twap slice must be released by a twap order for the same market and direction in group {}",
+            order.group_id
+        )?;
+    }
+
+    Ok(())
+}
@@ -1,9 +1,10 @@
+use crate::controller::position::PositionDirection;
 use crate::error::{DriftResult, ErrorCode};
 use crate::math::casting::Cast;
 use crate::math::constants::MAX_OPEN_ORDERS;
 use crate::math::orders::is_multiple_of_step_size;
 use crate::state::perp_market::PerpMarket;
-use crate::state::user::{PerpPosition, SpotPosition};
+use crate::state::user::{PerpPosition, PerpPositionTakeProfitStopLoss, SpotPosition, User};
 use crate::validate;
 use solana_program::msg;
 
@@ -37,6 +38,57 @@ pub fn validate_perp_position_with_perp_market(
     Ok(())
 }
 
+pub fn validate_perp_position_take_profit_and_stop_loss(
+    take_profit_stop_loss: &PerpPositionTakeProfitStopLoss,
+    user: &User,
+    oracle_price: u64,
+) -> DriftResult {
+    if !take_profit_stop_loss.is_set() {
+        return Ok(());
+    }
+
+    validate!(
+        take_profit_stop_loss.is_for_position(user),
+        ErrorCode::InvalidPositionTakeProfitStopLoss,
+        "perp market={} has no open {:?} position",
+        take_profit_stop_loss.market_index,
+        take_profit_stop_loss.position_direction
+    )?;
+
+    let take_profit_price = take_profit_stop_loss.take_profit_price;
+    let stop_loss_price = take_profit_stop_loss.stop_loss_price;
+    let (take_profit_valid, stop_loss_valid) = match take_profit_stop_loss.position_direction {
+        PositionDirection::Long => (
+            take_profit_price == 0 || take_profit_price > oracle_price,
+            stop_loss_price == 0 || stop_loss_price < oracle_price,
+        ),
+        PositionDirection::Short => (
+            take_profit_price == 0 || take_profit_price < oracle_price,
+            stop_loss_price == 0 || stop_loss_price > oracle_price,
+        ),
+    };
+
+    validate!(
+        take_profit_valid,
+        ErrorCode::InvalidPositionTakeProfitStopLoss,
+        "take_profit_price={} would trigger immediately for {:?} position with oracle_price={}",
+        take_profit_price,
+        take_profit_stop_loss.position_direction,
+        oracle_price
+    )?;
+
+    validate!(
+        stop_loss_valid,
+        ErrorCode::InvalidPositionTakeProfitStopLoss,
+        "stop_loss_price={} would trigger immediately for {:?} position with oracle_price={}",
+        stop_loss_price,
+        take_profit_stop_loss.position_direction,
+        oracle_price
+    )?;
+
+    Ok(())
+}
+
 pub fn validate_spot_position(position: &SpotPosition) -> DriftResult {
     validate!(
         position.open_orders <= MAX_OPEN_ORDERS,
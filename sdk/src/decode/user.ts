@@ -189,6 +189,12 @@ export function decodeUser(buffer: Buffer): UserAccount {
 			orderType = OrderType.TRIGGER_LIMIT;
 		} else if (orderTypeNum === 4) {
 			orderType = OrderType.ORACLE;
+		} else if (orderTypeNum === 5) {
+			orderType = OrderType.TRAILING_STOP;
+		} else if (orderTypeNum === 6) {
+			orderType = OrderType.TRAILING_TAKE_PROFIT;
+		} else if (orderTypeNum === 7) {
+			orderType = OrderType.TWAP;
 		}
 		offset += 1;
 		const marketTypeNum = buffer.readUInt8(offset);
@@ -237,7 +243,12 @@ export function decodeUser(buffer: Buffer): UserAccount {
 		offset += 1;
 		const auctionDuration = buffer.readUInt8(offset);
 		offset += 1;
-		offset += 3; // padding
+		const bitFlags = buffer.readUInt8(offset);
+		offset += 1;
+		const groupId = buffer.readUInt8(offset);
+		offset += 1;
+		const slicesRemaining = buffer.readUInt8(offset);
+		offset += 1;
 		orders.push({
 			slot,
 			price,
@@ -263,6 +274,9 @@ export function decodeUser(buffer: Buffer): UserAccount {
 			immediateOrCancel,
 			triggerCondition,
 			auctionDuration,
+			bitFlags,
+			groupId,
+			slicesRemaining,
 		});
 	}
 
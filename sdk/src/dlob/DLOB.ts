@@ -8,7 +8,9 @@ import {
 	DLOBNodeType,
 	DriftClient,
 	getLimitPrice,
+	getTriggerSource,
 	getVariant,
+	hasOraclePriceOffset,
 	isFallbackAvailableLiquiditySource,
 	isOneOfVariant,
 	isOrderExpired,
@@ -392,6 +394,15 @@ export class DLOB {
 		const isInactiveTriggerOrder =
 			mustBeTriggered(order) && !isTriggered(order);
 
+		// the trigger lists are sorted by trigger price against this market's oracle, orders with
+		// another trigger source have to be checked with getTriggerSource
+		if (isInactiveTriggerOrder) {
+			const triggerSource = getTriggerSource(order);
+			if (!triggerSource || !isVariant(triggerSource, 'oracle')) {
+				return undefined;
+			}
+		}
+
 		let type: DLOBNodeType;
 		if (isInactiveTriggerOrder) {
 			type = 'trigger';
@@ -399,7 +410,7 @@ export class DLOB {
 			isOneOfVariant(order.orderType, ['market', 'triggerMarket', 'oracle'])
 		) {
 			type = 'market';
-		} else if (order.oraclePriceOffset !== 0) {
+		} else if (hasOraclePriceOffset(order)) {
 			type = 'floatingLimit';
 		} else {
 			const isResting = isRestingLimitOrder(order, slot);
@@ -4,6 +4,7 @@ import {
 	convertToNumber,
 	getLimitPrice,
 	isVariant,
+	mustBeTriggered,
 	PRICE_PRECISION,
 	OraclePriceData,
 	Order,
@@ -52,7 +53,8 @@ export abstract class OrderNode implements DLOBNode {
 				3
 			)}`;
 		}
-		if (this.order.triggerPrice.gt(ZERO)) {
+		// triggerPrice only holds a price for trigger orders, see the order layout in math/orders.ts
+		if (mustBeTriggered(this.order) && this.order.triggerPrice.gt(ZERO)) {
 			msg += ` ${
 				isVariant(this.order.triggerCondition, 'below') ? 'BELOW' : 'ABOVE'
 			}`;
@@ -7,6 +7,7 @@ import {
 	calculateSpreadReserves,
 	calculateUpdatedAMM,
 	DLOBNode,
+	getVisibleBaseAssetAmountUnfilled,
 	OraclePriceData,
 	PerpMarketAccount,
 	PositionDirection,
@@ -69,9 +70,7 @@ export function* getL2GeneratorFromDLOBNodes(
 	slot: number
 ): Generator<L2Level> {
 	for (const dlobNode of dlobNodes) {
-		const size = dlobNode.order.baseAssetAmount.sub(
-			dlobNode.order.baseAssetAmountFilled
-		) as BN;
+		const size = getVisibleBaseAssetAmountUnfilled(dlobNode.order);
 		yield {
 			size,
 			price: dlobNode.getPrice(oraclePriceData, slot),
@@ -111,6 +111,7 @@ import { configs, DRIFT_PROGRAM_ID } from './config';
 import { WRAPPED_SOL_MINT } from './constants/spotMarkets';
 import { UserStats } from './userStats';
 import { isSpotPositionAvailable } from './math/spotPosition';
+import { getTriggerMarketIndex } from './math/orders';
 import { calculateMarketMaxAvailableInsurance } from './math/market';
 import { fetchUserStatsAccount } from './accounts/fetch';
 import { castNumberToSpotPrecision } from './math/spotMarket';
@@ -4277,6 +4278,7 @@ export class DriftClient {
 			remainingAccountsParams = {
 				userAccounts: [userAccount],
 				writablePerpMarketIndexes: [order.marketIndex],
+				readablePerpMarketIndex: getTriggerMarketIndex(order),
 			};
 		} else {
 			remainingAccountsParams = {
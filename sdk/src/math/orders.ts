@@ -5,9 +5,11 @@ import {
 	PerpMarketAccount,
 	AMM,
 	Order,
+	OrderBitFlag,
 	PositionDirection,
+	TriggerSource,
 } from '../types';
-import { ZERO, TWO } from '../constants/numericConstants';
+import { ZERO, TWO, TEN_THOUSAND } from '../constants/numericConstants';
 import { BN } from '@coral-xyz/anchor';
 import { OraclePriceData } from '../oracles/types';
 import {
@@ -159,7 +161,7 @@ export function getLimitPrice(
 	let limitPrice;
 	if (hasAuctionPrice(order, slot)) {
 		limitPrice = getAuctionPrice(order, slot, oraclePriceData.price);
-	} else if (order.oraclePriceOffset !== 0) {
+	} else if (hasOraclePriceOffset(order)) {
 		limitPrice = oraclePriceData.price.add(new BN(order.oraclePriceOffset));
 	} else if (order.price.eq(ZERO)) {
 		limitPrice = fallbackPrice;
@@ -167,13 +169,67 @@ export function getLimitPrice(
 		limitPrice = order.price;
 	}
 
-	return limitPrice;
+	const maxSlippagePrice = getMaxSlippagePrice(order, oraclePriceData.price);
+	if (maxSlippagePrice === undefined) {
+		return limitPrice;
+	}
+
+	if (limitPrice === undefined) {
+		return maxSlippagePrice;
+	}
+
+	return isVariant(order.direction, 'long')
+		? BN.min(limitPrice, maxSlippagePrice)
+		: BN.max(limitPrice, maxSlippagePrice);
+}
+
+export function hasOraclePriceOffset(order: Order): boolean {
+	// max slippage orders that aren't oracle orders use the offset as the max slippage
+	return (
+		order.oraclePriceOffset !== 0 &&
+		!isOneOfVariant(order.orderType, [
+			'trailingStop',
+			'trailingTakeProfit',
+		]) &&
+		(isVariant(order.orderType, 'oracle') ||
+			!isOrderBitFlagSet(order, OrderBitFlag.MAX_SLIPPAGE))
+	);
+}
+
+export function getMaxSlippageBps(order: Order): BN | undefined {
+	if (!isOrderBitFlagSet(order, OrderBitFlag.MAX_SLIPPAGE)) {
+		return undefined;
+	}
+
+	if (isVariant(order.orderType, 'oracle')) {
+		return order.triggerPrice;
+	}
+
+	return new BN(Math.abs(order.oraclePriceOffset));
+}
+
+/**
+ * For max slippage orders, the worst price the order can fill at given the oracle price
+ */
+export function getMaxSlippagePrice(
+	order: Order,
+	oraclePrice: BN
+): BN | undefined {
+	const maxSlippageBps = getMaxSlippageBps(order);
+	if (maxSlippageBps === undefined) {
+		return undefined;
+	}
+
+	const maxSlippage = oraclePrice.abs().mul(maxSlippageBps).div(TEN_THOUSAND);
+	return isVariant(order.direction, 'long')
+		? oraclePrice.abs().add(maxSlippage)
+		: oraclePrice.abs().sub(maxSlippage);
 }
 
 export function hasLimitPrice(order: Order, slot: number): boolean {
 	return (
 		order.price.gt(ZERO) ||
-		order.oraclePriceOffset != 0 ||
+		hasOraclePriceOffset(order) ||
 		!isAuctionComplete(order, slot)
 	);
 }
@@ -308,7 +364,13 @@ export function isOrderExpired(
 }
 
 export function isMarketOrder(order: Order): boolean {
-	return isOneOfVariant(order.orderType, ['market', 'triggerMarket', 'oracle']);
+	return isOneOfVariant(order.orderType, [
+		'market',
+		'triggerMarket',
+		'oracle',
+		'trailingStop',
+		'trailingTakeProfit',
+	]);
 }
 
 export function isLimitOrder(order: Order): boolean {
@@ -316,7 +378,97 @@ export function isLimitOrder(order: Order): boolean {
 }
 
 export function mustBeTriggered(order: Order): boolean {
-	return isOneOfVariant(order.orderType, ['triggerMarket', 'triggerLimit']);
+	return isOneOfVariant(order.orderType, [
+		'triggerMarket',
+		'triggerLimit',
+		'trailingStop',
+		'trailingTakeProfit',
+		'twap',
+	]);
+}
+
+export function isTwapOrder(order: Order): boolean {
+	return isVariant(order.orderType, 'twap');
+}
+
+// Order layout v2 (see Order in the program's state/user.rs). Some order types and
+// bit flags reuse fields they don't need for their own value, so read those fields
+// through the helpers below instead of as prices:
+// - twap: triggerPrice is the next slice release ts
+// - iceberg: triggerPrice is the display size
+// - trigger source: auctionStartPrice is the trigger source and auctionEndPrice the trigger
+//   market index, until the trigger order is triggered
+// - max slippage: triggerPrice (oracle orders) or abs(oraclePriceOffset) is the max slippage bps
+export function isOrderBitFlagSet(order: Order, bitFlag: number): boolean {
+	return (order.bitFlags & bitFlag) !== 0;
+}
+
+export function getTwapNextReleaseTs(order: Order): BN | undefined {
+	if (!isTwapOrder(order)) {
+		return undefined;
+	}
+
+	return order.triggerPrice;
+}
+
+const TRIGGER_SOURCES = [
+	TriggerSource.ORACLE,
+	TriggerSource.MARKET_ORACLE,
+	TriggerSource.FUNDING_RATE,
+	TriggerSource.MARK_ORACLE_DIVERGENCE,
+];
+
+export function getTriggerSource(order: Order): TriggerSource | undefined {
+	// the auction is only set once the order is triggered
+	if (
+		!isOneOfVariant(order.orderType, ['triggerMarket', 'triggerLimit']) ||
+		isTriggered(order)
+	) {
+		return TriggerSource.ORACLE;
+	}
+
+	return TRIGGER_SOURCES[order.auctionStartPrice.toNumber()];
+}
+
+export function getTriggerMarketIndex(order: Order): number | undefined {
+	const triggerSource = getTriggerSource(order);
+	if (!triggerSource || !isVariant(triggerSource, 'marketOracle')) {
+		return undefined;
+	}
+
+	return order.auctionEndPrice.toNumber();
+}
+
+export function getIcebergDisplayBaseAssetAmount(order: Order): BN | undefined {
+	if (
+		!isOrderBitFlagSet(order, OrderBitFlag.ICEBERG) ||
+		order.triggerPrice.eq(ZERO)
+	) {
+		return undefined;
+	}
+
+	return order.triggerPrice;
+}
+
+/**
+ * The unfilled amount takers can match against. For iceberg orders, what's left of the slice on display
+ */
+export function getVisibleBaseAssetAmountUnfilled(order: Order): BN {
+	const baseAssetAmountUnfilled = order.baseAssetAmount.sub(
+		order.baseAssetAmountFilled
+	);
+
+	const displayBaseAssetAmount = getIcebergDisplayBaseAssetAmount(order);
+	if (displayBaseAssetAmount === undefined) {
+		return baseAssetAmountUnfilled;
+	}
+
+	return BN.min(
+		displayBaseAssetAmount.sub(
+			order.baseAssetAmountFilled.mod(displayBaseAssetAmount)
+		),
+		baseAssetAmountUnfilled
+	);
 }
 
 export function isTriggered(order: Order): boolean {
@@ -104,6 +104,9 @@ export class OrderType {
 	static readonly TRIGGER_LIMIT = { triggerLimit: {} };
 	static readonly MARKET = { market: {} };
 	static readonly ORACLE = { oracle: {} };
+	static readonly TRAILING_STOP = { trailingStop: {} };
+	static readonly TRAILING_TAKE_PROFIT = { trailingTakeProfit: {} };
+	static readonly TWAP = { twap: {} }; // never filled itself, keepers release it in market order slices
 }
 
 export declare type MarketTypeStr = 'perp' | 'spot';
@@ -123,6 +126,7 @@ export class OrderAction {
 	static readonly EXPIRE = { expire: {} };
 	static readonly FILL = { fill: {} };
 	static readonly TRIGGER = { trigger: {} };
+	static readonly UPDATE_TRIGGER = { updateTrigger: {} };
 }
 
 export class OrderActionExplanation {
@@ -178,6 +182,42 @@ export class OrderActionExplanation {
 	static readonly DERISK_LP = {
 		deriskLp: {},
 	};
+	static readonly TRAILING_TRIGGER_PRICE_UPDATED = {
+		trailingTriggerPriceUpdated: {},
+	};
+	static readonly ONE_CANCELS_OTHER = {
+		oneCancelsOther: {},
+	};
+	static readonly BRACKET_ENTRY_CANCELED = {
+		bracketEntryCanceled: {},
+	};
+	static readonly POSITION_TAKE_PROFIT = {
+		positionTakeProfit: {},
+	};
+	static readonly POSITION_STOP_LOSS = {
+		positionStopLoss: {},
+	};
+	static readonly TWAP_SLICE_RELEASED = {
+		twapSliceReleased: {},
+	};
+	static readonly TWAP_SLICE_ROLLED_OVER = {
+		twapSliceRolledOver: {},
+	};
+	static readonly TWAP_COMPLETED = {
+		twapCompleted: {},
+	};
+	static readonly TWAP_CANCELED = {
+		twapCanceled: {},
+	};
+	static readonly SELF_TRADE_PREVENTED = {
+		selfTradePrevented: {},
+	};
+	static readonly CANCEL_AFTER_DEADLINE = {
+		cancelAfterDeadline: {},
+	};
+	static readonly MAX_SLIPPAGE_EXCEEDED = {
+		maxSlippageExceeded: {},
+	};
 }
 
 export class OrderTriggerCondition {
@@ -187,6 +227,26 @@ export class OrderTriggerCondition {
 	static readonly TRIGGERED_BELOW = { triggeredBelow: {} }; // below condition has been triggered
 }
 
+// what a trigger order's trigger price is compared against, see getTriggerSource
+export class TriggerSource {
+	static readonly ORACLE = { oracle: {} };
+	static readonly MARKET_ORACLE = { marketOracle: {} }; // another perp market's oracle
+	static readonly FUNDING_RATE = { fundingRate: {} }; // FUNDING_RATE_PRECISION
+	static readonly MARK_ORACLE_DIVERGENCE = { markOracleDivergence: {} }; // PERCENTAGE_PRECISION
+}
+
+// Order.bitFlags, see the order layout in math/orders.ts
+export class OrderBitFlag {
+	static readonly TRAILING_PERCENTAGE = 1;
+	static readonly ONE_CANCELS_OTHER = 2;
+	static readonly BRACKET_ENTRY = 4;
+	static readonly BRACKET_EXIT = 8;
+	static readonly TWAP_SLICE = 16;
+	static readonly FILL_OR_KILL = 32;
+	static readonly ICEBERG = 64;
+	static readonly MAX_SLIPPAGE = 128;
+}
+
 export class SpotFulfillmentType {
 	static readonly EXTERNAL = { external: {} };
 	static readonly MATCH = { match: {} };
@@ -909,6 +969,9 @@ export type Order = {
 	auctionStartPrice: BN;
 	auctionEndPrice: BN;
 	maxTs: BN;
+	bitFlags: number;
+	groupId: number;
+	slicesRemaining: number;
 };
 
 export type OrderParams = {
@@ -929,6 +992,15 @@ export type OrderParams = {
 	maxTs: BN | null;
 	auctionStartPrice: BN | null;
 	auctionEndPrice: BN | null;
+	bitFlags: number;
+	groupId: number;
+	twapSlices: number;
+	minFillBaseAssetAmount: BN;
+	displayBaseAssetAmount: BN;
+	maxSlippageBps: number;
+	maxSlideTicks: number;
+	triggerSource: TriggerSource;
+	triggerMarketIndex: number;
 };
 
 export class PostOnlyParams {
@@ -976,6 +1048,15 @@ export const DefaultOrderParams: OrderParams = {
 	maxTs: null,
 	auctionStartPrice: null,
 	auctionEndPrice: null,
+	bitFlags: 0,
+	groupId: 0,
+	twapSlices: 0,
+	minFillBaseAssetAmount: ZERO,
+	displayBaseAssetAmount: ZERO,
+	maxSlippageBps: 0,
+	maxSlideTicks: 0,
+	triggerSource: TriggerSource.ORACLE,
+	triggerMarketIndex: 0,
 };
 
 export type MakerInfo = {
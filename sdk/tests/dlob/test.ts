@@ -74,6 +74,9 @@ function insertOrderToDLOB(
 			auctionStartPrice,
 			auctionEndPrice,
 			maxTs,
+			bitFlags: 0,
+			groupId: 0,
+			slicesRemaining: 0,
 		},
 		userAccount.toString(),
 		slot.toNumber()
@@ -125,6 +128,9 @@ function insertTriggerOrderToDLOB(
 			auctionStartPrice,
 			auctionEndPrice,
 			maxTs,
+			bitFlags: 0,
+			groupId: 0,
+			slicesRemaining: 0,
 		},
 		userAccount.toString(),
 		slot.toNumber()
@@ -40,6 +40,9 @@ export const mockOrder: Order = {
 	auctionStartPrice: ZERO,
 	auctionEndPrice: ZERO,
 	maxTs: ZERO,
+	bitFlags: 0,
+	groupId: 0,
+	slicesRemaining: 0,
 };
 
 export const mockSpotPosition: SpotPosition = {